@@ -0,0 +1,129 @@
+// Benchmarks of the rules engine, run with `cargo bench --bench rules`.
+// Positions come from seeded random games, so every run measures the same
+// boards.
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use m3c4::{
+    action::BoardAction,
+    board::{CascadeStep, TerminalResult},
+    BoardState,
+};
+use mcts::GameState;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+// Every position of a number of random games with the move played from it.
+fn random_positions(games: usize) -> Vec<(BoardState, BoardAction)> {
+    let mut rng = StdRng::seed_from_u64(5);
+    let mut positions = Vec::new();
+    for _ in 0..games {
+        let mut state = BoardState::default();
+        while let Some(&mov) = state.available_moves().choose(&mut rng) {
+            positions.push((state.clone(), mov));
+            state.make_move(&mov);
+        }
+    }
+    positions
+}
+
+// Whether the move clears a line and the stones that fall clear another.
+fn cascades(state: &BoardState, mov: &BoardAction) -> bool {
+    let clears = state
+        .clone()
+        .play_steps(mov)
+        .iter()
+        .filter(|step| matches!(step, CascadeStep::Cleared(..)))
+        .count();
+    clears > 1
+}
+
+fn make_move(c: &mut Criterion) {
+    let positions = random_positions(200);
+    let (quiet, cascading): (Vec<_>, Vec<_>) = positions
+        .into_iter()
+        .partition(|(state, mov)| state.clone().play(mov).is_empty());
+    let cascading = cascading
+        .into_iter()
+        .filter(|(state, mov)| cascades(state, mov))
+        .collect::<Vec<_>>();
+    assert!(!cascading.is_empty(), "No cascades in the random games");
+
+    let mut group = c.benchmark_group("make_move");
+    for (name, positions) in [("quiet", &quiet), ("cascade", &cascading)] {
+        group.bench_function(name, |b| {
+            let mut i = 0;
+            b.iter_batched(
+                || {
+                    i = (i + 1) % positions.len();
+                    positions[i].clone()
+                },
+                |(mut state, mov)| {
+                    state.make_move(&mov);
+                    state
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn available_moves(c: &mut Criterion) {
+    let positions = random_positions(20);
+    let opening = BoardState::default();
+    // Later positions may have points to spend on switches.
+    let late = positions
+        .iter()
+        .filter(|(state, _)| state.board().stone_count() >= 12)
+        .map(|(state, _)| state.clone())
+        .collect::<Vec<_>>();
+    assert!(!late.is_empty(), "No late positions in the random games");
+
+    let mut group = c.benchmark_group("available_moves");
+    group.bench_function("opening", |b| {
+        b.iter(|| black_box(&opening).available_moves())
+    });
+    group.bench_function("late", |b| {
+        b.iter(|| {
+            black_box(&late)
+                .iter()
+                .map(|state| state.available_moves().len())
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+fn terminal_status(c: &mut Criterion) {
+    let positions = random_positions(20);
+    c.bench_function("get_board_terminal_status", |b| {
+        b.iter(|| {
+            positions
+                .iter()
+                .filter(|(state, _)| {
+                    state.board().get_board_terminal_status(state.rules()) != TerminalResult::None
+                })
+                .count()
+        })
+    });
+}
+
+fn random_game(c: &mut Criterion) {
+    c.bench_function("random_game", |b| {
+        let mut rng = StdRng::seed_from_u64(9);
+        b.iter(|| {
+            let mut state = BoardState::default();
+            while let Some(&mov) = state.available_moves().choose(&mut rng) {
+                state.make_move(&mov);
+            }
+            state.get_winner()
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    make_move,
+    available_moves,
+    terminal_status,
+    random_game
+);
+criterion_main!(benches);
@@ -0,0 +1,147 @@
+use std::io::{BufRead, Write};
+
+use m3c4::{
+    action::{BoardAction, Coordinate},
+    board::{Board, CascadeStep, Cell, MAX_HEIGHT, MAX_WIDTH},
+    player::Player,
+    rules::GameRules,
+};
+
+const HELP: &str = "\
+set <row>/<row>/...  set up the board, top row first, '.' or ' ' for empty
+player X|O           player making the moves
+move <action>        apply an action without resolving it (d3, s2,0-3,0)
+step                 resolve one cascade phase
+run                  resolve cascade phases until the board settles
+show                 print the board and the matches that would clear
+quit                 exit";
+
+fn main() {
+    let mut board = Board::default();
+    let mut player = Player::Player1;
+    let mut points = (0, 0);
+
+    println!("{}", HELP);
+
+    let stdin = std::io::stdin();
+    loop {
+        print!("> ");
+        std::io::stdout().flush().expect("Could not flush stdout");
+
+        let mut line = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .expect("Could not read line")
+            == 0
+        {
+            break;
+        }
+        let (command, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+
+        match command {
+            "set" => match parse_board(argument) {
+                Some(b) => {
+                    board = b;
+                    points = (0, 0);
+                    show(&board);
+                }
+                None => println!(
+                    "Expected at most {} rows of at most {} cells",
+                    MAX_HEIGHT, MAX_WIDTH
+                ),
+            },
+            "player" => match argument {
+                "X" => player = Player::Player1,
+                "O" => player = Player::Player2,
+                _ => println!("Unknown player: {}", argument),
+            },
+            "move" => match BoardAction::parse(argument, player) {
+                Some(mov) => {
+                    board.apply_action(&mov);
+                    show(&board);
+                }
+                None => println!("Could not parse action: {}", argument),
+            },
+            "step" => {
+                step(&mut board, player, &mut points);
+            }
+            "run" => while step(&mut board, player, &mut points) {},
+            "show" => show(&board),
+            "quit" => break,
+            _ => println!("{}", HELP),
+        }
+    }
+}
+
+// Returns whether another step could change the board.
+fn step(board: &mut Board, player: Player, points: &mut (usize, usize)) -> bool {
+    match board.cascade_step(player, &GameRules::default()) {
+        CascadeStep::Terminal(result) => {
+            println!("Terminal: {:?}", result);
+            false
+        }
+        CascadeStep::Cleared(results, cleared) => {
+            for result in &results {
+                match result.scorer() {
+                    Some(Player::Player1) => points.0 += 1,
+                    Some(Player::Player2) => points.1 += 1,
+                    None => {}
+                }
+            }
+            println!("Scored: {:?}", results);
+            println!("Cleared: {:?}", cleared);
+            println!("Points: X {} / O {}", points.0, points.1);
+            show(board);
+            true
+        }
+        CascadeStep::Settled => {
+            println!("Settled");
+            false
+        }
+    }
+}
+
+fn show(board: &Board) {
+    let (results, cells) = board.find_matches(&GameRules::default());
+    for y in (0..board.height()).rev() {
+        let row = (0..board.width())
+            .map(|x| {
+                let coord = Coordinate::new(x as isize, y as isize);
+                match board.get(coord) {
+                    _ if cells.contains(&coord) => '*',
+                    Cell::Empty => ' ',
+                    Cell::Filled(Player::Player1) => 'X',
+                    Cell::Filled(Player::Player2) => 'O',
+                }
+            })
+            .collect::<String>();
+        println!("|{}| {}", row, y);
+    }
+    if !results.is_empty() {
+        println!("Pending: {:?}", results);
+    }
+}
+
+// The board is as wide as the longest row, shorter rows are padded.
+fn parse_board(s: &str) -> Option<Board> {
+    let width = s.split('/').map(str::len).max()?;
+    let rows = s
+        .split('/')
+        .map(|row| format!("{:<width$}", row.replace('.', " "), width = width))
+        .collect::<Vec<_>>();
+
+    if rows.len() > MAX_HEIGHT || width == 0 || width > MAX_WIDTH {
+        return None;
+    }
+    if rows
+        .iter()
+        .flat_map(|r| r.chars())
+        .any(|c| !"XO ".contains(c))
+    {
+        return None;
+    }
+
+    let rows = rows.iter().map(String::as_str).collect::<Vec<_>>();
+    Some(Board::from(&rows[..]))
+}
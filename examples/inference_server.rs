@@ -0,0 +1,37 @@
+use std::path::Path;
+
+use catzero::{CatZeroModel, PyEnv};
+use m3c4::inference::{read_latest, serve, ModelBroker};
+use tracing::warn;
+
+const MODEL_PATH: &str = "data/models/graph";
+const REGISTRY: &str = "data/models/best";
+
+// Usage: inference_server [address]
+// Serves the generation named in data/models/best and swaps in newer
+// generations as they are promoted.
+fn main() {
+    tracing_subscriber::fmt::init();
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| String::from("0.0.0.0:8080"));
+
+    let mut pyenv = PyEnv::new();
+    let python = pyenv.python();
+    let load = |episode: usize| {
+        CatZeroModel::load(&python, MODEL_PATH, episode, (1, 3, 3))?.to_tf_model(episode)
+    };
+
+    let registry = Path::new(REGISTRY);
+    let generation = read_latest(registry)
+        .expect("Could not read model registry")
+        .unwrap_or(0);
+    let broker = ModelBroker::new(generation, load(generation).expect("Could not load model"));
+
+    serve(&broker, &address, |broker| {
+        if let Err(e) = broker.reload_if_newer(registry, load) {
+            warn!(error = %e, "Could not reload model");
+        }
+    })
+    .expect("Could not serve model");
+}
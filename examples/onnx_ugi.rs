@@ -0,0 +1,36 @@
+use std::{io, sync::Arc};
+
+use m3c4::{
+    onnx::{OnnxMCTS, OnnxModel},
+    planes::InputEncoding,
+    rules::GameRules,
+    ugi::Ugi,
+};
+
+const EXPLORATION: f64 = 1.45;
+
+// Usage: onnx_ugi <model.onnx>
+// Speaks UGI with a model exported to ONNX, on a build without TensorFlow:
+// cargo run --release --no-default-features --features onnx --example onnx_ugi -- model.onnx
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .expect("usage: onnx_ugi <model.onnx>");
+
+    let rules = GameRules::default();
+    let model = OnnxModel::load(
+        &path,
+        rules.width,
+        rules.height,
+        rules.diagonal_switches,
+        InputEncoding::default(),
+    )
+    .expect("Could not load model");
+    let model = Arc::new(model);
+
+    let mut ugi = Ugi::new("m3c4 onnx", rules, move |state| {
+        OnnxMCTS::create_search(state, model.clone(), EXPLORATION)
+    });
+    ugi.run(io::stdin().lock(), io::stdout())
+        .expect("Could not talk UGI");
+}
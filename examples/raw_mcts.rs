@@ -1,11 +1,31 @@
-use m3c4::{action::BoardAction, player::Player, BoardState};
+use m3c4::{
+    action::MoveList,
+    heuristic::{HeuristicEvaluator, StateEval},
+    render::RenderOptions,
+    rollout::{rollout, HeuristicPolicy, RolloutPolicy},
+    BoardState,
+};
 use mcts::{
     transposition_table::ApproxTable, tree_policy::UCTPolicy, Evaluator, GameState, MCTSManager,
     MCTS,
 };
-use rand::prelude::SliceRandom;
+use rand::{rngs::StdRng, SeedableRng};
 
+// Usage: raw_mcts [rollout|heuristic] [seed]
 fn main() {
+    let seed = std::env::args()
+        .nth(2)
+        .map(|seed| seed.parse().expect("Could not parse seed"))
+        .unwrap_or_else(rand::random);
+    let evaluator = match std::env::args().nth(1).as_deref() {
+        None | Some("rollout") => LeafEvaluator::Rollout(RolloutEvaluator {
+            policy: HeuristicPolicy::default(),
+            seed,
+        }),
+        Some("heuristic") => LeafEvaluator::Heuristic(HeuristicEvaluator::default()),
+        Some(other) => panic!("Unknown evaluator: {}", other),
+    };
+
     println!("Starting program...");
     let mut state = BoardState::default();
     println!("Created initial state...");
@@ -15,7 +35,7 @@ fn main() {
         let mut manager = MCTSManager::new(
             state.clone(),
             MyMCTS,
-            RandomEvaluator,
+            evaluator.clone(),
             UCTPolicy::new(exploration),
             ApproxTable::new(1024),
         );
@@ -26,23 +46,21 @@ fn main() {
         if let Some(best) = manager.best_move() {
             println!("Best move: {:?}", best);
             state.make_move(&best);
-        }
 
-        println!("{:?}", state);
+            let options = RenderOptions {
+                last_move: Some(best),
+                ..RenderOptions::labelled()
+            };
+            println!("{}", state.render_text(&options));
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-enum StateEval {
-    Win(Player),
-    Draw,
-}
-
 struct MyMCTS;
 
 impl MCTS for MyMCTS {
     type State = BoardState;
-    type Eval = RandomEvaluator;
+    type Eval = LeafEvaluator;
     type TreePolicy = UCTPolicy<()>;
     type NodeData = ();
     type TranspositionTable = ApproxTable<Self>;
@@ -53,33 +71,71 @@ impl MCTS for MyMCTS {
     }
 }
 
-struct RandomEvaluator;
+#[derive(Clone)]
+enum LeafEvaluator {
+    Rollout(RolloutEvaluator<HeuristicPolicy>),
+    Heuristic(HeuristicEvaluator),
+}
 
-impl Evaluator<MyMCTS> for RandomEvaluator {
+impl Evaluator<MyMCTS> for LeafEvaluator {
     type StateEvaluation = StateEval;
 
     fn evaluate_new_state(
         &self,
         state: &BoardState,
-        moves: &Vec<BoardAction>,
+        moves: &MoveList,
+        handle: Option<mcts::SearchHandle<MyMCTS>>,
+    ) -> (Vec<mcts::MoveEvaluation<MyMCTS>>, Self::StateEvaluation) {
+        match self {
+            LeafEvaluator::Rollout(eval) => eval.evaluate_new_state(state, moves, handle),
+            LeafEvaluator::Heuristic(eval) => eval.evaluate_new_state(state, moves, handle),
+        }
+    }
+
+    fn evaluate_existing_state(
+        &self,
+        _: &BoardState,
+        existing_evaln: &Self::StateEvaluation,
+        _: mcts::SearchHandle<MyMCTS>,
+    ) -> Self::StateEvaluation {
+        existing_evaln.clone()
+    }
+
+    fn interpret_evaluation_for_player(
+        &self,
+        evaluation: &Self::StateEvaluation,
+        player: &mcts::Player<MyMCTS>,
+    ) -> f64 {
+        evaluation.value_for(player)
+    }
+}
+
+// The rollout of a state is drawn from the seed and the state, so it is the
+// same whichever thread reaches the state first.
+#[derive(Clone)]
+struct RolloutEvaluator<P> {
+    policy: P,
+    seed: u64,
+}
+
+impl<P: RolloutPolicy + Send + Sync> Evaluator<MyMCTS> for RolloutEvaluator<P> {
+    type StateEvaluation = StateEval;
+
+    fn evaluate_new_state(
+        &self,
+        state: &BoardState,
+        moves: &MoveList,
         _: Option<mcts::SearchHandle<MyMCTS>>,
     ) -> (Vec<mcts::MoveEvaluation<MyMCTS>>, Self::StateEvaluation) {
         let evals = moves.iter().map(|_| ()).collect();
-        let mut rng = rand::thread_rng();
-        let mut state = state.clone();
-
-        while !state.is_terminal() {
-            let moves = state.available_moves();
-            let chosen = moves.choose(&mut rng).expect("Could not choose action");
-            state.make_move(chosen);
-        }
+        let mut rng = StdRng::seed_from_u64(self.seed ^ state.zobrist());
 
-        let random_play_result = match state.get_winner() {
-            Some(winner) => StateEval::Win(winner),
+        let rollout_result = match rollout(state, &self.policy, &mut rng) {
+            Some(winner) => StateEval::Winner(winner),
             None => StateEval::Draw,
         };
 
-        (evals, random_play_result)
+        (evals, rollout_result)
     }
 
     fn evaluate_existing_state(
@@ -97,9 +153,9 @@ impl Evaluator<MyMCTS> for RandomEvaluator {
         player: &mcts::Player<MyMCTS>,
     ) -> f64 {
         match evaluation {
-            StateEval::Win(winner) if player == winner => 1.0,
-            StateEval::Win(_) => -1.0,
-            StateEval::Draw => 0.0,
+            StateEval::Winner(winner) if player == winner => 1.0,
+            StateEval::Winner(_) => -1.0,
+            _ => 0.0,
         }
     }
 }
@@ -0,0 +1,12 @@
+use m3c4::stats::GameStats;
+
+// Usage: cargo run --example stats -- data/games
+fn main() {
+    let dir = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| String::from("data/games"));
+
+    let stats = GameStats::from_dir(&dir).expect("Could not read game records");
+
+    println!("{}", stats);
+}
@@ -1,6 +1,6 @@
 @Echo Off
 call conda activate tensorflow
 call set PYTHONHOME=C:\tools\miniconda3\envs\tensorflow
-call cargo run --example %1 --release
+call cargo run --release -- %*
 
 pause
\ No newline at end of file
@@ -1,14 +1,125 @@
-use std::ops::{Add, Sub};
+use std::{
+    fmt::Display,
+    ops::{Add, Sub},
+};
 
-use crate::player::Player;
+use smallvec::SmallVec;
+
+use crate::{
+    board::{MAX_HEIGHT, MAX_WIDTH},
+    player::Player,
+};
+
+// Actions a search expands stay on the stack up to this many, the 8 drops of
+// a standard board and a few dozen switches. Longer lists spill to the heap.
+pub const INLINE_MOVES: usize = 40;
+
+pub type MoveList = SmallVec<[BoardAction; INLINE_MOVES]>;
+
+// A column index that fits the largest supported board, the board decides
+// whether it actually has the column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "usize", into = "usize")
+)]
+pub struct Column(usize);
+
+impl Column {
+    pub fn new(col: usize) -> Option<Self> {
+        if col < MAX_WIDTH {
+            Some(Column(col))
+        } else {
+            None
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.0
+    }
+
+    // The column must be on a board of the width.
+    pub fn mirrored(&self, width: usize) -> Self {
+        debug_assert!(self.0 < width, "Column {} is not below {}", self.0, width);
+        Column(width - 1 - self.0)
+    }
+}
+
+impl TryFrom<usize> for Column {
+    type Error = String;
+
+    fn try_from(col: usize) -> Result<Self, Self::Error> {
+        Column::new(col).ok_or_else(|| format!("column {} is out of range", col))
+    }
+}
+
+impl From<Column> for usize {
+    fn from(col: Column) -> Self {
+        col.0
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// A row index that fits the largest supported board, 0 is the bottom row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "usize", into = "usize")
+)]
+pub struct Row(usize);
+
+impl Row {
+    pub fn new(row: usize) -> Option<Self> {
+        if row < MAX_HEIGHT {
+            Some(Row(row))
+        } else {
+            None
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+impl TryFrom<usize> for Row {
+    type Error = String;
+
+    fn try_from(row: usize) -> Result<Self, Self::Error> {
+        Row::new(row).ok_or_else(|| format!("row {} is out of range", row))
+    }
+}
+
+impl From<Row> for usize {
+    fn from(row: Row) -> Self {
+        row.0
+    }
+}
+
+impl Display for Row {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Coordinate(isize, isize);
 
 impl Coordinate {
     pub fn new(x: isize, y: isize) -> Self {
         Coordinate(x, y)
     }
+    pub fn at(col: Column, row: Row) -> Self {
+        Coordinate(col.index() as isize, row.index() as isize)
+    }
     pub fn x(&self) -> isize {
         self.0
     }
@@ -24,6 +135,11 @@ impl Coordinate {
     pub fn offset(&self, offset: (isize, isize), distance: isize) -> Self {
         Coordinate(self.0 + offset.0 * distance, self.1 + offset.0 * distance)
     }
+
+    // Reflects the coordinate left-right across a board of the given width.
+    pub fn mirrored(&self, width: usize) -> Self {
+        Coordinate(width as isize - 1 - self.0, self.1)
+    }
 }
 
 impl Add<(isize, isize)> for Coordinate {
@@ -42,8 +158,183 @@ impl Sub<(isize, isize)> for Coordinate {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+// The variants can be built directly, with any cells. Build switches with
+// `BoardAction::switch` and check actions against a position with
+// `BoardState::check_move` before playing them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BoardAction {
-    DropStone(Player, usize),
+    DropStone(Player, Column),
     SwitchStone(Coordinate, Coordinate),
 }
+
+// Drops are written as `d<col>`, switches as `s<x>,<y>-<x>,<y>`.
+impl Display for BoardAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BoardAction::DropStone(_, col) => write!(f, "d{}", col),
+            BoardAction::SwitchStone(a, b) => {
+                write!(f, "s{},{}-{},{}", a.x(), a.y(), b.x(), b.y())
+            }
+        }
+    }
+}
+
+impl BoardAction {
+    // A switch of two neighbouring cells of the largest supported board,
+    // sideways, up or diagonal. Whether the board has the cells and the
+    // rules allow the direction is up to the state.
+    pub fn switch(a: Coordinate, b: Coordinate) -> Option<BoardAction> {
+        let fits =
+            |c: Coordinate| c.is_contained((0, 0), (MAX_WIDTH as isize, MAX_HEIGHT as isize));
+        let neighbours = a != b && (a.x() - b.x()).abs() <= 1 && (a.y() - b.y()).abs() <= 1;
+        (fits(a) && fits(b) && neighbours).then_some(BoardAction::SwitchStone(a, b))
+    }
+
+    // Reflects the action left-right, keeping switches ordered left to right.
+    pub fn mirrored(&self, width: usize) -> Self {
+        match self {
+            BoardAction::DropStone(player, col) => {
+                BoardAction::DropStone(*player, col.mirrored(width))
+            }
+            BoardAction::SwitchStone(a, b) if a.y() == b.y() => {
+                BoardAction::SwitchStone(b.mirrored(width), a.mirrored(width))
+            }
+            BoardAction::SwitchStone(a, b) => {
+                BoardAction::SwitchStone(a.mirrored(width), b.mirrored(width))
+            }
+        }
+    }
+
+    // Position of the action in the flattened policy planes of a board,
+    // indexed [plane][x][y]: drops at the bottom of plane 0, then vertical,
+    // horizontal, up-right and up-left switches at their lower or left cell.
+    // Both orders of a switch share an index, the cells must be neighbours:
+    // any other switch would take the index of an unrelated action.
+    pub fn to_index(&self, width: usize, height: usize) -> usize {
+        if let BoardAction::SwitchStone(a, b) = self {
+            debug_assert!(
+                BoardAction::switch(*a, *b).is_some(),
+                "No index for a switch of {:?} and {:?}",
+                a,
+                b
+            );
+        }
+        let (plane, x, y) = match self {
+            BoardAction::DropStone(_, col) => (0, col.index() as isize, 0),
+            BoardAction::SwitchStone(a, b) if a.x() == b.x() => (1, a.x(), a.y().min(b.y())),
+            BoardAction::SwitchStone(a, b) if a.y() == b.y() => (2, a.x().min(b.x()), a.y()),
+            BoardAction::SwitchStone(a, b) if a.x() - b.x() == a.y() - b.y() => {
+                (3, a.x().min(b.x()), a.y().min(b.y()))
+            }
+            BoardAction::SwitchStone(a, b) => (4, a.x().max(b.x()), a.y().min(b.y())),
+        };
+        (plane * width + x as usize) * height + y as usize
+    }
+
+    // The action at a policy index, ordered like the generated moves. Indices
+    // of cells that name no action on the board give None.
+    pub fn from_index(
+        index: usize,
+        player: Player,
+        width: usize,
+        height: usize,
+    ) -> Option<BoardAction> {
+        let (plane, x, y) = (
+            index / (width * height),
+            (index / height) % width,
+            index % height,
+        );
+        let base = Coordinate::new(x as isize, y as isize);
+        let (next, fits) = match plane {
+            0 if y == 0 => return Some(BoardAction::DropStone(player, Column::new(x)?)),
+            1 => (base + (0, 1), y + 1 < height),
+            2 => (base + (1, 0), x + 1 < width),
+            3 => (base + (1, 1), x + 1 < width && y + 1 < height),
+            4 => (base + (-1, 1), x > 0 && y + 1 < height),
+            _ => return None,
+        };
+        fits.then_some(BoardAction::SwitchStone(base, next))
+    }
+
+    // The notation does not contain the player, so it has to be supplied.
+    pub fn parse(s: &str, player: Player) -> Option<BoardAction> {
+        let s = s.trim();
+        if let Some(col) = s.strip_prefix('d') {
+            let col = Column::new(col.parse().ok()?)?;
+            return Some(BoardAction::DropStone(player, col));
+        }
+
+        let (a, b) = s.strip_prefix('s')?.split_once('-')?;
+        BoardAction::switch(parse_coordinate(a)?, parse_coordinate(b)?)
+    }
+}
+
+fn parse_coordinate(s: &str) -> Option<Coordinate> {
+    let (x, y) = s.split_once(',')?;
+    Some(Coordinate::new(x.parse().ok()?, y.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{player::Player, rules::GameRules, testing::StateBuilder};
+    use mcts::GameState;
+
+    use super::{BoardAction, Coordinate};
+
+    #[test]
+    fn policy_index() {
+        let state = StateBuilder::new()
+            .board(["   ", "XOX", "OXO"])
+            .rules(GameRules {
+                width: 3,
+                height: 3,
+                diagonal_switches: true,
+                ..Default::default()
+            })
+            .points(Player::Player1, 1)
+            .build();
+
+        let moves = state.available_moves();
+        let mut indices = moves.iter().map(|m| m.to_index(3, 3)).collect::<Vec<_>>();
+        for (mov, &index) in moves.iter().zip(&indices) {
+            assert_eq!(
+                BoardAction::from_index(index, Player::Player1, 3, 3),
+                Some(*mov)
+            );
+        }
+        indices.sort_unstable();
+        indices.dedup();
+        assert_eq!(indices.len(), moves.len());
+
+        let switch = BoardAction::SwitchStone(Coordinate::new(1, 1), Coordinate::new(0, 2));
+        let reversed = BoardAction::SwitchStone(Coordinate::new(0, 2), Coordinate::new(1, 1));
+        assert_eq!(switch.to_index(3, 3), reversed.to_index(3, 3));
+        assert_eq!(BoardAction::from_index(1, Player::Player1, 3, 3), None);
+        assert_eq!(BoardAction::from_index(45, Player::Player1, 3, 3), None);
+    }
+
+    #[test]
+    fn switches_are_of_neighbours() {
+        let parse = |s| BoardAction::parse(s, Player::Player1);
+        assert_eq!(
+            parse("s1,1-0,2"),
+            Some(BoardAction::SwitchStone(
+                Coordinate::new(1, 1),
+                Coordinate::new(0, 2)
+            ))
+        );
+        assert_eq!(parse("s1,1-1,1"), None);
+        assert_eq!(parse("s1,1-3,1"), None);
+        assert_eq!(parse("s-1,0-0,0"), None);
+        assert_eq!(parse("s0,-1-0,0"), None);
+        assert_eq!(parse("s20,0-21,0"), None);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "No index for a switch")]
+    fn distant_switches_have_no_index() {
+        BoardAction::SwitchStone(Coordinate::new(0, 0), Coordinate::new(2, 0)).to_index(7, 6);
+    }
+}
@@ -0,0 +1,385 @@
+// Anything that can pick moves in a game: network-guided search, the
+// classical baselines or plain random play.
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::{
+    action::BoardAction,
+    alphabeta::AlphaBeta,
+    board::TerminalResult,
+    book::OpeningBook,
+    heuristic::{HeuristicEvaluator, HeuristicMCTS},
+    player::Player,
+    ponder::{self, Ponder},
+    rollout::HeuristicPolicy,
+    rules::GameRules,
+    solver::MctsSolver,
+    timecontrol::{Budget, SearchLimit},
+    BoardState,
+};
+use mcts::GameState;
+
+// The network needs TensorFlow, the other agents run anywhere.
+#[cfg(feature = "alphazero")]
+mod model;
+#[cfg(feature = "alphazero")]
+pub use model::ModelAgent;
+#[cfg(feature = "onnx")]
+mod onnx;
+#[cfg(feature = "onnx")]
+pub use onnx::OnnxAgent;
+
+pub trait Agent: Send {
+    fn name(&self) -> String;
+
+    // None when the game is over.
+    fn choose(&mut self, state: &BoardState) -> Option<BoardAction>;
+
+    // Keeps searching while the opponent thinks, see ponder.rs. Agents that
+    // do not search ignore it. Off by default, it takes the cores the
+    // opponent could use.
+    fn set_pondering(&mut self, _pondering: bool) {}
+
+    // How long the next searches may take. Agents without a tree search
+    // ignore it.
+    fn set_limit(&mut self, _limit: SearchLimit) {}
+
+    // Whether the agent can play games with the rules. The networks only
+    // search the board size and switch rule they were trained for.
+    fn supports(&self, _rules: &GameRules) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+pub struct AlphaBetaAgent {
+    pub engine: AlphaBeta,
+    // A time limit caps the deepening, a number of playouts leaves the
+    // engine to its depth.
+    limit: Option<SearchLimit>,
+}
+
+impl AlphaBetaAgent {
+    pub fn new(engine: AlphaBeta) -> Self {
+        Self {
+            engine,
+            limit: None,
+        }
+    }
+}
+
+impl Agent for AlphaBetaAgent {
+    fn name(&self) -> String {
+        format!("alphabeta:{}", self.engine.max_depth)
+    }
+
+    fn choose(&mut self, state: &BoardState) -> Option<BoardAction> {
+        let time_limit = match self.limit.map(|limit| limit.budget(state)) {
+            Some(Budget::Time(time)) => Some(time),
+            _ => self.engine.time_limit,
+        };
+        let engine = AlphaBeta {
+            time_limit,
+            ..self.engine.clone()
+        };
+        engine.search(state).best_move
+    }
+
+    fn set_limit(&mut self, limit: SearchLimit) {
+        self.limit = Some(limit);
+    }
+}
+
+// Search with the heuristic at the leaves, a baseline that is as fast as the
+// network search per playout and needs no training.
+pub struct HeuristicAgent {
+    pub evaluator: HeuristicEvaluator,
+    pub exploration: f64,
+    pub limit: SearchLimit,
+    pub threads: usize,
+    pub ponder: bool,
+    pondering: Option<Ponder<HeuristicMCTS>>,
+}
+
+impl HeuristicAgent {
+    pub fn new(playouts: usize) -> Self {
+        Self {
+            evaluator: HeuristicEvaluator::default(),
+            exploration: 1.4,
+            limit: SearchLimit::Playouts(playouts),
+            threads: 1,
+            ponder: false,
+            pondering: None,
+        }
+    }
+
+    pub fn with_threads(self, threads: usize) -> Self {
+        Self { threads, ..self }
+    }
+}
+
+impl Agent for HeuristicAgent {
+    fn name(&self) -> String {
+        match self.limit {
+            SearchLimit::Playouts(playouts) => format!("heuristic:{}", playouts),
+            _ => String::from("heuristic"),
+        }
+    }
+
+    fn choose(&mut self, state: &BoardState) -> Option<BoardAction> {
+        let (evaluator, exploration) = (self.evaluator, self.exploration);
+        let create = |state| HeuristicMCTS::create_search(state, evaluator, exploration);
+        // A ponder hit searches on in the tree of the pondered position.
+        let mut manager = self
+            .pondering
+            .take()
+            .and_then(|pondering| pondering.finish(state))
+            .unwrap_or_else(|| create(state.clone()));
+        self.limit.budget(state).search(&mut manager, self.threads);
+        let best = manager.best_move();
+        if let (true, Some(mov)) = (self.ponder, best) {
+            self.pondering =
+                Ponder::start(&manager, state, mov, self.threads, ponder::PLAYOUTS, create);
+        }
+        best
+    }
+
+    fn set_pondering(&mut self, pondering: bool) {
+        self.ponder = pondering;
+        if !pondering {
+            self.pondering = None;
+        }
+    }
+
+    fn set_limit(&mut self, limit: SearchLimit) {
+        self.limit = limit;
+    }
+}
+
+pub struct SolverAgent {
+    solver: MctsSolver<HeuristicPolicy>,
+    limit: SearchLimit,
+    rng: StdRng,
+}
+
+impl SolverAgent {
+    pub fn new(playouts: usize, seed: u64) -> Self {
+        Self {
+            solver: MctsSolver::new(1.4, HeuristicPolicy::default()),
+            limit: SearchLimit::Playouts(playouts),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Agent for SolverAgent {
+    fn name(&self) -> String {
+        match self.limit {
+            SearchLimit::Playouts(playouts) => format!("solver:{}", playouts),
+            _ => String::from("solver"),
+        }
+    }
+
+    fn choose(&mut self, state: &BoardState) -> Option<BoardAction> {
+        let budget = self.limit.budget(state);
+        self.solver.search(state, budget, &mut self.rng).best_move
+    }
+
+    fn set_limit(&mut self, limit: SearchLimit) {
+        self.limit = limit;
+    }
+}
+
+pub struct RandomAgent {
+    rng: StdRng,
+}
+
+impl RandomAgent {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Agent for RandomAgent {
+    fn name(&self) -> String {
+        String::from("random")
+    }
+
+    fn choose(&mut self, state: &BoardState) -> Option<BoardAction> {
+        state.available_moves().choose(&mut self.rng).copied()
+    }
+}
+
+// Plays the book move while the position is in the opening book and lets
+// the other agent search once it is not.
+pub struct BookAgent {
+    book: Arc<OpeningBook>,
+    inner: Box<dyn Agent>,
+}
+
+impl BookAgent {
+    pub fn new(book: Arc<OpeningBook>, inner: Box<dyn Agent>) -> Self {
+        Self { book, inner }
+    }
+}
+
+impl Agent for BookAgent {
+    fn name(&self) -> String {
+        format!("book:{}", self.inner.name())
+    }
+
+    fn choose(&mut self, state: &BoardState) -> Option<BoardAction> {
+        self.book.probe(state).or_else(|| self.inner.choose(state))
+    }
+
+    fn set_pondering(&mut self, pondering: bool) {
+        self.inner.set_pondering(pondering);
+    }
+
+    fn set_limit(&mut self, limit: SearchLimit) {
+        self.inner.set_limit(limit);
+    }
+
+    fn supports(&self, rules: &GameRules) -> Result<(), String> {
+        self.inner.supports(rules)
+    }
+}
+
+// Plays a game from the start, the first agent moving first. Returns the
+// moves that were played and the final state.
+pub fn play_game(first: &mut dyn Agent, second: &mut dyn Agent) -> (Vec<BoardAction>, BoardState) {
+    let mut state = BoardState::default();
+    let mut moves = Vec::new();
+
+    while !state.is_terminal() {
+        let agent: &mut dyn Agent = if moves.len() % 2 == 0 {
+            &mut *first
+        } else {
+            &mut *second
+        };
+        let mov = agent.choose(&state).expect("Agent did not choose a move");
+        state.make_move(&mov);
+        moves.push(mov);
+    }
+
+    (moves, state)
+}
+
+// How a match went for the first agent. The lengths and points are summed
+// over the games, the averages are per game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MatchReport {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    pub plies: usize,
+    pub points: usize,
+    pub opponent_points: usize,
+}
+
+impl MatchReport {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn score(&self) -> f64 {
+        self.average(2 * self.wins + self.draws) / 2.0
+    }
+
+    pub fn average_length(&self) -> f64 {
+        self.average(self.plies)
+    }
+
+    pub fn average_points(&self) -> f64 {
+        self.average(self.points)
+    }
+
+    pub fn average_opponent_points(&self) -> f64 {
+        self.average(self.opponent_points)
+    }
+
+    fn average(&self, total: usize) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        total as f64 / self.games() as f64
+    }
+
+    // Adds a finished game the agent played as `player`.
+    pub fn add(&mut self, state: &BoardState, plies: usize, player: Player) {
+        match state.result() {
+            TerminalResult::Win(winner) if winner == player => self.wins += 1,
+            TerminalResult::Win(_) => self.losses += 1,
+            _ => self.draws += 1,
+        }
+        self.plies += plies;
+        self.points += state.points(player);
+        self.opponent_points += state.points(player.next_player());
+    }
+}
+
+// Plays the games between the agents, the first agent moving first in the
+// even ones. `played` sees every game with its number once it is over.
+pub fn play_match(
+    a: &mut dyn Agent,
+    b: &mut dyn Agent,
+    games: usize,
+    mut played: impl FnMut(usize, &[BoardAction], &BoardState, &MatchReport),
+) -> MatchReport {
+    let mut report = MatchReport::default();
+    for game in 0..games {
+        let (moves, state, player) = if game % 2 == 0 {
+            let (moves, state) = play_game(a, b);
+            (moves, state, Player::Player1)
+        } else {
+            let (moves, state) = play_game(b, a);
+            (moves, state, Player::Player2)
+        };
+        report.add(&state, moves.len(), player);
+        played(game, &moves, &state, &report);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{alphabeta::AlphaBeta, board::TerminalResult};
+
+    use super::{play_game, play_match, AlphaBetaAgent, RandomAgent};
+
+    #[test]
+    fn random_games_are_reproducible() {
+        let (a, _) = play_game(&mut RandomAgent::new(7), &mut RandomAgent::new(8));
+        let (b, state) = play_game(&mut RandomAgent::new(7), &mut RandomAgent::new(8));
+        assert_eq!(a, b);
+        assert_ne!(state.result(), TerminalResult::None);
+
+        let mut baseline = AlphaBetaAgent::new(AlphaBeta::new(1));
+        let (_, state) = play_game(&mut baseline, &mut RandomAgent::new(1));
+        assert_ne!(state.result(), TerminalResult::None);
+    }
+
+    #[test]
+    fn match_report() {
+        let mut seen = 0;
+        let report = play_match(
+            &mut RandomAgent::new(3),
+            &mut RandomAgent::new(4),
+            4,
+            |game, moves, state, report| {
+                assert_eq!(game, seen);
+                assert_eq!(report.games(), game + 1);
+                assert!(!moves.is_empty());
+                assert_ne!(state.result(), TerminalResult::None);
+                seen += 1;
+            },
+        );
+        assert_eq!(seen, 4);
+        assert_eq!(report.games(), 4);
+        assert!(report.average_length() > 0.0);
+        assert!((0.0..=1.0).contains(&report.score()));
+        assert_eq!(report.average_points(), report.points as f64 / 4.0);
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use catzero::TFModel;
+use mcts::MCTSManager;
+
+use super::Agent;
+use crate::{
+    action::BoardAction,
+    alphazero::{MyMCTS, Network, NetworkSearch, SearchOptions},
+    planes::InputEncoding,
+    ponder::Ponder,
+    rules::GameRules,
+    timecontrol::SearchLimit,
+    BoardState,
+};
+
+pub struct ModelAgent {
+    name: String,
+    model: Arc<TFModel>,
+    options: SearchOptions,
+    limit: SearchLimit,
+    ponder: bool,
+    pondering: Option<Ponder<MyMCTS>>,
+}
+
+impl ModelAgent {
+    // The input is the encoding the model was trained with.
+    pub fn new(
+        name: String,
+        model: Arc<TFModel>,
+        input: InputEncoding,
+        exploration: f64,
+        playouts: usize,
+    ) -> Self {
+        Self {
+            name,
+            model,
+            options: SearchOptions::new(exploration, playouts).with_input(input),
+            limit: SearchLimit::Playouts(playouts),
+            ponder: false,
+            pondering: None,
+        }
+    }
+
+    // Searches with several threads, kept apart by virtual loss.
+    pub fn with_threads(self, threads: usize) -> Self {
+        Self {
+            options: self.options.with_threads(threads),
+            ..self
+        }
+    }
+
+    // A fresh search of the position within the limit, for a look at the
+    // tree instead of only the move.
+    pub fn search(&self, state: &BoardState) -> Result<MCTSManager<MyMCTS>, String> {
+        let network = Network::Model(self.model.clone());
+        let mut manager =
+            <MyMCTS as NetworkSearch>::create_search(state.clone(), network, self.options)?;
+        self.limit
+            .budget(state)
+            .search(&mut manager, self.options.threads);
+        Ok(manager)
+    }
+}
+
+impl Agent for ModelAgent {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn choose(&mut self, state: &BoardState) -> Option<BoardAction> {
+        let (model, options) = (self.model.clone(), self.options);
+        // Callers check the rules with `supports` first.
+        let create = |state| {
+            <MyMCTS as NetworkSearch>::create_search(state, Network::Model(model.clone()), options)
+                .unwrap_or_else(|e| panic!("{}", e))
+        };
+        let mut manager = self
+            .pondering
+            .take()
+            .and_then(|pondering| pondering.finish(state))
+            .unwrap_or_else(|| create(state.clone()));
+        self.limit
+            .budget(state)
+            .search(&mut manager, options.threads);
+        let best = manager.best_move();
+        if let (true, Some(mov)) = (self.ponder, best) {
+            self.pondering = Ponder::start(
+                &manager,
+                state,
+                mov,
+                options.threads,
+                options.ponder_playouts,
+                create,
+            );
+        }
+        best
+    }
+
+    fn set_pondering(&mut self, pondering: bool) {
+        self.ponder = pondering;
+        if !pondering {
+            self.pondering = None;
+        }
+    }
+
+    fn set_limit(&mut self, limit: SearchLimit) {
+        self.limit = limit;
+    }
+
+    fn supports(&self, rules: &GameRules) -> Result<(), String> {
+        MyMCTS::check(&BoardState::new(*rules))
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use super::Agent;
+use crate::{
+    action::BoardAction,
+    onnx::{OnnxMCTS, OnnxModel},
+    ponder::{self, Ponder},
+    rules::GameRules,
+    timecontrol::SearchLimit,
+    BoardState,
+};
+
+pub struct OnnxAgent {
+    name: String,
+    model: Arc<OnnxModel>,
+    exploration: f64,
+    limit: SearchLimit,
+    threads: usize,
+    ponder: bool,
+    pondering: Option<Ponder<OnnxMCTS>>,
+}
+
+impl OnnxAgent {
+    pub fn new(name: String, model: Arc<OnnxModel>, exploration: f64, playouts: usize) -> Self {
+        Self {
+            name,
+            model,
+            exploration,
+            limit: SearchLimit::Playouts(playouts),
+            threads: 1,
+            ponder: false,
+            pondering: None,
+        }
+    }
+
+    pub fn with_threads(self, threads: usize) -> Self {
+        Self { threads, ..self }
+    }
+}
+
+impl Agent for OnnxAgent {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn choose(&mut self, state: &BoardState) -> Option<BoardAction> {
+        let (model, exploration) = (self.model.clone(), self.exploration);
+        // Callers check the rules with `supports` first.
+        let create = |state| {
+            OnnxMCTS::create_search(state, model.clone(), exploration)
+                .unwrap_or_else(|e| panic!("{}", e))
+        };
+        let mut manager = self
+            .pondering
+            .take()
+            .and_then(|pondering| pondering.finish(state))
+            .unwrap_or_else(|| create(state.clone()));
+        self.limit.budget(state).search(&mut manager, self.threads);
+        let best = manager.best_move();
+        if let (true, Some(mov)) = (self.ponder, best) {
+            self.pondering =
+                Ponder::start(&manager, state, mov, self.threads, ponder::PLAYOUTS, create);
+        }
+        best
+    }
+
+    fn set_pondering(&mut self, pondering: bool) {
+        self.ponder = pondering;
+        if !pondering {
+            self.pondering = None;
+        }
+    }
+
+    fn set_limit(&mut self, limit: SearchLimit) {
+        self.limit = limit;
+    }
+
+    fn supports(&self, rules: &GameRules) -> Result<(), String> {
+        self.model.check(&BoardState::new(*rules))
+    }
+}
@@ -0,0 +1,342 @@
+// Classical negamax search with alpha-beta pruning and iterative deepening.
+// Positions are played and taken back on one state, and the scores of
+// positions reached again are looked up in a transposition table. Wins and
+// losses within the depth are exact, so endgames and tactical positions can
+// be solved and compared with the moves of the other engines.
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{action::BoardAction, heuristic::Heuristic, BoardState};
+use mcts::GameState;
+
+pub const WIN_SCORE: i32 = 1_000_000;
+
+// Scores beyond this are wins or losses some plies away.
+const WIN_BOUND: i32 = WIN_SCORE - 10_000;
+
+#[derive(Debug, Clone)]
+pub struct AlphaBeta {
+    pub max_depth: usize,
+    pub time_limit: Option<Duration>,
+    pub heuristic: Heuristic,
+    // Most positions kept in the transposition table, it is cleared when
+    // it fills up.
+    pub table_size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Option<BoardAction>,
+    pub score: i32,
+    // Deepest iteration that finished.
+    pub depth: usize,
+    pub nodes: u64,
+    // The score does not depend on the heuristic: every line was played to
+    // the end of the game, or the win or loss was found.
+    pub proven: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    depth: usize,
+    // Wins and losses are counted from the position, not from the root.
+    score: i32,
+    bound: Bound,
+    best_move: Option<BoardAction>,
+    proven: bool,
+}
+
+struct Search {
+    deadline: Option<Instant>,
+    nodes: u64,
+    table: HashMap<u64, Entry>,
+}
+
+impl Search {
+    fn out_of_time(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+impl Default for AlphaBeta {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+impl AlphaBeta {
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            max_depth,
+            time_limit: None,
+            heuristic: Heuristic::default(),
+            table_size: 1 << 20,
+        }
+    }
+
+    pub fn with_time_limit(max_depth: usize, time_limit: Duration) -> Self {
+        Self {
+            time_limit: Some(time_limit),
+            ..Self::new(max_depth)
+        }
+    }
+
+    // Deepens one ply at a time until max_depth or the time limit is reached,
+    // or until the position is solved. The best move of an iteration is
+    // searched first in the next one.
+    pub fn search(&self, state: &BoardState) -> SearchResult {
+        let mut search = Search {
+            deadline: self.time_limit.map(|limit| Instant::now() + limit),
+            nodes: 0,
+            table: HashMap::new(),
+        };
+        let mut state = state.clone();
+        let mut moves = state.available_moves();
+        let mut result = SearchResult {
+            best_move: moves.first().copied(),
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            proven: moves.is_empty(),
+        };
+
+        for depth in 1..=self.max_depth {
+            let mut alpha = -WIN_SCORE - 1;
+            let mut best = None;
+            let mut proven = true;
+
+            for mov in &moves {
+                state.play(mov);
+                let score = self.negamax(
+                    &mut state,
+                    depth - 1,
+                    -WIN_SCORE - 1,
+                    -alpha,
+                    1,
+                    &mut search,
+                );
+                state.undo();
+                let (score, exact) = match score {
+                    Some((score, exact)) => (-score, exact),
+                    None => break,
+                };
+                proven &= exact;
+                if score > alpha {
+                    alpha = score;
+                    best = Some(*mov);
+                }
+            }
+
+            if search.out_of_time() {
+                break;
+            }
+            if let Some(best) = best {
+                result.best_move = Some(best);
+                result.score = alpha;
+                result.depth = depth;
+                result.proven = proven || alpha.abs() > WIN_BOUND;
+                moves.retain(|mov| *mov != best);
+                moves.insert(0, best);
+            }
+            if result.proven {
+                break;
+            }
+        }
+
+        result.nodes = search.nodes;
+        result
+    }
+
+    // Score for the player to move and whether it is proven, None when the
+    // time ran out. The state is back where it was when this returns.
+    fn negamax(
+        &self,
+        state: &mut BoardState,
+        depth: usize,
+        mut alpha: i32,
+        mut beta: i32,
+        ply: i32,
+        search: &mut Search,
+    ) -> Option<(i32, bool)> {
+        search.nodes += 1;
+        if search.nodes & 1023 == 0 && search.out_of_time() {
+            return None;
+        }
+
+        let player = state.current_player();
+        let mut moves = state.available_moves();
+        if moves.is_empty() {
+            let score = match state.get_winner() {
+                Some(winner) if winner == player => WIN_SCORE - ply,
+                Some(_) => -(WIN_SCORE - ply),
+                None => 0,
+            };
+            return Some((score, true));
+        }
+        if depth == 0 {
+            return Some((self.heuristic.score(state, player), false));
+        }
+
+        let key = state.zobrist();
+        let original_alpha = alpha;
+        if let Some(entry) = search.table.get(&key).copied() {
+            if entry.depth >= depth {
+                let score = from_table(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return Some((score, entry.proven)),
+                    Bound::Lower => alpha = alpha.max(score),
+                    Bound::Upper => beta = beta.min(score),
+                }
+                if alpha >= beta {
+                    return Some((score, entry.proven));
+                }
+            }
+            if let Some(i) = entry
+                .best_move
+                .and_then(|best| moves.iter().position(|mov| *mov == best))
+            {
+                moves.swap(0, i);
+            }
+        }
+
+        let mut best = -WIN_SCORE - 1;
+        let mut best_move = None;
+        let mut proven = true;
+        for mov in &moves {
+            state.play(mov);
+            let result = self.negamax(state, depth - 1, -beta, -alpha, ply + 1, search);
+            state.undo();
+            let (score, exact) = result?;
+            let score = -score;
+            proven &= exact;
+            if score > best {
+                best = score;
+                best_move = Some(*mov);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        if search.table.len() >= self.table_size {
+            search.table.clear();
+        }
+        search.table.insert(
+            key,
+            Entry {
+                depth,
+                score: to_table(best, ply),
+                bound,
+                best_move,
+                proven,
+            },
+        );
+        Some((best, proven))
+    }
+}
+
+// Wins and losses are stored as the distance from the position, so they
+// stay right when the position is reached at another ply.
+fn to_table(score: i32, ply: i32) -> i32 {
+    if score > WIN_BOUND {
+        score + ply
+    } else if score < -WIN_BOUND {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn from_table(score: i32, ply: i32) -> i32 {
+    if score > WIN_BOUND {
+        score - ply
+    } else if score < -WIN_BOUND {
+        score + ply
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{BoardAction, Column},
+        player::Player,
+        rules::GameRules,
+        testing::StateBuilder,
+    };
+
+    use super::{AlphaBeta, WIN_SCORE};
+
+    #[test]
+    fn finds_win_and_block() {
+        let empty = "        ";
+        let state = StateBuilder::new()
+            .board([empty, empty, empty, empty, empty, empty, empty, "XX XO   "])
+            .build();
+        let result = AlphaBeta::new(2).search(&state);
+        assert_eq!(
+            result.best_move,
+            Some(BoardAction::DropStone(
+                Player::Player1,
+                Column::new(2).unwrap()
+            ))
+        );
+        assert_eq!(result.score, WIN_SCORE - 1);
+        assert!(result.proven);
+
+        let state = StateBuilder::new()
+            .board([empty, empty, empty, empty, empty, empty, empty, "OO OXX  "])
+            .build();
+        let result = AlphaBeta::new(2).search(&state);
+        assert_eq!(
+            result.best_move,
+            Some(BoardAction::DropStone(
+                Player::Player1,
+                Column::new(2).unwrap()
+            ))
+        );
+        assert!(!result.proven);
+    }
+
+    #[test]
+    fn solve_to_the_end() {
+        // Connect four on a small board: nothing is ever cleared, so every
+        // game ends once the eight empty cells are filled.
+        let rules = GameRules::parse("size=4x4 match=5").unwrap();
+        let state = StateBuilder::new()
+            .rules(rules)
+            .board(["    ", "    ", "OXOX", "XOXO"])
+            .build();
+        assert!(!AlphaBeta::new(2).search(&state).proven);
+        let solved = AlphaBeta::new(8).search(&state);
+        assert!(solved.proven);
+
+        // The table only saves work, the answer stays the same without it.
+        let without_table = AlphaBeta {
+            table_size: 0,
+            ..AlphaBeta::new(8)
+        }
+        .search(&state);
+        assert_eq!(without_table.score, solved.score);
+        assert!(without_table.nodes > solved.nodes);
+    }
+}
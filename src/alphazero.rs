@@ -1,55 +1,140 @@
-use crate::{action::BoardAction, player::Player, BoardState};
-use catzero::{AlphaEvaluator, AlphaGame, TFModel};
+use crate::{action::BoardAction, planes::masked_priors, player::Player, BoardState};
+use catzero::{AlphaGame, TFModel};
+use evaluator::NetworkEvaluator;
 use mcts::{
-    transposition_table::ApproxTable, tree_policy::UCTPolicy, CycleBehaviour, GameState,
-    MCTSManager, MCTS,
+    transposition_table::ApproxTable, tree_policy::UCTPolicy, CycleBehaviour, MCTSManager, MCTS,
 };
-use std::sync::Arc;
-
-#[derive(Debug, Clone)]
-pub enum StateEval {
-    Winner(Player),
-    Draw,
-    Evaluation(Player, f32),
-}
-
+use std::{marker::PhantomData, sync::Arc};
+
+pub mod batching;
+pub mod checkpoint;
+pub mod device;
+pub mod distributed;
+pub mod evaluator;
+pub mod game;
+pub mod noise;
+pub mod pipeline;
+pub mod replay;
+pub mod search;
+pub mod sprt;
+
+pub use batching::{InferenceClient, InferenceServer};
+pub use checkpoint::Checkpoint;
+pub use device::{Device, DeviceConfig};
+pub use distributed::Coordinator;
+pub use evaluator::Network;
+pub use game::NetworkGame;
+pub use noise::DirichletNoise;
+pub use pipeline::{Pipeline, PipelineConfig, Symmetries};
+pub use replay::{PrioritizedSampling, Priority, ReplayBuffer};
+pub use search::{NetworkSearch, SearchOptions};
+pub use sprt::{Sprt, SprtDecision};
+
+pub use crate::{heuristic::StateEval, planes::policy_planes};
+
+// The policy planes have the size of the board, so the search is specialised
+// for one board size. States of another size are rejected.
+//
+// The policy has a plane for drops, one for vertical and one for horizontal
+// switches. With diagonal switches `D` two more planes follow, for switches
+// up-right and up-left from the lower cell, and states must use that rule.
+// The game `G` is this crate's match-3 Connect 4 unless another variant is
+// named, see game.rs.
 #[derive(Clone)]
-pub struct MyMCTS {
+pub struct MyMCTS<
+    const W: usize = 8,
+    const H: usize = 8,
+    const D: bool = false,
+    G: NetworkGame = BoardState,
+> {
     exploration_constant: f64,
     playouts: usize,
+    virtual_loss: i64,
+    game: PhantomData<fn() -> G>,
 }
 
-impl MyMCTS {
+// Reflects a flattened policy of any number of planes left-right, so it fits
+// the mirrored position. Entries that name no action are dropped.
+pub fn mirror_policy(policy: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut mirrored = vec![0.0; policy.len()];
+    for (index, &p) in policy.iter().enumerate() {
+        if let Some(action) = BoardAction::from_index(index, Player::Player1, width, height) {
+            mirrored[action.mirrored(width).to_index(width, height)] = p;
+        }
+    }
+    mirrored
+}
+
+// Mirrors the board, policies are [1, planes, width, height].
+impl Symmetries for BoardState {
+    fn symmetries(&self, policy: &tensorflow::Tensor<f32>) -> Vec<(Self, tensorflow::Tensor<f32>)> {
+        let (width, height) = (self.board().width(), self.board().height());
+        let mirrored = tensorflow::Tensor::new(policy.dims())
+            .with_values(&mirror_policy(policy, width, height))
+            .expect("Could not mirror policy");
+        vec![(self.mirror(), mirrored)]
+    }
+}
+
+impl<const W: usize, const H: usize, const D: bool, G: NetworkGame> MyMCTS<W, H, D, G> {
     pub fn create_manager(
-        state: BoardState,
+        state: G,
         exploration_constant: f64,
         playouts: usize,
         model: Arc<TFModel>,
-    ) -> MCTSManager<MyMCTS> {
+    ) -> MCTSManager<Self> {
+        <Self as AlphaGame>::create_manager(state, exploration_constant, playouts, model)
+    }
+}
+
+impl<const W: usize, const H: usize, const D: bool, G: NetworkGame> NetworkSearch
+    for MyMCTS<W, H, D, G>
+{
+    fn check(state: &G) -> Result<(), String> {
+        state.check_search(W, H, D)
+    }
+
+    fn create_search(
+        state: G,
+        network: Network,
+        options: SearchOptions,
+    ) -> Result<MCTSManager<Self>, String> {
+        Self::check(&state)?;
         let manager = MyMCTS {
-            exploration_constant,
-            playouts,
+            exploration_constant: options.exploration,
+            playouts: options.playouts,
+            virtual_loss: options.effective_virtual_loss(),
+            game: PhantomData,
         };
-        let eval = AlphaEvaluator::new(state.current_player(), model);
-        let tree_policy = UCTPolicy::new(exploration_constant);
-        MCTSManager::new(state, manager, eval, tree_policy, ApproxTable::new(1024))
+        let eval = NetworkEvaluator::new(network, options.noise, options.input, options.seed);
+        let tree_policy = UCTPolicy::new(options.exploration);
+        Ok(MCTSManager::new(
+            state,
+            manager,
+            eval,
+            tree_policy,
+            ApproxTable::new(1024),
+        ))
     }
 }
 
-impl AlphaGame for MyMCTS {
+impl<const W: usize, const H: usize, const D: bool, G: NetworkGame> AlphaGame
+    for MyMCTS<W, H, D, G>
+{
     fn create_manager(
-        state: BoardState,
+        state: G,
         exploration_constant: f64,
         playouts: usize,
         model: Arc<TFModel>,
     ) -> MCTSManager<Self> {
-        let manager = MyMCTS {
-            exploration_constant,
-            playouts,
-        };
-        let eval = AlphaEvaluator::new(state.current_player(), model);
-        let tree_policy = UCTPolicy::new(exploration_constant);
-        MCTSManager::new(state, manager, eval, tree_policy, ApproxTable::new(1024))
+        // catzero has no way to fail here, it only searches the states it
+        // was set up for.
+        Self::create_search(
+            state,
+            Network::Model(model),
+            SearchOptions::new(exploration_constant, playouts),
+        )
+        .unwrap_or_else(|e| panic!("{}", e))
     }
 
     fn get_exploration(&self) -> f64 {
@@ -64,28 +149,18 @@ impl AlphaGame for MyMCTS {
         moves: &mcts::MoveList<Self>,
         policy: tensorflow::Tensor<f32>,
     ) -> Vec<mcts::MoveEvaluation<Self>> {
-        let policy = policy.iter().map(|d| *d as f64).collect::<Vec<_>>();
-        let policy = tensorflow::Tensor::new(&[1, 3, 8, 8])
-            .with_values(&policy)
-            .expect("Could not reshape");
-
-        moves
-            .iter()
-            .map(|mov| match mov {
-                BoardAction::DropStone(_, col) => policy.get(&[0, 0, *col as u64, 0]),
-                BoardAction::SwitchStone(a, b) if a.x() == b.x() => {
-                    policy.get(&[0, 1, a.x() as u64, a.y().min(b.y()) as u64])
-                }
-                BoardAction::SwitchStone(a, b) if a.y() == b.y() => {
-                    policy.get(&[0, 2, a.x().min(b.x()) as u64, a.y() as u64])
-                }
-                _ => unreachable!(),
-            })
-            .collect()
+        assert_eq!(
+            policy.len(),
+            G::policy_planes(D) * W * H,
+            "Policy does not match the search"
+        );
+
+        masked_priors(&policy, moves.iter().map(|mov| G::policy_index(mov, W, H)))
     }
 
     fn moves_to_tensorflow(moves: Vec<&mcts::MoveInfo<Self>>) -> tensorflow::Tensor<f32> {
-        let mut tensor = tensorflow::Tensor::new(&[1, 3, 8, 8]);
+        let planes = G::policy_planes(D) as u64;
+        let mut tensor = tensorflow::Tensor::new(&[1, planes, W as u64, H as u64]);
         let parent_visits: u64 = moves.iter().map(|&x| x.visits()).sum();
 
         if parent_visits == 0 {
@@ -97,32 +172,25 @@ impl AlphaGame for MyMCTS {
         for m in moves {
             let visit = m.visits() as f32;
             let probability = visit / parent_visits;
-            let indeces: [u64; 4] = match m.get_move() {
-                BoardAction::DropStone(_, col) => [0, 0, *col as u64, 0],
-                BoardAction::SwitchStone(a, b) if a.x() == b.x() => {
-                    [0, 1, a.x() as u64, a.y().min(b.y()) as u64]
-                }
-                BoardAction::SwitchStone(a, b) if a.y() == b.y() => {
-                    [0, 2, a.x().min(b.x()) as u64, a.y() as u64]
-                }
-                _ => unreachable!(),
-            };
-
-            tensor.set(&indeces, probability);
+            tensor[G::policy_index(m.get_move(), W, H)] = probability;
         }
 
         tensor
     }
 }
 
-impl MCTS for MyMCTS {
-    type State = BoardState;
-    type Eval = AlphaEvaluator<Self>;
+impl<const W: usize, const H: usize, const D: bool, G: NetworkGame> MCTS for MyMCTS<W, H, D, G> {
+    type State = G;
+    type Eval = NetworkEvaluator;
     type TreePolicy = UCTPolicy<f64>;
     type NodeData = ();
     type TranspositionTable = ApproxTable<Self>;
     type ExtraThreadData = ();
 
+    fn virtual_loss(&self) -> i64 {
+        self.virtual_loss
+    }
+
     fn cycle_behaviour(&self) -> CycleBehaviour<Self> {
         CycleBehaviour::UseCurrentEvalWhenCycleDetected
     }
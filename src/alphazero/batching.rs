@@ -0,0 +1,317 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use catzero::TFModel;
+use tracing::error;
+
+pub type Evaluation = (tensorflow::Tensor<f32>, f32);
+
+// A model that evaluates a batch of positions, [n, planes, width, height],
+// giving the policy and value of each.
+pub trait BatchModel: Send + Sync + 'static {
+    fn evaluate_batch(
+        &self,
+        inputs: &tensorflow::Tensor<f32>,
+    ) -> Result<Vec<Evaluation>, tensorflow::Status>;
+}
+
+// The exported graph takes one position per call, so the rows are evaluated
+// one after the other on the thread of the server.
+impl BatchModel for TFModel {
+    fn evaluate_batch(
+        &self,
+        inputs: &tensorflow::Tensor<f32>,
+    ) -> Result<Vec<Evaluation>, tensorflow::Status> {
+        let dims = inputs.dims();
+        let row = dims[1..].iter().product::<u64>() as usize;
+        let mut single = dims.to_vec();
+        single[0] = 1;
+
+        inputs
+            .chunks(row)
+            .map(|values| self.evaluate(tensorflow::Tensor::new(&single).with_values(values)?))
+            .collect()
+    }
+}
+
+struct Request {
+    input: tensorflow::Tensor<f32>,
+    reply: Sender<Result<Evaluation, String>>,
+}
+
+// Collects the leaf evaluations of every self-play game on one thread and
+// hands them to the model in batches. A batch is sent when it is full or
+// when `wait` has passed since its first request.
+pub struct InferenceServer {
+    sender: Option<Sender<Request>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl InferenceServer {
+    pub fn start<M: BatchModel>(model: Arc<M>, max_batch: usize, wait: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let thread = std::thread::spawn(move || serve(&*model, receiver, max_batch.max(1), wait));
+
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    pub fn client(&self) -> InferenceClient {
+        InferenceClient {
+            sender: self.sender.clone().expect("Inference server has stopped"),
+        }
+    }
+}
+
+// The server stops once all clients are gone, so they have to be dropped
+// before the server.
+impl Drop for InferenceServer {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("Inference server panicked");
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct InferenceClient {
+    sender: Sender<Request>,
+}
+
+impl InferenceClient {
+    // Evaluates one [1, planes, width, height] input, blocking until the
+    // batch it ends up in has been evaluated.
+    pub fn evaluate(&self, input: tensorflow::Tensor<f32>) -> Result<Evaluation, String> {
+        let (reply, receiver) = mpsc::channel();
+        self.sender
+            .send(Request { input, reply })
+            .map_err(|_| String::from("Inference server has stopped"))?;
+        receiver
+            .recv()
+            .map_err(|_| String::from("Inference server has stopped"))?
+    }
+}
+
+fn serve<M: BatchModel + ?Sized>(
+    model: &M,
+    receiver: Receiver<Request>,
+    max_batch: usize,
+    wait: Duration,
+) {
+    while let Ok(first) = receiver.recv() {
+        let deadline = Instant::now() + wait;
+        let mut batch = vec![first];
+        while batch.len() < max_batch {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(request) => batch.push(request),
+                Err(_) => break,
+            }
+        }
+
+        // Boards of different sizes can't share a tensor.
+        let mut by_shape: BTreeMap<Vec<u64>, Vec<Request>> = BTreeMap::new();
+        for request in batch {
+            by_shape
+                .entry(request.input.dims()[1..].to_vec())
+                .or_default()
+                .push(request);
+        }
+        for (shape, requests) in by_shape {
+            evaluate(model, &shape, requests);
+        }
+    }
+}
+
+// Every request is answered, with the error of the batch when it failed.
+fn evaluate<M: BatchModel + ?Sized>(model: &M, shape: &[u64], requests: Vec<Request>) {
+    match evaluate_batch(model, shape, &requests) {
+        Ok(evaluations) => {
+            for (request, evaluation) in requests.into_iter().zip(evaluations) {
+                request.reply.send(Ok(evaluation)).ok();
+            }
+        }
+        Err(e) => {
+            error!(error = %e, "Could not evaluate batch");
+            for request in requests {
+                request.reply.send(Err(e.clone())).ok();
+            }
+        }
+    }
+}
+
+// The inputs of the requests as one [n, planes, width, height] tensor.
+fn evaluate_batch<M: BatchModel + ?Sized>(
+    model: &M,
+    shape: &[u64],
+    requests: &[Request],
+) -> Result<Vec<Evaluation>, String> {
+    let mut dims = vec![requests.len() as u64];
+    dims.extend_from_slice(shape);
+    let values = requests
+        .iter()
+        .flat_map(|r| r.input.iter().copied())
+        .collect::<Vec<_>>();
+    let inputs = tensorflow::Tensor::new(&dims)
+        .with_values(&values)
+        .map_err(|e| format!("Could not batch inputs: {}", e))?;
+
+    let evaluations = model.evaluate_batch(&inputs).map_err(|e| e.to_string())?;
+    if evaluations.len() != requests.len() {
+        return Err(format!(
+            "Model answered {} of {} inputs",
+            evaluations.len(),
+            requests.len()
+        ));
+    }
+    Ok(evaluations)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeSet,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::{BatchModel, Evaluation, InferenceServer};
+    use crate::{
+        alphazero::{MyMCTS, Network, NetworkSearch, SearchOptions},
+        BoardState,
+    };
+
+    // Answers with the sum of every input and remembers the batch sizes.
+    #[derive(Default)]
+    struct SumModel {
+        batches: Mutex<Vec<usize>>,
+    }
+
+    impl BatchModel for SumModel {
+        fn evaluate_batch(
+            &self,
+            inputs: &tensorflow::Tensor<f32>,
+        ) -> Result<Vec<Evaluation>, tensorflow::Status> {
+            let n = inputs.dims()[0] as usize;
+            self.batches.lock().unwrap().push(n);
+            Ok(inputs
+                .chunks(inputs.len() / n)
+                .map(|row| (tensorflow::Tensor::new(&[1]), row.iter().sum()))
+                .collect())
+        }
+    }
+
+    // Loses the last input of every batch.
+    struct ShortModel;
+
+    impl BatchModel for ShortModel {
+        fn evaluate_batch(
+            &self,
+            inputs: &tensorflow::Tensor<f32>,
+        ) -> Result<Vec<Evaluation>, tensorflow::Status> {
+            let n = inputs.dims()[0] as usize;
+            Ok((1..n)
+                .map(|_| (tensorflow::Tensor::new(&[1]), 0.0))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn batches_requests() {
+        let model = Arc::new(SumModel::default());
+        let server = InferenceServer::start(model.clone(), 4, Duration::from_millis(200));
+
+        let values = std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|i| {
+                    let client = server.client();
+                    scope.spawn(move || {
+                        let input = tensorflow::Tensor::new(&[1, 1, 2, 1])
+                            .with_values(&[i as f32, 1.0])
+                            .unwrap();
+                        client.evaluate(input).unwrap().1
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        drop(server);
+
+        assert_eq!(values, vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(model.batches.lock().unwrap().iter().sum::<usize>(), 4);
+        assert!(model.batches.lock().unwrap().len() < 4);
+    }
+
+    // A uniform policy on the default board, remembering the size of every
+    // batch and how many different positions were in it.
+    #[derive(Default)]
+    struct LeafModel {
+        batches: Mutex<Vec<(usize, usize)>>,
+    }
+
+    impl BatchModel for LeafModel {
+        fn evaluate_batch(
+            &self,
+            inputs: &tensorflow::Tensor<f32>,
+        ) -> Result<Vec<Evaluation>, tensorflow::Status> {
+            let n = inputs.dims()[0] as usize;
+            let distinct = inputs
+                .chunks(inputs.len() / n)
+                .map(|row| row.iter().map(|v| v.to_bits()).collect::<Vec<_>>())
+                .collect::<BTreeSet<_>>()
+                .len();
+            self.batches.lock().unwrap().push((n, distinct));
+            (0..n)
+                .map(|_| {
+                    let policy = tensorflow::Tensor::new(&[1, 3, 8, 8]).with_values(&[1.0; 192])?;
+                    Ok((policy, 0.0))
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn virtual_loss_spreads_the_leaves_of_a_batch() {
+        let model = Arc::new(LeafModel::default());
+        let server = InferenceServer::start(model.clone(), 4, Duration::from_millis(50));
+        let options = SearchOptions::new(1.4, 64).with_threads(4);
+        assert_eq!(options.effective_virtual_loss(), 1);
+        let mut manager = MyMCTS::create_search(
+            BoardState::default(),
+            Network::Batched(server.client()),
+            options,
+        )
+        .unwrap();
+        options.playout(&mut manager);
+        drop(manager);
+        drop(server);
+
+        // The threads wait for each other, the leaves are evaluated four at
+        // a time and are different positions.
+        let batches = model.batches.lock().unwrap().clone();
+        let full = batches.iter().filter(|&&(size, _)| size == 4).count();
+        assert!(full * 2 >= batches.len(), "{:?}", batches);
+        let size = batches.iter().map(|&(size, _)| size).sum::<usize>();
+        let distinct = batches.iter().map(|&(_, distinct)| distinct).sum::<usize>();
+        assert!(distinct * 10 >= size * 9, "{:?}", batches);
+    }
+
+    #[test]
+    fn answers_every_request() {
+        let server = InferenceServer::start(Arc::new(ShortModel), 4, Duration::from_millis(10));
+        let input = tensorflow::Tensor::new(&[1, 1, 2, 1]);
+        assert!(server.client().evaluate(input).is_err());
+    }
+}
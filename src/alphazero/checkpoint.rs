@@ -0,0 +1,142 @@
+// State of the training loop after a finished episode, enough to go on as
+// if the run had not stopped. The models and the games of earlier episodes
+// are files of their own, the checkpoint only points at them:
+//
+//     episode 12
+//     best 10
+//     seed 8467201931
+//     window 9 12
+//     priorities 0.25 0.5 1 ...
+use std::{fmt::Display, fs, io, ops::Range, path::Path};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    // The next episode to play, also the generation of the model to train.
+    pub episode: usize,
+    // Generation that plays the self-play games.
+    pub best: usize,
+    // Seed of the self-play games of the next episode.
+    pub seed: u64,
+    // Episodes whose samples are in the replay buffer.
+    pub window: Range<usize>,
+    // Priority of every sample in the replay buffer, oldest episode first.
+    // Empty when the samples aren't drawn by priority.
+    pub priorities: Vec<f64>,
+}
+
+impl Checkpoint {
+    pub fn parse(content: &str) -> Option<Self> {
+        let mut episode = None;
+        let mut best = None;
+        let mut seed = None;
+        let mut window = None;
+        let mut priorities = Vec::new();
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            let (key, value) = line.trim().split_once(' ')?;
+            match key {
+                "episode" => episode = Some(value.parse().ok()?),
+                "best" => best = Some(value.parse().ok()?),
+                "seed" => seed = Some(value.parse().ok()?),
+                "window" => {
+                    let (start, end) = value.split_once(' ')?;
+                    window = Some(start.parse().ok()?..end.parse().ok()?);
+                }
+                "priorities" => {
+                    priorities = value
+                        .split_whitespace()
+                        .map(|p| p.parse().ok())
+                        .collect::<Option<_>>()?
+                }
+                _ => return None,
+            }
+        }
+        Some(Self {
+            episode: episode?,
+            best: best?,
+            seed: seed?,
+            window: window?,
+            priorities,
+        })
+    }
+
+    // None when the run has not finished an episode yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content)
+                .map(Some)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Bad checkpoint")),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Written to a temporary file first, a crash while saving leaves the
+    // previous checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, self.to_string())?;
+        fs::rename(temporary, path)
+    }
+}
+
+impl Display for Checkpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "episode {}", self.episode)?;
+        writeln!(f, "best {}", self.best)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "window {} {}", self.window.start, self.window.end)?;
+        if !self.priorities.is_empty() {
+            let priorities = self
+                .priorities
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>();
+            writeln!(f, "priorities {}", priorities.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Checkpoint;
+    use crate::testing::temp_path;
+
+    #[test]
+    fn save_and_load() {
+        let path = temp_path("checkpoint_test.txt");
+        std::fs::remove_file(&path).ok();
+        assert_eq!(Checkpoint::load(&path).expect("Could not read"), None);
+
+        let checkpoint = Checkpoint {
+            episode: 12,
+            best: 10,
+            seed: 8467201931,
+            window: 9..12,
+            priorities: vec![0.25, 0.5, 1.0],
+        };
+        checkpoint.save(&path).expect("Could not save checkpoint");
+        assert_eq!(
+            Checkpoint::load(&path).expect("Could not read"),
+            Some(checkpoint.clone())
+        );
+        std::fs::remove_file(&path).ok();
+
+        // Without prioritized sampling there is no priorities line.
+        let uniform = Checkpoint {
+            priorities: Vec::new(),
+            ..checkpoint
+        };
+        assert!(!uniform.to_string().contains("priorities"));
+        assert_eq!(Checkpoint::parse(&uniform.to_string()), Some(uniform));
+
+        assert_eq!(Checkpoint::parse("episode 1\nbest 0"), None);
+        assert_eq!(Checkpoint::parse("episode 1\nbest 0\nseed x"), None);
+        assert_eq!(Checkpoint::parse("episode 1\nbest 0\nseed 2"), None);
+        assert_eq!(
+            Checkpoint::parse("episode 1\nbest 0\nseed 2\nwindow 0 1\npriorities 1 x"),
+            None
+        );
+    }
+}
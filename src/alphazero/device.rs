@@ -0,0 +1,118 @@
+// Where and how TensorFlow runs the network. catzero creates the sessions
+// of its models itself, so the settings reach them through the environment
+// that TensorFlow reads when the process starts its runtime: apply them
+// before the first model is loaded. Without them every self-play worker on
+// a machine sizes its thread pools by all the cores and takes all the GPU
+// memory it can get.
+//
+// The environment has no share of the GPU memory, only whether it is
+// allocated as it is needed instead of all up front.
+use std::{fmt::Display, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Device {
+    // The GPUs when there are any, the CPU otherwise.
+    #[default]
+    Gpu,
+    // The GPUs are hidden from the process.
+    Cpu,
+}
+
+impl FromStr for Device {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gpu" => Ok(Device::Gpu),
+            "cpu" => Ok(Device::Cpu),
+            _ => Err(format!("Unknown device: {}", s)),
+        }
+    }
+}
+
+impl Display for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Device::Gpu => write!(f, "gpu"),
+            Device::Cpu => write!(f, "cpu"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DeviceConfig {
+    pub device: Device,
+    // Threads that run one operation and operations that run side by side,
+    // TensorFlow picks them by the cores when they are not set.
+    pub intra_op_threads: Option<usize>,
+    pub inter_op_threads: Option<usize>,
+    // The GPU memory is taken as the models need it, so several processes
+    // can share a GPU.
+    pub gpu_memory_growth: bool,
+}
+
+impl DeviceConfig {
+    // The variables TensorFlow reads for the settings.
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = Vec::new();
+        if self.device == Device::Cpu {
+            env.push(("CUDA_VISIBLE_DEVICES", String::from("-1")));
+        }
+        if let Some(threads) = self.intra_op_threads {
+            env.push(("TF_NUM_INTRAOP_THREADS", threads.to_string()));
+        }
+        if let Some(threads) = self.inter_op_threads {
+            env.push(("TF_NUM_INTEROP_THREADS", threads.to_string()));
+        }
+        if self.device == Device::Gpu && self.gpu_memory_growth {
+            env.push(("TF_FORCE_GPU_ALLOW_GROWTH", String::from("true")));
+        }
+        env
+    }
+
+    // Sets the variables for the process. Setting them is only sound while
+    // the process has a single thread, before TensorFlow or any worker is
+    // started.
+    pub fn apply(&self) {
+        for (key, value) in self.env() {
+            std::env::set_var(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Device, DeviceConfig};
+
+    #[test]
+    fn settings() {
+        assert!(DeviceConfig::default().env().is_empty());
+
+        let config = DeviceConfig {
+            device: Device::Cpu,
+            intra_op_threads: Some(2),
+            inter_op_threads: Some(1),
+            gpu_memory_growth: true,
+        };
+        assert_eq!(
+            config.env(),
+            vec![
+                ("CUDA_VISIBLE_DEVICES", String::from("-1")),
+                ("TF_NUM_INTRAOP_THREADS", String::from("2")),
+                ("TF_NUM_INTEROP_THREADS", String::from("1")),
+            ]
+        );
+
+        let config = DeviceConfig {
+            gpu_memory_growth: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.env(),
+            vec![("TF_FORCE_GPU_ALLOW_GROWTH", String::from("true"))]
+        );
+
+        assert_eq!("cpu".parse::<Device>(), Ok(Device::Cpu));
+        assert_eq!(Device::Gpu.to_string(), "gpu");
+    }
+}
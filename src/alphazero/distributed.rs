@@ -0,0 +1,562 @@
+// Self-play spread over machines. The coordinator runs the training loop and
+// hands out the games of an episode, workers connect to it, play them and
+// send the finished games back. Messages are lines of JSON over TCP:
+//
+//     worker       {"ready": true}
+//     coordinator  {"generation": 4, "seed": 8467201931, "config": "episodes = 80\n..."}
+//     worker       {"generation": 4, "seed": 8467201931, "game": {...}}
+//
+// A worker asks for its next game by sending the last one. Jobs name the
+// generation of the model, workers load it themselves or evaluate on an
+// inference server, see `work`. The game of a worker that disconnects, or
+// sends nothing for WORKER_TIMEOUT, is handed to another one. With a token
+// the first message of a worker has to carry it, `"token": "..."`. The
+// coordinator replays the moves of every game it gets back and takes the
+// winner from the board, not from the worker.
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use mcts::GameState;
+use serde_json::{json, Value};
+use tracing::{info, info_span, warn};
+
+use super::{evaluator::Network, pipeline::SelfPlayGame, MyMCTS, Pipeline};
+use crate::{
+    action::BoardAction, config::TrainingConfig, player::Player, record::SearchStats,
+    rules::GameRules, BoardState,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Longest a worker may take for a game, well above what a game takes.
+const WORKER_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+// Longest message, a game with all its policies is far shorter.
+const MAX_LINE: u64 = 16 << 20;
+
+// The games of the episode being played.
+#[derive(Default)]
+struct Episode {
+    generation: usize,
+    seeds: Vec<u64>,
+    pending: Vec<u64>,
+    in_flight: Vec<u64>,
+    games: Vec<SelfPlayGame<MyMCTS>>,
+}
+
+struct Shared {
+    config: String,
+    token: Option<String>,
+    episode: Mutex<Episode>,
+    changed: Condvar,
+}
+
+pub struct Coordinator {
+    shared: Arc<Shared>,
+    address: SocketAddr,
+}
+
+impl Coordinator {
+    // Accepts workers on the address from now on, they play with the
+    // config. Without a token any worker that connects is given games.
+    pub fn start(
+        address: &str,
+        config: &TrainingConfig,
+        token: Option<String>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        info!(%address, "Waiting for workers");
+
+        let shared = Arc::new(Shared {
+            config: config.to_string(),
+            token,
+            episode: Mutex::new(Episode::default()),
+            changed: Condvar::new(),
+        });
+        let accepting = shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = accepting.clone();
+                thread::spawn(move || {
+                    let peer = stream
+                        .peer_addr()
+                        .map(|a| a.to_string())
+                        .unwrap_or_default();
+                    info!(worker = %peer, "Worker connected");
+                    if let Err(e) = shared.serve(stream) {
+                        warn!(worker = %peer, error = %e, "Worker left");
+                    }
+                });
+            }
+        });
+
+        Ok(Self { shared, address })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    // Plays a game for every seed on the workers and returns them in the
+    // order of the seeds. Once the flag is set no new games are handed out,
+    // the games in flight are waited for.
+    pub fn play(
+        &self,
+        generation: usize,
+        seeds: Vec<u64>,
+        stop: &AtomicBool,
+    ) -> Vec<SelfPlayGame<MyMCTS>> {
+        let shared = &self.shared;
+        let mut episode = shared.episode.lock().expect("Could not lock episode");
+        *episode = Episode {
+            generation,
+            pending: seeds.iter().rev().copied().collect(),
+            seeds,
+            ..Default::default()
+        };
+        shared.changed.notify_all();
+
+        while !episode.pending.is_empty() || !episode.in_flight.is_empty() {
+            if stop.load(Ordering::SeqCst) {
+                episode.pending.clear();
+            }
+            episode = shared
+                .changed
+                .wait_timeout(episode, POLL_INTERVAL)
+                .expect("Could not lock episode")
+                .0;
+        }
+
+        let Episode {
+            seeds, mut games, ..
+        } = std::mem::take(&mut *episode);
+        games.sort_by_key(|game| seeds.iter().position(|&seed| seed == game.seed));
+        games
+    }
+}
+
+impl Shared {
+    fn serve(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(WORKER_TIMEOUT))?;
+        stream.set_write_timeout(Some(WORKER_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        let mut assigned = None;
+        let result = self.exchange(&mut reader, &mut writer, &mut assigned);
+
+        // The game the worker was playing goes to the next one asking.
+        if let Some((generation, seed)) = assigned {
+            let mut episode = self.episode.lock().expect("Could not lock episode");
+            if episode.generation == generation {
+                if let Some(i) = episode.in_flight.iter().position(|&s| s == seed) {
+                    episode.in_flight.remove(i);
+                    episode.pending.push(seed);
+                    self.changed.notify_all();
+                }
+            }
+        }
+        result
+    }
+
+    fn exchange(
+        &self,
+        reader: &mut impl BufRead,
+        writer: &mut impl Write,
+        assigned: &mut Option<(usize, u64)>,
+    ) -> io::Result<()> {
+        let mut line = String::new();
+        let mut greeted = false;
+        loop {
+            line.clear();
+            if read_line(reader, &mut line)? == 0 {
+                return Ok(());
+            }
+            let message: Value = serde_json::from_str(&line).map_err(invalid_data)?;
+            if !greeted {
+                if let Some(token) = &self.token {
+                    if message["token"].as_str() != Some(token.as_str()) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::PermissionDenied,
+                            "Wrong token",
+                        ));
+                    }
+                }
+                greeted = true;
+            }
+            if !message["game"].is_null() {
+                let game = decode_game(&message["game"]).map_err(invalid_data)?;
+                self.finish(message["generation"].as_u64(), game);
+                *assigned = None;
+            }
+
+            let (generation, seed) = self.next_job();
+            *assigned = Some((generation, seed));
+            let job = json!({ "generation": generation, "seed": seed, "config": self.config });
+            writeln!(writer, "{}", job)?;
+        }
+    }
+
+    // Games of an earlier episode or that were handed out twice are dropped.
+    fn finish(&self, generation: Option<u64>, game: SelfPlayGame<MyMCTS>) {
+        let mut episode = self.episode.lock().expect("Could not lock episode");
+        if generation != Some(episode.generation as u64) {
+            return;
+        }
+        if let Some(i) = episode.in_flight.iter().position(|&s| s == game.seed) {
+            episode.in_flight.remove(i);
+            episode.games.push(game);
+            self.changed.notify_all();
+        }
+    }
+
+    // Waits until there is a game to play.
+    fn next_job(&self) -> (usize, u64) {
+        let mut episode = self.episode.lock().expect("Could not lock episode");
+        loop {
+            if let Some(seed) = episode.pending.pop() {
+                episode.in_flight.push(seed);
+                return (episode.generation, seed);
+            }
+            episode = self.changed.wait(episode).expect("Could not lock episode");
+        }
+    }
+}
+
+// Plays the games handed out by the coordinator at the address, `parallel`
+// at a time, until the coordinator goes away. `load` gives the network of a
+// generation, it is called again when the generation changes. The token is
+// the one the coordinator was started with.
+pub fn work<F>(address: &str, token: Option<&str>, parallel: usize, load: F) -> io::Result<()>
+where
+    F: Fn(usize) -> Result<Network, String> + Sync,
+{
+    let network: Mutex<Option<(usize, Network)>> = Mutex::new(None);
+    thread::scope(|scope| {
+        let workers = (0..parallel.max(1))
+            .map(|_| scope.spawn(|| play_jobs(address, token, &network, &load)))
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().expect("Worker panicked"))
+    })
+}
+
+fn play_jobs<F>(
+    address: &str,
+    token: Option<&str>,
+    network: &Mutex<Option<(usize, Network)>>,
+    load: &F,
+) -> io::Result<()>
+where
+    F: Fn(usize) -> Result<Network, String>,
+{
+    let stream = TcpStream::connect(address)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    writeln!(writer, "{}", json!({ "ready": true, "token": token }))?;
+
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if read_line(&mut reader, &mut line)? == 0 {
+            return Ok(());
+        }
+        let job: Value = serde_json::from_str(&line).map_err(invalid_data)?;
+        let (generation, seed) = match (job["generation"].as_u64(), job["seed"].as_u64()) {
+            (Some(generation), Some(seed)) => (generation as usize, seed),
+            _ => return Err(invalid_data(format!("Invalid job: {}", line.trim()))),
+        };
+        let config = TrainingConfig::parse(job["config"].as_str().unwrap_or_default())
+            .map_err(invalid_data)?;
+
+        // The first worker to see a new generation loads it for all.
+        let current = {
+            let mut network = network.lock().expect("Could not lock network");
+            match &*network {
+                Some((loaded, current)) if *loaded == generation => current.clone(),
+                _ => {
+                    info!(generation, "Loading model");
+                    let current = load(generation).map_err(io::Error::other)?;
+                    *network = Some((generation, current.clone()));
+                    current
+                }
+            }
+        };
+
+        let game = info_span!("game", generation, seed)
+            .in_scope(|| Pipeline::<MyMCTS>::new(config.pipeline).play_a_game(current, seed));
+        let message = json!({ "generation": generation, "seed": seed, "game": encode_game(&game) });
+        writeln!(writer, "{}", message)?;
+    }
+}
+
+// Reads a line of at most MAX_LINE bytes, a longer one is an error.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    let read = reader.take(MAX_LINE).read_line(line)?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Err(invalid_data("Message too long"));
+    }
+    Ok(read)
+}
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn player_number(player: &Player) -> u8 {
+    match player {
+        Player::Player1 => 1,
+        Player::Player2 => 2,
+    }
+}
+
+fn parse_player(value: &Value) -> Result<Option<Player>, String> {
+    match value.as_u64() {
+        None if value.is_null() => Ok(None),
+        Some(1) => Ok(Some(Player::Player1)),
+        Some(2) => Ok(Some(Player::Player2)),
+        _ => Err(format!("Unknown player {}", value)),
+    }
+}
+
+// The states of a game follow from its start and moves, only the start is
+// sent, and so does the winner. A game that ended by resigning has a state
+// more than it has moves.
+pub fn encode_game(game: &SelfPlayGame<MyMCTS>) -> Value {
+    let start = game.histories.first().map(|(state, _)| state);
+    let stats = game
+        .stats
+        .iter()
+        .map(|s| json!([s.playouts, s.visits, s.value, s.time.as_secs_f64()]))
+        .collect::<Vec<_>>();
+    json!({
+        "rules": start.map(|state| state.rules().to_string()),
+        "start": start.map(|state| state.to_notation()),
+        "moves": game.moves.iter().map(|mov| mov.to_string()).collect::<Vec<_>>(),
+        "policy_dims": game.histories.first().map(|(_, policy)| policy.dims().to_vec()),
+        "policies": game.histories.iter().map(|(_, policy)| policy.to_vec()).collect::<Vec<_>>(),
+        "stats": stats,
+        "resigned": game.resigned.as_ref().map(player_number),
+        "would_resign": game.would_resign.as_ref().map(player_number),
+        "seed": game.seed,
+        "search_time": game.search_time.as_secs_f64(),
+    })
+}
+
+pub fn decode_game(game: &Value) -> Result<SelfPlayGame<MyMCTS>, String> {
+    let field = |name: &str| game[name].clone();
+    let policies: Vec<Vec<f32>> =
+        serde_json::from_value(field("policies")).map_err(|e| e.to_string())?;
+    let names: Vec<String> = serde_json::from_value(field("moves")).map_err(|e| e.to_string())?;
+
+    let resigned = parse_player(&game["resigned"])?;
+    let mut histories = Vec::new();
+    let mut moves = Vec::new();
+    let mut winner = None;
+    if !policies.is_empty() {
+        let dims: Vec<u64> =
+            serde_json::from_value(field("policy_dims")).map_err(|e| e.to_string())?;
+        let rules = game["rules"]
+            .as_str()
+            .and_then(GameRules::parse)
+            .ok_or("Invalid rules")?;
+        let mut state = game["start"]
+            .as_str()
+            .and_then(BoardState::parse_notation)
+            .ok_or("Invalid start position")?
+            .with_rules(rules);
+
+        for (i, policy) in policies.iter().enumerate() {
+            let policy = tensorflow::Tensor::new(&dims)
+                .with_values(policy)
+                .map_err(|e| format!("Policy {}: {}", i, e))?;
+            histories.push((state.clone(), policy));
+            if let Some(name) = names.get(i) {
+                let mov = BoardAction::parse(name, state.current_player())
+                    .filter(|mov| state.check_move(mov).is_ok())
+                    .ok_or_else(|| format!("Illegal move {}", name))?;
+                state.make_move(&mov);
+                moves.push(mov);
+            }
+        }
+        winner = match resigned {
+            Some(player) => Some(player.next_player()),
+            None if state.is_terminal() => state.get_winner(),
+            None => return Err(String::from("The game is not over")),
+        };
+    }
+    if moves.len() != names.len() {
+        return Err(String::from("More moves than states"));
+    }
+
+    let stats: Vec<(usize, u64, f32, f64)> =
+        serde_json::from_value(field("stats")).map_err(|e| e.to_string())?;
+    Ok(SelfPlayGame {
+        histories,
+        moves,
+        stats: stats
+            .into_iter()
+            .map(|(playouts, visits, value, time)| SearchStats {
+                playouts,
+                visits,
+                value,
+                time: Duration::from_secs_f64(time),
+            })
+            .collect(),
+        winner,
+        resigned,
+        would_resign: parse_player(&game["would_resign"])?,
+        seed: game["seed"].as_u64().ok_or("Missing seed")?,
+        search_time: Duration::from_secs_f64(game["search_time"].as_f64().unwrap_or(0.0)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpStream,
+        sync::atomic::AtomicBool,
+        time::Duration,
+    };
+
+    use serde_json::{json, Value};
+
+    use crate::{
+        action::{BoardAction, Column},
+        alphazero::{pipeline::SelfPlayGame, MyMCTS},
+        config::TrainingConfig,
+        player::Player,
+        record::SearchStats,
+        BoardState,
+    };
+    use mcts::GameState;
+
+    use super::{decode_game, encode_game, read_line, Coordinator, MAX_LINE};
+
+    fn game(seed: u64) -> SelfPlayGame<MyMCTS> {
+        let start = BoardState::default();
+        let mov = BoardAction::DropStone(Player::Player1, Column::new(3).unwrap());
+        let mut next = start.clone();
+        next.make_move(&mov);
+        let mut policy = tensorflow::Tensor::new(&[1, 3, 8, 8]);
+        policy.set(&[0, 0, 3, 0], 1.0);
+
+        // Player 2 resigned after the first move.
+        SelfPlayGame {
+            histories: vec![(start, policy.clone()), (next, policy)],
+            moves: vec![mov],
+            stats: vec![SearchStats {
+                playouts: 50,
+                visits: 20,
+                value: 0.25,
+                time: Duration::from_millis(3),
+            }],
+            winner: Some(Player::Player1),
+            resigned: Some(Player::Player2),
+            would_resign: None,
+            seed,
+            search_time: Duration::from_millis(3),
+        }
+    }
+
+    #[test]
+    fn coordinate_a_worker() {
+        let encoded = encode_game(&game(7));
+        let decoded = decode_game(&encoded).expect("Could not decode game");
+        assert_eq!(decoded.histories.len(), 2);
+        assert_eq!(
+            decoded.histories[1].0.to_notation(),
+            game(7).histories[1].0.to_notation()
+        );
+        assert_eq!(decoded.moves, game(7).moves);
+        assert_eq!(decoded.stats, game(7).stats);
+        assert_eq!(decoded.resigned, Some(Player::Player2));
+        assert_eq!(encode_game(&decoded), encoded);
+
+        // A worker that plays the same game whatever the job.
+        let coordinator = Coordinator::start("127.0.0.1:0", &TrainingConfig::default(), None)
+            .expect("Could not start coordinator");
+        let address = coordinator.address();
+        let worker = std::thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            writeln!(writer, "{}", json!({ "ready": true })).unwrap();
+            let mut line = String::new();
+            for _ in 0..2 {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let job: Value = serde_json::from_str(&line).unwrap();
+                assert!(TrainingConfig::parse(job["config"].as_str().unwrap()).is_ok());
+                let game = encode_game(&game(job["seed"].as_u64().unwrap()));
+                let message = json!({ "generation": job["generation"], "game": game });
+                writeln!(writer, "{}", message).unwrap();
+            }
+        });
+
+        let games = coordinator.play(3, vec![11, 12], &AtomicBool::new(false));
+        worker.join().unwrap();
+        assert_eq!(games.iter().map(|g| g.seed).collect::<Vec<_>>(), [11, 12]);
+    }
+
+    #[test]
+    fn take_the_winner_from_the_board() {
+        let mut encoded = encode_game(&game(7));
+        encoded["resigned"] = json!(1);
+        let decoded = decode_game(&encoded).expect("Could not decode game");
+        assert_eq!(decoded.winner, Some(Player::Player2));
+
+        encoded["resigned"] = Value::Null;
+        assert!(decode_game(&encoded).is_err());
+    }
+
+    #[test]
+    fn refuse_long_lines() {
+        let mut line = String::new();
+        let long = format!("{}\n", " ".repeat(MAX_LINE as usize));
+        assert!(read_line(&mut long.as_bytes(), &mut line).is_err());
+        line.clear();
+        let short = format!("{}\n", " ".repeat(MAX_LINE as usize - 1));
+        assert_eq!(
+            read_line(&mut short.as_bytes(), &mut line).unwrap(),
+            short.len()
+        );
+    }
+
+    #[test]
+    fn refuse_strangers() {
+        let token = Some(String::from("secret"));
+        let coordinator = Coordinator::start("127.0.0.1:0", &TrainingConfig::default(), token)
+            .expect("Could not start coordinator");
+        let address = coordinator.address();
+        let send = move |message: &str| {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            writeln!(writer, "{}", message).unwrap();
+            let mut line = String::new();
+            // The coordinator hangs up on the ones it refuses.
+            let read = reader.read_line(&mut line).unwrap_or(0);
+            read > 0
+        };
+        assert!(!send(&json!({ "ready": true }).to_string()));
+        assert!(!send(
+            &json!({ "ready": true, "token": "guess" }).to_string()
+        ));
+
+        // A worker with the token gets a game once there is one.
+        let playing = std::thread::spawn(move || {
+            send(&json!({ "ready": true, "token": "secret" }).to_string())
+        });
+        std::thread::spawn(move || coordinator.play(0, vec![5], &AtomicBool::new(false)));
+        assert!(playing.join().unwrap());
+    }
+}
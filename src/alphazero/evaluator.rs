@@ -0,0 +1,191 @@
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use catzero::{AlphaGame, TFModel};
+use mcts::{tree_policy::TreePolicy, Evaluator, GameState, MoveEvaluation, MoveList, SearchHandle};
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::{batching::InferenceClient, game::NetworkGame, noise::DirichletNoise, StateEval};
+#[cfg(feature = "onnx")]
+use crate::onnx::OnnxModel;
+use crate::{
+    inference::RemoteModel,
+    planes::{InputEncoding, Layout},
+    player::Player,
+};
+
+// Where the search gets its evaluations: the model itself, the inference
+// server that batches them with those of other games, a model served on
+// another machine or one exported to ONNX.
+#[derive(Clone)]
+pub enum Network {
+    Model(Arc<TFModel>),
+    Batched(InferenceClient),
+    Remote(RemoteModel),
+    // The expected score of a WDL head is the value.
+    #[cfg(feature = "onnx")]
+    Onnx(Arc<OnnxModel>),
+}
+
+impl Network {
+    // The policy and the value for the player to move of the state in the
+    // encoding. The policy is channels first whatever the layout of the
+    // model.
+    pub fn evaluate<G: NetworkGame>(
+        &self,
+        state: &G,
+        encoding: InputEncoding,
+    ) -> (tensorflow::Tensor<f32>, f32) {
+        // Remote and exported models take this crate's planes.
+        let planes = || {
+            state
+                .input_planes(encoding)
+                .unwrap_or_else(|e| panic!("Could not read input: {}", e))
+        };
+        let input = INPUT.with(|buf| state.input(encoding, &mut buf.borrow_mut()));
+        let dims = input.dims().to_vec();
+        let (policy, value) = match self {
+            Network::Model(model) => model.evaluate(input).expect("Could not evaluate state"),
+            Network::Batched(client) => client.evaluate(input).expect("Could not evaluate state"),
+            // The server hands back the policy channels first.
+            Network::Remote(remote) => {
+                let evaluation = remote
+                    .evaluate(&[planes()])
+                    .expect("Could not evaluate state")
+                    .remove(0);
+                let policy = evaluation
+                    .policy
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .copied()
+                    .collect::<Vec<_>>();
+                return (policy_tensor(&policy), evaluation.value);
+            }
+            // tract hands back the policy channels first as well.
+            #[cfg(feature = "onnx")]
+            Network::Onnx(model) => {
+                let (policy, value) = model.evaluate(&planes()).expect("Could not evaluate state");
+                return (policy_tensor(&policy), value.expected());
+            }
+        };
+        (channels_first(policy, &dims, encoding.layout), value)
+    }
+}
+
+thread_local! {
+    // The encoding buffer of each search thread, every state it evaluates
+    // is encoded in it.
+    static INPUT: RefCell<Vec<f32>> = const { RefCell::new(Vec::new()) };
+}
+
+fn policy_tensor(policy: &[f32]) -> tensorflow::Tensor<f32> {
+    tensorflow::Tensor::new(&[1, policy.len() as u64])
+        .with_values(policy)
+        .expect("Could not use tensor")
+}
+
+// The policy of a model in the layout, for an input of the dimensions.
+fn channels_first(
+    policy: tensorflow::Tensor<f32>,
+    dims: &[u64],
+    layout: Layout,
+) -> tensorflow::Tensor<f32> {
+    if layout == Layout::ChannelsFirst {
+        return policy;
+    }
+    let board = dims[dims.len() - 3..]
+        .iter()
+        .map(|&d| d as usize)
+        .collect::<Vec<_>>();
+    let [_, width, height] = layout.shape([board[0], board[1], board[2]]);
+    tensorflow::Tensor::new(policy.dims())
+        .with_values(&layout.to_channels_first(&policy, width, height))
+        .expect("Could not use tensor")
+}
+
+// Evaluates states with the network, adding the noise to the priors of the
+// root. Each search has its own evaluator and evaluates the root when it is
+// created, before any playout, so the root is the first state evaluated.
+// The noise is drawn from the seed, so a search with one thread is the
+// same every time.
+pub struct NetworkEvaluator {
+    network: Network,
+    noise: Option<DirichletNoise>,
+    input: InputEncoding,
+    rng: Mutex<StdRng>,
+    root_pending: AtomicBool,
+}
+
+impl NetworkEvaluator {
+    pub fn new(
+        network: Network,
+        noise: Option<DirichletNoise>,
+        input: InputEncoding,
+        seed: Option<u64>,
+    ) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            network,
+            noise,
+            input,
+            rng: Mutex::new(rng),
+            root_pending: AtomicBool::new(true),
+        }
+    }
+}
+
+impl<A> Evaluator<A> for NetworkEvaluator
+where
+    A: AlphaGame,
+    A::State: NetworkGame,
+    A::TreePolicy: TreePolicy<A, MoveEvaluation = f64>,
+{
+    type StateEvaluation = StateEval;
+
+    fn evaluate_new_state(
+        &self,
+        state: &A::State,
+        moves: &MoveList<A>,
+        _: Option<SearchHandle<A>>,
+    ) -> (Vec<MoveEvaluation<A>>, Self::StateEvaluation) {
+        let root = self.root_pending.swap(false, Ordering::SeqCst);
+        if let Some(evaluation) = StateEval::terminal(state) {
+            return (vec![0.0; moves.len()], evaluation);
+        }
+
+        let (policy, value) = self.network.evaluate(state, self.input);
+        let mut priors = A::moves_to_evaluation(moves, policy);
+        if let (true, Some(noise)) = (root, &self.noise) {
+            let mut rng = self.rng.lock().expect("Could not lock rng");
+            noise.apply(&mut priors, &mut *rng);
+        }
+
+        (priors, StateEval::Evaluation(state.current_player(), value))
+    }
+
+    fn evaluate_existing_state(
+        &self,
+        _: &A::State,
+        existing_evaln: &Self::StateEvaluation,
+        _: SearchHandle<A>,
+    ) -> Self::StateEvaluation {
+        existing_evaln.clone()
+    }
+
+    fn interpret_evaluation_for_player(
+        &self,
+        evaluation: &Self::StateEvaluation,
+        player: &Player,
+    ) -> f64 {
+        evaluation.value_for(player)
+    }
+}
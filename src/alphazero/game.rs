@@ -0,0 +1,177 @@
+// What the network search needs from a game. The search, the evaluator and
+// the self-play loop only go through this trait, so other board variants,
+// such as classic Connect 4 or pure match-3, can be trained with the same
+// pipeline once their state implements it. States are fed to the network
+// as planes over the board, actions are read from policy planes over the
+// board. The input planes are the game's own, only the remote and exported
+// models expect this crate's InputPlanes.
+use std::hash::Hash;
+
+use mcts::GameState;
+use smallvec::SmallVec;
+
+use super::pipeline::Symmetries;
+use crate::{
+    action::{BoardAction, INLINE_MOVES},
+    planes,
+    planes::{InputEncoding, InputPlanes},
+    player::Player,
+    BoardState,
+};
+
+// States are hashed for the transposition table.
+pub trait NetworkGame:
+    GameState<Player = Player, MoveList = SmallVec<[<Self as GameState>::Move; INLINE_MOVES]>>
+    + Hash
+    + Default
+    + Send
+    + Sync
+    + Symmetries
+{
+    // Policy planes of the game, `extended` when the search also covers the
+    // optional actions of the rules.
+    fn policy_planes(extended: bool) -> usize;
+
+    // Where the action is in a flattened policy of a board of the size.
+    fn policy_index(action: &Self::Move, width: usize, height: usize) -> usize;
+
+    // Fails when the state is not played on the board the search is set up
+    // for.
+    fn check_search(&self, width: usize, height: usize, extended: bool) -> Result<(), String>;
+
+    // The planes of a training sample.
+    fn planes(&self, encoding: InputEncoding) -> planes::Tensor<u8>;
+
+    // The [1, planes, width, height] input of the network, encoded in the
+    // buffer first so searches reuse its allocation for every state.
+    fn input(&self, encoding: InputEncoding, buf: &mut Vec<f32>) -> tensorflow::Tensor<f32>;
+
+    // The input of the network for the planes of a training sample.
+    fn sample_input(
+        sample: &planes::Tensor<u8>,
+        encoding: InputEncoding,
+    ) -> Result<tensorflow::Tensor<f32>, String>;
+
+    // The planes remote and exported models take, they are only trained on
+    // this crate's board.
+    fn input_planes(&self, _encoding: InputEncoding) -> Result<InputPlanes, String> {
+        Err(String::from(
+            "Remote and exported models only play match-3 Connect 4",
+        ))
+    }
+
+    // Points the player has banked, for the auxiliary points target.
+    fn points(&self, player: Player) -> usize;
+}
+
+// Extended searches have the planes of the diagonal switches.
+impl NetworkGame for BoardState {
+    fn policy_planes(extended: bool) -> usize {
+        planes::policy_planes(extended)
+    }
+
+    fn policy_index(action: &BoardAction, width: usize, height: usize) -> usize {
+        action.to_index(width, height)
+    }
+
+    fn check_search(&self, width: usize, height: usize, extended: bool) -> Result<(), String> {
+        let size = (self.board().width(), self.board().height());
+        if size != (width, height) {
+            return Err(format!(
+                "The search is for a {}x{} board, not {}x{}",
+                width, height, size.0, size.1
+            ));
+        }
+        if self.rules().diagonal_switches != extended {
+            return Err(format!("The search is for diagonal-switches={}", extended));
+        }
+        Ok(())
+    }
+
+    fn planes(&self, encoding: InputEncoding) -> planes::Tensor<u8> {
+        InputPlanes::encode(self, encoding).into()
+    }
+
+    fn input(&self, encoding: InputEncoding, buf: &mut Vec<f32>) -> tensorflow::Tensor<f32> {
+        self.encode_into(encoding, buf);
+        let dims = encoding.dims(self.board().width(), self.board().height());
+        tensorflow::Tensor::new(&dims.map(|d| d as u64))
+            .with_values(buf)
+            .expect("Input does not fit its dimensions")
+    }
+
+    fn sample_input(
+        sample: &planes::Tensor<u8>,
+        encoding: InputEncoding,
+    ) -> Result<tensorflow::Tensor<f32>, String> {
+        InputPlanes::from_tensor(sample.clone(), encoding)
+            .map(|planes| planes.to_tensorflow())
+            .map_err(|e| e.to_string())
+    }
+
+    fn input_planes(&self, encoding: InputEncoding) -> Result<InputPlanes, String> {
+        Ok(InputPlanes::encode(self, encoding))
+    }
+
+    fn points(&self, player: Player) -> usize {
+        BoardState::points(self, player)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{BoardAction, Column},
+        planes::{InputEncoding, PointsEncoding},
+        player::Player,
+        rules::GameRules,
+        BoardState,
+    };
+
+    use super::NetworkGame;
+
+    // The size of a policy the way a search of any game finds it.
+    fn policy_len<G: NetworkGame>(state: &G, width: usize, height: usize) -> usize {
+        state
+            .check_search(width, height, false)
+            .expect("Search does not fit");
+        G::policy_planes(false) * width * height
+    }
+
+    #[test]
+    fn board_state_encoding() {
+        let state = BoardState::new(GameRules::parse("size=5x4").unwrap());
+        let mut buf = Vec::new();
+        assert_eq!(policy_len(&state, 5, 4), 3 * 5 * 4);
+        assert_eq!(
+            state.input(InputEncoding::default(), &mut buf).dims(),
+            &[1, 4, 5, 4]
+        );
+        assert_eq!(
+            state
+                .input(
+                    InputEncoding {
+                        points: PointsEncoding::Thermometer(3),
+                        history: 1,
+                        ..Default::default()
+                    },
+                    &mut buf
+                )
+                .dims(),
+            &[1, 10, 5, 4]
+        );
+        assert_eq!(BoardState::policy_planes(true), 5);
+
+        let encoding = InputEncoding::default();
+        let sample = state.planes(encoding);
+        let input = BoardState::sample_input(&sample, encoding).unwrap();
+        assert_eq!(input.dims(), state.input(encoding, &mut buf).dims());
+        assert_eq!(&input[..], &state.input(encoding, &mut buf)[..]);
+        assert!(BoardState::sample_input(&sample[1..].to_vec(), encoding).is_err());
+        assert!(state.check_search(8, 8, false).is_err());
+        assert!(state.check_search(5, 4, true).is_err());
+
+        let drop = BoardAction::DropStone(Player::Player1, Column::new(2).unwrap());
+        assert_eq!(BoardState::policy_index(&drop, 5, 4), drop.to_index(5, 4));
+    }
+}
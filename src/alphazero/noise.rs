@@ -0,0 +1,50 @@
+use rand::Rng;
+use rand_distr::{Dirichlet, Distribution};
+
+// Exploration noise for the priors at the root of a self-play search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirichletNoise {
+    pub alpha: f64,
+    // Weight of the noise against the network priors.
+    pub epsilon: f64,
+}
+
+impl DirichletNoise {
+    pub fn apply<R: Rng + ?Sized>(&self, priors: &mut [f64], rng: &mut R) {
+        // The distribution needs at least two moves, one move has nothing to explore.
+        if priors.len() < 2 {
+            return;
+        }
+        let dirichlet =
+            Dirichlet::new_with_size(self.alpha, priors.len()).expect("Could not create noise");
+        for (prior, noise) in priors.iter_mut().zip(dirichlet.sample(rng)) {
+            *prior = (1.0 - self.epsilon) * *prior + self.epsilon * noise;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::DirichletNoise;
+
+    #[test]
+    fn mixes_noise_into_priors() {
+        let noise = DirichletNoise {
+            alpha: 0.3,
+            epsilon: 0.25,
+        };
+        let mut rng = StdRng::seed_from_u64(3);
+
+        let mut priors = vec![1.0, 0.0, 0.0, 0.0];
+        noise.apply(&mut priors, &mut rng);
+        assert!((priors.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(priors[0] >= 0.75);
+        assert_ne!(priors, vec![1.0, 0.0, 0.0, 0.0]);
+
+        let mut single = vec![1.0];
+        noise.apply(&mut single, &mut rng);
+        assert_eq!(single, vec![1.0]);
+    }
+}
@@ -0,0 +1,951 @@
+use std::{
+    fmt::Debug,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use catzero::{AlphaGame, CatZeroModel, TFModel, Tensor, TrainingData};
+use mcts::{GameState, Move, Player, ThreadData};
+use rand::{distributions::WeightedIndex, prelude::Distribution, rngs::StdRng, Rng, SeedableRng};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+use tracing::{debug, info, info_span, warn, Span};
+
+use crate::{planes::InputEncoding, record::SearchStats, samples::SampleStore, wdl::Wdl};
+
+use super::{
+    batching::InferenceServer,
+    evaluator::Network,
+    game::NetworkGame,
+    noise::DirichletNoise,
+    replay::{PrioritizedSampling, Priority, ReplayBuffer},
+    search::{NetworkSearch, SearchOptions},
+    sprt::{Sprt, SprtDecision},
+};
+
+// Most a batch of leaves waits for the search threads that are still on
+// their way down. A batch is sent as soon as every thread has its leaf, the
+// wait only matters for the threads that found the game over.
+const LEAF_WAIT: Duration = Duration::from_millis(10);
+
+// Plays the first moves of a self-play game from the start position, such
+// as a line of an opening book.
+pub type Opening<S> = Arc<dyn Fn(&S, &mut StdRng) -> S + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    pub exploration: f64,
+    pub games_per_episode: usize,
+    pub playouts: usize,
+    pub batch_size: u32,
+    pub epochs: u32,
+    pub learn_retries: usize,
+    pub arena_games: usize,
+    // Number of episodes whose games are trained on.
+    pub replay_episodes: usize,
+    // Samples drawn from every game in the sample store on top of the
+    // replay buffer, so older games keep being trained on without being
+    // held in memory. None are drawn at zero.
+    pub stored_samples: usize,
+    // Draw the samples by priority instead of taking each once.
+    pub prioritized: Option<PrioritizedSampling>,
+    // Score the candidate needs against the best model to replace it.
+    pub promotion_score: f32,
+    // Stops the arena match as soon as the test decides, `arena_games` is
+    // then the most games it plays. An undecided match promotes by score.
+    pub sprt: Option<Sprt>,
+    // Also train on the symmetries of every visited state.
+    pub augment: bool,
+    // Noise at the root of every self-play search, arena games have none.
+    pub noise: Option<DirichletNoise>,
+    // Self-play samples moves by visits^(1 / temperature) during the first
+    // `temperature_plies` plies and plays the most visited move after that.
+    pub temperature: f64,
+    pub temperature_plies: usize,
+    // Evaluate the leaves of all self-play games together in batches.
+    pub batch_inference: bool,
+    // Number of leaves a self-play search expands before they are evaluated
+    // together, one search thread per leaf. One searches a leaf at a time.
+    pub leaf_batch: usize,
+    // Applied while the leaves of a batch are waiting for the network.
+    pub virtual_loss: i64,
+    // A player resigns once the value of its chosen move stays below the
+    // threshold for `resign_plies` of its moves in a row. Resigning is
+    // disabled in a fraction of the games, to see how often it would have
+    // been wrong.
+    pub resign_threshold: Option<f32>,
+    pub resign_plies: usize,
+    pub resign_disabled_fraction: f64,
+    // Seeds the whole run: the games, their noise and the samples that are
+    // trained on. A random seed without it.
+    pub seed: Option<u64>,
+    // How the network sees a position, in the searches and in the samples
+    // it is trained on. Training takes the u8 planes, so scaled points
+    // would be trained on unscaled.
+    pub input: InputEncoding,
+    // Plies ahead of the auxiliary target of the points of both players,
+    // which the exported samples carry for a network with a points head.
+    // catzero trains no such head, so it is left out of its training.
+    pub points_horizon: Option<usize>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            exploration: 1.45,
+            games_per_episode: 25,
+            playouts: 500,
+            batch_size: 20,
+            epochs: 100,
+            learn_retries: 10,
+            arena_games: 10,
+            replay_episodes: 1,
+            stored_samples: 0,
+            prioritized: None,
+            promotion_score: 0.55,
+            sprt: None,
+            augment: false,
+            noise: None,
+            temperature: 1.0,
+            temperature_plies: 16,
+            batch_inference: false,
+            leaf_batch: 1,
+            virtual_loss: 1,
+            resign_threshold: None,
+            resign_plies: 3,
+            resign_disabled_fraction: 0.1,
+            seed: None,
+            input: InputEncoding::default(),
+            points_horizon: None,
+        }
+    }
+}
+
+// A finished self-play game: every visited state with the search policy
+// that was played from it, the moves that were played and the winner.
+// The seed drives the move sampling and the root noise, with one search
+// thread the same seed plays the same game.
+pub struct SelfPlayGame<G: AlphaGame> {
+    pub histories: Vec<(G::State, tensorflow::Tensor<f32>)>,
+    pub moves: Vec<Move<G>>,
+    // How the search went for every move that was played.
+    pub stats: Vec<SearchStats>,
+    pub winner: Option<Player<G>>,
+    // The player who resigned, the winner is then the opponent. The state
+    // it resigned in has a history but no move.
+    pub resigned: Option<Player<G>>,
+    // Who would have resigned first in a game where resigning was disabled.
+    pub would_resign: Option<Player<G>>,
+    pub seed: u64,
+    pub search_time: Duration,
+}
+
+impl<G: AlphaGame> SelfPlayGame<G>
+where
+    Player<G>: PartialEq,
+{
+    // Whether resigning would have thrown away a game that was not lost,
+    // None when nobody would have resigned.
+    pub fn false_resignation(&self) -> Option<bool> {
+        self.would_resign
+            .as_ref()
+            .map(|player| self.winner.is_none() || self.winner.as_ref() == Some(player))
+    }
+}
+
+impl<G: AlphaGame> SelfPlayGame<G>
+where
+    G::State: NetworkGame,
+{
+    // For every visited state the points of the player to move and of the
+    // opponent `horizon` plies later, or when the game ended if that is
+    // sooner.
+    pub fn points_ahead(&self, horizon: usize) -> Vec<[f32; 2]> {
+        let mut states = self
+            .histories
+            .iter()
+            .map(|(state, _)| state.clone())
+            .collect::<Vec<_>>();
+        // The state the last move led to has no history.
+        let last_move = states.len().checked_sub(1).and_then(|i| self.moves.get(i));
+        if let (Some(last), Some(mov)) = (states.last(), last_move) {
+            let mut end = last.clone();
+            end.make_move(mov);
+            states.push(end);
+        }
+
+        (0..self.histories.len())
+            .map(|ply| {
+                let player = states[ply].current_player();
+                let ahead = &states[(ply + horizon).min(states.len() - 1)];
+                [player, player.next_player()].map(|p| ahead.points(p) as f32)
+            })
+            .collect()
+    }
+}
+
+// Fraction of the games played without resigning in which resigning would
+// have been wrong, None when no player would have resigned.
+pub fn false_resignation_rate<G: AlphaGame>(games: &[SelfPlayGame<G>]) -> Option<f64>
+where
+    Player<G>: PartialEq,
+{
+    let checked = games
+        .iter()
+        .filter_map(|game| game.false_resignation())
+        .collect::<Vec<_>>();
+    if checked.is_empty() {
+        return None;
+    }
+    Some(checked.iter().filter(|&&wrong| wrong).count() as f64 / checked.len() as f64)
+}
+
+// Counts for every player how many of its last moves were valued below the
+// threshold.
+struct ResignCheck<P> {
+    threshold: f32,
+    plies: usize,
+    streaks: Vec<(P, usize)>,
+}
+
+impl<P: PartialEq + Clone> ResignCheck<P> {
+    fn new(threshold: f32, plies: usize) -> Self {
+        Self {
+            threshold,
+            plies: plies.max(1),
+            streaks: Vec::new(),
+        }
+    }
+
+    // Records the value of a move of the player, true when it should resign.
+    fn update(&mut self, player: &P, value: f32) -> bool {
+        let index = match self.streaks.iter().position(|(p, _)| p == player) {
+            Some(index) => index,
+            None => {
+                self.streaks.push((player.clone(), 0));
+                self.streaks.len() - 1
+            }
+        };
+        let streak = &mut self.streaks[index].1;
+        *streak = if value < self.threshold {
+            *streak + 1
+        } else {
+            0
+        };
+        *streak >= self.plies
+    }
+
+    // The player that wins when the given one resigns, None as long as the
+    // opponent has not moved.
+    fn opponent(&self, player: &P) -> Option<P> {
+        self.streaks
+            .iter()
+            .map(|(p, _)| p)
+            .find(|&p| p != player)
+            .cloned()
+    }
+}
+
+// Positions that are the same game as another one, like the reflection of a
+// board, so they can share its training sample.
+pub trait Symmetries: Sized {
+    // The equivalent positions with their policies, without the original.
+    fn symmetries(&self, policy: &tensorflow::Tensor<f32>) -> Vec<(Self, tensorflow::Tensor<f32>)>;
+}
+
+// Result of an arena match, seen from the candidate model.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ArenaResult {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl ArenaResult {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn score(&self) -> f32 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        (self.wins as f32 + 0.5 * self.draws as f32) / self.games() as f32
+    }
+
+    // Whether the candidate scored more than the share it needed.
+    pub fn promotes(&self, promotion_score: f32) -> bool {
+        self.games() > 0 && self.score() > promotion_score
+    }
+}
+
+// The AlphaZero loop of self-play, training and evaluation, for any game
+// that implements AlphaGame and can encode its states as input planes.
+pub struct Pipeline<G: AlphaGame> {
+    pub config: PipelineConfig,
+    pub buffer: ReplayBuffer,
+    start: G::State,
+    opening: Option<Opening<G::State>>,
+    // Seeds the games of the next episode.
+    seed: u64,
+    stop: Arc<AtomicBool>,
+    _game: std::marker::PhantomData<G>,
+}
+
+impl<G> Pipeline<G>
+where
+    G: NetworkSearch,
+    G::State: NetworkGame,
+    Player<G>: PartialEq + Clone + Send + Debug,
+    ThreadData<G>: Default,
+{
+    pub fn new(config: PipelineConfig) -> Self {
+        Self {
+            buffer: ReplayBuffer::new(config.replay_episodes).with_sampling(config.prioritized),
+            seed: config.seed.unwrap_or_else(rand::random),
+            config,
+            start: G::State::default(),
+            opening: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            _game: std::marker::PhantomData,
+        }
+    }
+
+    // Position every self-play and arena game starts from, for example an
+    // empty board of another size.
+    pub fn with_start(mut self, start: G::State) -> Self {
+        self.start = start;
+        self
+    }
+
+    // Self-play games start from the position the opening reaches, so they
+    // do not all follow the same first moves. Arena games are not changed.
+    pub fn with_opening(mut self, opening: Opening<G::State>) -> Self {
+        self.opening = Some(opening);
+        self
+    }
+
+    // Seed of the next episode, the same seed plays the same moves apart
+    // from what parallel searches do differently.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Setting the flag stops self-play from starting new games, games that
+    // are already being played are finished.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    pub fn self_play(&mut self, model: Arc<TFModel>) -> Vec<SelfPlayGame<G>> {
+        // Every game thread has at most one batch of leaves waiting.
+        let server = self.config.batch_inference.then(|| {
+            InferenceServer::start(
+                model.clone(),
+                rayon::current_num_threads() * self.config.leaf_batch,
+                Duration::from_millis(1),
+            )
+        });
+        let network = match &server {
+            Some(server) => Network::Batched(server.client()),
+            None => Network::Model(model),
+        };
+        self.self_play_with(network)
+    }
+
+    // Plays the games of an episode with any network, such as one served
+    // on another machine.
+    pub fn self_play_with(&mut self, network: Network) -> Vec<SelfPlayGame<G>> {
+        let seeds = self.episode_seeds();
+        let pipeline = &*self;
+        // The games run on other threads, they are put under the span of
+        // the caller by hand.
+        let parent = Span::current();
+        seeds
+            .into_par_iter()
+            .enumerate()
+            .filter(|_| !pipeline.is_stopping())
+            .map(|(i, seed)| {
+                info_span!(parent: &parent, "game", index = i, seed)
+                    .in_scope(|| pipeline.play_a_game(network.clone(), seed))
+            })
+            .collect()
+    }
+
+    // Seeds of the games of the next episode, for playing them elsewhere.
+    pub fn episode_seeds(&mut self) -> Vec<u64> {
+        let mut rng = self.next_rng();
+        (0..self.config.games_per_episode)
+            .map(|_| rng.gen())
+            .collect()
+    }
+
+    // A generator for the next step of the run, the seed moves on.
+    fn next_rng(&mut self) -> StdRng {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        self.seed = rng.gen();
+        rng
+    }
+
+    // play a game and a list of states
+    pub fn play_a_game(&self, network: Network, seed: u64) -> SelfPlayGame<G> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut state = match &self.opening {
+            Some(opening) => opening(&self.start, &mut rng),
+            None => self.start.clone(),
+        };
+
+        let mut histories = Vec::new();
+        let mut moves = Vec::new();
+        let mut stats = Vec::new();
+        let mut search_time = Duration::ZERO;
+
+        let mut resign_check = self
+            .config
+            .resign_threshold
+            .map(|threshold| ResignCheck::new(threshold, self.config.resign_plies));
+        // Only drawn when resigning is on, so the games of runs without it
+        // don't depend on the fraction.
+        let resign_disabled = resign_check.is_some()
+            && rng.gen_bool(self.config.resign_disabled_fraction.clamp(0.0, 1.0));
+        let mut resigned = None;
+        let mut would_resign = None;
+
+        // Without a shared server the leaves of one search are batched on
+        // their own. The server is declared first, so its clients are dropped
+        // before it.
+        let leaf_batch = self.config.leaf_batch.max(1);
+        let server = match &network {
+            Network::Model(model) if leaf_batch > 1 => {
+                Some(InferenceServer::start(model.clone(), leaf_batch, LEAF_WAIT))
+            }
+            _ => None,
+        };
+        let network = match &server {
+            Some(server) => Network::Batched(server.client()),
+            None => network,
+        };
+        let options = SearchOptions {
+            noise: self.config.noise,
+            virtual_loss: self.config.virtual_loss,
+            input: self.config.input,
+            ..SearchOptions::new(self.config.exploration, self.config.playouts)
+                .with_threads(leaf_batch)
+        };
+
+        while !state.is_terminal() {
+            let options = SearchOptions {
+                seed: Some(rng.gen()),
+                ..options
+            };
+            let mut mcts_manager = G::create_search(state.clone(), network.clone(), options)
+                .expect("Self-play is set up for the board of the search");
+
+            let start = Instant::now();
+            options.playout(&mut mcts_manager);
+            let elapsed = start.elapsed();
+            search_time += elapsed;
+
+            let root_node = mcts_manager.tree().root_node();
+            let root_moves = root_node.moves().collect::<Vec<_>>();
+
+            histories.push((state.clone(), G::moves_to_tensorflow(root_moves.clone())));
+
+            let temperature = if moves.len() < self.config.temperature_plies {
+                self.config.temperature
+            } else {
+                0.0
+            };
+            let visits = root_moves.iter().map(|m| m.visits()).collect::<Vec<_>>();
+            let weighted_action = root_moves[pick_by_visits(&visits, temperature, &mut rng)];
+
+            let value_of = |m: &mcts::MoveInfo<G>| match m.visits() {
+                0 => 0.0,
+                visits => (m.sum_rewards() / visits as f64) as f32,
+            };
+            let visits = weighted_action.visits();
+            let value = value_of(weighted_action);
+
+            // Whether to resign goes by the move the search thinks best, not
+            // the one the temperature picked.
+            if let Some(check) = &mut resign_check {
+                let player = state.current_player();
+                let best = root_moves.iter().copied().max_by_key(|m| m.visits());
+                if check.update(&player, best.map_or(value, value_of)) {
+                    if resign_disabled {
+                        would_resign.get_or_insert(player);
+                    } else if let Some(winner) = check.opponent(&player) {
+                        resigned = Some((player, winner));
+                        break;
+                    }
+                }
+            }
+
+            debug!(
+                ply = moves.len(),
+                visits,
+                value,
+                ms = elapsed.as_millis() as u64,
+                "Chose a move"
+            );
+            stats.push(SearchStats {
+                playouts: self.config.playouts,
+                visits,
+                value,
+                time: elapsed,
+            });
+            moves.push(weighted_action.get_move().clone());
+            state.make_move(weighted_action.get_move());
+        }
+
+        let (resigned, winner) = match resigned {
+            Some((player, winner)) => (Some(player), Some(winner)),
+            None => (None, state.get_winner()),
+        };
+        info!(
+            plies = moves.len(),
+            winner = ?winner,
+            resigned = resigned.is_some(),
+            search_ms = search_time.as_millis() as u64,
+            "Played a game"
+        );
+
+        SelfPlayGame {
+            histories,
+            moves,
+            stats,
+            winner,
+            resigned,
+            would_resign,
+            seed,
+            search_time,
+        }
+    }
+
+    pub fn training_data(&self, games: &[SelfPlayGame<G>]) -> TrainingData {
+        let augment = self.config.augment;
+        let samples = games
+            .iter()
+            .flat_map(|game| {
+                game.histories.iter().flat_map(move |(state, policy)| {
+                    // catzero trains a single value, the expected score of
+                    // the outcome. The exported samples spread it back into
+                    // the win, draw and loss target.
+                    let value = Wdl::outcome(game.winner.as_ref(), &state.current_player()).value();
+                    let symmetries = if augment {
+                        state.symmetries(policy)
+                    } else {
+                        Vec::new()
+                    };
+                    std::iter::once((state.clone(), policy_planes(policy), value)).chain(
+                        symmetries
+                            .into_iter()
+                            .map(move |(state, policy)| (state, policy_planes(&policy), value)),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut inputs: Vec<Tensor<u8>> = Vec::with_capacity(samples.len());
+        let mut output_policy: Vec<Tensor<f32>> = Vec::with_capacity(samples.len());
+        let mut output_value: Vec<f32> = Vec::with_capacity(samples.len());
+        for (state, policy, value) in samples {
+            inputs.push(state.planes(self.config.input));
+            output_policy.push(policy);
+            output_value.push(value);
+        }
+
+        assert!(inputs.len() == output_policy.len());
+        assert!(inputs.len() == output_value.len());
+
+        TrainingData {
+            inputs,
+            output_policy,
+            output_value,
+        }
+    }
+
+    // The auxiliary points targets of the samples of `training_data`, in
+    // the same order, when the config has a points horizon.
+    pub fn points_targets(&self, games: &[SelfPlayGame<G>]) -> Option<Vec<[f32; 2]>> {
+        let horizon = self.config.points_horizon?;
+        let mut targets = Vec::new();
+        for game in games {
+            for ((state, policy), target) in game.histories.iter().zip(game.points_ahead(horizon)) {
+                // The symmetries of a state have the same points.
+                let copies = if self.config.augment {
+                    1 + state.symmetries(policy).len()
+                } else {
+                    1
+                };
+                targets.resize(targets.len() + copies, target);
+            }
+        }
+        Some(targets)
+    }
+
+    // Scores the samples in the buffer by how far the value of the model is
+    // off, when they are drawn by value error. The samples are evaluated on
+    // all threads. The samples of the coming episode start with the highest
+    // priority.
+    pub fn update_priorities(&mut self, model: &TFModel) {
+        let by_value_error = matches!(
+            self.config.prioritized,
+            Some(PrioritizedSampling {
+                priority: Priority::ValueError,
+                ..
+            })
+        );
+        if !by_value_error {
+            return;
+        }
+
+        let input = self.config.input;
+        self.buffer.set_priorities(|samples| {
+            samples
+                .par_iter()
+                .map(|&(sample, target)| {
+                    let input = G::State::sample_input(sample, input)
+                        .unwrap_or_else(|e| panic!("Could not read sample: {}", e));
+                    let (_, value) = model.evaluate(input).expect("Could not evaluate sample");
+                    (value - target).abs() as f64
+                })
+                .collect()
+        });
+    }
+
+    // Trains on the replay buffer and on `stored_samples` samples of the
+    // store, when there is one.
+    pub fn train(
+        &mut self,
+        python_model: &mut CatZeroModel,
+        games: &[SelfPlayGame<G>],
+        store: Option<&SampleStore>,
+    ) {
+        self.buffer.push(self.training_data(games));
+        let mut rng = self.next_rng();
+        let mut data = self
+            .buffer
+            .sample(&mut rng)
+            .expect("Replay buffer is empty")
+            .weighted(&mut rng);
+        if let (Some(store), true) = (store, self.config.stored_samples > 0) {
+            let stored = store.sample(self.config.stored_samples, &mut rng);
+            data.inputs.extend(stored.inputs);
+            data.output_policy.extend(stored.output_policy);
+            data.output_value.extend(stored.output_value);
+        }
+
+        std::iter::repeat_with(|| {
+            python_model.learn(&data, self.config.batch_size, self.config.epochs)
+        })
+        .take(self.config.learn_retries)
+        .find(|a| match a {
+            Ok(_) => {
+                info!(samples = data.len(), "Learned an episode");
+                true
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed learning");
+                false
+            }
+        })
+        .expect("Could not learn after retries")
+        .unwrap();
+    }
+
+    // Plays the candidate against the best model and tells whether it should
+    // replace it. With a test the games are played in rounds of one game
+    // per thread, until the test decides or the games run out.
+    pub fn gate(&self, candidate: Arc<TFModel>, best: Arc<TFModel>) -> (ArenaResult, bool) {
+        let sprt = match self.config.sprt {
+            Some(sprt) => sprt,
+            None => {
+                let result = self.arena(candidate, best);
+                return (result, result.promotes(self.config.promotion_score));
+            }
+        };
+
+        // Even rounds, so both models start as often.
+        let round = rayon::current_num_threads().next_multiple_of(2);
+        let mut result = ArenaResult::default();
+        while result.games() < self.config.arena_games {
+            let games = result.games()..(result.games() + round).min(self.config.arena_games);
+            let played = self.arena_games(candidate.clone(), best.clone(), games);
+            result.wins += played.wins;
+            result.draws += played.draws;
+            result.losses += played.losses;
+            match sprt.test(&result) {
+                SprtDecision::Promote => return (result, true),
+                SprtDecision::Reject => return (result, false),
+                SprtDecision::Continue => {}
+            }
+        }
+        (result, result.promotes(self.config.promotion_score))
+    }
+
+    // Plays the candidate against the best model, both playing first in
+    // half of the games. Moves are picked greedily by visit count. With fewer
+    // games than threads the searches share the threads that are left.
+    pub fn arena(&self, candidate: Arc<TFModel>, best: Arc<TFModel>) -> ArenaResult {
+        self.arena_games(candidate, best, 0..self.config.arena_games)
+    }
+
+    // The games of the arena match with the given numbers, the candidate
+    // starts the even ones.
+    fn arena_games(
+        &self,
+        candidate: Arc<TFModel>,
+        best: Arc<TFModel>,
+        games: Range<usize>,
+    ) -> ArenaResult {
+        let threads = rayon::current_num_threads() / games.len().max(1);
+        let options = SearchOptions {
+            virtual_loss: self.config.virtual_loss,
+            input: self.config.input,
+            ..SearchOptions::new(self.config.exploration, self.config.playouts)
+                .with_threads(threads)
+        };
+        games
+            .into_par_iter()
+            .map(|i| {
+                let candidate_starts = i % 2 == 0;
+                let mut state = self.start.clone();
+                let first_player = state.current_player();
+
+                let mut candidate_turn = candidate_starts;
+                while !state.is_terminal() {
+                    let model = if candidate_turn {
+                        candidate.clone()
+                    } else {
+                        best.clone()
+                    };
+                    let mut manager =
+                        G::create_search(state.clone(), Network::Model(model), options)
+                            .expect("Arena games are set up for the board of the search");
+                    options.playout(&mut manager);
+                    let mov = manager.best_move().expect("No move found");
+                    state.make_move(&mov);
+                    candidate_turn = !candidate_turn;
+                }
+
+                state
+                    .get_winner()
+                    .map(|winner| (winner == first_player) == candidate_starts)
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .fold(ArenaResult::default(), |mut result, won| {
+                match won {
+                    Some(true) => result.wins += 1,
+                    Some(false) => result.losses += 1,
+                    None => result.draws += 1,
+                }
+                result
+            })
+    }
+}
+
+// Index of the move to play given the visits of every move. A temperature of
+// zero picks the most visited move, higher temperatures flatten the odds.
+fn pick_by_visits<R: Rng + ?Sized>(visits: &[u64], temperature: f64, rng: &mut R) -> usize {
+    if temperature <= 0.0 {
+        return (0..visits.len())
+            .max_by_key(|&i| visits[i])
+            .expect("Could not get a random action");
+    }
+    // Relative to the most visited move, so low temperatures can't overflow
+    // the weights. Without any visits every move is as likely.
+    let most = visits.iter().copied().max().unwrap_or(0);
+    if most == 0 {
+        return rng.gen_range(0..visits.len());
+    }
+    let weights = visits
+        .iter()
+        .map(|&v| (v as f64 / most as f64).powf(1.0 / temperature));
+    WeightedIndex::new(weights)
+        .expect("Could not get a random action")
+        .sample(rng)
+}
+
+// Reshapes a [1, planes, width, height] policy tensor into planes indexed
+// [plane][x][y], the order of `planes.rs`.
+fn policy_planes(tensor: &tensorflow::Tensor<f32>) -> Tensor<f32> {
+    let dims = tensor.dims();
+    let (width, height) = (dims[2] as usize, dims[3] as usize);
+    tensor
+        .chunks(width * height)
+        .map(|plane| {
+            plane
+                .chunks(height)
+                .map(|column| column.to_vec())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        action::{BoardAction, Column},
+        alphazero::MyMCTS,
+        player::Player,
+        testing::StateBuilder,
+        BoardState,
+    };
+    use mcts::GameState;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{pick_by_visits, ArenaResult, Pipeline, PipelineConfig, ResignCheck, SelfPlayGame};
+
+    #[test]
+    fn temperature() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let visits = [10, 30, 20];
+
+        assert_eq!(pick_by_visits(&visits, 0.0, &mut rng), 1);
+        assert_eq!(pick_by_visits(&[0, 0, 4], 1.0, &mut rng), 2);
+        assert_eq!(pick_by_visits(&[0, 100_000, 99_000], 0.001, &mut rng), 1);
+        assert!(pick_by_visits(&[0, 0, 0], 1.0, &mut rng) < 3);
+
+        let mut picked = |temperature| {
+            let mut counts = [0; 3];
+            for _ in 0..1000 {
+                counts[pick_by_visits(&visits, temperature, &mut rng)] += 1;
+            }
+            counts
+        };
+        let sampled = picked(1.0);
+        assert!(sampled.iter().all(|&c| c > 0));
+        assert!(picked(0.25)[1] > sampled[1]);
+    }
+
+    #[test]
+    fn seeded_runs() {
+        let config = PipelineConfig {
+            games_per_episode: 4,
+            seed: Some(9),
+            ..Default::default()
+        };
+        let mut first = Pipeline::<MyMCTS>::new(config.clone());
+        let mut second = Pipeline::<MyMCTS>::new(config);
+        let seeds = first.episode_seeds();
+        assert_eq!(seeds, second.episode_seeds());
+        assert_eq!(first.seed(), second.seed());
+        assert_ne!(seeds, first.episode_seeds());
+    }
+
+    #[test]
+    fn promotion() {
+        let result = ArenaResult {
+            wins: 5,
+            draws: 2,
+            losses: 3,
+        };
+        assert!(result.promotes(0.55));
+        assert!(!result.promotes(0.6));
+        assert!(!ArenaResult::default().promotes(0.0));
+    }
+
+    #[test]
+    fn resign_check() {
+        let mut check = ResignCheck::new(-0.9, 2);
+        assert!(!check.update(&Player::Player1, -0.95));
+        assert_eq!(check.opponent(&Player::Player1), None);
+        assert!(!check.update(&Player::Player2, 0.95));
+        assert!(!check.update(&Player::Player1, -0.5));
+        assert!(!check.update(&Player::Player2, 0.9));
+        assert!(!check.update(&Player::Player1, -0.92));
+        assert!(!check.update(&Player::Player2, 0.9));
+        assert!(check.update(&Player::Player1, -0.99));
+        assert_eq!(check.opponent(&Player::Player1), Some(Player::Player2));
+    }
+
+    #[test]
+    fn augmented_training_data() {
+        let mut state = BoardState::default();
+        state.make_move(&BoardAction::DropStone(
+            Player::Player1,
+            Column::new(0).unwrap(),
+        ));
+        let mut policy = tensorflow::Tensor::new(&[1, 3, 8, 8]);
+        policy.set(&[0, 0, 1, 0], 1.0);
+
+        let games = [SelfPlayGame::<MyMCTS> {
+            histories: vec![(state, policy)],
+            moves: Vec::new(),
+            stats: Vec::new(),
+            winner: Some(Player::Player2),
+            resigned: None,
+            would_resign: None,
+            seed: 0,
+            search_time: Duration::ZERO,
+        }];
+
+        let plain = Pipeline::<MyMCTS>::new(PipelineConfig::default()).training_data(&games);
+        assert_eq!(plain.inputs.len(), 1);
+
+        let augmented = Pipeline::<MyMCTS>::new(PipelineConfig {
+            augment: true,
+            ..Default::default()
+        })
+        .training_data(&games);
+        assert_eq!(augmented.inputs.len(), 2);
+        assert_eq!(augmented.output_value, vec![1.0, 1.0]);
+        assert_eq!(augmented.inputs[1][1][7][0], 1);
+        assert_eq!(augmented.output_policy[1][0][6][0], 1.0);
+    }
+
+    #[test]
+    fn points_targets() {
+        let start = StateBuilder::new()
+            .points(Player::Player1, 2)
+            .points(Player::Player2, 1)
+            .build();
+        let mov = BoardAction::DropStone(Player::Player1, Column::new(0).unwrap());
+        let mut next = start.clone();
+        next.make_move(&mov);
+        let policy = tensorflow::Tensor::new(&[1, 3, 8, 8]);
+
+        let games = [SelfPlayGame::<MyMCTS> {
+            histories: vec![(start, policy.clone()), (next, policy)],
+            moves: vec![mov],
+            stats: Vec::new(),
+            winner: None,
+            resigned: Some(Player::Player2),
+            would_resign: None,
+            seed: 0,
+            search_time: Duration::ZERO,
+        }];
+        // Seen by the player to move.
+        assert_eq!(games[0].points_ahead(5), vec![[2.0, 1.0], [1.0, 2.0]]);
+
+        let pipeline = Pipeline::<MyMCTS>::new(PipelineConfig::default());
+        assert_eq!(pipeline.points_targets(&games), None);
+        let pipeline = Pipeline::<MyMCTS>::new(PipelineConfig {
+            augment: true,
+            points_horizon: Some(1),
+            ..Default::default()
+        });
+        let targets = pipeline.points_targets(&games).unwrap();
+        assert_eq!(targets.len(), pipeline.training_data(&games).len());
+        assert_eq!(targets[0], [2.0, 1.0]);
+    }
+}
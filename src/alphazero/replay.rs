@@ -0,0 +1,401 @@
+use std::{collections::VecDeque, ops::Range};
+
+use catzero::{Tensor, TrainingData};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    seq::SliceRandom,
+    Rng,
+};
+use tracing::warn;
+
+// What makes a sample worth drawing more often.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Priority {
+    // Every episode weighs `decay` times the one after it.
+    Recency { decay: f64 },
+    // How far the value head was off, see `ReplayBuffer::set_priorities`.
+    ValueError,
+}
+
+// Draws samples by their priority instead of taking every sample once.
+// Samples are drawn with P(i) = priority^alpha, normalized, and corrected by
+// the importance weights (n * P(i))^-beta. catzero's loss takes no weights,
+// so the two are folded into a single draw with probability P(i) * (n *
+// P(i))^-beta: at a beta of zero purely by priority, at one uniform again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrioritizedSampling {
+    pub priority: Priority,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+// Samples drawn from the buffer, with the weight each is drawn by.
+pub struct Batch {
+    pub data: TrainingData,
+    pub weights: Vec<f32>,
+}
+
+impl Batch {
+    // catzero's loss takes no sample weights, so the weights go into the
+    // draw instead: as many samples as the batch has are drawn from it, each
+    // with a probability of its weight. A sample then counts in the loss as
+    // often as its weight says. Equal weights leave the batch as it is, so
+    // every sample is still taken once.
+    pub fn weighted<R: Rng + ?Sized>(self, rng: &mut R) -> TrainingData {
+        let data = self.data;
+        if self.weights.windows(2).all(|w| w[0] == w[1]) {
+            return data;
+        }
+        let distribution = match WeightedIndex::new(&self.weights) {
+            Ok(distribution) => distribution,
+            // No weights or all of them zero, nothing to correct.
+            Err(_) => return data,
+        };
+        let picked = (0..data.len())
+            .map(|_| distribution.sample(rng))
+            .collect::<Vec<_>>();
+
+        TrainingData {
+            inputs: picked.iter().map(|&i| data.inputs[i].clone()).collect(),
+            output_policy: picked
+                .iter()
+                .map(|&i| data.output_policy[i].clone())
+                .collect(),
+            output_value: picked.iter().map(|&i| data.output_value[i]).collect(),
+        }
+    }
+}
+
+// Samples that haven't been scored yet get the highest priority so far, and
+// no sample gets a priority of zero.
+const MIN_PRIORITY: f64 = 1e-3;
+
+// Holds the training samples of the last `window` episodes, the next
+// learning step is done on all of them together.
+pub struct ReplayBuffer {
+    episodes: VecDeque<(TrainingData, Vec<f64>)>,
+    window: usize,
+    sampling: Option<PrioritizedSampling>,
+}
+
+impl ReplayBuffer {
+    pub fn new(window: usize) -> Self {
+        Self {
+            episodes: VecDeque::new(),
+            window: window.max(1),
+            sampling: None,
+        }
+    }
+
+    pub fn with_sampling(mut self, sampling: Option<PrioritizedSampling>) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    // Adds the samples of the newest episode, dropping the oldest episode
+    // once the window is full.
+    pub fn push(&mut self, data: TrainingData) {
+        let priority = self
+            .episodes
+            .iter()
+            .flat_map(|(_, priorities)| priorities.iter().copied())
+            .fold(1.0, f64::max);
+        let priorities = vec![priority; data.len()];
+        self.episodes.push_back((data, priorities));
+        while self.episodes.len() > self.window {
+            self.episodes.pop_front();
+        }
+    }
+
+    // Scores all samples at once from their inputs and value targets, so
+    // they can be evaluated together. `priorities` answers in the order of
+    // the samples.
+    pub fn set_priorities<F>(&mut self, priorities: F)
+    where
+        F: FnOnce(&[(&Tensor<u8>, f32)]) -> Vec<f64>,
+    {
+        let samples = self
+            .episodes
+            .iter()
+            .flat_map(|(data, _)| data.inputs.iter().zip(data.output_value.iter().copied()))
+            .collect::<Vec<_>>();
+        let scores = priorities(&samples);
+        assert_eq!(scores.len(), samples.len(), "A priority for every sample");
+
+        let mut scores = scores.into_iter();
+        for (_, priorities) in &mut self.episodes {
+            for (p, score) in priorities.iter_mut().zip(&mut scores) {
+                *p = score.max(MIN_PRIORITY);
+            }
+        }
+    }
+
+    pub fn episodes(&self) -> usize {
+        self.episodes.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.episodes.iter().map(|(data, _)| data.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Priority of every sample, oldest episode first.
+    pub fn priorities(&self) -> Vec<f64> {
+        self.episodes
+            .iter()
+            .flat_map(|(_, priorities)| priorities.iter().copied())
+            .collect()
+    }
+
+    // Puts back priorities taken with `priorities`, they must be as many as
+    // the buffer holds samples.
+    pub fn restore_priorities(&mut self, restored: &[f64]) -> Result<(), String> {
+        if restored.len() != self.len() {
+            return Err(format!(
+                "{} priorities for {} samples",
+                restored.len(),
+                self.len()
+            ));
+        }
+        let mut restored = restored.iter();
+        for (_, priorities) in &mut self.episodes {
+            for (p, restored) in priorities.iter_mut().zip(&mut restored) {
+                *p = *restored;
+            }
+        }
+        Ok(())
+    }
+
+    // Fills the buffer with the saved samples of the episodes, as far as
+    // they fit the window. Episodes that can't be loaded are skipped.
+    pub fn load_saved<F: Fn(usize) -> String>(&mut self, episodes: Range<usize>, path: F) {
+        let start = episodes.start.max(episodes.end.saturating_sub(self.window));
+        for previous in start..episodes.end {
+            match TrainingData::load(&path(previous)) {
+                Ok(data) => self.push(data),
+                Err(e) => warn!(episode = previous, error = %e, "Did not load games"),
+            }
+        }
+    }
+
+    // Every sample the buffer holds once, in a random order so the batches
+    // mix the episodes. Without prioritized sampling all weights are one,
+    // with it they are the probabilities to draw them by, scaled to be at
+    // most one. See `Batch::weighted`.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Batch> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let samples = self
+            .episodes
+            .iter()
+            .enumerate()
+            .flat_map(|(index, (data, priorities))| {
+                let age = (self.episodes.len() - 1 - index) as i32;
+                (0..data.len()).map(move |i| (data, i, age, priorities[i]))
+            })
+            .collect::<Vec<_>>();
+
+        let mut picked = (0..samples.len()).collect::<Vec<_>>();
+        picked.shuffle(rng);
+        let weights = match self.sampling {
+            None => vec![1.0; samples.len()],
+            Some(sampling) => {
+                let probabilities = samples
+                    .iter()
+                    .map(|&(_, _, age, priority)| match sampling.priority {
+                        Priority::Recency { decay } => decay.powi(age).max(MIN_PRIORITY),
+                        Priority::ValueError => priority,
+                    })
+                    .map(|p| p.powf(sampling.alpha))
+                    .collect::<Vec<_>>();
+                let total = probabilities.iter().sum::<f64>();
+                let n = samples.len() as f64;
+                let weight = |i: usize| {
+                    let p = probabilities[i] / total;
+                    p * (n * p).powf(-sampling.beta)
+                };
+                let max_weight = (0..samples.len()).map(weight).fold(0.0, f64::max);
+                picked
+                    .iter()
+                    .map(|&i| (weight(i) / max_weight) as f32)
+                    .collect()
+            }
+        };
+
+        Some(Batch {
+            data: TrainingData {
+                inputs: picked
+                    .iter()
+                    .map(|&i| samples[i].0.inputs[samples[i].1].clone())
+                    .collect(),
+                output_policy: picked
+                    .iter()
+                    .map(|&i| samples[i].0.output_policy[samples[i].1].clone())
+                    .collect(),
+                output_value: picked
+                    .iter()
+                    .map(|&i| samples[i].0.output_value[samples[i].1])
+                    .collect(),
+            },
+            weights,
+        })
+    }
+
+    pub fn training_data<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<TrainingData> {
+        self.sample(rng).map(|batch| batch.data)
+    }
+}
+
+impl Default for ReplayBuffer {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use catzero::TrainingData;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{Batch, PrioritizedSampling, Priority, ReplayBuffer};
+
+    fn episode(value: f32, samples: usize) -> TrainingData {
+        TrainingData {
+            inputs: vec![vec![vec![vec![0]]]; samples],
+            output_policy: vec![vec![vec![vec![value]]]; samples],
+            output_value: vec![value; samples],
+        }
+    }
+
+    #[test]
+    fn keeps_last_episodes() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut buffer = ReplayBuffer::new(2);
+        assert!(buffer.training_data(&mut rng).is_none());
+
+        buffer.push(episode(0.0, 3));
+        buffer.push(episode(1.0, 2));
+        buffer.push(episode(2.0, 1));
+        assert_eq!(buffer.episodes(), 2);
+        assert_eq!(buffer.len(), 3);
+
+        let data = buffer.training_data(&mut rng).unwrap();
+        let mut values = data.output_value.clone();
+        values.sort_by(f32::total_cmp);
+        assert_eq!(values, vec![1.0, 1.0, 2.0]);
+        for (policy, value) in data.output_policy.iter().zip(&data.output_value) {
+            assert_eq!(policy[0][0][0], *value);
+        }
+    }
+
+    #[test]
+    fn uniform_sampling_takes_every_sample_once() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut buffer = ReplayBuffer::new(2);
+        buffer.push(episode(0.0, 3));
+        buffer.push(episode(1.0, 2));
+        for _ in 0..20 {
+            let data = buffer.sample(&mut rng).unwrap().weighted(&mut rng);
+            let mut values = data.output_value.clone();
+            values.sort_by(f32::total_cmp);
+            assert_eq!(values, vec![0.0, 0.0, 0.0, 1.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn prioritized_sampling() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut buffer = ReplayBuffer::new(2).with_sampling(Some(PrioritizedSampling {
+            priority: Priority::ValueError,
+            alpha: 1.0,
+            beta: 0.5,
+        }));
+        buffer.push(episode(0.0, 9));
+        buffer.push(episode(1.0, 1));
+        buffer.set_priorities(|samples| {
+            samples
+                .iter()
+                .map(|&(_, value)| if value > 0.5 { 9.0 } else { 1.0 })
+                .collect()
+        });
+
+        // At a beta of a half the rare sample weighs sqrt(9) times as much
+        // as each of the others, so it is drawn a quarter of the time.
+        let mut rare = 0;
+        for _ in 0..40 {
+            let batch = buffer.sample(&mut rng).unwrap();
+            assert_eq!(batch.data.len(), 10);
+            for (value, weight) in batch.data.output_value.iter().zip(&batch.weights) {
+                let expected = if *value == 1.0 { 1.0 } else { 1.0 / 3.0 };
+                assert!((weight - expected).abs() < 1e-6);
+            }
+            let drawn = batch.weighted(&mut rng);
+            assert_eq!(drawn.len(), 10);
+            rare += drawn.output_value.iter().filter(|&&v| v == 1.0).count();
+        }
+        assert!(rare > 60 && rare < 140, "{}", rare);
+
+        // At a beta of one the correction undoes the priorities.
+        let mut unbiased = ReplayBuffer::new(2).with_sampling(Some(PrioritizedSampling {
+            priority: Priority::ValueError,
+            alpha: 1.0,
+            beta: 1.0,
+        }));
+        unbiased.push(episode(0.0, 9));
+        unbiased.push(episode(1.0, 1));
+        unbiased.restore_priorities(&buffer.priorities()).unwrap();
+        let batch = unbiased.sample(&mut rng).unwrap();
+        assert!(batch.weights.iter().all(|w| (w - 1.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn restore_priorities() {
+        let mut buffer = ReplayBuffer::new(2);
+        buffer.push(episode(0.0, 2));
+        buffer.push(episode(1.0, 1));
+        buffer.set_priorities(|samples| (0..samples.len()).map(|i| i as f64 + 1.0).collect());
+        let priorities = buffer.priorities();
+        assert_eq!(priorities, vec![1.0, 2.0, 3.0]);
+
+        let mut resumed = ReplayBuffer::new(2);
+        resumed.push(episode(0.0, 2));
+        resumed.push(episode(1.0, 1));
+        assert!(resumed.restore_priorities(&priorities[1..]).is_err());
+        resumed.restore_priorities(&priorities).unwrap();
+        assert_eq!(resumed.priorities(), priorities);
+    }
+
+    // A sample of value 0 and one of value 1, the input holds the value.
+    fn pair() -> TrainingData {
+        TrainingData {
+            inputs: vec![vec![vec![vec![0]]], vec![vec![vec![1]]]],
+            output_policy: vec![vec![vec![vec![0.0]]], vec![vec![vec![1.0]]]],
+            output_value: vec![0.0, 1.0],
+        }
+    }
+
+    #[test]
+    fn weighted_draw() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut rare = 0;
+        for _ in 0..500 {
+            let batch = Batch {
+                data: pair(),
+                weights: vec![1.0, 1.0 / 9.0],
+            };
+            let drawn = batch.weighted(&mut rng);
+            assert_eq!(drawn.len(), 2);
+            for (value, input) in drawn.output_value.iter().zip(&drawn.inputs) {
+                assert_eq!(input[0][0][0] as f32, *value);
+            }
+            rare += drawn.output_value.iter().filter(|&&v| v == 1.0).count();
+        }
+        // One in ten of the draws.
+        assert!(rare > 60 && rare < 140, "{}", rare);
+    }
+}
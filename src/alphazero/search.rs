@@ -0,0 +1,101 @@
+use catzero::AlphaGame;
+use mcts::{MCTSManager, ThreadData, MCTS};
+
+use super::{evaluator::Network, noise::DirichletNoise};
+use crate::{planes::InputEncoding, ponder, timecontrol::Budget};
+
+// How a network-backed search is set up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchOptions {
+    pub exploration: f64,
+    pub playouts: usize,
+    // Noise at the root, for self-play only.
+    pub noise: Option<DirichletNoise>,
+    // Seeds the noise, from entropy without it.
+    pub seed: Option<u64>,
+    // Threads descending the tree at the same time.
+    pub threads: usize,
+    // Counted as a loss for every thread that is still below a node, so
+    // parallel playouts spread over the tree instead of all taking the same
+    // path. Only used with more than one thread.
+    pub virtual_loss: i64,
+    // How the network the search uses was trained to see a position.
+    pub input: InputEncoding,
+    // Playouts a search on the opponent's time runs at most, see ponder.rs.
+    pub ponder_playouts: usize,
+}
+
+impl SearchOptions {
+    pub fn new(exploration: f64, playouts: usize) -> Self {
+        Self {
+            exploration,
+            playouts,
+            noise: None,
+            seed: None,
+            threads: 1,
+            virtual_loss: 1,
+            input: InputEncoding::default(),
+            ponder_playouts: ponder::PLAYOUTS,
+        }
+    }
+
+    pub fn with_threads(self, threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            ..self
+        }
+    }
+
+    // The encoding the model was trained with, see ModelRegistry::input.
+    pub fn with_input(self, input: InputEncoding) -> Self {
+        Self { input, ..self }
+    }
+
+    // The virtual loss the search should use with these options.
+    pub fn effective_virtual_loss(&self) -> i64 {
+        if self.threads > 1 {
+            self.virtual_loss
+        } else {
+            0
+        }
+    }
+
+    // Runs all playouts on the manager, see Budget::search.
+    pub fn playout<G: MCTS>(&self, manager: &mut MCTSManager<G>)
+    where
+        ThreadData<G>: Default,
+    {
+        Budget::Playouts(self.playouts).search(manager, self.threads);
+    }
+}
+
+// Games whose search can be set up beyond what AlphaGame allows, like
+// self-play with root noise and parallel searches.
+pub trait NetworkSearch: AlphaGame {
+    // Whether the state can be searched, the policy planes fix the board
+    // size and the switch rule.
+    fn check(state: &Self::State) -> Result<(), String>;
+
+    // Fails for the states `check` rejects.
+    fn create_search(
+        state: Self::State,
+        network: Network,
+        options: SearchOptions,
+    ) -> Result<MCTSManager<Self>, String>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SearchOptions;
+
+    #[test]
+    fn virtual_loss_needs_threads() {
+        let options = SearchOptions::new(1.4, 100);
+        assert_eq!(options.effective_virtual_loss(), 0);
+
+        let parallel = options.with_threads(4);
+        assert_eq!(parallel.threads, 4);
+        assert_eq!(parallel.effective_virtual_loss(), 1);
+        assert_eq!(options.with_threads(0).threads, 1);
+    }
+}
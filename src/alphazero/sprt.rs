@@ -0,0 +1,122 @@
+// Sequential probability ratio test for the arena. The candidate plays the
+// best model until the games tell apart, with the chosen error rates, that
+// it is `elo1` stronger (promote) from that it is at most `elo0` stronger
+// (keep the best model). A clearly better or worse candidate is decided in
+// a few games, only close matches play up to the game limit.
+//
+// The log-likelihood ratio is the normal approximation of the generalized
+// SPRT over the per-game scores 1, 1/2 and 0.
+use super::pipeline::ArenaResult;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    // Elo difference of the hypothesis that the candidate is no better, and
+    // of the one that it is better.
+    pub elo0: f64,
+    pub elo1: f64,
+    // Chances of promoting a candidate that is no better and of keeping the
+    // best model when the candidate is better.
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        Self {
+            elo0: 0.0,
+            elo1: 35.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtDecision {
+    Promote,
+    Reject,
+    Continue,
+}
+
+impl Sprt {
+    // The ratio at or below which the test rejects and at or above which it
+    // promotes.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    // Half a game of every outcome is added, so a streak of wins or losses
+    // has a variance and the early games weigh a little less.
+    pub fn llr(&self, result: &ArenaResult) -> f64 {
+        let (wins, draws, losses) = (
+            result.wins as f64 + 0.5,
+            result.draws as f64 + 0.5,
+            result.losses as f64 + 0.5,
+        );
+        let games = wins + draws + losses;
+        let mean = (wins + 0.5 * draws) / games;
+        let variance =
+            (wins * (1.0 - mean).powi(2) + draws * (0.5 - mean).powi(2) + losses * mean.powi(2))
+                / games;
+
+        let (score0, score1) = (expected_score(self.elo0), expected_score(self.elo1));
+        games * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+
+    pub fn test(&self, result: &ArenaResult) -> SprtDecision {
+        if result.games() == 0 {
+            return SprtDecision::Continue;
+        }
+        let (lower, upper) = self.bounds();
+        let llr = self.llr(result);
+        if llr >= upper {
+            SprtDecision::Promote
+        } else if llr <= lower {
+            SprtDecision::Reject
+        } else {
+            SprtDecision::Continue
+        }
+    }
+}
+
+// Score per game of a player that is `elo` stronger, as in elo.rs.
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alphazero::pipeline::ArenaResult;
+
+    use super::{Sprt, SprtDecision};
+
+    fn result(wins: usize, draws: usize, losses: usize) -> ArenaResult {
+        ArenaResult {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    #[test]
+    fn decisions() {
+        let sprt = Sprt::default();
+        let (lower, upper) = sprt.bounds();
+        assert!((upper - 19f64.ln()).abs() < 1e-9);
+        assert!((lower + 19f64.ln()).abs() < 1e-9);
+
+        assert_eq!(sprt.test(&ArenaResult::default()), SprtDecision::Continue);
+        assert_eq!(sprt.test(&result(3, 1, 0)), SprtDecision::Continue);
+        assert_eq!(sprt.test(&result(20, 0, 0)), SprtDecision::Promote);
+        assert_eq!(sprt.test(&result(0, 2, 20)), SprtDecision::Reject);
+        assert_eq!(sprt.test(&result(10, 2, 10)), SprtDecision::Continue);
+        assert_eq!(sprt.test(&result(400, 40, 400)), SprtDecision::Reject);
+
+        // More of the same evidence moves the ratio further.
+        assert!(sprt.llr(&result(12, 2, 6)) > sprt.llr(&result(6, 1, 3)));
+        assert!(sprt.llr(&result(6, 1, 3)) > 0.0);
+    }
+}
@@ -0,0 +1,405 @@
+// HTTP service to play games against the engine, so web and mobile front
+// ends can play without linking Rust. Bodies are JSON, the games are kept in
+// memory:
+//
+// POST   /games                 {"rules": "size=7x6"}   starts a game, the rules may be left out
+// GET    /games/<id>                                    the game
+// GET    /games/<id>/moves                              its legal actions
+// POST   /games/<id>/moves      {"move": "d3"}          plays an action
+// POST   /games/<id>/reply      {"movetime": 500}       lets the engine play, or {"playouts": n}
+// DELETE /games/<id>
+//
+// Every answer is the game as json::state_json with its "id", after an
+// action also the "move" and the cascade "steps" it set off. Failures are
+// answered with {"error": "..."} and a 4xx status. Games with rules the
+// engine can't play are refused when they are started.
+//
+// A few threads answer the requests, while the engine searches for one game
+// the others are still read and played on.
+use std::{
+    collections::BTreeMap,
+    io::{self, Read},
+    sync::{Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{debug, info, warn};
+
+use crate::{
+    action::BoardAction,
+    agent::Agent,
+    json::{state_json, step_json},
+    rules::GameRules,
+    timecontrol::SearchLimit,
+    BoardState,
+};
+use mcts::GameState;
+
+// The oldest games are dropped beyond this, nobody ends a game over HTTP.
+const MAX_GAMES: usize = 1000;
+// Most a request may let the engine search.
+const MAX_MOVETIME: Duration = Duration::from_secs(10);
+const MAX_PLAYOUTS: usize = 200_000;
+// Every valid body is a few bytes, longer ones are refused unread.
+const MAX_BODY: usize = 4096;
+const WORKERS: usize = 4;
+
+type Failure = (u16, String);
+
+struct Games {
+    states: BTreeMap<u64, BoardState>,
+    next_id: u64,
+}
+
+pub struct GameApi {
+    engine: Mutex<Box<dyn Agent>>,
+    // How long the engine searches when the request does not say.
+    limit: SearchLimit,
+    games: Mutex<Games>,
+}
+
+impl GameApi {
+    pub fn new(engine: Box<dyn Agent>, limit: SearchLimit) -> Self {
+        Self {
+            engine: Mutex::new(engine),
+            limit,
+            games: Mutex::new(Games {
+                states: BTreeMap::new(),
+                next_id: 1,
+            }),
+        }
+    }
+
+    // The status and body of the answer to a request.
+    pub fn handle(&self, method: &Method, url: &str, body: &str) -> (u16, Value) {
+        match self.route(method, url, body) {
+            Ok(answer) => (200, answer),
+            Err((status, error)) => (status, json!({ "error": error })),
+        }
+    }
+
+    fn route(&self, method: &Method, url: &str, body: &str) -> Result<Value, Failure> {
+        let path = url.split('?').next().unwrap_or_default();
+        let parts = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        match (method, parts.as_slice()) {
+            (Method::Post, ["games"]) => self.create(&parse_body(body)?),
+            (Method::Get, ["games", id]) => {
+                let games = self.games();
+                let (id, state) = find(&games, id)?;
+                Ok(game_json(id, state))
+            }
+            (Method::Get, ["games", id, "moves"]) => {
+                let games = self.games();
+                let (id, state) = find(&games, id)?;
+                Ok(json!({ "id": id, "legal_moves": state_json(state)["legal_moves"] }))
+            }
+            (Method::Post, ["games", id, "moves"]) => {
+                let body = parse_body(body)?;
+                let action = body["move"]
+                    .as_str()
+                    .ok_or_else(|| (400, String::from("Missing move")))?;
+                let mut games = self.games();
+                let (id, _) = find(&games, id)?;
+                play(&mut games, id, action)
+            }
+            (Method::Post, ["games", id, "reply"]) => self.reply(id, &parse_body(body)?),
+            (Method::Delete, ["games", id]) => {
+                let mut games = self.games();
+                let (id, _) = find(&games, id)?;
+                games.states.remove(&id);
+                Ok(json!({ "id": id }))
+            }
+            _ => Err((404, format!("Not found: {} {}", method, path))),
+        }
+    }
+
+    fn games(&self) -> MutexGuard<'_, Games> {
+        self.games.lock().expect("Games lock poisoned")
+    }
+
+    fn engine(&self) -> MutexGuard<'_, Box<dyn Agent>> {
+        self.engine.lock().expect("Engine lock poisoned")
+    }
+
+    fn create(&self, body: &Value) -> Result<Value, Failure> {
+        let rules = match body["rules"].as_str() {
+            Some(rules) => {
+                GameRules::parse(rules).ok_or_else(|| (400, format!("Bad rules: {}", rules)))?
+            }
+            None => GameRules::default(),
+        };
+        self.engine()
+            .supports(&rules)
+            .map_err(|e| (400, format!("The engine can't play {}: {}", rules, e)))?;
+
+        let mut games = self.games();
+        if games.states.len() >= MAX_GAMES {
+            games.states.pop_first();
+        }
+        let id = games.next_id;
+        games.next_id += 1;
+        games.states.insert(id, BoardState::new(rules));
+        info!(id, %rules, "Started game");
+        Ok(game_json(id, &games.states[&id]))
+    }
+
+    // The engine searches a copy of the game, which may be played on or
+    // deleted in the meantime.
+    fn reply(&self, id: &str, body: &Value) -> Result<Value, Failure> {
+        let limit = match (body["movetime"].as_u64(), body["playouts"].as_u64()) {
+            (Some(ms), _) => SearchLimit::MoveTime(Duration::from_millis(ms).min(MAX_MOVETIME)),
+            (None, Some(playouts)) => SearchLimit::Playouts((playouts as usize).min(MAX_PLAYOUTS)),
+            (None, None) => self.limit,
+        };
+        let (id, state) = {
+            let games = self.games();
+            let (id, state) = find(&games, id)?;
+            (id, state.clone())
+        };
+        if state.is_terminal() {
+            return Err((409, String::from("The game is over")));
+        }
+
+        let mov = {
+            let mut engine = self.engine();
+            engine.set_limit(limit);
+            engine
+                .choose(&state)
+                .ok_or_else(|| (500, String::from("The engine found no move")))?
+        };
+        debug!(id, %mov, "Engine replied");
+
+        let mut games = self.games();
+        match games.states.get(&id) {
+            Some(current) if current.history().eq(state.history()) => {
+                play(&mut games, id, &mov.to_string())
+            }
+            Some(_) => Err((
+                409,
+                String::from("The game was played on during the search"),
+            )),
+            None => Err((404, format!("No game {}", id))),
+        }
+    }
+}
+
+fn find<'a>(games: &'a Games, id: &str) -> Result<(u64, &'a BoardState), Failure> {
+    id.parse()
+        .ok()
+        .and_then(|id| games.states.get(&id).map(|state| (id, state)))
+        .ok_or_else(|| (404, format!("No game {}", id)))
+}
+
+fn play(games: &mut Games, id: u64, action: &str) -> Result<Value, Failure> {
+    let state = games.states.get_mut(&id).expect("Game was found");
+    let mov = BoardAction::parse(action, state.current_player())
+        .ok_or_else(|| (400, format!("Could not read {}", action)))?;
+    state
+        .check_move(&mov)
+        .map_err(|e| (400, format!("Can't play {}: {}", action, e)))?;
+
+    let steps = state
+        .play_steps(&mov)
+        .iter()
+        .map(step_json)
+        .collect::<Vec<_>>();
+    let mut answer = game_json(id, state);
+    answer["move"] = json!(mov.to_string());
+    answer["steps"] = Value::Array(steps);
+    Ok(answer)
+}
+
+fn parse_body(body: &str) -> Result<Value, Failure> {
+    if body.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    serde_json::from_str(body).map_err(|e| (400, format!("Bad JSON: {}", e)))
+}
+
+fn game_json(id: u64, state: &BoardState) -> Value {
+    let mut game = state_json(state);
+    game["id"] = json!(id);
+    game
+}
+
+// Answers requests on WORKERS threads until the server fails.
+pub fn serve(api: &GameApi, address: &str) -> io::Result<()> {
+    let server = Server::http(address).map_err(io::Error::other)?;
+    info!(address, "Serving games");
+
+    thread::scope(|scope| {
+        let workers = (0..WORKERS)
+            .map(|_| scope.spawn(|| answer_requests(api, &server)))
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("Worker panicked"))
+            .collect()
+    })
+}
+
+fn answer_requests(api: &GameApi, server: &Server) -> io::Result<()> {
+    let header =
+        |name: &str, value: &str| Header::from_bytes(name, value).expect("Could not create header");
+    // Front ends are usually served from another origin.
+    let cors = [
+        header("Access-Control-Allow-Origin", "*"),
+        header("Access-Control-Allow-Methods", "GET, POST, DELETE"),
+        header("Access-Control-Allow-Headers", "Content-Type"),
+    ];
+
+    loop {
+        let mut request = server.recv()?;
+        let (status, answer) = if request.method() == &Method::Options {
+            (204, Value::Null)
+        } else {
+            match read_body(&mut request) {
+                Ok(body) => api.handle(request.method(), request.url(), &body),
+                Err((status, error)) => (status, json!({ "error": error })),
+            }
+        };
+        debug!(method = %request.method(), url = request.url(), status, "Answered");
+
+        let mut response = match answer {
+            Value::Null => Response::from_string(""),
+            answer => Response::from_string(answer.to_string())
+                .with_header(header("Content-Type", "application/json")),
+        }
+        .with_status_code(status);
+        for header in &cors {
+            response.add_header(header.clone());
+        }
+        if let Err(e) = request.respond(response) {
+            warn!(error = %e, "Could not answer request");
+        }
+    }
+}
+
+// Reads at most MAX_BODY bytes, whatever the request claims its length is.
+fn read_body(request: &mut Request) -> Result<String, Failure> {
+    let too_large = || (413, format!("The body is longer than {} bytes", MAX_BODY));
+    if request
+        .body_length()
+        .is_some_and(|length| length > MAX_BODY)
+    {
+        return Err(too_large());
+    }
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY as u64 + 1)
+        .read_to_string(&mut body)
+        .map_err(|e| (400, e.to_string()))?;
+    if body.len() > MAX_BODY {
+        return Err(too_large());
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use tiny_http::Method;
+
+    use crate::{
+        action::BoardAction,
+        agent::{Agent, RandomAgent},
+        rules::GameRules,
+        timecontrol::SearchLimit,
+        BoardState,
+    };
+
+    use super::GameApi;
+
+    // Only plays on the default board, like a network trained for it.
+    struct DefaultBoard(RandomAgent);
+
+    impl Agent for DefaultBoard {
+        fn name(&self) -> String {
+            self.0.name()
+        }
+
+        fn choose(&mut self, state: &BoardState) -> Option<BoardAction> {
+            self.0.choose(state)
+        }
+
+        fn supports(&self, rules: &GameRules) -> Result<(), String> {
+            let default = GameRules::default();
+            match (rules.width, rules.height) == (default.width, default.height) {
+                true => Ok(()),
+                false => Err(String::from("Not the default board")),
+            }
+        }
+    }
+
+    #[test]
+    fn refuse_rules_the_engine_cant_play() {
+        let engine = DefaultBoard(RandomAgent::new(5));
+        let api = GameApi::new(Box::new(engine), SearchLimit::Playouts(10));
+
+        let (status, answer) = api.handle(&Method::Post, "/games", r#"{"rules": "size=5x4"}"#);
+        assert_eq!(status, 400);
+        assert!(answer["error"].is_string());
+        assert_eq!(api.handle(&Method::Get, "/games/1", "").0, 404);
+
+        let (status, game) = api.handle(&Method::Post, "/games", "");
+        assert_eq!(status, 200);
+        let reply = format!("/games/{}/reply", game["id"]);
+        assert_eq!(api.handle(&Method::Post, &reply, "").0, 200);
+    }
+
+    #[test]
+    fn play_a_game() {
+        let api = GameApi::new(Box::new(RandomAgent::new(5)), SearchLimit::Playouts(10));
+
+        let (status, game) = api.handle(&Method::Post, "/games", r#"{"rules": "size=5x4"}"#);
+        assert_eq!(status, 200);
+        let id = game["id"].as_u64().expect("No id");
+        assert_eq!(game["player"], 1);
+        assert_eq!(game["rows"].as_array().map(Vec::len), Some(4));
+
+        let moves = api
+            .handle(&Method::Get, &format!("/games/{}/moves", id), "")
+            .1;
+        assert!(moves["legal_moves"]
+            .as_array()
+            .unwrap()
+            .contains(&"d4".into()));
+
+        let (status, game) = api.handle(
+            &Method::Post,
+            &format!("/games/{}/moves", id),
+            r#"{"move": "d2"}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(game["move"], "d2");
+        assert_eq!(game["player"], 2);
+
+        let (status, game) = api.handle(&Method::Post, &format!("/games/{}/reply", id), "");
+        assert_eq!(status, 200);
+        assert_eq!(game["history"].as_array().map(Vec::len), Some(2));
+        assert_eq!(game["player"], 1);
+
+        let illegal = api.handle(
+            &Method::Post,
+            &format!("/games/{}/moves", id),
+            r#"{"move": "d7"}"#,
+        );
+        assert_eq!(illegal.0, 400);
+        assert!(illegal.1["error"].is_string());
+        assert_eq!(api.handle(&Method::Get, "/games/99", "").0, 404);
+        assert_eq!(api.handle(&Method::Post, "/games", "{").0, 400);
+        assert_eq!(api.handle(&Method::Get, "/players", "").0, 404);
+
+        assert_eq!(
+            api.handle(&Method::Delete, &format!("/games/{}", id), "").0,
+            200
+        );
+        assert_eq!(
+            api.handle(&Method::Get, &format!("/games/{}", id), "").0,
+            404
+        );
+    }
+}
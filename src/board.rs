@@ -1,14 +1,28 @@
 use std::{cmp::Reverse, collections::HashSet, fmt::Display};
 
+use bitboard::Bitboard;
+
 use crate::{
-    action::{BoardAction, Coordinate},
+    action::{BoardAction, Column, Coordinate, Row},
+    error::InvariantError,
     player::Player,
+    rules::{GameRules, ResolutionPolicy},
 };
 
+// Size of the standard board, other sizes are set through GameRules.
 pub const WIDTH: usize = 8;
 pub const HEIGHT: usize = 8;
 
+// Largest sizes along either side, the whole board also has to fit in the
+// bitboards, see Board::fits.
+pub const MAX_WIDTH: usize = 16;
+pub const MAX_HEIGHT: usize = 16;
+
+mod bitboard;
+pub(crate) mod zobrist;
+
 #[derive(Debug, Clone, Copy, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Cell {
     Empty,
     Filled(Player),
@@ -20,7 +34,8 @@ impl Default for Cell {
     }
 }
 
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TerminalResult {
     None,
     Win(Player),
@@ -37,45 +52,100 @@ impl Default for TerminalResult {
 pub enum MoveResult {
     Winner(Player),
     Draw,
-    Three(Player),
+    Three(Match),
+}
+
+impl MoveResult {
+    // The player who scored a point with this result.
+    pub fn scorer(&self) -> Option<Player> {
+        match self {
+            MoveResult::Three(matched) => Some(matched.player),
+            _ => None,
+        }
+    }
+}
+
+// A line that scored a point and was removed from the board.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub player: Player,
+    // The cells of the line, in order along it.
+    pub cells: Vec<Coordinate>,
+    // Phase of the cascade that removed the line, 0 for lines made by the
+    // action itself.
+    pub cascade: usize,
+    // Position among all lines the action removed. The lines of a phase are
+    // removed together, they are ordered by their first cell, bottom row
+    // first.
+    pub order: usize,
 }
 
-#[derive(Debug, Default, Clone, Hash)]
+// The stones of both players are kept in bitboards, so whole lines are found
+// with a few shifts instead of walking the cells.
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct Board {
-    board: [[Cell; HEIGHT]; WIDTH],
+    width: usize,
+    height: usize,
+    stones: [Bitboard; 2],
+    // Zobrist hash of the stones, kept up to date by every change.
+    hash: u64,
 }
 
-impl From<[&str; 8]> for Board {
-    fn from(a: [&str; 8]) -> Self {
-        let mut board = Self::default();
-        let a = a
-            .into_iter()
-            .map(|s| s.chars().collect::<Vec<_>>())
-            .collect::<Vec<_>>();
-
-        for x in 0..WIDTH {
-            for y in 0..HEIGHT {
-                let cell = match a[y][x] {
-                    'X' => Cell::Filled(Player::Player1),
-                    'O' => Cell::Filled(Player::Player2),
-                    ' ' => Cell::Empty,
-                    _ => unreachable!(),
-                };
+pub(crate) fn side(player: Player) -> usize {
+    match player {
+        Player::Player1 => 0,
+        Player::Player2 => 1,
+    }
+}
 
-                board.set(cell, Coordinate::new(x as isize, (HEIGHT - 1 - y) as isize));
-            }
-        }
+impl Default for Board {
+    fn default() -> Self {
+        Self::new(WIDTH, HEIGHT)
+    }
+}
+
+// Rows are given top row first, the board is as wide as the first row.
+impl<const N: usize> From<[&str; N]> for Board {
+    fn from(a: [&str; N]) -> Self {
+        Self::from(&a[..])
+    }
+}
+
+// Panics on rows Board::from_rows refuses, for boards drawn in code.
+impl From<&[&str]> for Board {
+    fn from(a: &[&str]) -> Self {
+        Board::from_rows(a).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
 
-        board
+// Stored as its rows, top row first, in the same form Board::from takes.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Board {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.rows())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Board {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let rows = Vec::<String>::deserialize(deserializer)?;
+        let rows = rows.iter().map(String::as_str).collect::<Vec<_>>();
+        let board = Board::from_rows(&rows).map_err(D::Error::custom)?;
+        // The matches depend on the rules, BoardState checks those.
+        board.check_stones().map_err(D::Error::custom)?;
+        Ok(board)
     }
 }
 
 impl Display for Board {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for y in 0..HEIGHT {
+        for y in 0..self.height {
             f.write_str("|")?;
-            for x in 0..WIDTH {
-                match self.get(Coordinate::new(x as isize, (HEIGHT - 1 - y) as isize)) {
+            for x in 0..self.width {
+                match self.get(Coordinate::new(x as isize, (self.height - 1 - y) as isize)) {
                     Cell::Empty => f.write_str(" "),
                     Cell::Filled(Player::Player1) => f.write_str("X"),
                     Cell::Filled(Player::Player2) => f.write_str("O"),
@@ -89,18 +159,257 @@ impl Display for Board {
     }
 }
 
+// One phase of the cascade that follows an action.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CascadeStep {
+    // The board is won or drawn, nothing more is resolved.
+    Terminal(MoveResult),
+    // Matches were scored and their cells removed, stones above fell down.
+    Cleared(Vec<MoveResult>, Vec<Coordinate>),
+    // No matches are left on the board.
+    Settled,
+}
+
+impl CascadeStep {
+    pub fn results(&self) -> &[MoveResult] {
+        match self {
+            CascadeStep::Terminal(result) => std::slice::from_ref(result),
+            CascadeStep::Cleared(results, _) => results,
+            CascadeStep::Settled => &[],
+        }
+    }
+}
+
 impl Board {
-    pub fn make_move(&mut self, mov: &BoardAction) -> Vec<MoveResult> {
-        let mut results = Vec::new();
-        match mov {
-            BoardAction::DropStone(player, col) => {
-                assert!(self.board[*col][HEIGHT - 1] == Cell::Empty);
-                for y in 0..HEIGHT {
-                    if self.board[*col][y] == Cell::Empty {
-                        self.board[*col][y] = Cell::Filled(*player);
-                        break;
+    // The board is as wide as the rows and as high as the number of rows,
+    // the top row first. The rows must be equally long and hold X, O or
+    // spaces. The stones are set as drawn, unchecked, so tests can draw
+    // boards in the middle of a cascade; boards from outside the code go
+    // through `validate`.
+    pub fn from_rows(rows: &[&str]) -> Result<Self, String> {
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        let height = rows.len();
+        if !Self::fits(width, height) {
+            return Err(format!("Board does not fit: {}x{}", width, height));
+        }
+        let mut board = Self::new(width, height);
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(String::from("Rows must be equally long"));
+            }
+            for (x, c) in row.chars().enumerate() {
+                let cell = match c {
+                    'X' => Cell::Filled(Player::Player1),
+                    'O' => Cell::Filled(Player::Player2),
+                    ' ' => Cell::Empty,
+                    _ => return Err(format!("Rows hold X, O or spaces, not {:?}", c)),
+                };
+                board.set(cell, Coordinate::new(x as isize, (height - 1 - y) as isize));
+            }
+        }
+        Ok(board)
+    }
+
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(
+            Self::fits(width, height),
+            "Board does not fit: {}x{}",
+            width,
+            height
+        );
+        Self {
+            width,
+            height,
+            stones: [Bitboard::default(); 2],
+            hash: 0,
+        }
+    }
+
+    // Whether a board of this size can be played on.
+    pub fn fits(width: usize, height: usize) -> bool {
+        (1..=MAX_WIDTH).contains(&width)
+            && (1..=MAX_HEIGHT).contains(&height)
+            && Bitboard::fits(width, height)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = Column> {
+        (0..self.width).filter_map(Column::new)
+    }
+
+    pub fn contains(&self, coord: Coordinate) -> bool {
+        coord.is_contained((0, 0), (self.width as isize, self.height as isize))
+    }
+
+    // The rows top row first, with X and O for the stones of player 1 and 2.
+    pub fn rows(&self) -> Vec<String> {
+        (0..self.height)
+            .rev()
+            .map(|y| {
+                (0..self.width)
+                    .map(
+                        |x| match self.get(Coordinate::new(x as isize, y as isize)) {
+                            Cell::Empty => ' ',
+                            Cell::Filled(Player::Player1) => 'X',
+                            Cell::Filled(Player::Player2) => 'O',
+                        },
+                    )
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn zobrist(&self) -> u64 {
+        self.hash
+    }
+
+    // The board reflected left-right.
+    pub fn mirrored(&self) -> Board {
+        let mut mirrored = Board::new(self.width, self.height);
+        for x in 0..self.width {
+            for y in 0..self.height {
+                let coord = Coordinate::new(x as isize, y as isize);
+                mirrored.set(self.get(coord), coord.mirrored(self.width));
+            }
+        }
+        mirrored
+    }
+
+    pub fn stone_count(&self) -> usize {
+        self.occupied().count()
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        x * (self.height + 1) + y
+    }
+
+    fn coordinate(&self, index: usize) -> Coordinate {
+        let (x, y) = (index / (self.height + 1), index % (self.height + 1));
+        Coordinate::new(x as isize, y as isize)
+    }
+
+    // Bit distance between neighbouring cells in the direction.
+    fn shift(&self, (dx, dy): (isize, isize)) -> usize {
+        (dx * (self.height as isize + 1) + dy) as usize
+    }
+
+    fn occupied(&self) -> Bitboard {
+        self.stones[0].union(self.stones[1])
+    }
+
+    // Xor of the keys of the stones in the cells.
+    fn keys(&self, cells: Bitboard) -> u64 {
+        (0..2)
+            .flat_map(|side| {
+                self.stones[side]
+                    .intersection(cells)
+                    .ones()
+                    .map(move |index| zobrist::stone(side, index))
+            })
+            .fold(0, |hash, key| hash ^ key)
+    }
+
+    fn place(&mut self, index: usize, cell: Cell) {
+        for side in 0..2 {
+            if self.stones[side].get(index) {
+                self.hash ^= zobrist::stone(side, index);
+            }
+        }
+        self.stones[0].set(index, cell == Cell::Filled(Player::Player1));
+        self.stones[1].set(index, cell == Cell::Filled(Player::Player2));
+        if let Cell::Filled(player) = cell {
+            self.hash ^= zobrist::stone(side(player), index);
+        }
+    }
+
+    pub fn make_move(
+        &mut self,
+        player: Player,
+        mov: &BoardAction,
+        rules: &GameRules,
+    ) -> Vec<MoveResult> {
+        self.make_move_steps(player, mov, rules)
+            .iter()
+            .flat_map(|step| step.results().iter().cloned())
+            .collect()
+    }
+
+    // Plays the action and resolves the cascade, returning every phase that
+    // changed the board. The last step is Terminal if the game ended.
+    pub fn make_move_steps(
+        &mut self,
+        player: Player,
+        mov: &BoardAction,
+        rules: &GameRules,
+    ) -> Vec<CascadeStep> {
+        let mut steps = Vec::new();
+
+        // Only lines through the cells that changed can end the game or
+        // score, the board was settled before the action.
+        let mut changed = self.apply(mov);
+
+        loop {
+            let (step, cleared) = self.resolve_step(player, rules, changed);
+            changed = cleared;
+            match step {
+                CascadeStep::Settled => break,
+                step @ CascadeStep::Terminal(_) => {
+                    steps.push(step);
+                    break;
+                }
+                step => steps.push(step),
+            }
+
+            if !rules.cascade {
+                // Gravity can still complete a line after the first clear.
+                match self.terminal_status_near(changed, rules) {
+                    TerminalResult::None => {}
+                    TerminalResult::Win(player) => {
+                        steps.push(CascadeStep::Terminal(MoveResult::Winner(player)))
                     }
+                    TerminalResult::Draw => steps.push(CascadeStep::Terminal(MoveResult::Draw)),
                 }
+                break;
+            }
+        }
+
+        number_matches(&mut steps);
+        steps
+    }
+
+    // Replays a cleared phase of make_move_steps: the stones at the cells
+    // are removed in order and the stones above them fall.
+    pub fn remove_stones(&mut self, cells: &[Coordinate]) {
+        for coord in cells {
+            self.remove_stone(*coord);
+        }
+    }
+
+    // Drops or switches the stones without resolving any matches.
+    pub fn apply_action(&mut self, mov: &BoardAction) {
+        self.apply(mov);
+    }
+
+    // Same as apply_action, returning the cells that changed.
+    fn apply(&mut self, mov: &BoardAction) -> Bitboard {
+        let mut changed = Bitboard::default();
+        match mov {
+            BoardAction::DropStone(player, col) => {
+                assert!(self.is_col_free(*col));
+                let column = Bitboard::column(col.index(), self.height);
+                let index = self
+                    .occupied()
+                    .lowest_free(column)
+                    .expect("Column is not free");
+                self.place(index, Cell::Filled(*player));
+                changed.set(index, true);
             }
             BoardAction::SwitchStone(a, b) => {
                 let stone_a = self.get(*a);
@@ -108,178 +417,322 @@ impl Board {
 
                 self.set(stone_a, *b);
                 self.set(stone_b, *a);
+                for coord in [a, b] {
+                    changed.set(self.index(coord.x() as usize, coord.y() as usize), true);
+                }
             }
         }
+        changed
+    }
 
-        loop {
-            match self.get_board_terminal_status() {
-                TerminalResult::None => {}
-                TerminalResult::Win(player) => {
-                    results.push(MoveResult::Winner(player));
-                    return results;
-                }
-                TerminalResult::Draw => {
-                    results.push(MoveResult::Draw);
-                    return results;
-                }
-            }
+    // The matches currently on the board and the cells they cover,
+    // ordered top to bottom so they can be removed one by one.
+    pub fn find_matches(&self, rules: &GameRules) -> (Vec<MoveResult>, Vec<Coordinate>) {
+        let mut lines = find_lines(self, Player::Player1, rules, Bitboard::full());
+        lines.extend(find_lines(self, Player::Player2, rules, Bitboard::full()));
 
-            let (p1, ps1) = find_points(self, Player::Player1);
-            let (p2, ps2) = find_points(self, Player::Player2);
+        collect_lines(lines)
+    }
+
+    // The matches the next cascade phase resolves under the given policy.
+    // The mover is the player whose action started the cascade.
+    pub fn pending_matches(
+        &self,
+        mover: Player,
+        rules: &GameRules,
+    ) -> (Vec<MoveResult>, Vec<Coordinate>) {
+        self.pending_matches_near(mover, rules, Bitboard::full())
+    }
 
-            for _ in 0..p1 {
-                results.push(MoveResult::Three(Player::Player1));
+    // Same as pending_matches, only for lines that cover a changed cell or
+    // end next to one. With cascades a settled board has no other matches.
+    fn pending_matches_near(
+        &self,
+        mover: Player,
+        rules: &GameRules,
+        changed: Bitboard,
+    ) -> (Vec<MoveResult>, Vec<Coordinate>) {
+        match rules.resolution {
+            ResolutionPolicy::Simultaneous => {
+                let mut lines = find_lines(self, Player::Player1, rules, changed);
+                lines.extend(find_lines(self, Player::Player2, rules, changed));
+
+                collect_lines(lines)
             }
-            for _ in 0..p2 {
-                results.push(MoveResult::Three(Player::Player2));
+            ResolutionPolicy::MoverFirst => {
+                let lines = find_lines(self, mover, rules, changed);
+                if lines.is_empty() {
+                    collect_lines(find_lines(self, mover.next_player(), rules, changed))
+                } else {
+                    collect_lines(lines)
+                }
             }
+            ResolutionPolicy::BottomUp => {
+                let mut lines = find_lines(self, Player::Player1, rules, changed);
+                lines.extend(find_lines(self, Player::Player2, rules, changed));
 
-            let mut total = HashSet::union(&ps1, &ps2).collect::<Vec<_>>();
-            total.sort_by_key(|&c| (Reverse(c.y()), c.x()));
+                let lowest = lines.into_iter().min_by_key(|(_, cells)| {
+                    cells
+                        .iter()
+                        .map(|c| (c.y(), c.x()))
+                        .min()
+                        .expect("Lines are never empty")
+                });
+                collect_lines(lowest.into_iter().collect())
+            }
+        }
+    }
 
-            // println!("{}", self);
+    // Resolves one phase. The steps do not know how many came before them,
+    // their lines are numbered as if they were the first phase.
+    pub fn cascade_step(&mut self, mover: Player, rules: &GameRules) -> CascadeStep {
+        self.resolve_step(mover, rules, Bitboard::full()).0
+    }
 
-            for coord in total {
-                self.remove_stone(*coord);
+    // One cascade phase that only looks for lines near the changed cells.
+    // Returns the cells the phase changed in turn.
+    fn resolve_step(
+        &mut self,
+        mover: Player,
+        rules: &GameRules,
+        changed: Bitboard,
+    ) -> (CascadeStep, Bitboard) {
+        match self.terminal_status_near(changed, rules) {
+            TerminalResult::None => {}
+            TerminalResult::Win(player) => {
+                return (
+                    CascadeStep::Terminal(MoveResult::Winner(player)),
+                    Bitboard::default(),
+                )
             }
-
-            if p1 == 0 && p2 == 0 {
-                break;
+            TerminalResult::Draw => {
+                return (CascadeStep::Terminal(MoveResult::Draw), Bitboard::default())
             }
         }
 
-        return results;
+        // Without cascades the lines gravity formed stay on the board, they
+        // score with the next action wherever it is played.
+        let near = if rules.cascade {
+            changed
+        } else {
+            Bitboard::full()
+        };
+        let (results, cleared) = self.pending_matches_near(mover, rules, near);
+
+        if results.is_empty() {
+            return (CascadeStep::Settled, Bitboard::default());
+        }
+
+        // Policies that leave some of the lines for a later phase have to
+        // keep looking around the cells that formed them.
+        let mut changed = match rules.resolution {
+            ResolutionPolicy::Simultaneous => Bitboard::default(),
+            ResolutionPolicy::MoverFirst | ResolutionPolicy::BottomUp => changed,
+        };
+        for coord in &cleared {
+            changed = changed.union(self.remove_stone(*coord));
+        }
+
+        (CascadeStep::Cleared(results, cleared), changed)
+    }
+
+    // False for columns that are full or not on this board.
+    pub fn is_col_free(&self, col: Column) -> bool {
+        col.index() < self.width
+            && !self
+                .occupied()
+                .get(self.index(col.index(), self.height - 1))
     }
 
-    pub fn is_col_free(&self, col: usize) -> bool {
-        self.board[col][HEIGHT - 1] == Cell::Empty
+    pub fn cell(&self, col: Column, row: Row) -> Cell {
+        self.get(Coordinate::at(col, row))
     }
 
     pub fn set(&mut self, cell: Cell, coord: Coordinate) {
-        self.board[coord.x() as usize][coord.y() as usize] = cell;
+        assert!(self.contains(coord));
+        let index = self.index(coord.x() as usize, coord.y() as usize);
+        self.place(index, cell);
     }
 
     pub fn get(&self, coord: Coordinate) -> Cell {
-        if coord.is_contained((0, 0), (WIDTH as isize, HEIGHT as isize)) {
-            self.board[coord.x() as usize][coord.y() as usize].clone()
+        if !self.contains(coord) {
+            return Cell::Empty;
+        }
+        let index = self.index(coord.x() as usize, coord.y() as usize);
+        if self.stones[0].get(index) {
+            Cell::Filled(Player::Player1)
+        } else if self.stones[1].get(index) {
+            Cell::Filled(Player::Player2)
         } else {
             Cell::Empty
         }
     }
 
-    pub fn get_board_terminal_status(&self) -> TerminalResult {
-        let mut player_1_four = 0;
-        let mut player_2_four = 0;
-        // Check horizontal lines starting left or right
-        for y in 0..HEIGHT {
-            for x in 0..WIDTH {
-                match is_four_directional(self, Coordinate::new(x as isize, y as isize), (1, 0)) {
-                    Some(Player::Player1) => player_1_four += 1,
-                    Some(Player::Player2) => player_2_four += 1,
-                    None => {}
-                }
-                match is_four_directional(self, Coordinate::new(x as isize, y as isize), (0, 1)) {
-                    Some(Player::Player1) => player_1_four += 1,
-                    Some(Player::Player2) => player_2_four += 1,
-                    None => {}
-                }
-                match is_four_directional(self, Coordinate::new(x as isize, y as isize), (1, 1)) {
-                    Some(Player::Player1) => player_1_four += 1,
-                    Some(Player::Player2) => player_2_four += 1,
-                    None => {}
-                }
-                match is_four_directional(self, Coordinate::new(x as isize, y as isize), (-1, 1)) {
-                    Some(Player::Player1) => player_1_four += 1,
-                    Some(Player::Player2) => player_2_four += 1,
-                    None => {}
-                }
-            }
+    pub fn get_board_terminal_status(&self, rules: &GameRules) -> TerminalResult {
+        self.terminal_status_near(Bitboard::full(), rules)
+    }
+
+    // Only considers winning lines that cover a changed cell or end next to
+    // one, as those are the only lines a change can create.
+    fn terminal_status_near(&self, changed: Bitboard, rules: &GameRules) -> TerminalResult {
+        let has_line = |player: Player| {
+            directions(rules).iter().any(|&direction| {
+                let shift = self.shift(direction);
+                !self.stones[side(player)]
+                    .exact_line_starts(shift, rules.win_length)
+                    .intersection(changed.near(shift, rules.win_length, 1))
+                    .is_empty()
+            })
+        };
+
+        match (has_line(Player::Player1), has_line(Player::Player2)) {
+            (true, true) => TerminalResult::Draw,
+            (false, false) => TerminalResult::None,
+            (true, false) => TerminalResult::Win(Player::Player1),
+            (false, true) => TerminalResult::Win(Player::Player2),
         }
+    }
 
-        if player_1_four > 0 && player_2_four > 0 {
-            TerminalResult::Draw
-        } else if player_1_four == 0 && player_2_four == 0 {
-            TerminalResult::None
-        } else if player_1_four > 0 && player_2_four == 0 {
-            TerminalResult::Win(Player::Player1)
-        } else {
-            TerminalResult::Win(Player::Player2)
+    // Checks what every settled board has: stones on the board that rest on
+    // each other, a hash that matches them and, when cascades run, no
+    // matches that are left unless the game is over.
+    pub fn validate(&self, rules: &GameRules) -> Result<(), InvariantError> {
+        self.check_stones()?;
+        if rules.cascade && self.get_board_terminal_status(rules) == TerminalResult::None {
+            let (results, cells) = self.find_matches(rules);
+            if !results.is_empty() {
+                return Err(InvariantError::ResidualMatch(cells));
+            }
         }
+        Ok(())
     }
 
-    fn remove_stone(&mut self, mut coord: Coordinate) {
-        self.board[coord.x() as usize][coord.y() as usize] = Cell::Empty;
+    // The invariants that hold under any rules.
+    fn check_stones(&self) -> Result<(), InvariantError> {
+        if let Some(index) = self.stones[0].intersection(self.stones[1]).ones().next() {
+            return Err(InvariantError::SharedCell(self.coordinate(index)));
+        }
+        let cells = (0..self.width).fold(Bitboard::default(), |cells, x| {
+            cells.union(Bitboard::column(x, self.height))
+        });
+        if self.occupied().count() != self.occupied().intersection(cells).count() {
+            return Err(InvariantError::OutsideBoard);
+        }
+        for x in 0..self.width {
+            let mut below_empty = false;
+            for y in 0..self.height {
+                let empty = !self.occupied().get(self.index(x, y));
+                if !empty && below_empty {
+                    return Err(InvariantError::FloatingStone(Coordinate::new(
+                        x as isize, y as isize,
+                    )));
+                }
+                below_empty |= empty;
+            }
+        }
+        if self.hash != self.keys(Bitboard::full()) {
+            return Err(InvariantError::StaleHash);
+        }
+        Ok(())
+    }
 
-        while coord.is_contained((0, 0), (WIDTH as isize, HEIGHT as isize)) {
-            self.set(self.get(coord + (0, 1)), coord);
-            coord = coord + (0, 1);
+    // Returns the cells that changed, the removed one and those above it.
+    fn remove_stone(&mut self, coord: Coordinate) -> Bitboard {
+        assert!(self.contains(coord));
+        let index = self.index(coord.x() as usize, coord.y() as usize);
+        let column = Bitboard::column(coord.x() as usize, self.height);
+        // Only the stones of this column move.
+        let before = self.keys(column);
+        for stones in &mut self.stones {
+            stones.remove_and_drop(index, column);
         }
+        self.hash ^= before ^ self.keys(column);
+        Bitboard::at_or_above(index).intersection(column)
     }
 }
 
-fn directional_stone_len(
-    board: &Board,
-    player: Player,
-    coord: Coordinate,
-    direction: (isize, isize),
-) -> Vec<Coordinate> {
-    let mut m = Vec::new();
-    let mut current_coord = coord;
-
-    while Cell::Filled(player) == board.get(current_coord) {
-        m.push(current_coord);
-        current_coord = current_coord + direction
+// Up, up-right, right and down-right, leaving out the diagonals when they
+// don't count.
+fn directions(rules: &GameRules) -> &'static [(isize, isize)] {
+    if rules.diagonals {
+        &[(0, 1), (1, 1), (1, 0), (1, -1)]
+    } else {
+        &[(0, 1), (1, 0)]
     }
-    m
 }
 
-fn is_four_directional(board: &Board, start: Coordinate, offset: (isize, isize)) -> Option<Player> {
-    if let Cell::Filled(player) = board.get(start) {
-        let forward = directional_stone_len(board, player, start, offset).len();
-        let backward =
-            directional_stone_len(board, player, start - offset, (-offset.0, -offset.1)).len();
-        if forward == 4 && backward == 0 {
-            return Some(player);
+// Every line of the player of at least the match length, except lines of
+// exactly the win length which end the game. Lines are ordered by their
+// first cell, bottom row first. Lines away from the changed cells are skipped.
+fn find_lines(
+    board: &Board,
+    player: Player,
+    rules: &GameRules,
+    changed: Bitboard,
+) -> Vec<(Player, Vec<Coordinate>)> {
+    let stones = board.stones[side(player)];
+    let mut lines = Vec::new();
+
+    for (order, &direction) in directions(rules).iter().enumerate() {
+        let shift = board.shift(direction);
+        for start in stones.line_starts(shift, rules.match_length).ones() {
+            let length = stones.line_length(start, shift);
+            if length == rules.win_length || !changed.near(shift, length, 1).get(start) {
+                continue;
+            }
+            let cells = (0..length)
+                .map(|i| board.coordinate(start + i * shift))
+                .collect::<Vec<_>>();
+            lines.push((order, cells));
         }
     }
 
-    return None;
+    lines.sort_by_key(|(order, cells)| (cells[0].y(), cells[0].x(), *order));
+    lines
+        .into_iter()
+        .map(|(_, cells)| (player, cells))
+        .collect()
 }
 
-fn find_points(board: &Board, player: Player) -> (usize, HashSet<Coordinate>) {
-    let mut points = 0;
-    let mut coords = HashSet::new();
-    let mut up_set = HashSet::new();
-    let mut up_right_set = HashSet::new();
-    let mut right_set = HashSet::new();
-    let mut down_right_set = HashSet::new();
-
-    let mut check_direction =
-        |coord: Coordinate, set: &mut HashSet<Coordinate>, direction: (isize, isize)| {
-            if !set.contains(&coord) {
-                let cells = directional_stone_len(board, player, coord, direction);
-                if cells.len() >= 3 && cells.len() != 4 {
-                    points += 1;
-                    for coordinate in cells {
-                        set.insert(coordinate);
-                        coords.insert(coordinate);
-                    }
+// Numbers the lines of the phases of one action.
+fn number_matches(steps: &mut [CascadeStep]) {
+    let mut order = 0;
+    for (cascade, step) in steps.iter_mut().enumerate() {
+        if let CascadeStep::Cleared(results, _) = step {
+            for result in results {
+                if let MoveResult::Three(matched) = result {
+                    matched.cascade = cascade;
+                    matched.order = order;
+                    order += 1;
                 }
             }
-        };
-
-    // Horizontal
-    for y in 0..HEIGHT {
-        for x in 0..WIDTH {
-            let coord = Coordinate::new(x as isize, y as isize);
-            check_direction(coord, &mut up_set, (0, 1));
-            check_direction(coord, &mut up_right_set, (1, 1));
-            check_direction(coord, &mut right_set, (1, 0));
-            check_direction(coord, &mut down_right_set, (1, -1));
         }
     }
+}
+
+fn collect_lines(lines: Vec<(Player, Vec<Coordinate>)>) -> (Vec<MoveResult>, Vec<Coordinate>) {
+    let results = lines
+        .iter()
+        .enumerate()
+        .map(|(order, (player, cells))| {
+            MoveResult::Three(Match {
+                player: *player,
+                cells: cells.clone(),
+                cascade: 0,
+                order,
+            })
+        })
+        .collect();
+
+    let mut total = lines
+        .into_iter()
+        .flat_map(|(_, cells)| cells)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    total.sort_by_key(|&c| (Reverse(c.y()), c.x()));
 
-    (points, coords)
+    (results, total)
 }
 
 #[cfg(test)]
@@ -287,22 +740,39 @@ mod tests {
     use crate::{
         action::{BoardAction, Coordinate},
         board::MoveResult,
+        error::InvariantError,
         player::Player,
+        rules::{GameRules, ResolutionPolicy},
+        testing::col,
+        BoardState,
     };
+    use mcts::GameState;
 
-    use super::{Board, Cell};
+    use super::{number_matches, Board, CascadeStep, Cell, Match, TerminalResult};
 
     #[test]
     fn drop_stone() {
         let mut state = Board::default();
-        let a = state.make_move(&BoardAction::DropStone(Player::Player1, 0));
-        let b = state.make_move(&BoardAction::DropStone(Player::Player1, 0));
-        let c = state.make_move(&BoardAction::DropStone(Player::Player1, 0));
+        let a = state.make_move(
+            Player::Player1,
+            &BoardAction::DropStone(Player::Player1, col(0)),
+            &GameRules::default(),
+        );
+        let b = state.make_move(
+            Player::Player1,
+            &BoardAction::DropStone(Player::Player1, col(0)),
+            &GameRules::default(),
+        );
+        let c = state.make_move(
+            Player::Player1,
+            &BoardAction::DropStone(Player::Player1, col(0)),
+            &GameRules::default(),
+        );
 
         assert_eq!(a.len(), 0);
         assert_eq!(b.len(), 0);
         assert_eq!(c.len(), 1);
-        assert_eq!(c[0], MoveResult::Three(Player::Player1));
+        assert_eq!(c[0].scorer(), Some(Player::Player1));
     }
 
     #[test]
@@ -310,35 +780,52 @@ mod tests {
         let mut state = Board::default();
         assert_eq!(
             state
-                .make_move(&BoardAction::DropStone(Player::Player1, 0))
+                .make_move(
+                    Player::Player1,
+                    &BoardAction::DropStone(Player::Player1, col(0)),
+                    &GameRules::default()
+                )
                 .len(),
             0
         );
         assert_eq!(
             state
-                .make_move(&BoardAction::DropStone(Player::Player1, 1))
+                .make_move(
+                    Player::Player1,
+                    &BoardAction::DropStone(Player::Player1, col(1)),
+                    &GameRules::default()
+                )
                 .len(),
             0
         );
         assert_eq!(
             state
-                .make_move(&BoardAction::DropStone(Player::Player2, 2))
+                .make_move(
+                    Player::Player2,
+                    &BoardAction::DropStone(Player::Player2, col(2)),
+                    &GameRules::default()
+                )
                 .len(),
             0
         );
         assert_eq!(
             state
-                .make_move(&BoardAction::DropStone(Player::Player1, 3))
+                .make_move(
+                    Player::Player1,
+                    &BoardAction::DropStone(Player::Player1, col(3)),
+                    &GameRules::default()
+                )
                 .len(),
             0
         );
-        let a = state.make_move(&BoardAction::SwitchStone(
-            Coordinate::new(2, 0),
-            Coordinate::new(3, 0),
-        ));
+        let a = state.make_move(
+            Player::Player1,
+            &BoardAction::SwitchStone(Coordinate::new(2, 0), Coordinate::new(3, 0)),
+            &GameRules::default(),
+        );
 
         assert_eq!(a.len(), 1);
-        assert_eq!(a[0], MoveResult::Three(Player::Player1));
+        assert_eq!(a[0].scorer(), Some(Player::Player1));
     }
 
     #[test]
@@ -351,30 +838,29 @@ mod tests {
 
         println!("{}", state);
 
-        let results = state.make_move(&BoardAction::DropStone(Player::Player1, 3));
+        let results = state.make_move(
+            Player::Player1,
+            &BoardAction::DropStone(Player::Player1, col(3)),
+            &GameRules::default(),
+        );
 
         println!("{}", state);
 
         // assert_eq!(results.len(), 1 + 9 + 1);
-        assert_eq!(results[0], MoveResult::Three(Player::Player1));
+        assert_eq!(results[0].scorer(), Some(Player::Player1));
 
-        assert_eq!(results[1], MoveResult::Three(Player::Player1));
-        assert_eq!(results[2], MoveResult::Three(Player::Player1));
-        assert_eq!(results[3], MoveResult::Three(Player::Player1));
-        assert_eq!(results[4], MoveResult::Three(Player::Player2));
-        assert_eq!(results[5], MoveResult::Three(Player::Player2));
-        assert_eq!(results[6], MoveResult::Three(Player::Player2));
-        assert_eq!(results[7], MoveResult::Three(Player::Player2));
-        assert_eq!(results[8], MoveResult::Three(Player::Player2));
+        assert_eq!(results[1].scorer(), Some(Player::Player1));
+        assert_eq!(results[2].scorer(), Some(Player::Player1));
+        assert_eq!(results[3].scorer(), Some(Player::Player1));
+        assert_eq!(results[4].scorer(), Some(Player::Player2));
+        assert_eq!(results[5].scorer(), Some(Player::Player2));
+        assert_eq!(results[6].scorer(), Some(Player::Player2));
+        assert_eq!(results[7].scorer(), Some(Player::Player2));
+        assert_eq!(results[8].scorer(), Some(Player::Player2));
 
-        assert_eq!(results[9], MoveResult::Three(Player::Player1));
+        assert_eq!(results[9].scorer(), Some(Player::Player1));
 
-        let left = state
-            .board
-            .iter()
-            .flat_map(|s| s.iter())
-            .filter(|&&x| x != Cell::Empty)
-            .count();
+        let left = state.stone_count();
 
         assert_eq!(left, 4);
     }
@@ -389,11 +875,342 @@ mod tests {
 
         println!("{}", state);
 
-        let results = state.make_move(&BoardAction::DropStone(Player::Player1, 4));
+        let results = state.make_move(
+            Player::Player1,
+            &BoardAction::DropStone(Player::Player1, col(4)),
+            &GameRules::default(),
+        );
 
         println!("{}", state);
 
-        assert_eq!(results[0], MoveResult::Three(Player::Player1));
+        assert_eq!(results[0].scorer(), Some(Player::Player1));
         assert_eq!(results[1], MoveResult::Winner(Player::Player2));
     }
+
+    #[test]
+    fn no_cascade() {
+        let board = [
+            "XXO     ", "OOX     ", "XXO     ", "OOX     ", "XXO X   ", "OOX O   ", "XXO OXX ",
+            "OOX XOOX",
+        ];
+        let mut cascading = Board::from(board);
+        let mut single = Board::from(board);
+
+        let rules = GameRules {
+            cascade: false,
+            ..Default::default()
+        };
+
+        let all = cascading.make_move(
+            Player::Player1,
+            &BoardAction::DropStone(Player::Player1, col(3)),
+            &GameRules::default(),
+        );
+        let first = single.make_move(
+            Player::Player1,
+            &BoardAction::DropStone(Player::Player1, col(3)),
+            &rules,
+        );
+
+        // Only the drop itself scores, the waves after it are left on the board.
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].scorer(), Some(Player::Player1));
+        assert_eq!(all[0], first[0]);
+        assert!(all.len() > first.len());
+        assert!(!single.find_matches(&rules).0.is_empty());
+    }
+
+    #[test]
+    fn resolution_policies() {
+        let board = [
+            "XXO     ", "OOX     ", "XXO     ", "OOX     ", "XXO X   ", "OOX O   ", "XXO OXX ",
+            "OOX XOOX",
+        ];
+        let play = |resolution| {
+            let rules = GameRules {
+                resolution,
+                ..Default::default()
+            };
+            Board::from(board).make_move(
+                Player::Player1,
+                &BoardAction::DropStone(Player::Player1, col(3)),
+                &rules,
+            )
+        };
+
+        let scorers =
+            |results: Vec<MoveResult>| results.iter().map(MoveResult::scorer).collect::<Vec<_>>();
+        let x = Some(Player::Player1);
+        let o = Some(Player::Player2);
+
+        // Both players' matches of a phase are scored together, crosses first.
+        assert_eq!(
+            scorers(play(ResolutionPolicy::Simultaneous)),
+            vec![x, x, x, x, o, o, o, o, o, x]
+        );
+
+        // Removing the mover's stones first drops the circles into a line of four.
+        let mover_first = play(ResolutionPolicy::MoverFirst);
+        assert_eq!(
+            mover_first.last(),
+            Some(&MoveResult::Winner(Player::Player2))
+        );
+        assert_eq!(scorers(mover_first), vec![x, x, x, x, None]);
+
+        // The bottom row is cleared over and over while the stacks fall down.
+        assert_eq!(
+            scorers(play(ResolutionPolicy::BottomUp)),
+            vec![x, o, x, o, x, o, x, o, o, x]
+        );
+    }
+
+    #[test]
+    fn match_details() {
+        let mut board = Board::from(["O       ", "X X     ", "OOX     ", "OXO     "]);
+        let results = board.make_move(
+            Player::Player1,
+            &BoardAction::DropStone(Player::Player1, col(1)),
+            &GameRules::default(),
+        );
+
+        // The crosses are removed first, the circle above them falls into a
+        // column and a diagonal with the circles below.
+        let at = |x, y| Coordinate::new(x, y);
+        assert_eq!(
+            results,
+            vec![
+                MoveResult::Three(Match {
+                    player: Player::Player1,
+                    cells: vec![at(0, 2), at(1, 2), at(2, 2)],
+                    cascade: 0,
+                    order: 0,
+                }),
+                MoveResult::Three(Match {
+                    player: Player::Player2,
+                    cells: vec![at(0, 0), at(0, 1), at(0, 2)],
+                    cascade: 1,
+                    order: 1,
+                }),
+                MoveResult::Three(Match {
+                    player: Player::Player2,
+                    cells: vec![at(0, 2), at(1, 1), at(2, 0)],
+                    cascade: 1,
+                    order: 2,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn small_board() {
+        let mut board = Board::from(["     ", "XO   ", "OXOX "]);
+        assert_eq!((board.width(), board.height()), (5, 3));
+        assert_eq!(board.columns().count(), 5);
+        assert!(board.is_col_free(col(4)));
+        assert!(!board.is_col_free(col(5)));
+        assert_eq!(
+            board.get(Coordinate::new(1, 1)),
+            Cell::Filled(Player::Player2)
+        );
+        assert_eq!(board.get(Coordinate::new(5, 0)), Cell::Empty);
+
+        board.make_move(
+            Player::Player1,
+            &BoardAction::DropStone(Player::Player1, col(1)),
+            &GameRules::default(),
+        );
+        assert!(board.is_col_free(col(0)));
+        assert!(!board.is_col_free(col(1)));
+    }
+
+    #[test]
+    fn validate_invariants() {
+        let rules = GameRules::default();
+        assert_eq!(
+            Board::from(["X   ", "O   ", "XO  "]).validate(&rules),
+            Ok(())
+        );
+        assert_eq!(
+            Board::from(["X   ", "    "]).validate(&rules),
+            Err(InvariantError::FloatingStone(Coordinate::new(0, 1)))
+        );
+
+        // A match is only left behind when cascades are off.
+        let unresolved = Board::from(["OXXX"]);
+        assert!(matches!(
+            unresolved.validate(&rules),
+            Err(InvariantError::ResidualMatch(cells)) if cells.len() == 3
+        ));
+        let no_cascade = GameRules {
+            cascade: false,
+            ..rules
+        };
+        assert_eq!(unresolved.validate(&no_cascade), Ok(()));
+
+        let mut state = BoardState::default();
+        for x in [3, 4, 3, 4, 2] {
+            state.play(&BoardAction::DropStone(state.current_player(), col(x)));
+            assert_eq!(state.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn from_rows() {
+        let board = Board::from_rows(&["X ", "OX"]).unwrap();
+        assert_eq!((board.width(), board.height()), (2, 2));
+        assert_eq!(board.rows(), vec!["X ", "OX"]);
+        assert!(Board::from_rows(&[]).is_err());
+        assert!(Board::from_rows(&[""]).is_err());
+        assert!(Board::from_rows(&["X ", "OXO"]).is_err());
+        assert!(Board::from_rows(&["X?"]).is_err());
+    }
+
+    #[test]
+    fn configurable_lines() {
+        let empty = "        ";
+        let diagonal = Board::from([
+            empty, empty, empty, empty, "   X    ", "  XO    ", " XOO    ", "XOOX    ",
+        ]);
+        let no_diagonals = GameRules {
+            diagonals: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            diagonal.get_board_terminal_status(&GameRules::default()),
+            TerminalResult::Win(Player::Player1)
+        );
+        assert_eq!(
+            diagonal.get_board_terminal_status(&no_diagonals),
+            TerminalResult::None
+        );
+
+        let four = Board::from([empty, empty, empty, empty, empty, empty, empty, "XXXX    "]);
+        let five = GameRules {
+            win_length: 5,
+            ..Default::default()
+        };
+        assert_eq!(four.get_board_terminal_status(&five), TerminalResult::None);
+        let (matches, _) = four.find_matches(&five);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].scorer(), Some(Player::Player1));
+    }
+
+    #[test]
+    fn zobrist_follows_cascades() {
+        let mut board = Board::from([
+            "XXO     ", "OOX     ", "XXO     ", "OOX     ", "XXO X   ", "OOX O   ", "XXO OXX ",
+            "OOX XOOX",
+        ]);
+        board.make_move(
+            Player::Player1,
+            &BoardAction::DropStone(Player::Player1, col(3)),
+            &GameRules::default(),
+        );
+
+        let mut rebuilt = Board::default();
+        for x in 0..board.width() {
+            for y in 0..board.height() {
+                let coord = Coordinate::new(x as isize, y as isize);
+                rebuilt.set(board.get(coord), coord);
+            }
+        }
+        assert_eq!(board.zobrist(), rebuilt.zobrist());
+        assert_ne!(board.zobrist(), Board::default().zobrist());
+    }
+
+    #[test]
+    fn incremental_terminal_status() {
+        use crate::BoardState;
+        use mcts::GameState;
+        use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let rules = [
+            GameRules::default(),
+            GameRules {
+                cascade: false,
+                ..Default::default()
+            },
+            GameRules {
+                diagonals: false,
+                win_length: 3,
+                match_length: 2,
+                ..Default::default()
+            },
+        ];
+        for rules in rules.iter().cycle().take(60) {
+            let mut state = BoardState::new(*rules);
+            while let Some(mov) = state.available_moves().choose(&mut rng) {
+                let found = match state.play(mov).last() {
+                    Some(MoveResult::Winner(player)) => TerminalResult::Win(*player),
+                    Some(MoveResult::Draw) => TerminalResult::Draw,
+                    _ => TerminalResult::None,
+                };
+                assert_eq!(state.board().get_board_terminal_status(rules), found);
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_cascades() {
+        use crate::BoardState;
+        use mcts::GameState;
+        use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let policies = [
+            ResolutionPolicy::Simultaneous,
+            ResolutionPolicy::MoverFirst,
+            ResolutionPolicy::BottomUp,
+        ];
+        let mut variants = Vec::new();
+        for resolution in policies {
+            for variant in 0..8 {
+                variants.push(GameRules {
+                    resolution,
+                    cascade: variant & 1 == 0,
+                    diagonals: variant & 2 == 0,
+                    diagonal_switches: variant & 4 != 0,
+                    ..Default::default()
+                });
+            }
+        }
+        for rules in variants.iter().cycle().take(120) {
+            let mut state = BoardState::new(*rules);
+            while let Some(mov) = state.available_moves().choose(&mut rng) {
+                // Every phase rescans the whole board.
+                let mut board = state.board().clone();
+                board.apply_action(mov);
+                let mut expected = Vec::new();
+                loop {
+                    match board.cascade_step(state.current_player(), rules) {
+                        CascadeStep::Settled => break,
+                        step @ CascadeStep::Terminal(_) => {
+                            expected.push(step);
+                            break;
+                        }
+                        step => expected.push(step),
+                    }
+                    if !rules.cascade {
+                        // Only a line that gravity completed can still end
+                        // the game.
+                        match board.get_board_terminal_status(rules) {
+                            TerminalResult::None => {}
+                            TerminalResult::Win(player) => {
+                                expected.push(CascadeStep::Terminal(MoveResult::Winner(player)))
+                            }
+                            TerminalResult::Draw => {
+                                expected.push(CascadeStep::Terminal(MoveResult::Draw))
+                            }
+                        }
+                        break;
+                    }
+                }
+                number_matches(&mut expected);
+
+                assert_eq!(state.play_steps(mov), expected, "{}", rules);
+                assert_eq!(state.board(), &board, "{}", rules);
+            }
+        }
+    }
 }
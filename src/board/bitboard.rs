@@ -0,0 +1,157 @@
+// The stones of one player, one bit per cell. Cells are numbered column by
+// column from the bottom, with a spare bit on top of every column so lines
+// can be followed by shifting without running into the next column.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Bitboard(u128);
+
+pub(super) const BITS: usize = 128;
+
+fn shl(bits: u128, by: usize) -> u128 {
+    bits.checked_shl(by as u32).unwrap_or(0)
+}
+
+fn shr(bits: u128, by: usize) -> u128 {
+    bits.checked_shr(by as u32).unwrap_or(0)
+}
+
+impl Bitboard {
+    // Whether a board of this size fits, spare bits included.
+    pub fn fits(width: usize, height: usize) -> bool {
+        width * (height + 1) <= BITS
+    }
+
+    pub fn full() -> Bitboard {
+        Bitboard(!0)
+    }
+
+    // The cell and every cell above it in its column, and beyond.
+    pub fn at_or_above(index: usize) -> Bitboard {
+        Bitboard(!((1 << index) - 1))
+    }
+
+    // The bits of the cells of column `x` on a board of `height` rows.
+    pub fn column(x: usize, height: usize) -> Bitboard {
+        Bitboard(((1 << height) - 1) << (x * (height + 1)))
+    }
+
+    pub fn get(self, index: usize) -> bool {
+        self.0 & (1 << index) != 0
+    }
+
+    pub fn set(&mut self, index: usize, value: bool) {
+        if value {
+            self.0 |= 1 << index;
+        } else {
+            self.0 &= !(1 << index);
+        }
+    }
+
+    pub fn union(self, other: Bitboard) -> Bitboard {
+        Bitboard(self.0 | other.0)
+    }
+
+    pub fn intersection(self, other: Bitboard) -> Bitboard {
+        Bitboard(self.0 & other.0)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn count(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    // Lowest cell of the column mask that is not set.
+    pub fn lowest_free(self, column: Bitboard) -> Option<usize> {
+        let free = !self.0 & column.0;
+        (free != 0).then(|| free.trailing_zeros() as usize)
+    }
+
+    pub fn ones(self) -> impl Iterator<Item = usize> {
+        let mut bits = self.0;
+        std::iter::from_fn(move || {
+            if bits == 0 {
+                return None;
+            }
+            let index = bits.trailing_zeros() as usize;
+            bits &= bits - 1;
+            Some(index)
+        })
+    }
+
+    // First cells of the lines of at least `length` stones in the direction
+    // `shift`. Only whole lines count, not the tail of a longer one.
+    pub fn line_starts(self, shift: usize, length: usize) -> Bitboard {
+        let mut starts = self.0 & !shl(self.0, shift);
+        for i in 1..length {
+            starts &= shr(self.0, i * shift);
+        }
+        Bitboard(starts)
+    }
+
+    // First cells of the lines of exactly `length` stones.
+    pub fn exact_line_starts(self, shift: usize, length: usize) -> Bitboard {
+        Bitboard(self.line_starts(shift, length).0 & !shr(self.0, length * shift))
+    }
+
+    // Every cell up to `back` steps before or `ahead` steps after a set cell
+    // in the direction `shift`.
+    pub fn near(self, shift: usize, back: usize, ahead: usize) -> Bitboard {
+        let mut bits = self.0;
+        for i in 1..=back {
+            bits |= shr(self.0, i * shift);
+        }
+        for i in 1..=ahead {
+            bits |= shl(self.0, i * shift);
+        }
+        Bitboard(bits)
+    }
+
+    pub fn line_length(self, start: usize, shift: usize) -> usize {
+        (0..)
+            .take_while(|i| start + i * shift < BITS && self.get(start + i * shift))
+            .count()
+    }
+
+    // Clears the cell and moves the cells above it in the column down by
+    // one, the top cell of the column becomes empty.
+    pub fn remove_and_drop(&mut self, index: usize, column: Bitboard) {
+        let below = (1 << index) - 1;
+        let above = column.0 & !below & !(1 << index);
+        self.0 = (self.0 & !column.0) | (self.0 & column.0 & below) | ((self.0 & above) >> 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bitboard;
+
+    #[test]
+    fn lines() {
+        // A column of 3 rows and a spare bit: a vertical line of two at the
+        // bottom of column 0 and a line of three in column 1.
+        let mut bits = Bitboard::default();
+        for index in [0, 1, 4, 5, 6] {
+            bits.set(index, true);
+        }
+
+        assert_eq!(
+            bits.line_starts(1, 2).ones().collect::<Vec<_>>(),
+            vec![0, 4]
+        );
+        assert_eq!(
+            bits.exact_line_starts(1, 3).ones().collect::<Vec<_>>(),
+            vec![4]
+        );
+        assert_eq!(bits.line_length(4, 1), 3);
+        assert_eq!(
+            Bitboard(1 << 5).near(2, 2, 1).ones().collect::<Vec<_>>(),
+            vec![1, 3, 5, 7]
+        );
+
+        bits.remove_and_drop(4, Bitboard::column(1, 3));
+        assert_eq!(bits.ones().collect::<Vec<_>>(), vec![0, 1, 4, 5]);
+        assert_eq!(bits.lowest_free(Bitboard::column(1, 3)), Some(6));
+    }
+}
@@ -0,0 +1,38 @@
+// Keys for Zobrist hashing. They are generated at compile time from a fixed
+// seed, so a position hashes the same in every run.
+use super::bitboard::BITS;
+
+const fn splitmix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+const fn stone_keys() -> [[u64; BITS]; 2] {
+    let mut keys = [[0; BITS]; 2];
+    let mut i = 0;
+    while i < BITS {
+        keys[0][i] = splitmix(2 * i as u64);
+        keys[1][i] = splitmix(2 * i as u64 + 1);
+        i += 1;
+    }
+    keys
+}
+
+static STONES: [[u64; BITS]; 2] = stone_keys();
+
+pub const PLAYER_2_TO_MOVE: u64 = splitmix(1 << 32);
+
+// Key of a stone of the side on the bitboard cell.
+pub fn stone(side: usize, index: usize) -> u64 {
+    STONES[side][index]
+}
+
+// Key of the points of a side, no points hash to 0.
+pub fn points(side: usize, points: usize) -> u64 {
+    if points == 0 {
+        return 0;
+    }
+    splitmix(((side as u64 + 2) << 32) ^ points as u64)
+}
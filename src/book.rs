@@ -0,0 +1,335 @@
+// An opening book: how the moves from the early positions of earlier games
+// turned out. Positions are looked up by their Zobrist hash, so a move order
+// that transposes into a known position finds it too. Engines play the best
+// book move instead of searching, self-play samples its openings from the
+// book so the games do not all start the same way.
+//
+//     rules size=8x8 win=4 match=3 diagonals=true diagonal-switches=false cascade=true resolution=simultaneous
+//     plies 8
+//     9f3a61c2d4e5b780 X d3 120 64 10
+//
+// A line holds the hash of a position, the player to move, the move and its
+// games, wins and draws for the player who played it.
+use std::{collections::HashMap, fmt::Write, fs, io, path::Path};
+
+use mcts::GameState;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    action::BoardAction, board::TerminalResult, player::Player, rules::GameRules, BoardState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookMove {
+    pub action: BoardAction,
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+}
+
+impl BookMove {
+    // Share of the games the mover won, draws counting half.
+    pub fn score(&self) -> f32 {
+        (self.wins as f32 + self.draws as f32 / 2.0) / self.games.max(1) as f32
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BookPosition {
+    player: Player,
+    moves: Vec<BookMove>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OpeningBook {
+    rules: GameRules,
+    plies: usize,
+    // Book moves with fewer games are not played by engines.
+    pub min_games: u32,
+    positions: HashMap<u64, BookPosition>,
+}
+
+impl OpeningBook {
+    // A book of the first `plies` moves of games under the rules.
+    pub fn new(rules: GameRules, plies: usize) -> Self {
+        Self {
+            rules,
+            plies,
+            min_games: 10,
+            positions: HashMap::new(),
+        }
+    }
+
+    pub fn rules(&self) -> &GameRules {
+        &self.rules
+    }
+
+    pub fn plies(&self) -> usize {
+        self.plies
+    }
+
+    // Number of positions in the book.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    // What the book knows about the moves from the position.
+    pub fn moves(&self, state: &BoardState) -> &[BookMove] {
+        if *state.rules() != self.rules {
+            return &[];
+        }
+        self.positions
+            .get(&state.zobrist())
+            .map_or(&[], |position| position.moves.as_slice())
+    }
+
+    // Adds the opening of a game, along with its mirror image. Games under
+    // other rules are left out.
+    pub fn add_game(&mut self, start: &BoardState, moves: &[BoardAction], result: &TerminalResult) {
+        if *start.rules() != self.rules {
+            return;
+        }
+        let width = start.board().width();
+        let mut state = start.clone();
+        for mov in moves.iter().take(self.plies) {
+            if state.is_terminal() {
+                break;
+            }
+            let mover = state.current_player();
+            let hash = state.zobrist();
+            self.add_move(hash, *mov, mover, result);
+            // A symmetric position would count its game twice.
+            let mirror = state.mirror().zobrist();
+            if mirror != hash {
+                self.add_move(mirror, mov.mirrored(width), mover, result);
+            }
+            state.play(mov);
+        }
+    }
+
+    fn add_move(&mut self, hash: u64, action: BoardAction, mover: Player, result: &TerminalResult) {
+        let moves = &mut self
+            .positions
+            .entry(hash)
+            .or_insert_with(|| BookPosition {
+                player: mover,
+                moves: Vec::new(),
+            })
+            .moves;
+        let index = match moves.iter().position(|m| m.action == action) {
+            Some(index) => index,
+            None => {
+                moves.push(BookMove {
+                    action,
+                    games: 0,
+                    wins: 0,
+                    draws: 0,
+                });
+                moves.len() - 1
+            }
+        };
+        let entry = &mut moves[index];
+        entry.games += 1;
+        match result {
+            TerminalResult::Win(winner) if *winner == mover => entry.wins += 1,
+            TerminalResult::Draw => entry.draws += 1,
+            _ => {}
+        }
+    }
+
+    // The best scoring book move with enough games, None when the position
+    // is not in the book and the engine has to search.
+    pub fn probe(&self, state: &BoardState) -> Option<BoardAction> {
+        let available = state.available_moves();
+        self.moves(state)
+            .iter()
+            .filter(|m| m.games >= self.min_games && available.contains(&m.action))
+            .max_by(|a, b| {
+                a.score()
+                    .partial_cmp(&b.score())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(a.games.cmp(&b.games))
+            })
+            .map(|m| m.action)
+    }
+
+    // A book move drawn by how often it was played.
+    pub fn sample<R: Rng>(&self, state: &BoardState, rng: &mut R) -> Option<BoardAction> {
+        let available = state.available_moves();
+        let moves = self
+            .moves(state)
+            .iter()
+            .filter(|m| available.contains(&m.action))
+            .collect::<Vec<_>>();
+        moves
+            .choose_weighted(rng, |m| m.games)
+            .ok()
+            .map(|m| m.action)
+    }
+
+    // Follows sampled book moves from the position until the book runs out.
+    pub fn play_line<R: Rng>(&self, start: &BoardState, rng: &mut R) -> BoardState {
+        let mut state = start.clone();
+        for _ in 0..self.plies {
+            if state.is_terminal() {
+                break;
+            }
+            match self.sample(&state, rng) {
+                Some(mov) => {
+                    state.play(&mov);
+                }
+                None => break,
+            }
+        }
+        state
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut content = String::new();
+        writeln!(content, "rules {}", self.rules).expect("Could not format rules");
+        writeln!(content, "plies {}", self.plies).expect("Could not format plies");
+        // Sorted so the same book is written the same way.
+        let mut hashes = self.positions.keys().collect::<Vec<_>>();
+        hashes.sort();
+        for hash in hashes {
+            let position = &self.positions[hash];
+            let mover = match position.player {
+                Player::Player1 => 'X',
+                Player::Player2 => 'O',
+            };
+            for mov in &position.moves {
+                writeln!(
+                    content,
+                    "{:016x} {} {} {} {} {}",
+                    hash, mover, mov.action, mov.games, mov.wins, mov.draws
+                )
+                .expect("Could not format book move");
+            }
+        }
+        fs::write(path, content)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let invalid = |what: &str, line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid {}: {}", what, line),
+            )
+        };
+
+        let mut book = Self::new(GameRules::default(), 0);
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "rules" => {
+                    book.rules = GameRules::parse(value).ok_or_else(|| invalid("rules", line))?
+                }
+                "plies" => book.plies = value.parse().map_err(|_| invalid("plies", line))?,
+                _ => {
+                    let (hash, player, mov) =
+                        parse_move(line).ok_or_else(|| invalid("move", line))?;
+                    book.positions
+                        .entry(hash)
+                        .or_insert_with(|| BookPosition {
+                            player,
+                            moves: Vec::new(),
+                        })
+                        .moves
+                        .push(mov);
+                }
+            }
+        }
+        Ok(book)
+    }
+}
+
+fn parse_move(line: &str) -> Option<(u64, Player, BookMove)> {
+    let parts = line.split_whitespace().collect::<Vec<_>>();
+    let [hash, player, action, games, wins, draws] = parts[..] else {
+        return None;
+    };
+    let player = match player {
+        "X" => Player::Player1,
+        "O" => Player::Player2,
+        _ => return None,
+    };
+    let mov = BookMove {
+        action: BoardAction::parse(action, player)?,
+        games: games.parse().ok()?,
+        wins: wins.parse().ok()?,
+        draws: draws.parse().ok()?,
+    };
+    Some((u64::from_str_radix(hash, 16).ok()?, player, mov))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        action::{BoardAction, Column},
+        board::TerminalResult,
+        player::Player,
+        rules::GameRules,
+        testing::temp_path,
+        BoardState,
+    };
+
+    use super::OpeningBook;
+
+    #[test]
+    fn build_and_probe() {
+        let drop = |player, col| BoardAction::DropStone(player, Column::new(col).unwrap());
+        let start = BoardState::default();
+        let mut book = OpeningBook::new(GameRules::default(), 2);
+        book.min_games = 2;
+        let x_wins = [drop(Player::Player1, 3), drop(Player::Player2, 3)];
+        let o_wins = [drop(Player::Player1, 3), drop(Player::Player2, 5)];
+        for _ in 0..2 {
+            book.add_game(&start, &x_wins, &TerminalResult::Win(Player::Player1));
+        }
+        book.add_game(&start, &o_wins, &TerminalResult::Win(Player::Player2));
+        book.add_game(&start, &[drop(Player::Player1, 0)], &TerminalResult::Draw);
+
+        // The start is its own mirror image, d3 was played three times.
+        assert_eq!(book.moves(&start).len(), 2);
+        assert_eq!(book.probe(&start), Some(drop(Player::Player1, 3)));
+
+        // O won its only game with d5, too few to be played.
+        let mut state = start.clone();
+        state.play(&drop(Player::Player1, 3));
+        assert_eq!(book.probe(&state), Some(drop(Player::Player2, 3)));
+        book.min_games = 1;
+        assert_eq!(book.probe(&state), Some(drop(Player::Player2, 5)));
+
+        // The mirror image of the line is in the book as well.
+        let mut mirrored = start.clone();
+        mirrored.play(&drop(Player::Player1, 4));
+        assert_eq!(book.probe(&mirrored), Some(drop(Player::Player2, 2)));
+        assert!(book
+            .probe(&BoardState::new(GameRules::parse("size=5x4").unwrap()))
+            .is_none());
+
+        let line = book.play_line(&start, &mut StdRng::seed_from_u64(3));
+        assert!((1..=2).contains(&line.history().count()));
+
+        let path = temp_path("book_test.txt");
+        book.save(&path).expect("Could not save book");
+        let loaded = OpeningBook::load(&path);
+        std::fs::remove_file(&path).ok();
+        let loaded = loaded.expect("Could not load book");
+        assert_eq!(loaded.len(), book.len());
+        assert_eq!(loaded.plies(), 2);
+        assert_eq!(loaded.moves(&state), book.moves(&state));
+        assert_eq!(loaded.moves(&mirrored), book.moves(&mirrored));
+    }
+}
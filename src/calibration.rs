@@ -0,0 +1,176 @@
+// Compares the value head's predictions with how games actually ended.
+// Values in [-1, 1] are read as win probabilities (v + 1) / 2, a draw
+// counts as half a win.
+use std::{collections::BTreeMap, fmt::Display};
+
+use catzero::{AlphaGame, TFModel};
+use mcts::{GameState, Player};
+
+use crate::{
+    alphazero::{game::NetworkGame, pipeline::SelfPlayGame},
+    planes::InputEncoding,
+};
+
+pub const BUCKETS: usize = 10;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    pub count: usize,
+    pub predicted: f64,
+    pub observed: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    pub buckets: [Bucket; BUCKETS],
+    squared_error: f64,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            buckets: [Bucket::default(); BUCKETS],
+            squared_error: 0.0,
+        }
+    }
+}
+
+impl Calibration {
+    // Both the prediction and the outcome are seen from the same player.
+    pub fn add(&mut self, value: f32, outcome: f32) {
+        let predicted = ((value as f64 + 1.0) / 2.0).clamp(0.0, 1.0);
+        let observed = (outcome as f64 + 1.0) / 2.0;
+
+        let index = ((predicted * BUCKETS as f64) as usize).min(BUCKETS - 1);
+        let bucket = &mut self.buckets[index];
+        bucket.count += 1;
+        bucket.predicted += predicted;
+        bucket.observed += observed;
+
+        self.squared_error += (predicted - observed).powi(2);
+    }
+
+    pub fn count(&self) -> usize {
+        self.buckets.iter().map(|b| b.count).sum()
+    }
+
+    pub fn brier_score(&self) -> f64 {
+        self.squared_error / self.count().max(1) as f64
+    }
+
+    // Expected calibration error: the gap between predicted and observed
+    // win rate per bucket, weighted by the bucket size.
+    pub fn expected_error(&self) -> f64 {
+        let total = self.count().max(1) as f64;
+        self.buckets
+            .iter()
+            .filter(|b| b.count > 0)
+            .map(|b| (b.predicted - b.observed).abs() / total)
+            .sum()
+    }
+
+    // Evaluates every position of the games with the model, which reads
+    // the encoding it was trained on.
+    pub fn from_games<G>(
+        model: &TFModel,
+        games: &[SelfPlayGame<G>],
+        encoding: InputEncoding,
+    ) -> Self
+    where
+        G: AlphaGame,
+        G::State: NetworkGame,
+        Player<G>: PartialEq,
+    {
+        let mut calibration = Self::default();
+        let mut buf = Vec::new();
+        for game in games {
+            for (state, _) in &game.histories {
+                let (_, value) = model
+                    .evaluate(state.input(encoding, &mut buf))
+                    .expect("Could not evaluate state");
+                let outcome = match &game.winner {
+                    Some(winner) if *winner == state.current_player() => 1.0,
+                    Some(_) => -1.0,
+                    None => 0.0,
+                };
+                calibration.add(value, outcome);
+            }
+        }
+        calibration
+    }
+}
+
+impl Display for Calibration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:>11} {:>7} {:>9} {:>8}",
+            "bucket", "count", "predicted", "observed"
+        )?;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            if bucket.count == 0 {
+                continue;
+            }
+            let count = bucket.count as f64;
+            writeln!(
+                f,
+                "{:>5.2}-{:<5.2} {:>7} {:>9.3} {:>8.3}",
+                i as f64 / BUCKETS as f64,
+                (i + 1) as f64 / BUCKETS as f64,
+                bucket.count,
+                bucket.predicted / count,
+                bucket.observed / count
+            )?;
+        }
+        writeln!(
+            f,
+            "positions {} brier {:.4} ece {:.4}",
+            self.count(),
+            self.brier_score(),
+            self.expected_error()
+        )
+    }
+}
+
+// Reliability per model generation.
+#[derive(Debug, Default, Clone)]
+pub struct CalibrationReport {
+    pub generations: BTreeMap<usize, Calibration>,
+}
+
+impl CalibrationReport {
+    pub fn add(&mut self, generation: usize, calibration: Calibration) {
+        self.generations.insert(generation, calibration);
+    }
+}
+
+impl Display for CalibrationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (generation, calibration) in &self.generations {
+            writeln!(f, "Generation {}", generation)?;
+            writeln!(f, "{}", calibration)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Calibration;
+
+    #[test]
+    fn buckets_and_scores() {
+        let mut calibration = Calibration::default();
+        // Predicted 0.8 four times, won three of them.
+        for outcome in [1.0, 1.0, 1.0, -1.0] {
+            calibration.add(0.6, outcome);
+        }
+
+        let bucket = calibration.buckets[8];
+        assert_eq!(bucket.count, 4);
+        assert!((bucket.observed / 4.0 - 0.75).abs() < 1e-9);
+        assert!((calibration.expected_error() - 0.05).abs() < 1e-6);
+        assert!((calibration.brier_score() - 0.19).abs() < 1e-6);
+        assert!(calibration.to_string().contains("positions 4"));
+    }
+}
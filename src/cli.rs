@@ -0,0 +1,238 @@
+// Subcommands of the m3c4 binary. Every command gets the arguments after
+// its name and fails with a message for the user, the usage follows it.
+use std::sync::Arc;
+
+use catzero::{CatZeroModel, PyEnv, TFModel};
+use m3c4::{
+    action::BoardAction,
+    alphazero::{
+        pipeline::{Opening, SelfPlayGame},
+        DirichletNoise, MyMCTS,
+    },
+    board::TerminalResult,
+    book::OpeningBook,
+    config::TrainingConfig,
+    planes::{policy_planes, InputEncoding},
+    record::{GameMeta, GameRecord},
+    registry::ModelRegistry,
+    rules::GameRules,
+    samples::Shape,
+    BoardState,
+};
+use mcts::GameState;
+use tracing_subscriber::EnvFilter;
+
+pub mod analyze;
+pub mod book;
+pub mod eval;
+pub mod export;
+pub mod live;
+pub mod perft;
+pub mod play;
+pub mod replay;
+pub mod selfplay;
+pub mod serve;
+pub mod tournament;
+pub mod train;
+pub mod tui;
+pub mod ugi;
+pub mod worker;
+
+pub const MODEL_PATH: &str = "data/models/graph";
+pub const CHECKPOINT: &str = "data/checkpoint.txt";
+// Generation that won its arena match last, it plays the self-play games.
+pub const BEST: &str = "data/models/best";
+pub const REGISTRY: &str = "data/models/registry.jsonl";
+pub const GAME_LOG: &str = "data/selfplay.jsonl";
+pub const SAMPLES: &str = "data/samples.bin";
+pub const CALIBRATION: &str = "data/calibration.txt";
+pub const RATINGS: &str = "data/ratings.txt";
+// Every match result, see ResultPool.
+pub const RESULTS: &str = "data/results.txt";
+// TensorBoard event files, and the same scalars as a table.
+pub const METRICS: &str = "data/metrics";
+pub const METRICS_CSV: &str = "data/metrics.csv";
+pub const BOOK: &str = "data/book.txt";
+// The secret workers show the coordinator, kept out of the arguments so it
+// does not show up in the process list.
+pub const WORKER_TOKEN: &str = "M3C4_WORKER_TOKEN";
+
+// Removes `--flag value` from the arguments.
+pub fn take_flag(args: &mut Vec<String>, flag: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(i) if i + 1 < args.len() => {
+            let value = args.remove(i + 1);
+            args.remove(i);
+            Ok(Some(value))
+        }
+        Some(_) => Err(format!("missing value for {}", flag)),
+        None => Ok(None),
+    }
+}
+
+// Removes `--flag` from the arguments, true when it was there.
+pub fn take_switch(args: &mut Vec<String>, flag: &str) -> bool {
+    match args.iter().position(|arg| arg == flag) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+// Sets up the log from `--log-level LEVEL` and `--log-json`, which may be
+// given with any command. The level may also be a filter such as
+// "info,m3c4::alphazero=debug", it defaults to M3C4_LOG or info. JSON
+// output has an object per line for log aggregation.
+pub fn init_logging(args: &mut Vec<String>) -> Result<(), String> {
+    let level = match take_flag(args, "--log-level")? {
+        Some(level) => level,
+        None => std::env::var("M3C4_LOG").unwrap_or_else(|_| String::from("info")),
+    };
+    let filter = EnvFilter::try_new(&level)
+        .map_err(|e| format!("Could not parse log level {}: {}", level, e))?;
+
+    // The log goes to stderr, stdout is left to the output of the commands
+    // and to the engine protocol.
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    if take_switch(args, "--log-json") {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
+    }
+    Ok(())
+}
+
+// Defaults of a run, a config file and the command line override them.
+pub fn default_config() -> TrainingConfig {
+    let mut config = TrainingConfig::default();
+    let pipeline = &mut config.pipeline;
+    pipeline.replay_episodes = 5;
+    pipeline.augment = true;
+    pipeline.batch_inference = true;
+    pipeline.leaf_batch = 8;
+    pipeline.resign_threshold = Some(-0.9);
+    pipeline.noise = Some(DirichletNoise {
+        alpha: 0.3,
+        epsilon: 0.25,
+    });
+    config
+}
+
+// The generation a model name stands for, a number or "best" or "latest"
+// of the model registry.
+pub fn resolve_model(name: &str) -> Result<usize, String> {
+    if let Ok(generation) = name.parse() {
+        return Ok(generation);
+    }
+    ModelRegistry::load(REGISTRY)
+        .map_err(|e| format!("Could not read model registry: {}", e))?
+        .resolve(name)
+}
+
+// The model of a generation and how it was trained to see a position.
+// Python only runs while the model is converted.
+pub fn load_model(generation: usize) -> Result<(Arc<TFModel>, InputEncoding), String> {
+    let input = ModelRegistry::load(REGISTRY)
+        .map_err(|e| format!("Could not read model registry: {}", e))?
+        .input(generation);
+    let mut pyenv = PyEnv::new();
+    let python = pyenv.python();
+    let model = CatZeroModel::load(&python, MODEL_PATH, generation, (1, 3, 3))
+        .and_then(|model| model.to_tf_model(generation))
+        .map_err(|e| format!("Could not load model {}: {}", generation, e))?;
+    Ok((Arc::new(model), input))
+}
+
+// Reads `--config FILE` and the `--<key> <value>` overrides, the arguments
+// that are left are returned.
+pub fn read_config(mut args: Vec<String>) -> Result<(TrainingConfig, Vec<String>), String> {
+    let mut config = default_config();
+    if let Some(path) = take_flag(&mut args, "--config")? {
+        config
+            .apply_file(&path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+    }
+    let rest = config.apply_args(args)?;
+    // Nothing has started a thread yet, see DeviceConfig::apply.
+    config.device.apply();
+    Ok((config, rest))
+}
+
+// Shapes of the input and policy planes of the samples of a run, the
+// self-play games are played under the default rules.
+pub fn sample_shapes(input: InputEncoding) -> (Shape, Shape) {
+    let rules = GameRules::default();
+    (
+        [input.planes(), rules.width, rules.height],
+        [
+            policy_planes(rules.diagonal_switches),
+            rules.width,
+            rules.height,
+        ],
+    )
+}
+
+pub fn game_data_path(episode: usize) -> String {
+    format!("data/{}.games", episode)
+}
+
+// Self-play openings sampled from the opening book in the file.
+pub fn book_opening(path: &str) -> Result<Opening<BoardState>, String> {
+    let book = OpeningBook::load(path)
+        .map_err(|e| format!("Could not load opening book {}: {}", path, e))?;
+    Ok(Arc::new(move |start, rng| book.play_line(start, rng)))
+}
+
+// Writes the games with how they were played. `meta` holds what all games
+// share, every game adds its seed.
+pub fn save_records(
+    dir: &str,
+    results: &[SelfPlayGame<MyMCTS>],
+    meta: GameMeta,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for (i, result) in results.iter().enumerate() {
+        // Games that followed an opening keep its moves in their history,
+        // they are stored without statistics.
+        let mut start = result
+            .histories
+            .first()
+            .map_or_else(Default::default, |(state, _)| state.clone());
+        let opening = start.history().copied().collect::<Vec<_>>();
+        while start.undo().is_some() {}
+        let mut record = GameRecord::from_position(start, opening);
+        record.meta = GameMeta {
+            seed: Some(result.seed),
+            ..meta
+        };
+        for ((mov, stats), (state, policy)) in result
+            .moves
+            .iter()
+            .zip(&result.stats)
+            .zip(&result.histories)
+        {
+            record.push_searched(*mov, *stats);
+            let (width, height) = (state.board().width(), state.board().height());
+            let player = state.current_player();
+            let visits = policy
+                .iter()
+                .enumerate()
+                .filter(|(_, &share)| share > 0.0)
+                .filter_map(|(index, &share)| {
+                    BoardAction::from_index(index, player, width, height)
+                        .map(|action| (action, share))
+                })
+                .collect();
+            *record.visits.last_mut().expect("Could not add visits") = visits;
+        }
+        if result.resigned.is_some() {
+            record.outcome = result.winner.map(TerminalResult::Win);
+        }
+        record.save(format!("{}/{}.game", dir, i))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+// m3c4 analyze [playouts] <moves...> [--seed N] [--depth D] [--model E] [--threads T], for example: m3c4 analyze 20000 d3 d4 d3
+// The rollouts are drawn from the seed, the same seed gives the same answer.
+// --depth also searches D plies with alpha-beta, which is exact for wins and
+// losses within the depth, to check the move of the search against.
+// --model searches with the network of episode E, or of the best or latest
+// model of the registry, instead of rollouts, on all cores unless --threads
+// says otherwise.
+use std::thread;
+
+use m3c4::{
+    action::BoardAction,
+    agent::ModelAgent,
+    alphabeta::{AlphaBeta, WIN_SCORE},
+    player::Player,
+    render::RenderOptions,
+    rollout::HeuristicPolicy,
+    rules::GameRules,
+    solver::MctsSolver,
+    timecontrol::Budget,
+    BoardState,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::{load_model, resolve_model, take_flag};
+
+const EXPLORATION: f64 = 1.45;
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let seed = match take_flag(&mut args, "--seed")? {
+        Some(seed) => seed
+            .parse()
+            .map_err(|_| format!("Could not parse seed: {}", seed))?,
+        None => rand::random(),
+    };
+    let depth = match take_flag(&mut args, "--depth")? {
+        Some(depth) => Some(
+            depth
+                .parse::<usize>()
+                .map_err(|_| format!("Could not parse depth: {}", depth))?,
+        ),
+        None => None,
+    };
+    let model = match take_flag(&mut args, "--model")? {
+        Some(name) => Some(resolve_model(&name)?),
+        None => None,
+    };
+    let threads = match take_flag(&mut args, "--threads")? {
+        Some(threads) => threads
+            .parse()
+            .map_err(|_| format!("Could not parse threads: {}", threads))?,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let mut args = args.into_iter().peekable();
+    let playouts = match args.peek().and_then(|a| a.parse::<usize>().ok()) {
+        Some(playouts) => {
+            args.next();
+            playouts
+        }
+        None => 10000,
+    };
+
+    let mut player = Player::Player1;
+    let mut moves = Vec::new();
+    for arg in args {
+        let mov = BoardAction::parse(&arg, player)
+            .ok_or_else(|| format!("Could not parse action: {}", arg))?;
+        moves.push(mov);
+        player = player.next_player();
+    }
+    let state = BoardState::from_moves(GameRules::default(), &moves)?;
+
+    println!("{}", state.render_text(&RenderOptions::labelled()));
+
+    if let Some(episode) = model {
+        return analyze_with_model(&state, episode, playouts, threads);
+    }
+
+    let solver = MctsSolver::new(1.4, HeuristicPolicy::default());
+    let result = solver.search(
+        &state,
+        Budget::Playouts(playouts),
+        &mut StdRng::seed_from_u64(seed),
+    );
+
+    match result.best_move {
+        Some(mov) => println!("Best move: {}", mov),
+        None => println!("No moves left"),
+    }
+    match result.proof {
+        Some(proof) => println!("Solved: {}", proof),
+        None => println!(
+            "Value: {:.3} after {} playouts",
+            result.value, result.playouts
+        ),
+    }
+
+    if let Some(depth) = depth {
+        let exact = AlphaBeta::new(depth).search(&state);
+        let best = exact
+            .best_move
+            .map_or_else(|| String::from("none"), |mov| mov.to_string());
+        let score = match exact.score {
+            score if score >= WIN_SCORE - depth as i32 => {
+                format!("win in {}", WIN_SCORE - score)
+            }
+            score if score <= -(WIN_SCORE - depth as i32) => {
+                format!("loss in {}", WIN_SCORE + score)
+            }
+            score if exact.proven => format!("draw ({})", score),
+            score => format!("{} by the heuristic", score),
+        };
+        println!(
+            "Alpha-beta: {} at depth {}, {}, {} nodes{}",
+            best,
+            exact.depth,
+            score,
+            exact.nodes,
+            if exact.best_move == result.best_move {
+                ""
+            } else {
+                ", disagrees with the search"
+            }
+        );
+    }
+    Ok(())
+}
+
+// The network search with its playouts spread over the threads.
+fn analyze_with_model(
+    state: &BoardState,
+    episode: usize,
+    playouts: usize,
+    threads: usize,
+) -> Result<(), String> {
+    let (model, input) = load_model(episode)?;
+    let agent = ModelAgent::new(
+        format!("model:{}", episode),
+        model,
+        input,
+        EXPLORATION,
+        playouts,
+    )
+    .with_threads(threads);
+    let manager = agent.search(state)?;
+
+    let mut moves = manager.tree().root_node().moves().collect::<Vec<_>>();
+    moves.sort_by_key(|m| std::cmp::Reverse(m.visits()));
+    for m in moves.iter().take(5) {
+        let value = match m.visits() {
+            0 => 0.0,
+            visits => m.sum_rewards() / visits as f64,
+        };
+        println!(
+            "{:>10} {:>8} visits, value {:.3}",
+            m.get_move(),
+            m.visits(),
+            value
+        );
+    }
+    let line = manager
+        .principal_variation(8)
+        .iter()
+        .map(|mov| mov.to_string())
+        .collect::<Vec<_>>();
+    println!(
+        "Principal variation: {} ({} threads)",
+        line.join(" "),
+        threads
+    );
+    Ok(())
+}
@@ -0,0 +1,49 @@
+// m3c4 book [--plies N] [--out FILE] [DIR ...]
+// Builds an opening book from the game records in the directories, the
+// self-play games of training by default. The book is used by the
+// book:<player> players and by --book of selfplay and train.
+use m3c4::{book::OpeningBook, record::GameRecord};
+
+use super::{take_flag, BOOK};
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let plies = match take_flag(&mut args, "--plies")? {
+        Some(plies) => plies
+            .parse()
+            .map_err(|_| format!("Could not parse plies: {}", plies))?,
+        None => 8,
+    };
+    let out = take_flag(&mut args, "--out")?.unwrap_or_else(|| BOOK.to_string());
+    if args.is_empty() {
+        args.push(String::from("data/games"));
+    }
+
+    let mut records = Vec::new();
+    for dir in &args {
+        records.extend(GameRecord::load_dir(dir).map_err(|e| format!("{}: {}", dir, e))?);
+    }
+    // The book is for the rules of the first game, games under other rules
+    // are left out.
+    let rules = match records.first() {
+        Some(record) => *record.start.rules(),
+        None => return Err(String::from("No game records found")),
+    };
+
+    let mut book = OpeningBook::new(rules, plies);
+    let mut games = 0;
+    for record in &records {
+        if *record.start.rules() == rules {
+            book.add_game(&record.start, &record.moves, &record.result());
+            games += 1;
+        }
+    }
+    book.save(&out)
+        .map_err(|e| format!("Could not save book: {}", e))?;
+    println!(
+        "{} positions from {} games written to {}",
+        book.len(),
+        games,
+        out
+    );
+    Ok(())
+}
@@ -0,0 +1,315 @@
+// m3c4 eval [<player> [<player>]] [--games K] [--playouts N] [--movetime MS] [--threads T] [--seed S] [--out DIR]
+// Plays the players against each other, both starting half of the games,
+// and updates their ratings. --movetime gives the searches a fixed time per
+// move instead of a number of playouts.
+//
+// With a single player, the latest model when none is given, it plays K
+// games per side against each of the baseline bots. The win rate, the game
+// lengths and the points against them show whether training makes progress.
+use std::{
+    fmt::{self, Write},
+    sync::Arc,
+    time::Duration,
+};
+
+use catzero::TFModel;
+use m3c4::{
+    agent::{
+        play_match, Agent, AlphaBetaAgent, BookAgent, HeuristicAgent, MatchReport, ModelAgent,
+        RandomAgent, SolverAgent,
+    },
+    alphabeta::AlphaBeta,
+    alphazero::pipeline::ArenaResult,
+    book::OpeningBook,
+    elo::{Ratings, ResultPool},
+    planes::InputEncoding,
+    record::GameRecord,
+    timecontrol::SearchLimit,
+};
+
+use super::{load_model, resolve_model, BOOK, RATINGS, RESULTS};
+
+#[cfg(not(feature = "onnx"))]
+pub const PLAYERS: &str =
+    "players: model:<episode|best|latest>  alphabeta:<depth>  heuristic  solver  random  book:<player>";
+#[cfg(feature = "onnx")]
+pub const PLAYERS: &str =
+    "players: model:<episode|best|latest>  onnx:<file>  onnx-nhwc:<file>  alphabeta:<depth>  heuristic  solver  random  book:<player>";
+const EXPLORATION: f64 = 1.45;
+// The bots a single player is measured against.
+const BASELINES: [&str; 2] = ["random", "heuristic"];
+
+struct Options {
+    players: Vec<String>,
+    games: usize,
+    playouts: usize,
+    movetime: Option<u64>,
+    threads: usize,
+    seed: u64,
+    out: String,
+}
+
+fn parse_args(args: Vec<String>) -> Option<Options> {
+    let mut options = Options {
+        players: Vec::new(),
+        games: 10,
+        playouts: 500,
+        movetime: None,
+        threads: 1,
+        seed: 0,
+        out: String::from("data/versus"),
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--games" => options.games = args.next()?.parse().ok()?,
+            "--playouts" => options.playouts = args.next()?.parse().ok()?,
+            "--movetime" => options.movetime = Some(args.next()?.parse().ok()?),
+            "--threads" => options.threads = args.next()?.parse().ok()?,
+            "--seed" => options.seed = args.next()?.parse().ok()?,
+            "--out" => options.out = args.next()?,
+            _ => options.players.push(arg),
+        }
+    }
+
+    match options.players.len() {
+        0 => options.players.push(String::from("model:latest")),
+        1 | 2 => {}
+        _ => return None,
+    }
+    Some(options)
+}
+
+// Models are only loaded for the players that need them.
+pub fn create_agent(
+    spec: &str,
+    playouts: usize,
+    threads: usize,
+    seed: u64,
+    model: &dyn Fn(usize) -> Result<(Arc<TFModel>, InputEncoding), String>,
+) -> Result<Box<dyn Agent>, String> {
+    let (kind, argument) = spec.split_once(':').unwrap_or((spec, ""));
+    Ok(match kind {
+        // Named by generation, so "model:best" is rated as the model it is.
+        "model" => {
+            let episode = resolve_model(argument)?;
+            let (model, input) = model(episode)?;
+            Box::new(
+                ModelAgent::new(
+                    format!("model:{}", episode),
+                    model,
+                    input,
+                    EXPLORATION,
+                    playouts,
+                )
+                .with_threads(threads),
+            )
+        }
+        // Exported models only need tract, they play on the default board.
+        // Models exported channels last take their input as onnx-nhwc.
+        #[cfg(feature = "onnx")]
+        "onnx" | "onnx-nhwc" => {
+            let rules = m3c4::rules::GameRules::default();
+            let layout = match kind {
+                "onnx-nhwc" => m3c4::planes::Layout::ChannelsLast,
+                _ => m3c4::planes::Layout::ChannelsFirst,
+            };
+            let model = m3c4::onnx::OnnxModel::load(
+                argument,
+                rules.width,
+                rules.height,
+                rules.diagonal_switches,
+                m3c4::planes::InputEncoding {
+                    layout,
+                    ..Default::default()
+                },
+            )?;
+            Box::new(
+                m3c4::agent::OnnxAgent::new(
+                    spec.to_string(),
+                    Arc::new(model),
+                    EXPLORATION,
+                    playouts,
+                )
+                .with_threads(threads),
+            )
+        }
+        "alphabeta" => Box::new(AlphaBetaAgent::new(AlphaBeta::new(
+            argument.parse().unwrap_or(4),
+        ))),
+        "heuristic" => Box::new(HeuristicAgent::new(playouts).with_threads(threads)),
+        "solver" => Box::new(SolverAgent::new(playouts, seed)),
+        "random" => Box::new(RandomAgent::new(seed)),
+        // Plays from the opening book until the position is not in it.
+        "book" => {
+            let book = OpeningBook::load(BOOK)
+                .map_err(|e| format!("Could not load opening book {}: {}", BOOK, e))?;
+            let inner = create_agent(argument, playouts, threads, seed, model)?;
+            Box::new(BookAgent::new(Arc::new(book), inner))
+        }
+        _ => return Err(format!("Unknown player: {}\n{}", spec, PLAYERS)),
+    })
+}
+
+// Models are rated by generation, the other players by how they were named.
+pub fn rating_name(spec: &str, agent: &dyn Agent) -> String {
+    if spec.starts_with("model:") {
+        agent.name()
+    } else {
+        spec.to_string()
+    }
+}
+
+// Plays the match, saving the games as <out>/<prefix><game>.game, and
+// updates the ratings of the players. The ratings are read before the games,
+// so a broken file stops the match before it starts. The match is played to
+// the end and rated when a game can't be saved, the error comes after.
+fn play(
+    a: &mut dyn Agent,
+    b: &mut dyn Agent,
+    names: [&str; 2],
+    games: usize,
+    out: &str,
+    prefix: &str,
+) -> Result<MatchReport, String> {
+    let mut ratings =
+        Ratings::load(RATINGS).map_err(|e| format!("Could not read ratings: {}", e))?;
+    let mut results =
+        ResultPool::load(RESULTS).map_err(|e| format!("Could not read results: {}", e))?;
+
+    let mut unsaved = None;
+    let report = play_match(a, b, games, |game, moves, state, report| {
+        let path = format!("{}/{}{}.game", out, prefix, game);
+        if let Err(e) = GameRecord::new(moves.to_vec()).save(&path) {
+            unsaved.get_or_insert(format!("Could not save game record {}: {}", path, e));
+        }
+        println!(
+            "Game {}: {:?} (+{} ={} -{})",
+            game,
+            state.result(),
+            report.wins,
+            report.draws,
+            report.losses
+        );
+    });
+
+    let [a, b] = names;
+    let result = arena_result(&report);
+    ratings.record(a, b, &result);
+    if let Err(e) = results.append(RESULTS, a, b, &result) {
+        println!("Did not save the result: {}", e);
+    }
+    println!(
+        "Ratings: {} {:.0}, {} {:.0}",
+        a,
+        ratings.rating(a),
+        b,
+        ratings.rating(b)
+    );
+    if let Err(e) = ratings.append_history(RATINGS, "eval", &[a, b]) {
+        println!("Did not save ratings: {}", e);
+    }
+    match unsaved {
+        Some(e) => Err(e),
+        None => Ok(report),
+    }
+}
+
+pub fn arena_result(report: &MatchReport) -> ArenaResult {
+    ArenaResult {
+        wins: report.wins,
+        draws: report.draws,
+        losses: report.losses,
+    }
+}
+
+fn summarize(summary: &mut String, a: &str, b: &str, report: &MatchReport) -> fmt::Result {
+    writeln!(summary, "{} vs {}", a, b)?;
+    writeln!(
+        summary,
+        "wins {} draws {} losses {} score {:.3}",
+        report.wins,
+        report.draws,
+        report.losses,
+        report.score()
+    )?;
+    writeln!(
+        summary,
+        "length {:.1} points {:.2} opponent points {:.2}",
+        report.average_length(),
+        report.average_points(),
+        report.average_opponent_points()
+    )
+}
+
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = parse_args(args).ok_or_else(|| {
+        format!(
+            "usage: m3c4 eval [<player> [<player>]] [--games K] [--playouts N] [--movetime MS] \
+             [--threads T] [--seed S] [--out DIR]\n{}",
+            PLAYERS
+        )
+    })?;
+
+    // A single player is measured against the baselines.
+    let (playouts, threads) = (options.playouts, options.threads);
+    let (opponents, games) = match options.players.as_slice() {
+        [_, b] => (vec![b.as_str()], options.games),
+        _ => (BASELINES.to_vec(), 2 * options.games),
+    };
+
+    let mut a = create_agent(
+        &options.players[0],
+        playouts,
+        threads,
+        options.seed,
+        &load_model,
+    )?;
+    let limit = options
+        .movetime
+        .map(|ms| SearchLimit::MoveTime(Duration::from_millis(ms)));
+    if let Some(limit) = limit {
+        a.set_limit(limit);
+    }
+    let a_name = rating_name(&options.players[0], a.as_ref());
+
+    std::fs::create_dir_all(&options.out)
+        .map_err(|e| format!("Could not create {}: {}", options.out, e))?;
+
+    let mut summary = String::new();
+    writeln!(
+        summary,
+        "games {} playouts {} seed {}",
+        games, options.playouts, options.seed
+    )
+    .map_err(|e| e.to_string())?;
+    for opponent in opponents {
+        let mut b = create_agent(opponent, playouts, threads, options.seed + 1, &load_model)?;
+        if let Some(limit) = limit {
+            b.set_limit(limit);
+        }
+        let b_name = rating_name(opponent, b.as_ref());
+
+        // Head to head the games keep their plain numbers.
+        let prefix = if options.players.len() == 2 {
+            String::new()
+        } else {
+            format!("{}-", opponent)
+        };
+        let report = play(
+            a.as_mut(),
+            b.as_mut(),
+            [a_name.as_str(), b_name.as_str()],
+            games,
+            &options.out,
+            &prefix,
+        )?;
+        summarize(&mut summary, &a.name(), &b.name(), &report).map_err(|e| e.to_string())?;
+    }
+
+    print!("{}", summary);
+    let path = format!("{}/summary.txt", options.out);
+    std::fs::write(&path, summary).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    Ok(())
+}
@@ -0,0 +1,53 @@
+// m3c4 export <out.npz|out.tfrecord> [--samples FILE] [--episode N]
+// Writes training data as NumPy arrays or TFRecord examples for scripts
+// outside catzero, the whole sample store by default or the games of one
+// episode as saved by train. HDF5 is not written, h5py converts the arrays
+// when needed.
+use std::fs::File;
+
+use catzero::TrainingData;
+use m3c4::{
+    export::{write_npz, write_tfrecord},
+    samples::SampleStore,
+};
+use tracing::info;
+
+use super::{game_data_path, take_flag, SAMPLES};
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let samples = take_flag(&mut args, "--samples")?;
+    let episode = take_flag(&mut args, "--episode")?;
+    let out = match &args[..] {
+        [out] => out.clone(),
+        [] => return Err(String::from("missing output file")),
+        [_, arg, ..] => return Err(format!("unexpected argument {}", arg)),
+    };
+
+    let data = match (episode, samples) {
+        (Some(_), Some(_)) => {
+            return Err(String::from("--episode and --samples can't be combined"))
+        }
+        (Some(episode), None) => {
+            let episode = episode
+                .parse()
+                .map_err(|_| format!("Could not parse episode: {}", episode))?;
+            TrainingData::load(&game_data_path(episode))
+                .map_err(|e| format!("Could not load episode {}: {}", episode, e))?
+        }
+        (None, samples) => {
+            let path = samples.unwrap_or_else(|| String::from(SAMPLES));
+            SampleStore::open(&path)
+                .map_err(|e| format!("Could not open {}: {}", path, e))?
+                .training_data()
+        }
+    };
+
+    let written = if out.ends_with(".tfrecord") {
+        File::create(&out).and_then(|mut file| write_tfrecord(&mut file, &data, None))
+    } else {
+        write_npz(&out, &data, None)
+    };
+    written.map_err(|e| format!("Could not write {}: {}", out, e))?;
+    info!(samples = data.len(), path = %out, "Exported training data");
+    Ok(())
+}
@@ -0,0 +1,92 @@
+// m3c4 live [--address A] [--model E] [--playouts N] [--movetime MS] [--threads T] [--spectate]
+// Plays live games for WebSocket clients on A, see live.rs. Clients see the
+// board, the cascades and the search of the engine as it thinks, and play
+// the first player against it. With --spectate the engine plays both sides,
+// one game after the other, and the clients only watch. The engine searches with the heuristic, or with
+// the network of episode E or of the best or latest model of the registry,
+// N playouts or MS milliseconds per move.
+use std::time::Duration;
+
+use m3c4::{
+    alphazero::{MyMCTS, Network, NetworkSearch, SearchOptions},
+    heuristic::{HeuristicEvaluator, HeuristicMCTS},
+    live::{self, LiveGame},
+    player::Player,
+    timecontrol::SearchLimit,
+    BoardState,
+};
+use mcts::{ThreadData, MCTS};
+
+use super::{load_model, resolve_model, take_flag, take_switch};
+
+const EXPLORATION: f64 = 1.45;
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let address =
+        take_flag(&mut args, "--address")?.unwrap_or_else(|| String::from("0.0.0.0:8081"));
+    let model = match take_flag(&mut args, "--model")? {
+        Some(name) => Some(resolve_model(&name)?),
+        None => None,
+    };
+    let playouts = match take_flag(&mut args, "--playouts")? {
+        Some(playouts) => playouts
+            .parse()
+            .map_err(|_| format!("Could not parse playouts: {}", playouts))?,
+        None => 2000,
+    };
+    let limit = match take_flag(&mut args, "--movetime")? {
+        Some(ms) => SearchLimit::MoveTime(Duration::from_millis(
+            ms.parse()
+                .map_err(|_| format!("Could not parse movetime: {}", ms))?,
+        )),
+        None => SearchLimit::Playouts(playouts),
+    };
+    let threads = match take_flag(&mut args, "--threads")? {
+        Some(threads) => threads
+            .parse()
+            .map_err(|_| format!("Could not parse threads: {}", threads))?,
+        None => 1,
+    };
+    let engines = if take_switch(&mut args, "--spectate") {
+        vec![Player::Player1, Player::Player2]
+    } else {
+        vec![Player::Player2]
+    };
+    if !args.is_empty() {
+        return Err(String::from(
+            "usage: m3c4 live [--address A] [--model E] [--playouts N] [--movetime MS] \
+             [--threads T] [--spectate]",
+        ));
+    }
+
+    match model {
+        Some(episode) => {
+            let (model, input) = load_model(episode)?;
+            let options = SearchOptions::new(EXPLORATION, playouts)
+                .with_threads(threads)
+                .with_input(input);
+            let create = move |state: BoardState| {
+                MyMCTS::create_search(state, Network::Model(model.clone()), options)
+            };
+            serve(LiveGame::new(create, limit, threads, engines), &address)
+        }
+        None => {
+            let create = |state: BoardState| {
+                Ok(HeuristicMCTS::create_search(
+                    state,
+                    HeuristicEvaluator::default(),
+                    EXPLORATION,
+                ))
+            };
+            serve(LiveGame::new(create, limit, threads, engines), &address)
+        }
+    }
+}
+
+fn serve<Spec>(game: LiveGame<Spec>, address: &str) -> Result<(), String>
+where
+    Spec: MCTS<State = BoardState>,
+    ThreadData<Spec>: Default,
+{
+    live::serve(game, address).map_err(|e| format!("Could not serve {}: {}", address, e))
+}
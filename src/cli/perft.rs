@@ -0,0 +1,68 @@
+// m3c4 perft <depth> [--rules RULES] [--position NOTATION] [--divide]
+// Counts the positions after every number of actions up to the depth, from
+// an empty board or the position. --divide splits the last count by the
+// first action.
+use std::time::Instant;
+
+use m3c4::{rules::GameRules, BoardState};
+
+use super::{take_flag, take_switch};
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let rules = match take_flag(&mut args, "--rules")? {
+        Some(rules) => {
+            Some(GameRules::parse(&rules).ok_or_else(|| format!("Bad rules: {}", rules))?)
+        }
+        None => None,
+    };
+    let position = take_flag(&mut args, "--position")?;
+    let divide = take_switch(&mut args, "--divide");
+    let depth = match &args[..] {
+        [depth] => depth
+            .parse::<usize>()
+            .map_err(|_| format!("Could not parse depth: {}", depth))?,
+        [] => return Err(String::from("missing depth")),
+        [_, arg, ..] => return Err(format!("unexpected argument {}", arg)),
+    };
+
+    let state = match (position, rules) {
+        (Some(position), rules) => {
+            let state = BoardState::parse_notation(&position)
+                .ok_or_else(|| format!("Could not parse position: {}", position))?;
+            match rules {
+                Some(rules)
+                    if (rules.width, rules.height)
+                        != (state.board().width(), state.board().height()) =>
+                {
+                    return Err(String::from("Rules are for another board size"))
+                }
+                Some(rules) => state.with_rules(rules),
+                None => state,
+            }
+        }
+        (None, rules) => BoardState::new(rules.unwrap_or_default()),
+    };
+
+    println!("depth        nodes        drops     switches        ended      ms");
+    for d in 1..=depth {
+        let start = Instant::now();
+        let perft = state.perft(d);
+        println!(
+            "{:>5} {:>12} {:>12} {:>12} {:>12} {:>7}",
+            d,
+            perft.nodes,
+            perft.drops,
+            perft.switches,
+            perft.ended,
+            start.elapsed().as_millis()
+        );
+    }
+
+    if divide && depth > 0 {
+        println!();
+        for (mov, perft) in state.perft_divide(depth) {
+            println!("{:<12} {}", mov.to_string(), perft.nodes);
+        }
+    }
+    Ok(())
+}
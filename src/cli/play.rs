@@ -0,0 +1,150 @@
+// m3c4 play [engine] [--second] [--tui] [--ponder] [--playouts N] [--movetime MS] [--clock M+S] [--threads T] [--seed S]
+// A human plays against an engine in the terminal. Moves are typed in
+// action notation: d3 drops in column 3, s3,0-3,1 switches two stones.
+// With --tui they are picked with a cursor instead, see tui.rs. With
+// --ponder a searching engine keeps searching while the human thinks.
+// --movetime gives the engine a fixed time per move, --clock plays with
+// minutes per side and an increment in seconds, the engine spreads its
+// time over the game.
+use std::{
+    io::{self, BufRead, Write},
+    time::{Duration, Instant},
+};
+
+use m3c4::{
+    action::BoardAction,
+    board::TerminalResult,
+    player::Player,
+    render::RenderOptions,
+    rules::GameRules,
+    session::{Clocks, GameSession},
+    timecontrol::{parse_clock, SearchLimit, TimeAllocator},
+};
+use mcts::GameState;
+
+use super::{eval::create_agent, load_model, take_flag, take_switch, tui};
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let playouts = parse(take_flag(&mut args, "--playouts")?, 2000)?;
+    let threads = parse(take_flag(&mut args, "--threads")?, 1)?;
+    let seed = parse(take_flag(&mut args, "--seed")?, rand::random())?;
+    let human_second = take_switch(&mut args, "--second");
+    let tui = take_switch(&mut args, "--tui");
+    let ponder = take_switch(&mut args, "--ponder");
+    let movetime = take_flag(&mut args, "--movetime")?;
+    let clock = match take_flag(&mut args, "--clock")? {
+        Some(clock) => {
+            Some(parse_clock(&clock).ok_or_else(|| format!("Could not parse clock: {}", clock))?)
+        }
+        None => None,
+    };
+    if clock.is_some() && tui {
+        return Err(String::from("--clock does not work with --tui"));
+    }
+    let engine = match args.as_slice() {
+        [] => String::from("solver"),
+        [engine] => engine.clone(),
+        [_, arg, ..] => return Err(format!("unexpected argument {}", arg)),
+    };
+
+    let mut agent = create_agent(&engine, playouts, threads, seed, &load_model)?;
+    agent.set_pondering(ponder);
+    let limit = match (clock, movetime) {
+        (Some(clock), _) => SearchLimit::Clock(clock, TimeAllocator::default()),
+        (None, Some(ms)) => SearchLimit::MoveTime(Duration::from_millis(parse(Some(ms), 0)?)),
+        (None, None) => SearchLimit::Playouts(playouts),
+    };
+    agent.set_limit(limit);
+    if tui {
+        return tui::run(agent.as_mut(), human_second);
+    }
+
+    println!(
+        "Playing against {}, type a move, undo or quit",
+        agent.name()
+    );
+    let options = RenderOptions::labelled();
+    let mut session = match clock {
+        Some(clock) => GameSession::with_clocks(GameRules::default(), Clocks::new(clock)),
+        None => GameSession::default(),
+    };
+    let mut lines = io::stdin().lock().lines();
+
+    while !session.state().is_terminal() {
+        let state = session.state().clone();
+        let mover = state.current_player();
+        if let Some(clocks) = session.clocks() {
+            let flagged = [Player::Player1, Player::Player2]
+                .into_iter()
+                .find(|player| clocks.get(*player).is_flagged());
+            if let Some(player) = flagged {
+                println!("{:?} lost on time", player);
+                return Ok(());
+            }
+            agent.set_limit(limit.with_clock(*clocks.get(mover)));
+        }
+        let human_turn = (session.history().len() % 2 == 1) == human_second;
+        let start = Instant::now();
+        if !human_turn {
+            let mov = agent.choose(&state).expect("Engine did not choose a move");
+            println!("{} plays {}", agent.name(), mov);
+            session.play_timed(&mov, start.elapsed());
+            continue;
+        }
+
+        println!("{}", state.render_text(&options));
+        if let Some(clocks) = session.clocks() {
+            println!(
+                "X {}  O {}",
+                format_clock(clocks.player_1.remaining),
+                format_clock(clocks.player_2.remaining)
+            );
+        }
+        print!("{:?}> ", state.current_player());
+        io::stdout().flush().ok();
+        let line = match lines.next() {
+            Some(line) => line.map_err(|e| e.to_string())?,
+            None => return Ok(()),
+        };
+        match line.trim() {
+            "quit" => return Ok(()),
+            // Takes back the move of the engine as well.
+            "undo" => {
+                session.undo();
+                session.undo();
+            }
+            text => match BoardAction::parse(text, state.current_player()) {
+                Some(mov) => match state.check_move(&mov) {
+                    Ok(()) => {
+                        session.play_timed(&mov, start.elapsed());
+                    }
+                    Err(e) => println!("Can't play {}: {}", text, e),
+                },
+                None => println!("Could not read {}", text),
+            },
+        }
+    }
+
+    let state = session.state();
+    println!("{}", state.render_text(&options));
+    match state.result() {
+        TerminalResult::Win(player) => println!("{:?} wins", player),
+        _ => println!("Draw"),
+    }
+    Ok(())
+}
+
+fn format_clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+// Removes `--flag` from the arguments, true when it was there.
+fn parse<T: std::str::FromStr>(value: Option<String>, default: T) -> Result<T, String> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("Could not parse number: {}", value)),
+        None => Ok(default),
+    }
+}
@@ -0,0 +1,160 @@
+// m3c4 replay <file> [--all]
+// Steps through a saved game record. Every move shows the board with the
+// cleared cells marked, the lines every phase of the cascade scored, the
+// points and the statistics of the search that chose the move. Enter shows
+// the next move, back the one before and quit stops, --all prints the whole
+// game at once.
+use std::io::{self, BufRead, Write};
+
+use m3c4::{
+    board::{CascadeStep, MoveResult, TerminalResult},
+    player::Player,
+    record::GameRecord,
+    render::RenderOptions,
+    BoardState,
+};
+use mcts::GameState;
+
+use super::take_switch;
+
+// The position after a move and how the move got there.
+struct Ply {
+    mover: Player,
+    state: BoardState,
+    steps: Vec<CascadeStep>,
+}
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let all = take_switch(&mut args, "--all");
+    let path = match args.as_slice() {
+        [path] => path.clone(),
+        [] => return Err(String::from("missing game record")),
+        [_, arg, ..] => return Err(format!("unexpected argument {}", arg)),
+    };
+    let record = GameRecord::load(&path).map_err(|e| format!("{}: {}", path, e))?;
+
+    let mut state = record.start.clone();
+    let plies = record
+        .moves
+        .iter()
+        .map(|mov| {
+            let mover = state.current_player();
+            let steps = state.play_steps(mov);
+            Ply {
+                mover,
+                state: state.clone(),
+                steps,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let meta = &record.meta;
+    if let (Some(model), Some(seed)) = (meta.model, meta.seed) {
+        println!("Played by model {} from seed {}", model, seed);
+    }
+    println!("{}", record.start.render_text(&RenderOptions::labelled()));
+    let mut lines = io::stdin().lock().lines();
+    let mut index = 0;
+    while index < plies.len() {
+        println!("{}", describe_move(&record, &plies[index], index));
+        if all {
+            index += 1;
+            continue;
+        }
+
+        print!("[{}/{}]> ", index + 1, plies.len());
+        io::stdout().flush().ok();
+        let line = match lines.next() {
+            Some(line) => line.map_err(|e| e.to_string())?,
+            None => return Ok(()),
+        };
+        match line.trim() {
+            "quit" | "q" => return Ok(()),
+            "back" | "b" => index = index.saturating_sub(1),
+            _ => index += 1,
+        }
+    }
+    match record.result() {
+        TerminalResult::Win(player) => println!("{} won", symbol(player)),
+        TerminalResult::Draw => println!("Draw"),
+        TerminalResult::None => println!("The game was not finished"),
+    }
+    Ok(())
+}
+
+fn describe_move(record: &GameRecord, ply: &Ply, index: usize) -> String {
+    let mov = record.moves[index];
+    let cleared = ply
+        .steps
+        .iter()
+        .flat_map(|step| match step {
+            CascadeStep::Cleared(_, cells) => cells.clone(),
+            _ => Vec::new(),
+        })
+        .collect();
+    let options = RenderOptions {
+        last_move: Some(mov),
+        cleared,
+        ..RenderOptions::labelled()
+    };
+
+    let mut out = format!("{}. {} plays {}\n", index + 1, symbol(ply.mover), mov);
+    if let Some(Some(stats)) = record.stats.get(index) {
+        out.push_str(&format!(
+            "Search: {} playouts, {} visits, value {:.3}, {} ms\n",
+            stats.playouts,
+            stats.visits,
+            stats.value,
+            stats.time.as_millis()
+        ));
+    }
+    if let Some(visits) = record.visits.get(index).filter(|visits| !visits.is_empty()) {
+        let mut visits = visits.clone();
+        visits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        let shares = visits
+            .iter()
+            .take(5)
+            .map(|(action, share)| format!("{} {:.0}%", action, share * 100.0))
+            .collect::<Vec<_>>();
+        out.push_str(&format!("Visits: {}\n", shares.join(", ")));
+    }
+    for step in &ply.steps {
+        for result in step.results() {
+            out.push_str(&describe_result(result));
+            out.push('\n');
+        }
+    }
+    out.push_str(&ply.state.render_text(&options));
+    out
+}
+
+fn describe_result(result: &MoveResult) -> String {
+    match result {
+        MoveResult::Three(matched) => {
+            let cells = matched
+                .cells
+                .iter()
+                .map(|cell| format!("{},{}", cell.x(), cell.y()))
+                .collect::<Vec<_>>();
+            let phase = match matched.cascade {
+                0 => String::from("by the move"),
+                cascade => format!("in cascade {}", cascade),
+            };
+            format!(
+                "{} scores {} {}",
+                symbol(matched.player),
+                cells.join(" "),
+                phase
+            )
+        }
+        MoveResult::Winner(player) => format!("{} wins", symbol(*player)),
+        MoveResult::Draw => String::from("Draw"),
+    }
+}
+
+fn symbol(player: Player) -> char {
+    match player {
+        Player::Player1 => 'X',
+        Player::Player2 => 'O',
+    }
+}
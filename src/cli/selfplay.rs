@@ -0,0 +1,134 @@
+// m3c4 selfplay [--model N] [--remote ADDRESS] [--onnx FILE] [--tfrecord FILE] [--book FILE] [--config FILE] [--<key> <value> ...]
+// Plays one episode of self-play games with a model generation, the best
+// one by default, and stores them like training does without training.
+// --model takes a generation or "best" or "latest" of the model registry.
+// With --remote the positions are evaluated by the inference server at the
+// address, --model then only names the generation it serves. With --onnx
+// they are evaluated by a model exported to the file, which needs the onnx
+// feature, and --model again only names the generation. --tfrecord
+// also appends the samples to a TFRecord file for a tf.data pipeline, with
+// the auxiliary points targets when the config has a `points_horizon`.
+// --book starts the games from lines of an opening book.
+use catzero::{CatZeroModel, PyEnv};
+use m3c4::{
+    alphazero::{Checkpoint, MyMCTS, Network, Pipeline},
+    export::write_tfrecord,
+    gamelog::{GameLog, GameLogEntry},
+    inference::RemoteModel,
+    planes::InputEncoding,
+    record::GameMeta,
+    samples::SampleWriter,
+};
+use std::{fs::OpenOptions, sync::Arc};
+use tracing::{info, warn};
+
+use super::{
+    book_opening, read_config, resolve_model, sample_shapes, save_records, take_flag, CHECKPOINT,
+    GAME_LOG, MODEL_PATH, SAMPLES,
+};
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let model = take_flag(&mut args, "--model")?;
+    let remote = take_flag(&mut args, "--remote")?;
+    let onnx = take_flag(&mut args, "--onnx")?;
+    let tfrecord = take_flag(&mut args, "--tfrecord")?;
+    let book = take_flag(&mut args, "--book")?;
+    let (config, rest) = read_config(args)?;
+    if let Some(arg) = rest.first() {
+        return Err(format!("unexpected argument {}", arg));
+    }
+
+    let generation = match model {
+        Some(model) => resolve_model(&model)?,
+        None => Checkpoint::load(CHECKPOINT)
+            .map_err(|e| format!("Could not read checkpoint: {}", e))?
+            .map_or(0, |checkpoint| checkpoint.best),
+    };
+
+    info!(
+        games = config.pipeline.games_per_episode,
+        generation, "Playing self-play games"
+    );
+    let mut pipeline = Pipeline::<MyMCTS>::new(config.pipeline);
+    if let Some(path) = book {
+        pipeline = pipeline.with_opening(book_opening(&path)?);
+    }
+    let results = match (remote, onnx) {
+        (Some(address), _) => {
+            info!(%address, "Evaluating remotely");
+            pipeline.self_play_with(Network::Remote(RemoteModel::new(&address)))
+        }
+        (None, Some(path)) => {
+            info!(%path, "Evaluating with the exported model");
+            pipeline.self_play_with(onnx_network(&path, pipeline.config.input)?)
+        }
+        (None, None) => {
+            let mut pyenv = PyEnv::new();
+            let python = pyenv.python();
+            let model = CatZeroModel::load(&python, MODEL_PATH, generation, (1, 3, 3))
+                .and_then(|model| model.to_tf_model(generation))
+                .map_err(|e| format!("Could not load model {}: {}", generation, e))?;
+            pipeline.self_play(Arc::new(model))
+        }
+    };
+    let data = pipeline.training_data(&results);
+    let points = pipeline.points_targets(&results);
+
+    let meta = GameMeta {
+        model: Some(generation),
+        playouts: Some(pipeline.config.playouts),
+        exploration: Some(pipeline.config.exploration),
+        ..Default::default()
+    };
+    let dir = format!("data/games/selfplay-{}", generation);
+    if let Err(e) = save_records(&dir, &results, meta) {
+        warn!(error = %e, "Did not save game records");
+    }
+    let (input, policy) = sample_shapes(pipeline.config.input);
+    match SampleWriter::open(SAMPLES, input, policy).and_then(|mut s| s.append(&data)) {
+        Ok(()) => info!(samples = data.len(), "Stored samples"),
+        Err(e) => warn!(error = %e, "Did not store samples"),
+    }
+    if let Some(path) = tfrecord {
+        let appended = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| write_tfrecord(&mut file, &data, points.as_deref()));
+        match appended {
+            Ok(()) => info!(samples = data.len(), %path, "Wrote TFRecord examples"),
+            Err(e) => warn!(error = %e, %path, "Did not write TFRecord examples"),
+        }
+    }
+    match GameLog::open(GAME_LOG) {
+        Ok(mut log) => {
+            for (i, result) in results.iter().enumerate() {
+                if let Err(e) = log.write(&GameLogEntry::from_game(generation, i, result)) {
+                    warn!(game = i, error = %e, "Did not log game");
+                }
+            }
+        }
+        Err(e) => warn!(error = %e, "Did not open game log"),
+    }
+    Ok(())
+}
+
+// The exported model plays on the default board, like the onnx player of
+// eval.
+#[cfg(feature = "onnx")]
+fn onnx_network(path: &str, input: InputEncoding) -> Result<Network, String> {
+    let rules = m3c4::rules::GameRules::default();
+    let model = m3c4::onnx::OnnxModel::load(
+        path,
+        rules.width,
+        rules.height,
+        rules.diagonal_switches,
+        input,
+    )?;
+    Ok(Network::Onnx(Arc::new(model)))
+}
+
+#[cfg(not(feature = "onnx"))]
+fn onnx_network(_: &str, _: InputEncoding) -> Result<Network, String> {
+    Err(String::from("--onnx needs the onnx feature"))
+}
@@ -0,0 +1,39 @@
+// m3c4 serve [--address A] [--engine <player>] [--playouts N] [--threads T]
+// Serves games against the engine over HTTP, see api.rs, on 0.0.0.0:8080
+// unless --address says otherwise. The engine is any player of eval, the
+// best model by default. --playouts is how long it searches when a request
+// does not ask for a time or a number of playouts.
+use m3c4::{
+    api::{serve, GameApi},
+    timecontrol::SearchLimit,
+};
+
+use super::{eval::create_agent, load_model, take_flag};
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let address =
+        take_flag(&mut args, "--address")?.unwrap_or_else(|| String::from("0.0.0.0:8080"));
+    let engine = take_flag(&mut args, "--engine")?.unwrap_or_else(|| String::from("model:best"));
+    let playouts = match take_flag(&mut args, "--playouts")? {
+        Some(playouts) => playouts
+            .parse()
+            .map_err(|_| format!("Could not parse playouts: {}", playouts))?,
+        None => 800,
+    };
+    let threads = match take_flag(&mut args, "--threads")? {
+        Some(threads) => threads
+            .parse()
+            .map_err(|_| format!("Could not parse threads: {}", threads))?,
+        None => 1,
+    };
+    if !args.is_empty() {
+        return Err(String::from(
+            "usage: m3c4 serve [--address A] [--engine <player>] [--playouts N] [--threads T]",
+        ));
+    }
+
+    let engine = create_agent(&engine, playouts, threads, rand::random(), &load_model)?;
+
+    let api = GameApi::new(engine, SearchLimit::Playouts(playouts));
+    serve(&api, &address).map_err(|e| format!("Could not serve games: {}", e))
+}
@@ -0,0 +1,156 @@
+// m3c4 tournament [<player>...] [--games K] [--playouts N] [--threads T] [--seed S] [--anchor <player>[=<rating>]]...
+// Plays every two players K games, both starting half of them, and rates
+// all players of the result pool from every result it holds. The results
+// are saved after every game, so a tournament that is started again only
+// plays the games that are missing, earlier matches between two of the
+// players count as well. Without players it rates the pool as it is.
+//
+// Anchored players keep their rating, the one given or their current one,
+// and the others are rated against them. New models can be anchored against
+// the baselines or earlier generations this way.
+use m3c4::{
+    agent::{play_game, MatchReport},
+    elo::{Ratings, ResultPool},
+    player::Player,
+};
+
+use super::{
+    eval::{arena_result, create_agent, rating_name, PLAYERS},
+    load_model, RATINGS, RESULTS,
+};
+
+struct Options {
+    players: Vec<String>,
+    games: usize,
+    playouts: usize,
+    threads: usize,
+    seed: u64,
+    anchors: Vec<(String, Option<f64>)>,
+}
+
+fn parse_args(args: Vec<String>) -> Option<Options> {
+    let mut options = Options {
+        players: Vec::new(),
+        games: 10,
+        playouts: 500,
+        threads: 1,
+        seed: 0,
+        anchors: Vec::new(),
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--games" => options.games = args.next()?.parse().ok()?,
+            "--playouts" => options.playouts = args.next()?.parse().ok()?,
+            "--threads" => options.threads = args.next()?.parse().ok()?,
+            "--seed" => options.seed = args.next()?.parse().ok()?,
+            "--anchor" => {
+                let anchor = args.next()?;
+                options.anchors.push(match anchor.split_once('=') {
+                    Some((player, rating)) => (player.to_string(), Some(rating.parse().ok()?)),
+                    None => (anchor, None),
+                });
+            }
+            _ => options.players.push(arg),
+        }
+    }
+
+    if options.players.len() == 1 {
+        return None;
+    }
+    Some(options)
+}
+
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = parse_args(args).ok_or_else(|| {
+        format!(
+            "usage: m3c4 tournament [<player> <player>...] [--games K] [--playouts N] \
+             [--threads T] [--seed S] [--anchor <player>[=<rating>]]...\n{}",
+            PLAYERS
+        )
+    })?;
+
+    let mut results = ResultPool::load(RESULTS).expect("Could not read results");
+    if !options.players.is_empty() {
+        play(&options, &mut results)?;
+    }
+
+    let current = Ratings::load(RATINGS).expect("Could not read ratings");
+    let anchors = options
+        .anchors
+        .iter()
+        .map(|(player, rating)| {
+            (
+                player.as_str(),
+                rating.unwrap_or_else(|| current.rating(player)),
+            )
+        })
+        .collect::<Vec<_>>();
+    let ratings = results.ratings(&anchors);
+
+    let mut table = ratings.players().collect::<Vec<_>>();
+    table.sort_by(|a, b| b.1.total_cmp(&a.1));
+    for (player, rating) in &table {
+        let games = results
+            .players()
+            .into_iter()
+            .map(|opponent| results.result(player, opponent).games())
+            .sum::<usize>();
+        println!("{:>6.0}  {:<24} {} games", rating, player, games);
+    }
+
+    let players = table.iter().map(|(player, _)| *player).collect::<Vec<_>>();
+    if let Err(e) = ratings.append_history(RATINGS, "tournament", &players) {
+        println!("Did not save ratings: {}", e);
+    }
+    Ok(())
+}
+
+// Plays the games of the round robin that are not in the pool yet.
+fn play(options: &Options, results: &mut ResultPool) -> Result<(), String> {
+    let mut agents = Vec::new();
+    let mut names = Vec::new();
+    for (i, spec) in options.players.iter().enumerate() {
+        let agent = create_agent(
+            spec,
+            options.playouts,
+            options.threads,
+            options.seed + i as u64,
+            &load_model,
+        )?;
+        names.push(rating_name(spec, agent.as_ref()));
+        agents.push(agent);
+    }
+
+    for j in 1..agents.len() {
+        for i in 0..j {
+            let (left, right) = agents.split_at_mut(j);
+            let (a, b) = (left[i].as_mut(), right[0].as_mut());
+            let (a_name, b_name) = (&names[i], &names[j]);
+
+            // The first player starts the even games, also after a resume.
+            let played = results.result(a_name, b_name).games();
+            for game in played..options.games {
+                let mut report = MatchReport::default();
+                if game % 2 == 0 {
+                    let (moves, state) = play_game(a, b);
+                    report.add(&state, moves.len(), Player::Player1);
+                } else {
+                    let (moves, state) = play_game(b, a);
+                    report.add(&state, moves.len(), Player::Player2);
+                }
+                results
+                    .append(RESULTS, a_name, b_name, &arena_result(&report))
+                    .map_err(|e| format!("Could not save result: {}", e))?;
+
+                let total = results.result(a_name, b_name);
+                println!(
+                    "{} vs {} game {}: +{} ={} -{}",
+                    a_name, b_name, game, total.wins, total.draws, total.losses
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,329 @@
+// m3c4 train [--workers ADDRESS] [--book FILE] [--config FILE] [--<key> <value> ...]
+// Runs the self-play and training loop, resuming from the checkpoint. The
+// keys are those of the config file, see m3c4::config. With --workers the
+// self-play games are played by `m3c4 worker` processes that connect to the
+// address, only those with the token in $M3C4_WORKER_TOKEN when it is set.
+// --book starts the games played here from lines of an opening book,
+// workers play from the empty board.
+use catzero::TFModel;
+use m3c4::{
+    alphazero::{pipeline::false_resignation_rate, Checkpoint, Coordinator, MyMCTS, Pipeline},
+    calibration::{Calibration, CalibrationReport},
+    elo::{Ratings, ResultPool},
+    gamelog::{GameLog, GameLogEntry},
+    inference::write_latest,
+    metrics::{loss, EpisodeMetrics, MetricsWriter},
+    record::GameMeta,
+    registry::{config_hash, ArenaRecord, ModelEntry, ModelRegistry},
+    samples::{SampleStore, SampleWriter},
+};
+use std::{
+    env,
+    path::Path,
+    sync::{atomic::Ordering, Arc},
+};
+use tracing::{info, info_span, warn};
+
+use super::{
+    book_opening, game_data_path, read_config, sample_shapes, save_records, take_flag, BEST,
+    CALIBRATION, CHECKPOINT, GAME_LOG, METRICS, METRICS_CSV, MODEL_PATH, RATINGS, REGISTRY,
+    RESULTS, SAMPLES, WORKER_TOKEN,
+};
+
+// Samples of an episode the losses are measured on.
+const LOSS_SAMPLES: usize = 256;
+
+// The input planes are those of the configured InputEncoding, the policy
+// planes those of `policy_planes`, on the board of the default rules.
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let workers = take_flag(&mut args, "--workers")?;
+    let book = take_flag(&mut args, "--book")?;
+    let (config, rest) = read_config(args)?;
+    if let Some(arg) = rest.first() {
+        return Err(format!("unexpected argument {}", arg));
+    }
+    info!("Training with\n{}", config);
+
+    let coordinator = workers
+        .map(|address| Coordinator::start(&address, &config, env::var(WORKER_TOKEN).ok()))
+        .transpose()
+        .map_err(|e| format!("Could not listen for workers: {}", e))?;
+
+    let mut pyenv = catzero::PyEnv::new();
+    let python = pyenv.python();
+
+    // Continue after the last episode that finished training.
+    let checkpoint =
+        Checkpoint::load(CHECKPOINT).map_err(|e| format!("Could not read checkpoint: {}", e))?;
+    let (start, best_generation) = checkpoint.as_ref().map_or((0, 0), |c| (c.episode, c.best));
+
+    let (input_shape, policy_shape) = sample_shapes(config.pipeline.input);
+    let mut python_model = if start == 0 {
+        let [planes, width, height] = input_shape;
+        let [policy_planes, _, _] = policy_shape;
+        catzero::CatZeroModel::new(
+            &python,
+            (planes, width, height),
+            (policy_planes, width, height),
+            0.001,
+            1.0,
+            10,
+            String::from(MODEL_PATH),
+        )
+        .expect("Could not create new model")
+    } else {
+        catzero::CatZeroModel::load(&python, MODEL_PATH, start, (1, 3, 3))
+            .expect("Could not load model")
+    };
+
+    let mut best: Option<(usize, Arc<TFModel>)> = if best_generation < start {
+        let model = catzero::CatZeroModel::load(&python, MODEL_PATH, best_generation, (1, 3, 3))
+            .and_then(|model| model.to_tf_model(best_generation))
+            .expect("Could not load best model");
+        Some((best_generation, Arc::new(model)))
+    } else {
+        None
+    };
+
+    let mut pipeline = Pipeline::<MyMCTS>::new(config.pipeline.clone());
+    if let Some(path) = book {
+        pipeline = pipeline.with_opening(book_opening(&path)?);
+    }
+    // The games in the replay buffer before a resume are trained on again,
+    // drawn as often as before.
+    if let Some(checkpoint) = &checkpoint {
+        info!(episode = checkpoint.episode, "Resuming");
+        pipeline = pipeline.with_seed(checkpoint.seed);
+        pipeline
+            .buffer
+            .load_saved(checkpoint.window.clone(), game_data_path);
+        if !checkpoint.priorities.is_empty() {
+            if let Err(e) = pipeline.buffer.restore_priorities(&checkpoint.priorities) {
+                warn!(error = %e, "Did not restore the sample priorities");
+            }
+        }
+    }
+
+    let mut game_log = GameLog::open(GAME_LOG).expect("Could not open game log");
+    let mut samples = SampleWriter::open(SAMPLES, input_shape, policy_shape)
+        .map_err(|e| format!("Could not open sample file: {}", e))?;
+
+    let mut calibration_report = CalibrationReport::default();
+    let mut metrics_writers = [
+        MetricsWriter::tensorboard(METRICS),
+        MetricsWriter::csv(METRICS_CSV),
+    ]
+    .into_iter()
+    .filter_map(|writer| {
+        writer
+            .map_err(|e| warn!(error = %e, "Did not open metrics file"))
+            .ok()
+    })
+    .collect::<Vec<_>>();
+    let mut ratings = Ratings::load(RATINGS).expect("Could not read ratings");
+    let mut result_pool = ResultPool::load(RESULTS).expect("Could not read results");
+    let mut registry = ModelRegistry::load(REGISTRY).expect("Could not read model registry");
+    let config_hash = config_hash(&config);
+
+    let stop = pipeline.stop_flag();
+    ctrlc::set_handler(move || {
+        if stop.swap(true, Ordering::SeqCst) {
+            warn!("Aborting");
+            std::process::exit(130);
+        }
+        warn!("Stopping after the games in flight, press Ctrl-C again to abort");
+    })
+    .expect("Could not set signal handler");
+
+    for episode in start..config.episodes {
+        let model: Arc<TFModel> = Arc::new(
+            python_model
+                .to_tf_model(episode)
+                .expect("Could not create tensor model"),
+        );
+
+        let _span = info_span!("episode", episode).entered();
+        info!("Starting episode");
+
+        // The freshly trained model has to beat the best one before it plays
+        // the self-play games.
+        let mut arena_score = None;
+        let mut arena = None;
+        let mut rating = None;
+        let model = match best.take() {
+            Some((generation, best_model)) => {
+                let (result, promoted) = pipeline.gate(model.clone(), best_model.clone());
+                arena_score = Some(result.score());
+                info!(
+                    opponent = generation,
+                    wins = result.wins,
+                    draws = result.draws,
+                    losses = result.losses,
+                    llr = pipeline.config.sprt.map(|sprt| sprt.llr(&result)),
+                    "Played the arena match"
+                );
+
+                // Named like the players of the versus example.
+                let candidate = format!("model:{}", episode);
+                let incumbent = format!("model:{}", generation);
+                ratings.record(&candidate, &incumbent, &result);
+                if let Err(e) = result_pool.append(RESULTS, &candidate, &incumbent, &result) {
+                    warn!(error = %e, "Did not save the arena result");
+                }
+                arena = Some(ArenaRecord {
+                    opponent: generation,
+                    result,
+                });
+                rating = Some(ratings.rating(&candidate));
+                info!(rating = ratings.rating(&candidate).round(), "Rated");
+                let event = format!("arena:{}", episode);
+                if let Err(e) = ratings.append_history(RATINGS, &event, &[&candidate, &incumbent]) {
+                    warn!(error = %e, "Did not save ratings");
+                }
+                if promoted {
+                    model
+                } else {
+                    best = Some((generation, best_model.clone()));
+                    best_model
+                }
+            }
+            None => model,
+        };
+        if best.is_none() {
+            info!("Promoted");
+            best = Some((episode, model.clone()));
+            if let Err(e) = write_latest(Path::new(BEST), episode) {
+                warn!(error = %e, "Did not update best model");
+            }
+        }
+
+        // The model was trained on the games of the episodes in the replay
+        // buffer before this one.
+        let replay_episodes = pipeline.config.replay_episodes.max(1);
+        registry.register(ModelEntry {
+            version: episode,
+            episode,
+            data: episode
+                .checked_sub(1)
+                .map(|last| ((last + 1).saturating_sub(replay_episodes), last)),
+            config: config_hash.clone(),
+            arena,
+            rating,
+            promoted: best.as_ref().map(|(generation, _)| *generation) == Some(episode),
+            input: pipeline.config.input,
+        });
+        if let Err(e) = registry.save(REGISTRY) {
+            warn!(error = %e, "Did not save model registry");
+        }
+
+        let results = match &coordinator {
+            Some(coordinator) => {
+                let generation = best.as_ref().map_or(episode, |(generation, _)| *generation);
+                coordinator.play(generation, pipeline.episode_seeds(), &pipeline.stop_flag())
+            }
+            None => pipeline.self_play(model.clone()),
+        };
+
+        if let Some(rate) = false_resignation_rate(&results) {
+            info!(rate, "False resignations");
+        }
+
+        let calibration = Calibration::from_games(&model, &results, pipeline.config.input);
+        info!("Calibration\n{}", calibration);
+        calibration_report.add(episode, calibration);
+        if let Err(e) = std::fs::write(CALIBRATION, calibration_report.to_string()) {
+            warn!(error = %e, "Did not save calibration report");
+        }
+
+        let data = pipeline.training_data(&results);
+
+        info!(states = data.len(), games = results.len(), "Collected");
+
+        data.print(0..data.len().min(10));
+
+        let (policy_loss, value_loss) =
+            match loss(&model, &data, LOSS_SAMPLES, pipeline.config.input) {
+                Ok((policy_loss, value_loss)) => (Some(policy_loss), Some(value_loss)),
+                Err(e) => {
+                    warn!(error = %e, "Did not measure the losses");
+                    (None, None)
+                }
+            };
+        let metrics = EpisodeMetrics {
+            policy_loss,
+            value_loss,
+            arena_score,
+            ..EpisodeMetrics::from_games(episode, &results)
+        };
+        info!(
+            policy_loss,
+            value_loss,
+            game_length = metrics.game_length,
+            points = metrics.points,
+            "Measured"
+        );
+        for writer in &mut metrics_writers {
+            if let Err(e) = writer.write(&metrics) {
+                warn!(error = %e, "Did not write metrics");
+            }
+        }
+
+        if let Err(e) = data.save(&game_data_path(episode)) {
+            warn!(error = %e, "Did not save game data");
+        }
+
+        if let Err(e) = samples.append(&data) {
+            warn!(error = %e, "Did not store samples");
+        }
+
+        let meta = GameMeta {
+            model: Some(best.as_ref().map_or(episode, |(generation, _)| *generation)),
+            playouts: Some(pipeline.config.playouts),
+            exploration: Some(pipeline.config.exploration),
+            ..Default::default()
+        };
+        if let Err(e) = save_records(&format!("data/games/{}", episode), &results, meta) {
+            warn!(error = %e, "Did not save game records");
+        }
+
+        for (i, result) in results.iter().enumerate() {
+            if let Err(e) = game_log.write(&GameLogEntry::from_game(episode, i, result)) {
+                warn!(game = i, error = %e, "Did not log game");
+            }
+        }
+
+        // The unfinished episode is played again from the start on resume.
+        if pipeline.is_stopping() {
+            info!("Stopped during the episode");
+            return Ok(());
+        }
+
+        pipeline.update_priorities(&model);
+        // The store is opened again for the samples appended above.
+        let store = match pipeline.config.stored_samples {
+            0 => None,
+            _ => SampleStore::open(SAMPLES)
+                .map_err(|e| warn!(error = %e, "Did not open the sample store"))
+                .ok(),
+        };
+        pipeline.train(&mut python_model, &results, store.as_ref());
+
+        let checkpoint = Checkpoint {
+            episode: episode + 1,
+            best: best.as_ref().map_or(episode, |(generation, _)| *generation),
+            seed: pipeline.seed(),
+            window: episode + 1 - pipeline.buffer.episodes()..episode + 1,
+            priorities: if pipeline.config.prioritized.is_some() {
+                pipeline.buffer.priorities()
+            } else {
+                Vec::new()
+            },
+        };
+        if let Err(e) = checkpoint.save(CHECKPOINT) {
+            warn!(error = %e, "Did not save checkpoint");
+        }
+    }
+
+    Ok(())
+}
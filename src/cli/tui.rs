@@ -0,0 +1,347 @@
+// m3c4 play --tui: the board is drawn in the terminal and moves are picked
+// with a cursor instead of typed.
+//
+//     arrows     move the cursor
+//     enter      drop in the cursor column, or switch the selected stone
+//                with the stone under the cursor
+//     s          select the stone under the cursor for a switch
+//     esc        drop the selection
+//     u          take back the last moves
+//     q          quit
+use std::{
+    io::{self, Write},
+    panic,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Print, PrintStyledContent, Stylize},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use m3c4::{
+    action::{BoardAction, Column, Coordinate},
+    agent::Agent,
+    board::{Board, CascadeStep, Cell, TerminalResult},
+    player::Player,
+    session::GameSession,
+    BoardState,
+};
+use mcts::GameState;
+
+// How long each phase of a cascade stays on screen.
+const FRAME: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cursor {
+    x: usize,
+    y: usize,
+    // First stone of a switch.
+    selected: Option<Coordinate>,
+}
+
+impl Cursor {
+    fn coordinate(&self) -> Coordinate {
+        Coordinate::new(self.x as isize, self.y as isize)
+    }
+
+    // Moves the cursor, it stays on the board.
+    fn step(&mut self, (dx, dy): (isize, isize), board: &Board) {
+        self.x = (self.x as isize + dx).clamp(0, board.width() as isize - 1) as usize;
+        self.y = (self.y as isize + dy).clamp(0, board.height() as isize - 1) as usize;
+    }
+
+    // Selecting the selected stone again drops the selection.
+    fn toggle_selection(&mut self) {
+        self.selected = match self.selected {
+            Some(coord) if coord == self.coordinate() => None,
+            _ => Some(self.coordinate()),
+        };
+    }
+
+    fn action(&self, player: Player) -> BoardAction {
+        match self.selected {
+            Some(coord) => BoardAction::SwitchStone(coord, self.coordinate()),
+            None => BoardAction::DropStone(
+                player,
+                Column::new(self.x).expect("Could not create column"),
+            ),
+        }
+    }
+}
+
+// The boards shown while an action resolves: the stones moved by the action,
+// then the board after every cleared phase with the cells that were cleared.
+// The phases are the ones the move is played with.
+fn cascade_frames(state: &BoardState, mov: &BoardAction) -> Vec<(Board, Vec<Coordinate>)> {
+    let mut board = state.board().clone();
+    let steps = board
+        .clone()
+        .make_move_steps(state.current_player(), mov, state.rules());
+    board.apply_action(mov);
+    let mut frames = vec![(board.clone(), Vec::new())];
+
+    for step in steps {
+        if let CascadeStep::Cleared(_, cleared) = step {
+            board.remove_stones(&cleared);
+            frames.push((board.clone(), cleared));
+        }
+    }
+    frames
+}
+
+pub fn run(agent: &mut dyn Agent, human_second: bool) -> Result<(), String> {
+    let mut out = io::stdout();
+    terminal::enable_raw_mode().map_err(|e| e.to_string())?;
+    execute!(out, EnterAlternateScreen, Hide).map_err(|e| e.to_string())?;
+
+    // A panic, in the engine or here, restores the terminal before the
+    // message is printed.
+    let hook = Arc::new(panic::take_hook());
+    let original = hook.clone();
+    panic::set_hook(Box::new(move |info| {
+        restore();
+        original(info);
+    }));
+
+    let result = play(&mut out, agent, human_second);
+
+    // The terminal is restored even when the game failed.
+    restore();
+    panic::set_hook(Box::new(move |info| hook(info)));
+
+    match result.map_err(|e| e.to_string())? {
+        Some(TerminalResult::Win(player)) => println!("{:?} wins", player),
+        Some(_) => println!("Draw"),
+        None => {}
+    }
+    Ok(())
+}
+
+fn restore() {
+    execute!(io::stdout(), Show, LeaveAlternateScreen).ok();
+    terminal::disable_raw_mode().ok();
+}
+
+// Returns the result of the game, None when the human quit.
+fn play<W: Write>(
+    out: &mut W,
+    agent: &mut dyn Agent,
+    human_second: bool,
+) -> io::Result<Option<TerminalResult>> {
+    let mut session = GameSession::default();
+    let board = session.state().board();
+    let mut cursor = Cursor {
+        x: board.width() / 2,
+        y: 0,
+        selected: None,
+    };
+    let mut message = format!("Playing against {}", agent.name());
+
+    loop {
+        let state = session.state().clone();
+        if state.is_terminal() {
+            let result = state.result();
+            message = match result {
+                TerminalResult::Win(player) => format!("{:?} wins, press a key", player),
+                _ => String::from("Draw, press a key"),
+            };
+            draw(out, &state, state.board(), &cursor, &[], &message)?;
+            read_key()?;
+            return Ok(Some(result));
+        }
+
+        let human_turn = (session.history().len() % 2 == 1) == human_second;
+        if !human_turn {
+            draw(out, &state, state.board(), &cursor, &[], "Thinking...")?;
+            let mov = agent.choose(&state).expect("Engine did not choose a move");
+            animate(out, &state, &mov, &cursor)?;
+            session.play(&mov);
+            message = format!("{} played {}", agent.name(), mov);
+            continue;
+        }
+
+        draw(out, &state, state.board(), &cursor, &[], &message)?;
+        let (code, modifiers) = read_key()?;
+        match code {
+            KeyCode::Left => cursor.step((-1, 0), state.board()),
+            KeyCode::Right => cursor.step((1, 0), state.board()),
+            KeyCode::Up => cursor.step((0, 1), state.board()),
+            KeyCode::Down => cursor.step((0, -1), state.board()),
+            KeyCode::Char('s') => cursor.toggle_selection(),
+            KeyCode::Esc => cursor.selected = None,
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                let mov = cursor.action(state.current_player());
+                match state.check_move(&mov) {
+                    Ok(()) => {
+                        animate(out, &state, &mov, &cursor)?;
+                        session.play(&mov);
+                        cursor.selected = None;
+                        message.clear();
+                    }
+                    Err(e) => message = format!("Can't play {}: {}", mov, e),
+                }
+            }
+            // Takes back the move of the engine as well.
+            KeyCode::Char('u') => {
+                session.undo();
+                session.undo();
+                cursor.selected = None;
+            }
+            KeyCode::Char('q') => return Ok(None),
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+// Only presses count, some terminals report releases as well.
+fn read_key() -> io::Result<(KeyCode, KeyModifiers)> {
+    loop {
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                return Ok((key.code, key.modifiers));
+            }
+        }
+    }
+}
+
+fn animate<W: Write>(
+    out: &mut W,
+    state: &BoardState,
+    mov: &BoardAction,
+    cursor: &Cursor,
+) -> io::Result<()> {
+    for (board, cleared) in cascade_frames(state, mov) {
+        draw(out, state, &board, cursor, &cleared, "")?;
+        thread::sleep(FRAME);
+    }
+    Ok(())
+}
+
+// Draws the board with the points of the state. While a cascade is shown
+// the board is already ahead of the state.
+fn draw<W: Write>(
+    out: &mut W,
+    state: &BoardState,
+    board: &Board,
+    cursor: &Cursor,
+    cleared: &[Coordinate],
+    message: &str,
+) -> io::Result<()> {
+    queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+
+    let label_width = (board.height() - 1).to_string().len();
+    for y in (0..board.height()).rev() {
+        queue!(out, Print(format!("{:<width$} |", y, width = label_width)))?;
+        for x in 0..board.width() {
+            let coord = Coordinate::new(x as isize, y as isize);
+            let mut glyph = match board.get(coord) {
+                Cell::Empty if cleared.contains(&coord) => "*".white(),
+                Cell::Empty => " ".reset(),
+                Cell::Filled(Player::Player1) => "X".red(),
+                Cell::Filled(Player::Player2) => "O".yellow(),
+            };
+            if cursor.selected == Some(coord) {
+                glyph = glyph.bold().underlined();
+            }
+            if cursor.coordinate() == coord {
+                glyph = glyph.reverse();
+            }
+            queue!(out, PrintStyledContent(glyph))?;
+        }
+        queue!(out, Print("|\r\n"))?;
+    }
+
+    // Column numbers, with a marker under the column a drop would go to.
+    let indent = " ".repeat(label_width + 2);
+    let columns = (0..board.width())
+        .map(|x| (x % 10).to_string())
+        .collect::<String>();
+    let marker = format!("{}^", " ".repeat(cursor.x));
+    queue!(
+        out,
+        Print(format!(
+            "{}{}\r\n{}{}\r\n\r\n",
+            indent, columns, indent, marker
+        )),
+        PrintStyledContent("X".red()),
+        Print(format!(" {} points  ", state.points(Player::Player1))),
+        PrintStyledContent("O".yellow()),
+        Print(format!(" {} points\r\n", state.points(Player::Player2))),
+        Print(format!(
+            "Turn: {:?}\r\n{}\r\n\r\n",
+            state.current_player(),
+            message
+        )),
+        Print("arrows move, enter plays, s selects a switch, u undoes, q quits\r\n"),
+    )?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use m3c4::{
+        action::{BoardAction, Column, Coordinate},
+        player::Player,
+        testing::StateBuilder,
+        BoardState,
+    };
+
+    use super::{cascade_frames, Cursor};
+
+    #[test]
+    fn cursor_actions() {
+        let state = BoardState::default();
+        let board = state.board();
+        let mut cursor = Cursor {
+            x: 0,
+            y: 0,
+            selected: None,
+        };
+
+        cursor.step((-1, -1), board);
+        assert_eq!((cursor.x, cursor.y), (0, 0));
+        cursor.step((board.width() as isize, 1), board);
+        assert_eq!((cursor.x, cursor.y), (board.width() - 1, 1));
+
+        let drop = cursor.action(Player::Player1);
+        assert_eq!(
+            drop,
+            BoardAction::DropStone(Player::Player1, Column::new(board.width() - 1).unwrap())
+        );
+        assert_eq!(cascade_frames(&state, &drop).len(), 1);
+
+        cursor.toggle_selection();
+        cursor.step((-1, 0), board);
+        assert_eq!(
+            cursor.action(Player::Player1),
+            BoardAction::SwitchStone(
+                Coordinate::new(board.width() as isize - 1, 1),
+                Coordinate::new(board.width() as isize - 2, 1)
+            )
+        );
+        cursor.step((1, 0), board);
+        cursor.toggle_selection();
+        assert_eq!(cursor.selected, None);
+    }
+
+    #[test]
+    fn frames_end_on_the_played_board() {
+        let empty = "        ";
+        let mut state = StateBuilder::new()
+            .board([empty, empty, empty, empty, empty, empty, empty, "XX OO   "])
+            .build();
+        let drop = BoardAction::DropStone(Player::Player1, Column::new(2).unwrap());
+        let frames = cascade_frames(&state, &drop);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].1.len(), 3);
+
+        state.make_move(&drop);
+        m3c4::assert_boards_eq!(frames[1].0, *state.board());
+    }
+}
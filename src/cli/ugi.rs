@@ -0,0 +1,76 @@
+// m3c4 ugi [--model E] [--playouts N] [--threads T]
+// Speaks the UGI protocol on stdin and stdout, see ugi.rs. The engine
+// searches with the heuristic, or with the network of episode E or of the
+// best or latest model of the registry. --playouts is the search without a
+// limit in `go`, both can also be set with `setoption`.
+use std::io;
+
+use m3c4::{
+    alphazero::{MyMCTS, Network, NetworkSearch, SearchOptions},
+    heuristic::{HeuristicEvaluator, HeuristicMCTS},
+    rules::GameRules,
+    ugi::Ugi,
+    BoardState,
+};
+use mcts::{ThreadData, MCTS};
+
+use super::{load_model, resolve_model, take_flag};
+
+const EXPLORATION: f64 = 1.45;
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let model = match take_flag(&mut args, "--model")? {
+        Some(name) => Some(resolve_model(&name)?),
+        None => None,
+    };
+    let playouts = match take_flag(&mut args, "--playouts")? {
+        Some(playouts) => playouts
+            .parse()
+            .map_err(|_| format!("Could not parse playouts: {}", playouts))?,
+        None => 10000,
+    };
+    let threads = match take_flag(&mut args, "--threads")? {
+        Some(threads) => threads
+            .parse()
+            .map_err(|_| format!("Could not parse threads: {}", threads))?,
+        None => 1,
+    };
+    if !args.is_empty() {
+        return Err(String::from(
+            "usage: m3c4 ugi [--model E] [--playouts N] [--threads T]",
+        ));
+    }
+
+    let rules = GameRules::default();
+    match model {
+        Some(episode) => {
+            let (model, input) = load_model(episode)?;
+            let options = SearchOptions::new(EXPLORATION, playouts)
+                .with_threads(threads)
+                .with_input(input);
+            let ugi = Ugi::new(&format!("m3c4 model:{}", episode), rules, move |state| {
+                MyMCTS::create_search(state, Network::Model(model.clone()), options)
+            });
+            serve(ugi.with_playouts(playouts).with_threads(threads))
+        }
+        None => {
+            let ugi = Ugi::new("m3c4", rules, |state: BoardState| {
+                Ok(HeuristicMCTS::create_search(
+                    state,
+                    HeuristicEvaluator::default(),
+                    EXPLORATION,
+                ))
+            });
+            serve(ugi.with_playouts(playouts).with_threads(threads))
+        }
+    }
+}
+
+fn serve<Spec>(mut ugi: Ugi<Spec>) -> Result<(), String>
+where
+    Spec: MCTS<State = BoardState>,
+    ThreadData<Spec>: Default,
+{
+    ugi.run(io::stdin().lock(), io::stdout())
+        .map_err(|e| format!("Could not talk UGI: {}", e))
+}
@@ -0,0 +1,55 @@
+// m3c4 worker <coordinator address> [--games N] [--remote ADDRESS] [--config FILE] [--<key> <value> ...]
+// Plays self-play games for a `m3c4 train --workers ADDRESS` run, N at a
+// time, one per core by default. The generations are loaded from
+// data/models like training does, so the directory has to be shared or
+// synced. With --remote they are evaluated on an inference server instead.
+// The token of the run is read from $M3C4_WORKER_TOKEN.
+// The games are played with the config of the run, the config here only
+// sets how TensorFlow runs on this machine, such as `--device cpu` or
+// `--intra-op-threads 1`.
+use std::{env, sync::Arc, thread};
+
+use catzero::{CatZeroModel, PyEnv};
+use m3c4::{
+    alphazero::{distributed, Network},
+    inference::RemoteModel,
+};
+use tracing::info;
+
+use super::{read_config, take_flag, MODEL_PATH, WORKER_TOKEN};
+
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let remote = take_flag(&mut args, "--remote")?;
+    let games = match take_flag(&mut args, "--games")? {
+        Some(games) => games
+            .parse()
+            .map_err(|_| format!("Could not parse number of games: {}", games))?,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let (config, args) = read_config(args)?;
+    let address = match &args[..] {
+        [address] => address.clone(),
+        [] => return Err(String::from("missing coordinator address")),
+        [_, arg, ..] => return Err(format!("unexpected argument {}", arg)),
+    };
+
+    info!(parallel = games, coordinator = %address, "Working");
+    let token = env::var(WORKER_TOKEN).ok();
+    distributed::work(
+        &address,
+        token.as_deref(),
+        games,
+        |generation| match &remote {
+            Some(remote) => Ok(Network::Remote(RemoteModel::new(remote))),
+            None => {
+                let mut pyenv = PyEnv::new();
+                let python = pyenv.python();
+                CatZeroModel::load(&python, MODEL_PATH, generation, (1, 3, 3))
+                    .and_then(|model| model.to_tf_model(generation))
+                    .map(|model| Network::Model(Arc::new(model)))
+                    .map_err(|e| format!("Could not load model {}: {}", generation, e))
+            }
+        },
+    )
+    .map_err(|e| format!("Could not play for {}: {}", address, e))
+}
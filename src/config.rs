@@ -0,0 +1,489 @@
+// Parameters of a training run, read from a file of `key = value` lines
+// (a flat TOML file) and overridden from the command line:
+//
+//     # Search
+//     exploration = 1.45
+//     playouts = 500
+//     priority = "recency:0.8"
+//     resign_threshold = -0.9
+//     points = "clamp:8"
+//     history = 2
+//     side_to_move = true
+//     # Inference
+//     device = "cpu"
+//     intra_op_threads = 2
+//
+// Keys that are left out keep their default value, optional values can be
+// set to "none". The keys under another one, such as noise_epsilon under
+// noise_alpha, may come before it and are kept while it is "none". There
+// are no [sections].
+use std::{fmt::Display, fs, io, path::Path};
+
+use crate::{
+    alphazero::{
+        DeviceConfig, DirichletNoise, PipelineConfig, PrioritizedSampling, Priority, Sprt,
+    },
+    planes::PointsEncoding,
+};
+
+#[derive(Debug, Clone)]
+pub struct TrainingConfig {
+    pub episodes: usize,
+    pub pipeline: PipelineConfig,
+    // How this process runs TensorFlow, see DeviceConfig::apply. Workers
+    // keep their own settings, they only take the pipeline of the run.
+    pub device: DeviceConfig,
+    held: Held,
+}
+
+// The keys set under a key that is off, they apply once it is turned on.
+#[derive(Debug, Clone, Copy, Default)]
+struct Held {
+    sprt_elo0: Option<f64>,
+    sprt_alpha: Option<f64>,
+    sprt_beta: Option<f64>,
+    noise_epsilon: Option<f64>,
+    priority_alpha: Option<f64>,
+    priority_beta: Option<f64>,
+}
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        Self {
+            episodes: 80,
+            pipeline: PipelineConfig::default(),
+            device: DeviceConfig::default(),
+            held: Held::default(),
+        }
+    }
+}
+
+impl TrainingConfig {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        config.apply(content)?;
+        Ok(config)
+    }
+
+    // Sets the keys in the content, on top of what is set already.
+    pub fn apply(&mut self, content: &str) -> Result<(), String> {
+        for (i, line) in content.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                return Err(format!("line {}: the keys are not in sections", i + 1));
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected key = value", i + 1))?;
+            self.set(key.trim(), value.trim())
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn apply_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.apply(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Sets one parameter, the value may be quoted.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let value = value.trim_matches('"');
+        let pipeline = &mut self.pipeline;
+        let device = &mut self.device;
+        let held = &mut self.held;
+        let parsed = match key {
+            "episodes" => parse(value).map(|v| self.episodes = v),
+            "seed" => parse_optional(value).map(|v| pipeline.seed = v),
+            "exploration" => parse(value).map(|v| pipeline.exploration = v),
+            "games" => parse(value).map(|v| pipeline.games_per_episode = v),
+            "playouts" => parse(value).map(|v| pipeline.playouts = v),
+            "batch_size" => parse(value).map(|v| pipeline.batch_size = v),
+            "epochs" => parse(value).map(|v| pipeline.epochs = v),
+            "learn_retries" => parse(value).map(|v| pipeline.learn_retries = v),
+            "arena_games" => parse(value).map(|v| pipeline.arena_games = v),
+            "promotion_score" => parse(value).map(|v| pipeline.promotion_score = v),
+            // The test is on with an Elo difference to detect.
+            "sprt_elo1" => parse_optional(value).map(|elo1| {
+                let sprt = pipeline.sprt.unwrap_or_default();
+                pipeline.sprt = elo1.map(|elo1| Sprt {
+                    elo0: held.sprt_elo0.unwrap_or(sprt.elo0),
+                    elo1,
+                    alpha: held.sprt_alpha.unwrap_or(sprt.alpha),
+                    beta: held.sprt_beta.unwrap_or(sprt.beta),
+                })
+            }),
+            "sprt_elo0" => parse(value).map(|elo0| {
+                held.sprt_elo0 = Some(elo0);
+                if let Some(sprt) = &mut pipeline.sprt {
+                    sprt.elo0 = elo0;
+                }
+            }),
+            "sprt_alpha" => parse::<f64>(value)
+                .filter(|&p| p > 0.0 && p < 0.5)
+                .map(|alpha| {
+                    held.sprt_alpha = Some(alpha);
+                    if let Some(sprt) = &mut pipeline.sprt {
+                        sprt.alpha = alpha;
+                    }
+                }),
+            "sprt_beta" => parse::<f64>(value)
+                .filter(|&p| p > 0.0 && p < 0.5)
+                .map(|beta| {
+                    held.sprt_beta = Some(beta);
+                    if let Some(sprt) = &mut pipeline.sprt {
+                        sprt.beta = beta;
+                    }
+                }),
+            "replay_episodes" => parse(value).map(|v| pipeline.replay_episodes = v),
+            "stored_samples" => parse(value).map(|v| pipeline.stored_samples = v),
+            "augment" => parse(value).map(|v| pipeline.augment = v),
+            "temperature" => parse(value).map(|v| pipeline.temperature = v),
+            "temperature_plies" => parse(value).map(|v| pipeline.temperature_plies = v),
+            "batch_inference" => parse(value).map(|v| pipeline.batch_inference = v),
+            "leaf_batch" => parse(value).map(|v| pipeline.leaf_batch = v),
+            "virtual_loss" => parse(value).map(|v| pipeline.virtual_loss = v),
+            "resign_threshold" => parse_optional(value).map(|v| pipeline.resign_threshold = v),
+            "resign_plies" => parse(value).map(|v| pipeline.resign_plies = v),
+            "resign_disabled_fraction" => {
+                parse(value).map(|v| pipeline.resign_disabled_fraction = v)
+            }
+            // Dirichlet noise needs a positive alpha.
+            "noise_alpha" => parse_optional::<f64>(value)
+                .filter(|alpha| alpha.map_or(true, |alpha| alpha > 0.0))
+                .map(|alpha| {
+                    let epsilon = pipeline.noise.map_or(0.25, |noise| noise.epsilon);
+                    pipeline.noise = alpha.map(|alpha| DirichletNoise {
+                        alpha,
+                        epsilon: held.noise_epsilon.unwrap_or(epsilon),
+                    })
+                }),
+            "noise_epsilon" => parse::<f64>(value)
+                .filter(|epsilon| (0.0..=1.0).contains(epsilon))
+                .map(|epsilon| {
+                    held.noise_epsilon = Some(epsilon);
+                    if let Some(noise) = &mut pipeline.noise {
+                        noise.epsilon = epsilon;
+                    }
+                }),
+            "priority" => parse_priority(value).map(|priority| {
+                let sampling = pipeline.prioritized.unwrap_or(DEFAULT_SAMPLING);
+                pipeline.prioritized = priority.map(|priority| PrioritizedSampling {
+                    priority,
+                    alpha: held.priority_alpha.unwrap_or(sampling.alpha),
+                    beta: held.priority_beta.unwrap_or(sampling.beta),
+                })
+            }),
+            "priority_alpha" => parse(value).map(|alpha| {
+                held.priority_alpha = Some(alpha);
+                if let Some(sampling) = &mut pipeline.prioritized {
+                    sampling.alpha = alpha;
+                }
+            }),
+            "priority_beta" => parse(value).map(|beta| {
+                held.priority_beta = Some(beta);
+                if let Some(sampling) = &mut pipeline.prioritized {
+                    sampling.beta = beta;
+                }
+            }),
+            // Training reads the u8 planes, which hold scaled points unscaled.
+            "points" => parse(value)
+                .filter(|v| !matches!(v, PointsEncoding::Scaled(_)))
+                .map(|v| pipeline.input.points = v),
+            "history" => parse(value).map(|v| pipeline.input.history = v),
+            "side_to_move" => parse(value).map(|v| pipeline.input.side_to_move = v),
+            "move_count" => parse(value).map(|v| pipeline.input.move_count = v),
+            "points_horizon" => parse_optional(value).map(|v| pipeline.points_horizon = v),
+            "device" => parse(value).map(|v| device.device = v),
+            "intra_op_threads" => parse_optional(value).map(|v| device.intra_op_threads = v),
+            "inter_op_threads" => parse_optional(value).map(|v| device.inter_op_threads = v),
+            "gpu_memory_growth" => parse(value).map(|v| device.gpu_memory_growth = v),
+            _ => return Err(format!("unknown key {}", key)),
+        };
+        parsed.ok_or_else(|| format!("bad value for {}: {}", key, value))
+    }
+
+    // Applies `--key value` pairs, dashes in keys may stand for
+    // underscores. Returns the arguments that are not overrides.
+    pub fn apply_args<I: IntoIterator<Item = String>>(
+        &mut self,
+        args: I,
+    ) -> Result<Vec<String>, String> {
+        let mut rest = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(key) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("missing value for --{}", key))?;
+                    self.set(&key.replace('-', "_"), &value)?;
+                }
+                None => rest.push(arg),
+            }
+        }
+        Ok(rest)
+    }
+}
+
+const DEFAULT_SAMPLING: PrioritizedSampling = PrioritizedSampling {
+    priority: Priority::ValueError,
+    alpha: 0.6,
+    beta: 0.4,
+};
+
+// The line up to a '#' that is not inside a quoted value.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Option<T> {
+    value.parse().ok()
+}
+
+fn parse_optional<T: std::str::FromStr>(value: &str) -> Option<Option<T>> {
+    match value {
+        "none" => Some(None),
+        _ => parse(value).map(Some),
+    }
+}
+
+// "value-error", "recency:<decay>" or "none".
+fn parse_priority(value: &str) -> Option<Option<Priority>> {
+    match value.split_once(':') {
+        Some(("recency", decay)) => Some(Some(Priority::Recency {
+            decay: parse(decay)?,
+        })),
+        None if value == "value-error" => Some(Some(Priority::ValueError)),
+        None if value == "none" => Some(None),
+        _ => None,
+    }
+}
+
+// Writes every key, so the output can be read back as a config file.
+impl Display for TrainingConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let p = &self.pipeline;
+        let optional = |value: Option<String>| value.unwrap_or_else(|| String::from("none"));
+
+        writeln!(f, "episodes = {}", self.episodes)?;
+        writeln!(f, "seed = {}", optional(p.seed.map(|s| s.to_string())))?;
+        writeln!(f, "exploration = {}", p.exploration)?;
+        writeln!(f, "games = {}", p.games_per_episode)?;
+        writeln!(f, "playouts = {}", p.playouts)?;
+        writeln!(f, "batch_size = {}", p.batch_size)?;
+        writeln!(f, "epochs = {}", p.epochs)?;
+        writeln!(f, "learn_retries = {}", p.learn_retries)?;
+        writeln!(f, "arena_games = {}", p.arena_games)?;
+        writeln!(f, "promotion_score = {}", p.promotion_score)?;
+        writeln!(
+            f,
+            "sprt_elo1 = {}",
+            optional(p.sprt.map(|s| s.elo1.to_string()))
+        )?;
+        let held = self.held;
+        let children = [
+            ("sprt_elo0", p.sprt.map(|s| s.elo0).or(held.sprt_elo0)),
+            ("sprt_alpha", p.sprt.map(|s| s.alpha).or(held.sprt_alpha)),
+            ("sprt_beta", p.sprt.map(|s| s.beta).or(held.sprt_beta)),
+        ];
+        for (key, value) in children {
+            if let Some(value) = value {
+                writeln!(f, "{} = {}", key, value)?;
+            }
+        }
+        writeln!(f, "replay_episodes = {}", p.replay_episodes)?;
+        writeln!(f, "stored_samples = {}", p.stored_samples)?;
+        writeln!(f, "augment = {}", p.augment)?;
+        writeln!(f, "temperature = {}", p.temperature)?;
+        writeln!(f, "temperature_plies = {}", p.temperature_plies)?;
+        writeln!(f, "batch_inference = {}", p.batch_inference)?;
+        writeln!(f, "leaf_batch = {}", p.leaf_batch)?;
+        writeln!(f, "virtual_loss = {}", p.virtual_loss)?;
+        writeln!(
+            f,
+            "resign_threshold = {}",
+            optional(p.resign_threshold.map(|t| t.to_string()))
+        )?;
+        writeln!(f, "resign_plies = {}", p.resign_plies)?;
+        writeln!(
+            f,
+            "resign_disabled_fraction = {}",
+            p.resign_disabled_fraction
+        )?;
+        writeln!(
+            f,
+            "noise_alpha = {}",
+            optional(p.noise.map(|n| n.alpha.to_string()))
+        )?;
+        if let Some(epsilon) = p.noise.map(|n| n.epsilon).or(self.held.noise_epsilon) {
+            writeln!(f, "noise_epsilon = {}", epsilon)?;
+        }
+        let priority = p.prioritized.map(|s| match s.priority {
+            Priority::Recency { decay } => format!("recency:{}", decay),
+            Priority::ValueError => String::from("value-error"),
+        });
+        writeln!(f, "priority = \"{}\"", optional(priority))?;
+        let alpha = p.prioritized.map(|s| s.alpha).or(self.held.priority_alpha);
+        if let Some(alpha) = alpha {
+            writeln!(f, "priority_alpha = {}", alpha)?;
+        }
+        let beta = p.prioritized.map(|s| s.beta).or(self.held.priority_beta);
+        if let Some(beta) = beta {
+            writeln!(f, "priority_beta = {}", beta)?;
+        }
+        writeln!(f, "points = \"{}\"", p.input.points)?;
+        writeln!(f, "history = {}", p.input.history)?;
+        writeln!(f, "side_to_move = {}", p.input.side_to_move)?;
+        writeln!(f, "move_count = {}", p.input.move_count)?;
+        writeln!(
+            f,
+            "points_horizon = {}",
+            optional(p.points_horizon.map(|h| h.to_string()))
+        )?;
+        let d = &self.device;
+        writeln!(f, "device = \"{}\"", d.device)?;
+        writeln!(
+            f,
+            "intra_op_threads = {}",
+            optional(d.intra_op_threads.map(|t| t.to_string()))
+        )?;
+        writeln!(
+            f,
+            "inter_op_threads = {}",
+            optional(d.inter_op_threads.map(|t| t.to_string()))
+        )?;
+        writeln!(f, "gpu_memory_growth = {}", d.gpu_memory_growth)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        alphazero::{Device, Priority},
+        planes::PointsEncoding,
+    };
+
+    use super::{strip_comment, TrainingConfig};
+
+    #[test]
+    fn parse_and_override() {
+        let mut config = TrainingConfig::parse(
+            "# A short run\nepisodes = 3\nplayouts = 50 # per move\n\
+             noise_alpha = 0.3\npriority = \"recency:0.5\"\npoints = \"clamp:6\"\n",
+        )
+        .expect("Could not parse config");
+        assert_eq!(config.episodes, 3);
+        assert_eq!(config.pipeline.playouts, 50);
+        assert_eq!(config.pipeline.noise.map(|n| n.epsilon), Some(0.25));
+        assert_eq!(
+            config.pipeline.prioritized.map(|s| s.priority),
+            Some(Priority::Recency { decay: 0.5 })
+        );
+        assert_eq!(config.pipeline.input.points, PointsEncoding::Clamped(6));
+
+        let rest = config
+            .apply_args(
+                [
+                    "--leaf-batch",
+                    "8",
+                    "model:3",
+                    "--resign-threshold",
+                    "-0.9",
+                    "--history",
+                    "2",
+                    "--move-count",
+                    "true",
+                    "--points-horizon",
+                    "4",
+                    "--device",
+                    "cpu",
+                    "--gpu-memory-growth",
+                    "true",
+                    "--sprt-elo1",
+                    "20",
+                    "--sprt-alpha",
+                    "0.1",
+                ]
+                .map(String::from),
+            )
+            .expect("Could not apply overrides");
+        assert_eq!(rest, vec![String::from("model:3")]);
+        assert_eq!(config.pipeline.leaf_batch, 8);
+        assert_eq!(config.pipeline.resign_threshold, Some(-0.9));
+        assert_eq!(config.pipeline.input.history, 2);
+        assert!(config.pipeline.input.move_count);
+        assert_eq!(config.pipeline.points_horizon, Some(4));
+        assert_eq!(config.device.device, Device::Cpu);
+        assert!(config.device.gpu_memory_growth);
+        let sprt = config.pipeline.sprt.expect("SPRT not set");
+        assert_eq!((sprt.elo0, sprt.elo1, sprt.alpha), (0.0, 20.0, 0.1));
+
+        let reparsed = TrainingConfig::parse(&config.to_string()).expect("Could not reparse");
+        assert_eq!(reparsed.to_string(), config.to_string());
+
+        assert!(TrainingConfig::parse("playouts = many").is_err());
+        assert!(TrainingConfig::parse("speed = 3").is_err());
+        assert!(TrainingConfig::parse("points = \"thermometer:0\"").is_err());
+        assert!(TrainingConfig::parse("points = \"scale:6\"").is_err());
+        assert!(TrainingConfig::parse("gpu_memory_growth = 1.5").is_err());
+        assert!(TrainingConfig::parse("noise_alpha = 0").is_err());
+        assert!(TrainingConfig::parse("noise_epsilon = 1.5").is_err());
+        assert!(TrainingConfig::parse("sprt_elo1 = 20\nsprt_beta = 0.5").is_err());
+        assert!(config.apply_args(["--epochs"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn keys_before_their_parent() {
+        let config = TrainingConfig::parse(
+            "noise_epsilon = 0.1\nnoise_alpha = 0.3\n\
+             priority_alpha = 0.7\npriority_beta = 0.5\npriority = \"value-error\"\n",
+        )
+        .expect("Could not parse config");
+        assert_eq!(config.pipeline.noise.map(|n| n.epsilon), Some(0.1));
+        let sampling = config.pipeline.prioritized.expect("Sampling not set");
+        assert_eq!((sampling.alpha, sampling.beta), (0.7, 0.5));
+
+        // Set in the file while the parent is off, turned on by an argument.
+        let mut config = TrainingConfig::parse("noise_alpha = none\nnoise_epsilon = 0.4\n")
+            .expect("Could not parse config");
+        let reparsed = TrainingConfig::parse(&config.to_string()).expect("Could not reparse");
+        assert_eq!(reparsed.to_string(), config.to_string());
+        config
+            .apply_args(["--noise-alpha", "0.5"].map(String::from))
+            .expect("Could not apply overrides");
+        assert_eq!(config.pipeline.noise.map(|n| n.epsilon), Some(0.4));
+    }
+
+    #[test]
+    fn sprt_keys_before_elo1() {
+        let config = TrainingConfig::parse(
+            "sprt_elo0 = 5\nsprt_alpha = 0.1\nsprt_beta = 0.2\nsprt_elo1 = 30\n",
+        )
+        .expect("Could not parse config");
+        let sprt = config.pipeline.sprt.expect("SPRT not set");
+        assert_eq!((sprt.elo0, sprt.elo1), (5.0, 30.0));
+        assert_eq!((sprt.alpha, sprt.beta), (0.1, 0.2));
+        let reparsed = TrainingConfig::parse(&config.to_string()).expect("Could not reparse");
+        assert_eq!(reparsed.to_string(), config.to_string());
+    }
+
+    #[test]
+    fn comments_and_sections() {
+        assert_eq!(strip_comment("a = \"x#y\" # z"), "a = \"x#y\" ");
+        assert_eq!(strip_comment("# all of it"), "");
+        assert!(TrainingConfig::parse("[pipeline]\nplayouts = 50").is_err());
+    }
+}
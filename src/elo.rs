@@ -0,0 +1,415 @@
+// Elo ratings for model generations and baseline bots, updated from match
+// results. The history file holds one line per rating change:
+//
+//     <event> <player> <rating>
+//
+// so the latest line of a player holds its current rating. Player names may
+// have spaces, events may not.
+//
+// The results the ratings come from are kept in a pool, see ResultPool, from
+// which they can be fitted again all at once.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::alphazero::pipeline::ArenaResult;
+
+pub const INITIAL_RATING: f64 = 1000.0;
+// Every two players that met also draw this many games in the fit, so a
+// player that won or lost all its games has a finite rating.
+const PRIOR_DRAWS: f64 = 1.0;
+const FIT_ITERATIONS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ratings {
+    // Largest change a single game can make.
+    pub k: f64,
+    ratings: BTreeMap<String, f64>,
+}
+
+impl Default for Ratings {
+    fn default() -> Self {
+        Self {
+            k: 32.0,
+            ratings: BTreeMap::new(),
+        }
+    }
+}
+
+impl Ratings {
+    // Players that have not played yet have the initial rating.
+    pub fn rating(&self, player: &str) -> f64 {
+        self.ratings.get(player).copied().unwrap_or(INITIAL_RATING)
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.ratings
+            .iter()
+            .map(|(name, &rating)| (name.as_str(), rating))
+    }
+
+    // The score the first player is expected to make in one game.
+    pub fn expected(&self, a: &str, b: &str) -> f64 {
+        1.0 / (1.0 + 10f64.powf((self.rating(b) - self.rating(a)) / 400.0))
+    }
+
+    // Updates both ratings with a match, seen from the first player. All
+    // games of the match are scored against the ratings before it.
+    pub fn record(&mut self, a: &str, b: &str, result: &ArenaResult) {
+        let games = result.games() as f64;
+        if games == 0.0 {
+            return;
+        }
+        let score = result.wins as f64 + 0.5 * result.draws as f64;
+        let change = self.k * (score - games * self.expected(a, b));
+
+        let (rating_a, rating_b) = (self.rating(a), self.rating(b));
+        self.ratings.insert(a.to_string(), rating_a + change);
+        self.ratings.insert(b.to_string(), rating_b - change);
+    }
+
+    // Reads the latest rating of every player in a history file, a missing
+    // file has no ratings yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut ratings = Self::default();
+        for line in content.lines() {
+            let (player, rating) = parse_line(line).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("Bad rating: {}", line))
+            })?;
+            ratings.ratings.insert(player, rating);
+        }
+        Ok(ratings)
+    }
+
+    // Appends the current ratings of the players to the history file.
+    pub fn append_history<P: AsRef<Path>>(
+        &self,
+        path: P,
+        event: &str,
+        players: &[&str],
+    ) -> io::Result<()> {
+        if event.is_empty() || event.contains(char::is_whitespace) {
+            return Err(bad_name("event", event));
+        }
+        if let Some(player) = players
+            .iter()
+            .find(|p| p.trim() != **p || p.is_empty() || p.contains('\n'))
+        {
+            return Err(bad_name("player", player));
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        for player in players {
+            writeln!(file, "{} {} {:.1}", event, player, self.rating(player))?;
+        }
+        file.flush()
+    }
+}
+
+// Every result between two players, kept as the games are played. A line of
+// its file holds a match or a part of one, seen from the first player:
+//
+//     <player> <player> <wins> <draws> <losses>
+//
+// Appending a line per game lets an interrupted tournament go on with the
+// games it has not played. The names can't have spaces.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResultPool {
+    // Seen from the first player of the key, which comes before the second.
+    results: BTreeMap<(String, String), ArenaResult>,
+}
+
+impl ResultPool {
+    // A missing file has no results yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut pool = Self::default();
+        for line in content.lines() {
+            let (a, b, result) = parse_result(line).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("Bad result: {}", line))
+            })?;
+            pool.record(&a, &b, &result);
+        }
+        Ok(pool)
+    }
+
+    pub fn record(&mut self, a: &str, b: &str, result: &ArenaResult) {
+        let (key, result) = if a <= b {
+            ((a.to_string(), b.to_string()), *result)
+        } else {
+            ((b.to_string(), a.to_string()), flipped(result))
+        };
+        let total = self.results.entry(key).or_default();
+        total.wins += result.wins;
+        total.draws += result.draws;
+        total.losses += result.losses;
+    }
+
+    // Records the result and appends it to the file.
+    pub fn append<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        a: &str,
+        b: &str,
+        result: &ArenaResult,
+    ) -> io::Result<()> {
+        for player in [a, b] {
+            if player.is_empty() || player.contains(char::is_whitespace) {
+                return Err(bad_name("player", player));
+            }
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(
+            file,
+            "{} {} {} {} {}",
+            a, b, result.wins, result.draws, result.losses
+        )?;
+        file.flush()?;
+        self.record(a, b, result);
+        Ok(())
+    }
+
+    // All games between the players, seen from the first.
+    pub fn result(&self, a: &str, b: &str) -> ArenaResult {
+        if a <= b {
+            let key = (a.to_string(), b.to_string());
+            self.results.get(&key).copied().unwrap_or_default()
+        } else {
+            flipped(&self.result(b, a))
+        }
+    }
+
+    pub fn players(&self) -> Vec<&str> {
+        self.results
+            .keys()
+            .flat_map(|(a, b)| [a.as_str(), b.as_str()])
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    // The ratings that explain all results best, fitted together instead of
+    // one match after the other, so the order of the matches does not
+    // matter. Anchored players keep their rating and the others are rated
+    // against them, such as new agents against the ratings of historic ones.
+    pub fn ratings(&self, anchors: &[(&str, f64)]) -> Ratings {
+        let players = self.players();
+        let anchor = |player: &str| {
+            anchors
+                .iter()
+                .find(|(anchor, _)| *anchor == player)
+                .map(|&(_, rating)| rating)
+        };
+        let index = |player: &str| {
+            players
+                .binary_search(&player)
+                .expect("Player of a result is missing")
+        };
+
+        // Strengths relative to the initial rating, the fit is the
+        // minorization-maximization of the Bradley-Terry model with a draw
+        // counting as half a win.
+        let mut strength = players
+            .iter()
+            .map(|&p| anchor(p).map_or(1.0, |r| 10f64.powf((r - INITIAL_RATING) / 400.0)))
+            .collect::<Vec<_>>();
+        let mut score = vec![0.0; players.len()];
+        let pairs = self
+            .results
+            .iter()
+            .map(|((a, b), result)| {
+                let (a, b) = (index(a), index(b));
+                let points = result.wins as f64 + 0.5 * (result.draws as f64 + PRIOR_DRAWS);
+                let games = result.games() as f64 + PRIOR_DRAWS;
+                score[a] += points;
+                score[b] += games - points;
+                (a, b, games)
+            })
+            .collect::<Vec<_>>();
+
+        for _ in 0..FIT_ITERATIONS {
+            let mut expected = vec![0.0; players.len()];
+            for &(a, b, games) in &pairs {
+                let share = games / (strength[a] + strength[b]);
+                expected[a] += share;
+                expected[b] += share;
+            }
+
+            let mut change = 0f64;
+            for (i, &player) in players.iter().enumerate() {
+                if anchor(player).is_none() {
+                    let fitted = score[i] / expected[i];
+                    change = change.max((fitted / strength[i]).ln().abs());
+                    strength[i] = fitted;
+                }
+            }
+            if change < 1e-10 {
+                break;
+            }
+        }
+
+        // Only the differences are fitted, without anchors the pool
+        // averages the initial rating.
+        let unanchored = players.iter().all(|&p| anchor(p).is_none());
+        let shift = if unanchored && !players.is_empty() {
+            -400.0 * strength.iter().map(|s| s.log10()).sum::<f64>() / players.len() as f64
+        } else {
+            0.0
+        };
+        let mut ratings = Ratings::default();
+        for (player, strength) in players.into_iter().zip(strength) {
+            let rating =
+                anchor(player).unwrap_or(INITIAL_RATING + 400.0 * strength.log10() + shift);
+            ratings.ratings.insert(player.to_string(), rating);
+        }
+        ratings
+    }
+}
+
+fn flipped(result: &ArenaResult) -> ArenaResult {
+    ArenaResult {
+        wins: result.losses,
+        draws: result.draws,
+        losses: result.wins,
+    }
+}
+
+fn parse_result(line: &str) -> Option<(String, String, ArenaResult)> {
+    let parts = line.split_whitespace().collect::<Vec<_>>();
+    match parts.as_slice() {
+        [a, b, wins, draws, losses] => Some((
+            a.to_string(),
+            b.to_string(),
+            ArenaResult {
+                wins: wins.parse().ok()?,
+                draws: draws.parse().ok()?,
+                losses: losses.parse().ok()?,
+            },
+        )),
+        _ => None,
+    }
+}
+
+// The event is the first word and the rating the last, the player is what
+// is between them.
+fn parse_line(line: &str) -> Option<(String, f64)> {
+    let (_event, rest) = line.split_once(' ')?;
+    let (player, rating) = rest.rsplit_once(' ')?;
+    if player.is_empty() || player.trim() != player {
+        return None;
+    }
+    Some((player.to_string(), rating.parse().ok()?))
+}
+
+fn bad_name(kind: &str, name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Can't store the {} {:?} in the file", kind, name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{alphazero::pipeline::ArenaResult, testing::temp_path};
+
+    use super::{Ratings, ResultPool, INITIAL_RATING};
+
+    #[test]
+    fn record_and_reload() {
+        let mut ratings = Ratings::default();
+        assert!((ratings.expected("model:1", "random") - 0.5).abs() < 1e-9);
+
+        ratings.record(
+            "model:1",
+            "random",
+            &ArenaResult {
+                wins: 3,
+                draws: 1,
+                losses: 0,
+            },
+        );
+        assert!((ratings.rating("model:1") - (INITIAL_RATING + 48.0)).abs() < 1e-9);
+        assert!((ratings.rating("random") - (INITIAL_RATING - 48.0)).abs() < 1e-9);
+        assert!(ratings.expected("model:1", "random") > 0.5);
+
+        ratings.record("onnx:my model.onnx", "random", &ArenaResult::default());
+        let path = temp_path("elo_test.txt");
+        std::fs::remove_file(&path).ok();
+        ratings
+            .append_history(
+                &path,
+                "versus",
+                &["model:1", "random", "onnx:my model.onnx"],
+            )
+            .unwrap();
+        assert!(ratings
+            .append_history(&path, "two words", &["random"])
+            .is_err());
+        assert!(ratings
+            .append_history(&path, "versus", &["two\nlines"])
+            .is_err());
+        let loaded = Ratings::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!((loaded.rating("model:1") - 1048.0).abs() < 1e-9);
+        assert_eq!(loaded.rating("onnx:my model.onnx"), INITIAL_RATING);
+        assert_eq!(loaded.players().count(), 3);
+    }
+
+    #[test]
+    fn result_pool() {
+        let result = |wins, draws, losses| ArenaResult {
+            wins,
+            draws,
+            losses,
+        };
+        let path = temp_path("results_test.txt");
+        std::fs::remove_file(&path).ok();
+
+        let mut pool = ResultPool::default();
+        pool.append(&path, "model:2", "model:1", &result(6, 2, 2))
+            .unwrap();
+        pool.append(&path, "model:1", "random", &result(8, 1, 1))
+            .unwrap();
+        pool.append(&path, "model:1", "model:2", &result(1, 0, 1))
+            .unwrap();
+        assert!(pool
+            .append(&path, "onnx:my model.onnx", "random", &result(1, 0, 0))
+            .is_err());
+        assert_eq!(pool.result("model:1", "model:2"), result(3, 2, 7));
+        assert_eq!(pool.players(), vec!["model:1", "model:2", "random"]);
+
+        let loaded = ResultPool::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, pool);
+
+        let ratings = pool.ratings(&[]);
+        assert!(ratings.rating("model:2") > ratings.rating("model:1"));
+        assert!(ratings.rating("model:1") > ratings.rating("random"));
+
+        // Anchoring moves the pool, the differences stay.
+        let anchored = pool.ratings(&[("random", 0.0)]);
+        assert_eq!(anchored.rating("random"), 0.0);
+        let difference = |r: &Ratings| r.rating("model:2") - r.rating("model:1");
+        assert!((difference(&anchored) - difference(&ratings)).abs() < 1e-6);
+
+        let mut even = ResultPool::default();
+        even.record("a", "b", &result(3, 4, 3));
+        let ratings = even.ratings(&[]);
+        assert!((ratings.rating("a") - INITIAL_RATING).abs() < 1e-6);
+        assert!((ratings.rating("b") - INITIAL_RATING).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,148 @@
+// Compact binary form of a position, for storing many of them cheaply.
+//
+// byte 0      format version
+// bytes 1-2   board width and height
+// byte 3      player to move, 1 or 2
+// bytes 4-19  points of player 1 and player 2, u64 little endian
+// bytes 20..  2 bits per cell, column by column from the bottom, the lowest
+//             bits first: 0 empty, 1 player 1, 2 player 2
+use crate::{
+    action::Coordinate,
+    board::{Board, Cell},
+    player::Player,
+    rules::GameRules,
+    BoardState,
+};
+
+pub const VERSION: u8 = 2;
+
+const HEADER: usize = 20;
+
+// Length of the encoding of a position on a board of this size.
+pub fn encoded_len(width: usize, height: usize) -> usize {
+    HEADER + (width * height).div_ceil(4)
+}
+
+impl BoardState {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (width, height) = (self.board.width(), self.board.height());
+        let mut bytes = vec![0; encoded_len(width, height)];
+        bytes[0] = VERSION;
+        bytes[1] = width as u8;
+        bytes[2] = height as u8;
+        bytes[3] = match self.current_player {
+            Player::Player1 => 1,
+            Player::Player2 => 2,
+        };
+        bytes[4..12].copy_from_slice(&(self.player_1_points as u64).to_le_bytes());
+        bytes[12..20].copy_from_slice(&(self.player_2_points as u64).to_le_bytes());
+
+        for (i, coord) in cells(width, height).enumerate() {
+            let code = match self.board.get(coord) {
+                Cell::Empty => 0,
+                Cell::Filled(Player::Player1) => 1,
+                Cell::Filled(Player::Player2) => 2,
+            };
+            bytes[HEADER + i / 4] |= code << (2 * (i % 4));
+        }
+
+        bytes
+    }
+
+    // The rules other than the board size are not stored, the defaults are
+    // used. Returns None for malformed input, another version or a position
+    // no game can reach.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER || bytes[0] != VERSION {
+            return None;
+        }
+        let (width, height) = (bytes[1] as usize, bytes[2] as usize);
+        if !Board::fits(width, height) || bytes.len() != encoded_len(width, height) {
+            return None;
+        }
+
+        let rules = GameRules {
+            width,
+            height,
+            ..Default::default()
+        };
+        let mut state = BoardState::new(rules);
+        state.current_player = match bytes[3] {
+            1 => Player::Player1,
+            2 => Player::Player2,
+            _ => return None,
+        };
+        state.player_1_points = points(&bytes[4..12])?;
+        state.player_2_points = points(&bytes[12..20])?;
+
+        for (i, coord) in cells(width, height).enumerate() {
+            let cell = match (bytes[HEADER + i / 4] >> (2 * (i % 4))) & 0b11 {
+                0 => Cell::Empty,
+                1 => Cell::Filled(Player::Player1),
+                2 => Cell::Filled(Player::Player2),
+                _ => return None,
+            };
+            state.board.set(cell, coord);
+        }
+        state.winner = state.board.get_board_terminal_status(&state.rules);
+        state.validate().ok()?;
+
+        Some(state)
+    }
+}
+
+// None when the points do not fit this platform's usize.
+fn points(bytes: &[u8]) -> Option<usize> {
+    let points = u64::from_le_bytes(bytes.try_into().ok()?);
+    usize::try_from(points).ok()
+}
+
+fn cells(width: usize, height: usize) -> impl Iterator<Item = Coordinate> {
+    (0..width).flat_map(move |x| (0..height).map(move |y| Coordinate::new(x as isize, y as isize)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{BoardAction, Column},
+        player::Player,
+        rules::GameRules,
+        BoardState,
+    };
+
+    use super::{encoded_len, HEADER};
+
+    #[test]
+    fn round_trip() {
+        let mut state = BoardState::default();
+        for (player, col) in [
+            (Player::Player1, 3),
+            (Player::Player2, 3),
+            (Player::Player1, 7),
+        ] {
+            state.play(&BoardAction::DropStone(player, Column::new(col).unwrap()));
+        }
+        state.player_1_points = 2;
+        state.player_2_points = 70_000;
+
+        let bytes = state.to_bytes();
+        assert_eq!(bytes.len(), encoded_len(8, 8));
+        assert_eq!(bytes.len(), 36);
+
+        let loaded = BoardState::from_bytes(&bytes).expect("Could not decode state");
+        assert_eq!(loaded.board(), state.board());
+        assert_eq!(loaded.zobrist(), state.zobrist());
+        assert_eq!(loaded.rules(), &GameRules::default());
+        assert_eq!(loaded.player_2_points, 70_000);
+
+        assert!(BoardState::from_bytes(&bytes[..30]).is_none());
+        let mut other_version = bytes.clone();
+        other_version[0] = 0;
+        assert!(BoardState::from_bytes(&other_version).is_none());
+
+        // A stone of player 1 above the empty bottom cell of column 0.
+        let mut floating = BoardState::default().to_bytes();
+        floating[HEADER] = 1 << 2;
+        assert!(BoardState::from_bytes(&floating).is_none());
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use crate::{
+    action::BoardAction,
+    board::{MoveResult, TerminalResult},
+    rules::GameRules,
+    session::{Clocks, GameSession},
+    BoardState,
+};
+use mcts::GameState;
+
+// Gets notified of everything that happens in a game driven by a GameEngine.
+pub trait GameObserver {
+    fn on_move(&mut self, _state: &BoardState, _mov: &BoardAction, _results: &[MoveResult]) {}
+    fn on_undo(&mut self, _state: &BoardState, _mov: &BoardAction) {}
+    fn on_game_over(&mut self, _state: &BoardState, _result: &TerminalResult) {}
+}
+
+// The high level entry point for front ends and self-play: one game with its
+// rules, clocks and history, and the observers that follow it.
+#[derive(Default)]
+pub struct GameEngine {
+    session: GameSession,
+    observers: Vec<Box<dyn GameObserver + Send>>,
+}
+
+impl GameEngine {
+    pub fn new(rules: GameRules) -> Self {
+        Self::from_session(GameSession::new(rules))
+    }
+
+    pub fn with_clocks(rules: GameRules, clocks: Clocks) -> Self {
+        Self::from_session(GameSession::with_clocks(rules, clocks))
+    }
+
+    pub fn from_session(session: GameSession) -> Self {
+        Self {
+            session,
+            observers: Vec::new(),
+        }
+    }
+
+    pub fn add_observer<O: GameObserver + Send + 'static>(&mut self, observer: O) {
+        self.observers.push(Box::new(observer));
+    }
+
+    pub fn session(&self) -> &GameSession {
+        &self.session
+    }
+
+    pub fn state(&self) -> &BoardState {
+        self.session.state()
+    }
+
+    pub fn history(&self) -> Vec<BoardAction> {
+        self.session.history()
+    }
+
+    pub fn legal_moves(&self) -> Vec<BoardAction> {
+        self.state().available_moves().into_vec()
+    }
+
+    pub fn result(&self) -> TerminalResult {
+        self.state().result()
+    }
+
+    // Plays the action if it is legal, otherwise explains why it is not.
+    pub fn play(&mut self, mov: &BoardAction) -> Result<Vec<MoveResult>, String> {
+        self.play_timed(mov, Duration::ZERO)
+    }
+
+    pub fn play_timed(
+        &mut self,
+        mov: &BoardAction,
+        elapsed: Duration,
+    ) -> Result<Vec<MoveResult>, String> {
+        if let Some(reason) = self.state().explain_illegal(mov) {
+            return Err(reason);
+        }
+
+        let results = self.session.play_timed(mov, elapsed);
+        for observer in &mut self.observers {
+            observer.on_move(self.session.state(), mov, &results);
+        }
+
+        let result = self.result();
+        if result != TerminalResult::None {
+            for observer in &mut self.observers {
+                observer.on_game_over(self.session.state(), &result);
+            }
+        }
+
+        Ok(results)
+    }
+
+    pub fn undo(&mut self) -> Option<BoardAction> {
+        let mov = self.session.undo()?;
+        for observer in &mut self.observers {
+            observer.on_undo(self.session.state(), &mov);
+        }
+        Some(mov)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        action::BoardAction,
+        board::{MoveResult, TerminalResult},
+        player::Player,
+        testing::col,
+        BoardState,
+    };
+
+    use super::{GameEngine, GameObserver};
+
+    struct Counter(Arc<Mutex<usize>>);
+
+    impl GameObserver for Counter {
+        fn on_move(&mut self, _: &BoardState, _: &BoardAction, _: &[MoveResult]) {
+            *self.0.lock().unwrap() += 1;
+        }
+    }
+
+    #[test]
+    fn play_and_undo() {
+        let moves = Arc::new(Mutex::new(0));
+        let mut engine = GameEngine::default();
+        engine.add_observer(Counter(moves.clone()));
+
+        engine
+            .play(&BoardAction::DropStone(Player::Player1, col(3)))
+            .unwrap();
+        assert!(engine
+            .play(&BoardAction::DropStone(Player::Player1, col(3)))
+            .is_err());
+        engine
+            .play(&BoardAction::DropStone(Player::Player2, col(3)))
+            .unwrap();
+
+        assert_eq!(*moves.lock().unwrap(), 2);
+        assert_eq!(engine.history().len(), 2);
+
+        assert_eq!(
+            engine.undo(),
+            Some(BoardAction::DropStone(Player::Player2, col(3)))
+        );
+        assert_eq!(engine.history().len(), 1);
+        assert_eq!(engine.legal_moves().len(), 8);
+        assert_eq!(engine.result(), TerminalResult::None);
+    }
+}
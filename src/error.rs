@@ -0,0 +1,105 @@
+use std::fmt::Display;
+
+use crate::{
+    action::{Column, Coordinate},
+    board::TerminalResult,
+    player::Player,
+};
+
+// Why an action can not be played in a state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    GameOver,
+    // Holds the player whose turn it is.
+    WrongPlayer(Player),
+    ColumnFull(Column),
+    ColumnOutOfBounds(Column),
+    OutOfBounds(Coordinate),
+    NotEnoughPoints,
+    IllegalSwitch(SwitchError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchError {
+    NotAdjacent,
+    EmptyCell(Coordinate),
+    SamePlayer,
+}
+
+// In words that can be shown to a human player.
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::GameOver => f.write_str("the game is already over"),
+            MoveError::WrongPlayer(player) => write!(f, "it is {:?}'s turn", player),
+            MoveError::ColumnFull(col) => write!(f, "column {} is full", col),
+            MoveError::ColumnOutOfBounds(col) => write!(f, "column {} is outside the board", col),
+            MoveError::OutOfBounds(coord) => {
+                write!(
+                    f,
+                    "cell ({}, {}) is outside the board",
+                    coord.x(),
+                    coord.y()
+                )
+            }
+            MoveError::NotEnoughPoints => f.write_str("you have 0 switch points"),
+            MoveError::IllegalSwitch(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Display for SwitchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SwitchError::NotAdjacent => f.write_str("cells are not adjacent"),
+            SwitchError::EmptyCell(coord) => {
+                write!(f, "cell ({}, {}) is empty", coord.x(), coord.y())
+            }
+            SwitchError::SamePlayer => f.write_str("both stones belong to the same player"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+// A broken invariant of a board or a state, as found by validate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantError {
+    // Both players have a stone in the cell.
+    SharedCell(Coordinate),
+    OutsideBoard,
+    // A stone above an empty cell.
+    FloatingStone(Coordinate),
+    // Lines that are left after the cascade, with their cells.
+    ResidualMatch(Vec<Coordinate>),
+    // The hash that is kept up to date does not match the stones.
+    StaleHash,
+    // The result stored in the state is not the one on the board.
+    WrongResult {
+        stored: TerminalResult,
+        board: TerminalResult,
+    },
+}
+
+impl Display for InvariantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvariantError::SharedCell(coord) => {
+                write!(f, "cell ({}, {}) has two stones", coord.x(), coord.y())
+            }
+            InvariantError::OutsideBoard => f.write_str("a stone is outside the board"),
+            InvariantError::FloatingStone(coord) => {
+                write!(f, "stone at ({}, {}) is floating", coord.x(), coord.y())
+            }
+            InvariantError::ResidualMatch(cells) => {
+                write!(f, "{} cells of a match were not cleared", cells.len())
+            }
+            InvariantError::StaleHash => f.write_str("the hash does not match the stones"),
+            InvariantError::WrongResult { stored, board } => {
+                write!(f, "result is {:?} but the board shows {:?}", stored, board)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantError {}
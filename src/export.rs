@@ -0,0 +1,305 @@
+// Training data as NumPy arrays, for training scripts and notebooks outside
+// catzero. An `.npz` file holds four arrays:
+//
+//     inputs    uint8    [n, 4, width, height]
+//     policies  float32  [n, planes, width, height]
+//     values    float32  [n]
+//     wdl       float32  [n, 3]
+//     points    float32  [n, 2]
+//
+// The win, draw and loss targets are spread from the values, a WDL head is
+// trained on them and a scalar head on the values. The points array is only
+// written with the auxiliary targets of self-play, the points of the player
+// to move and of the opponent some plies ahead.
+//
+//     data = numpy.load("samples.npz")
+//     data["inputs"].shape
+//
+// Or as a TFRecord file of tf.train.Example records for a tf.data pipeline,
+// the input planes are raw bytes next to their shape:
+//
+//     features = {
+//         "input": tf.io.FixedLenFeature([], tf.string),
+//         "input_shape": tf.io.FixedLenFeature([3], tf.int64),
+//         "policy": tf.io.FixedLenFeature([planes * width * height], tf.float32),
+//         "policy_shape": tf.io.FixedLenFeature([3], tf.int64),
+//         "value": tf.io.FixedLenFeature([1], tf.float32),
+//         "wdl": tf.io.FixedLenFeature([3], tf.float32),
+//         "points": tf.io.FixedLenFeature([2], tf.float32),  # when written
+//     }
+//     dataset = tf.data.TFRecordDataset("samples.tfrecord").map(
+//         lambda record: tf.io.parse_single_example(record, features))
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+use catzero::{Tensor, TrainingData};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    tfrecord::{put_bytes, put_floats, put_varints, write_record},
+    wdl::Wdl,
+};
+
+// The arrays are stored uncompressed, like numpy.savez does. The points
+// targets are one per sample.
+pub fn write_npz<P: AsRef<Path>>(
+    path: P,
+    data: &TrainingData,
+    points: Option<&[[f32; 2]]>,
+) -> io::Result<()> {
+    check_points(data, points)?;
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let inputs = data.inputs.iter().flatten().flatten().flatten().copied();
+    let policies = data.output_policy.iter().flatten().flatten().flatten();
+    let mut arrays = vec![
+        (
+            "inputs.npy",
+            npy_header("|u1", &shape(&data.inputs)),
+            inputs.collect::<Vec<_>>(),
+        ),
+        (
+            "policies.npy",
+            npy_header("<f4", &shape(&data.output_policy)),
+            policies.flat_map(|p| p.to_le_bytes()).collect(),
+        ),
+        (
+            "values.npy",
+            npy_header("<f4", &[data.len()]),
+            data.output_value
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+        ),
+        (
+            "wdl.npy",
+            npy_header("<f4", &[data.len(), 3]),
+            data.output_value
+                .iter()
+                .flat_map(|&v| Wdl::from_value(v).to_array())
+                .flat_map(|p| p.to_le_bytes())
+                .collect(),
+        ),
+    ];
+    if let Some(points) = points {
+        arrays.push((
+            "points.npy",
+            npy_header("<f4", &[data.len(), 2]),
+            points
+                .iter()
+                .flatten()
+                .flat_map(|p| p.to_le_bytes())
+                .collect(),
+        ));
+    }
+    for (name, header, values) in arrays {
+        zip.start_file(name, options).map_err(io::Error::other)?;
+        zip.write_all(&header)?;
+        zip.write_all(&values)?;
+    }
+    zip.finish().map_err(io::Error::other)?;
+    Ok(())
+}
+
+// An Example record per sample. Records can be appended to an existing file.
+pub fn write_tfrecord<W: Write>(
+    out: &mut W,
+    data: &TrainingData,
+    points: Option<&[[f32; 2]]>,
+) -> io::Result<()> {
+    check_points(data, points)?;
+    for i in 0..data.len() {
+        let input = &data.inputs[i];
+        let policy = &data.output_policy[i];
+        let input_bytes = input
+            .iter()
+            .flatten()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        let policy_values = policy
+            .iter()
+            .flatten()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+
+        let mut features = Vec::new();
+        let points = points.map(|points| ("points", float_feature(&points[i])));
+        for (key, feature) in [
+            ("input", bytes_feature(&input_bytes)),
+            ("input_shape", int64_feature(&sample_shape(input))),
+            ("policy", float_feature(&policy_values)),
+            ("policy_shape", int64_feature(&sample_shape(policy))),
+            ("value", float_feature(&[data.output_value[i]])),
+            (
+                "wdl",
+                float_feature(&Wdl::from_value(data.output_value[i]).to_array()),
+            ),
+        ]
+        .into_iter()
+        .chain(points)
+        {
+            let mut entry = Vec::new();
+            put_bytes(&mut entry, 1, key.as_bytes());
+            put_bytes(&mut entry, 2, &feature);
+            put_bytes(&mut features, 1, &entry);
+        }
+        let mut example = Vec::new();
+        put_bytes(&mut example, 1, &features);
+        write_record(out, &example)?;
+    }
+    out.flush()
+}
+
+fn check_points(data: &TrainingData, points: Option<&[[f32; 2]]>) -> io::Result<()> {
+    match points {
+        Some(points) if points.len() != data.len() => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} points targets for {} samples", points.len(), data.len()),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn bytes_feature(bytes: &[u8]) -> Vec<u8> {
+    let mut list = Vec::new();
+    put_bytes(&mut list, 1, bytes);
+    let mut feature = Vec::new();
+    put_bytes(&mut feature, 1, &list);
+    feature
+}
+
+fn float_feature(values: &[f32]) -> Vec<u8> {
+    let mut list = Vec::new();
+    put_floats(&mut list, 1, values);
+    let mut feature = Vec::new();
+    put_bytes(&mut feature, 2, &list);
+    feature
+}
+
+fn int64_feature(values: &[u64]) -> Vec<u8> {
+    let mut list = Vec::new();
+    put_varints(&mut list, 1, values);
+    let mut feature = Vec::new();
+    put_bytes(&mut feature, 3, &list);
+    feature
+}
+
+fn sample_shape<T>(tensor: &Tensor<T>) -> [u64; 3] {
+    let plane = tensor.first();
+    [
+        tensor.len() as u64,
+        plane.map_or(0, |p| p.len()) as u64,
+        plane.and_then(|p| p.first()).map_or(0, |r| r.len()) as u64,
+    ]
+}
+
+// The samples followed by the shape of the first one, every sample has the
+// same shape.
+fn shape<T>(tensors: &[Tensor<T>]) -> Vec<usize> {
+    let first = tensors.first();
+    vec![
+        tensors.len(),
+        first.map_or(0, |t| t.len()),
+        first.and_then(|t| t.first()).map_or(0, |p| p.len()),
+        first
+            .and_then(|t| t.first())
+            .and_then(|p| p.first())
+            .map_or(0, |r| r.len()),
+    ]
+}
+
+// Version 1.0 of the .npy format: the magic string, the version, the length
+// of the header and a Python dict literal padded so the data is aligned to
+// 64 bytes.
+fn npy_header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let dims = match shape {
+        [n] => format!("{},", n),
+        _ => shape
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let mut dict = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}), }}",
+        descr, dims
+    );
+    let padding = 63 - (10 + dict.len()) % 64;
+    dict.push_str(&" ".repeat(padding));
+    dict.push('\n');
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use catzero::TrainingData;
+
+    use crate::testing::temp_path;
+
+    use super::{npy_header, write_npz, write_tfrecord};
+
+    #[test]
+    fn write_arrays() {
+        let header = npy_header("<f4", &[3]);
+        assert_eq!(header.len() % 64, 0);
+        assert!(String::from_utf8_lossy(&header).contains("'shape': (3,)"));
+
+        let mut input = vec![vec![vec![0u8; 2]; 3]; 4];
+        input[1][2][1] = 1;
+        let data = TrainingData {
+            inputs: vec![input.clone(), input],
+            output_policy: vec![vec![vec![vec![0.5f32; 2]; 3]; 5]; 2],
+            output_value: vec![1.0, -1.0],
+        };
+        let path = temp_path("export_test.npz");
+        write_npz(&path, &data, Some(&[[2.0, 1.0], [0.0, 3.0]])).expect("Could not write npz");
+
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut read = |name: &str| {
+            let mut bytes = Vec::new();
+            zip.by_name(name).unwrap().read_to_end(&mut bytes).unwrap();
+            bytes
+        };
+        let inputs = read("inputs.npy");
+        let policies = read("policies.npy");
+        let values = read("values.npy");
+        let wdl = read("wdl.npy");
+        let points = read("points.npy");
+        std::fs::remove_file(&path).ok();
+
+        assert!(String::from_utf8_lossy(&inputs).contains("'shape': (2, 4, 3, 2)"));
+        assert_eq!(inputs.len(), 128 + 2 * 4 * 3 * 2);
+        assert_eq!(inputs[128 + 6 + 2 * 2 + 1], 1);
+        assert_eq!(policies.len(), 128 + 4 * 2 * 5 * 3 * 2);
+        assert_eq!(values[values.len() - 4..], (-1.0f32).to_le_bytes());
+        // The second sample is a loss.
+        assert!(String::from_utf8_lossy(&wdl).contains("'shape': (2, 3)"));
+        assert_eq!(wdl[wdl.len() - 4..], 1.0f32.to_le_bytes());
+        assert_eq!(points[points.len() - 4..], 3.0f32.to_le_bytes());
+        assert!(write_npz(&path, &data, Some(&[[0.0, 0.0]])).is_err());
+
+        let mut records = Vec::new();
+        write_tfrecord(&mut records, &data, None).expect("Could not write records");
+        let length = u64::from_le_bytes(records[..8].try_into().unwrap()) as usize;
+        assert_eq!(records.len(), 2 * (16 + length));
+        let example = &records[12..12 + length];
+        let contains = |part: &[u8]| example.windows(part.len()).any(|w| w == part);
+        assert!(contains(b"input_shape"));
+        // The packed shape of the input: 4 planes of 3 by 2.
+        assert!(contains(&[0x1a, 5, 0x0a, 3, 4, 3, 2]));
+        // The value of the first sample, 1.0 in a float list.
+        assert!(contains(&[0x12, 6, 0x0a, 4, 0, 0, 0x80, 0x3f]));
+    }
+}
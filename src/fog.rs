@@ -0,0 +1,260 @@
+// Experimental hidden-information variant of the game.
+//
+// A player only sees the stones in columns they have dropped into and in rows
+// that were revealed to them: rows they switched stones in and rows in which
+// a match was cleared. Column heights and points are public.
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    action::{BoardAction, Column, Coordinate},
+    board::{CascadeStep, Cell},
+    player::Player,
+    rules::GameRules,
+    BoardState,
+};
+use mcts::GameState;
+
+const DETERMINIZE_ATTEMPTS: usize = 100;
+
+#[derive(Debug, Clone, Hash)]
+pub struct FogState {
+    state: BoardState,
+    visible_columns: [Vec<bool>; 2],
+    revealed_rows: [Vec<bool>; 2],
+}
+
+// Everything a single player knows about the position.
+#[derive(Debug, Clone)]
+pub struct InformationSet {
+    pub player: Player,
+    pub current_player: Player,
+    pub rules: GameRules,
+    pub cells: Vec<Vec<Option<Cell>>>,
+    pub heights: Vec<usize>,
+    pub player_1_points: usize,
+    pub player_2_points: usize,
+}
+
+fn index(player: Player) -> usize {
+    match player {
+        Player::Player1 => 0,
+        Player::Player2 => 1,
+    }
+}
+
+impl Default for FogState {
+    fn default() -> Self {
+        Self::new(GameRules::default())
+    }
+}
+
+impl FogState {
+    pub fn new(rules: GameRules) -> Self {
+        Self {
+            state: BoardState::new(rules),
+            visible_columns: [vec![false; rules.width], vec![false; rules.width]],
+            revealed_rows: [vec![false; rules.height], vec![false; rules.height]],
+        }
+    }
+
+    pub fn state(&self) -> &BoardState {
+        &self.state
+    }
+
+    pub fn is_visible(&self, player: Player, coord: Coordinate) -> bool {
+        self.visible_columns[index(player)][coord.x() as usize]
+            || self.revealed_rows[index(player)][coord.y() as usize]
+    }
+
+    pub fn information_set(&self, player: Player) -> InformationSet {
+        let board = self.state.board();
+        let mut cells = vec![vec![None; board.height()]; board.width()];
+        let mut heights = vec![0; board.width()];
+
+        for (x, height) in heights.iter_mut().enumerate() {
+            *height = (0..board.height())
+                .take_while(|&y| self.state.board.get(coord(x, y)) != Cell::Empty)
+                .count();
+
+            for (y, cell) in cells[x].iter_mut().enumerate() {
+                if y >= *height {
+                    *cell = Some(Cell::Empty);
+                } else if self.is_visible(player, coord(x, y)) {
+                    *cell = Some(self.state.board.get(coord(x, y)));
+                }
+            }
+        }
+
+        InformationSet {
+            player,
+            current_player: self.state.current_player,
+            rules: *self.state.rules(),
+            cells,
+            heights,
+            player_1_points: self.state.player_1_points,
+            player_2_points: self.state.player_2_points,
+        }
+    }
+
+    fn reveal(&mut self, player: Player, mov: &BoardAction, steps: &[CascadeStep]) {
+        let player = index(player);
+        match mov {
+            BoardAction::DropStone(_, col) => self.visible_columns[player][col.index()] = true,
+            BoardAction::SwitchStone(a, b) => {
+                self.revealed_rows[player][a.y() as usize] = true;
+                self.revealed_rows[player][b.y() as usize] = true;
+            }
+        }
+
+        for step in steps {
+            if let CascadeStep::Cleared(_, cleared) = step {
+                for coord in cleared {
+                    self.revealed_rows[0][coord.y() as usize] = true;
+                    self.revealed_rows[1][coord.y() as usize] = true;
+                }
+            }
+        }
+    }
+}
+
+impl InformationSet {
+    // Samples a full state that agrees with everything this player knows.
+    // Hidden stones get random owners, rejecting samples that contain
+    // unresolved matches or lines of four when possible.
+    pub fn determinize<R: Rng>(&self, rng: &mut R) -> BoardState {
+        let mut state = self.sample(rng);
+        for _ in 1..DETERMINIZE_ATTEMPTS {
+            let (matches, _) = state.board.find_matches(&state.rules);
+            if matches.is_empty() && state.get_winner().is_none() {
+                break;
+            }
+            state = self.sample(rng);
+        }
+        state
+    }
+
+    fn sample<R: Rng>(&self, rng: &mut R) -> BoardState {
+        let mut state = BoardState::new(self.rules);
+        for (x, &height) in self.heights.iter().enumerate() {
+            for y in 0..height {
+                let cell = match self.cells[x][y] {
+                    Some(cell) => cell,
+                    None => *[Cell::Filled(Player::Player1), Cell::Filled(Player::Player2)]
+                        .choose(rng)
+                        .expect("Could not choose a stone"),
+                };
+                state.board.set(cell, coord(x, y));
+            }
+        }
+
+        state.player_1_points = self.player_1_points;
+        state.player_2_points = self.player_2_points;
+        state.current_player = self.current_player;
+        state
+    }
+
+    // Actions the player can choose from with the knowledge they have:
+    // switches are only offered between visible stones.
+    pub fn available_moves(&self) -> Vec<BoardAction> {
+        let (width, height) = (self.rules.width, self.rules.height);
+        let mut actions = (0..width)
+            .filter_map(Column::new)
+            .filter(|col| self.heights[col.index()] < height)
+            .map(|col| BoardAction::DropStone(self.current_player, col))
+            .collect::<Vec<_>>();
+
+        let points = match self.current_player {
+            Player::Player1 => self.player_1_points,
+            Player::Player2 => self.player_2_points,
+        };
+        if points == 0 {
+            return actions;
+        }
+
+        for x in 0..width {
+            for y in 0..height {
+                for (dx, dy) in [(1, 0), (0, 1)] {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx >= width || ny >= height {
+                        continue;
+                    }
+                    if let (Some(Cell::Filled(a)), Some(Cell::Filled(b))) =
+                        (self.cells[x][y], self.cells[nx][ny])
+                    {
+                        if a != b {
+                            actions.push(BoardAction::SwitchStone(coord(x, y), coord(nx, ny)));
+                        }
+                    }
+                }
+            }
+        }
+
+        actions
+    }
+}
+
+fn coord(x: usize, y: usize) -> Coordinate {
+    Coordinate::new(x as isize, y as isize)
+}
+
+impl GameState for FogState {
+    type Move = BoardAction;
+    type Player = Player;
+    type MoveList = Vec<Self::Move>;
+
+    fn current_player(&self) -> Self::Player {
+        self.state.current_player()
+    }
+
+    fn available_moves(&self) -> Self::MoveList {
+        if self.state.is_terminal() {
+            return Vec::new();
+        }
+        self.information_set(self.current_player())
+            .available_moves()
+    }
+
+    fn make_move(&mut self, mov: &Self::Move) {
+        let player = self.current_player();
+        let steps = self.state.play_steps(mov);
+        self.reveal(player, mov, &steps);
+    }
+
+    fn get_winner(&self) -> Option<Self::Player> {
+        self.state.get_winner()
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.state.is_terminal()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{BoardAction, Coordinate},
+        board::Cell,
+        player::Player,
+        testing::col,
+    };
+    use mcts::GameState;
+
+    use super::FogState;
+
+    #[test]
+    fn opponent_columns_are_hidden() {
+        let mut state = FogState::default();
+        state.make_move(&BoardAction::DropStone(Player::Player1, col(2)));
+        state.make_move(&BoardAction::DropStone(Player::Player2, col(5)));
+
+        let info = state.information_set(Player::Player1);
+        assert_eq!(info.cells[2][0], Some(Cell::Filled(Player::Player1)));
+        assert_eq!(info.cells[5][0], None);
+        assert_eq!(info.cells[5][1], Some(Cell::Empty));
+        assert_eq!(info.heights[5], 1);
+        assert!(state.is_visible(Player::Player2, Coordinate::new(5, 0)));
+
+        let determinized = info.determinize(&mut rand::thread_rng());
+        assert_eq!(determinized.current_player(), Player::Player1);
+    }
+}
@@ -0,0 +1,131 @@
+// Line-delimited JSON log with one entry per finished self-play game.
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use serde_json::json;
+
+use crate::{
+    action::BoardAction,
+    alphazero::{pipeline::SelfPlayGame, MyMCTS},
+    board::MoveResult,
+    player::Player,
+    record::GameRecord,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameLogEntry {
+    pub episode: usize,
+    pub game: usize,
+    pub seed: u64,
+    pub plies: usize,
+    pub winner: Option<Player>,
+    pub resigned: bool,
+    pub switches: usize,
+    // Moves that cleared at least one match, and the matches cleared.
+    pub cascades: usize,
+    pub matches: usize,
+    pub search_ms: u128,
+}
+
+impl GameLogEntry {
+    pub fn from_game(episode: usize, game: usize, result: &SelfPlayGame<MyMCTS>) -> Self {
+        let start = result
+            .histories
+            .first()
+            .map_or_else(Default::default, |(state, _)| state.clone());
+        let (_, results) = GameRecord::from_position(start, result.moves.clone()).replay();
+        let matches_per_move = results.iter().map(|r| {
+            r.iter()
+                .filter(|r| matches!(r, MoveResult::Three(_)))
+                .count()
+        });
+
+        Self {
+            episode,
+            game,
+            seed: result.seed,
+            plies: result.moves.len(),
+            winner: result.winner,
+            resigned: result.resigned.is_some(),
+            switches: result
+                .moves
+                .iter()
+                .filter(|m| matches!(m, BoardAction::SwitchStone(_, _)))
+                .count(),
+            cascades: matches_per_move.clone().filter(|&m| m > 0).count(),
+            matches: matches_per_move.sum(),
+            search_ms: result.search_time.as_millis(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let winner = self.winner.map(|winner| match winner {
+            Player::Player1 => 1,
+            Player::Player2 => 2,
+        });
+        let mean_move_ms = self.search_ms / self.plies.max(1) as u128;
+
+        json!({
+            "episode": self.episode,
+            "game": self.game,
+            "seed": self.seed,
+            "plies": self.plies,
+            "winner": winner,
+            "resigned": self.resigned,
+            "switches": self.switches,
+            "cascades": self.cascades,
+            "matches": self.matches,
+            "search_ms": self.search_ms,
+            "mean_move_ms": mean_move_ms,
+        })
+        .to_string()
+    }
+}
+
+pub struct GameLog {
+    file: File,
+}
+
+impl GameLog {
+    // Appends to the log when it already exists.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    pub fn write(&mut self, entry: &GameLogEntry) -> io::Result<()> {
+        writeln!(self.file, "{}", entry.to_json())?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::player::Player;
+
+    use super::GameLogEntry;
+
+    #[test]
+    fn entry_to_json() {
+        let entry = GameLogEntry {
+            episode: 3,
+            game: 1,
+            seed: 42,
+            plies: 10,
+            winner: Some(Player::Player2),
+            resigned: false,
+            switches: 1,
+            cascades: 2,
+            matches: 3,
+            search_ms: 250,
+        };
+        let json: serde_json::Value =
+            serde_json::from_str(&entry.to_json()).expect("Could not parse entry");
+        assert_eq!(json["winner"], 2);
+        assert_eq!(json["mean_move_ms"], 25);
+        assert_eq!(json["seed"], 42);
+    }
+}
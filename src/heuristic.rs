@@ -0,0 +1,321 @@
+// Hand-crafted position evaluation, used where no network is available:
+// as the leaf evaluation of alpha-beta and of a search that serves as a
+// baseline opponent for the network.
+use mcts::{
+    transposition_table::ApproxTable, tree_policy::UCTPolicy, CycleBehaviour, Evaluator, GameState,
+    MCTSManager, MoveEvaluation, SearchHandle, MCTS,
+};
+
+use crate::{
+    action::Coordinate,
+    board::{Board, Cell},
+    player::Player,
+    wdl::{Value, Wdl},
+    BoardState,
+};
+
+// What an evaluator knows about a state, shared with the network evaluator.
+#[derive(Debug, Clone)]
+pub enum StateEval {
+    Winner(Player),
+    Draw,
+    Evaluation(Player, f32),
+    // The prediction of a network with a win, draw and loss head.
+    Wdl(Player, Wdl),
+}
+
+impl StateEval {
+    // The result of a finished game, None while it goes on.
+    pub fn terminal<S: GameState<Player = Player>>(state: &S) -> Option<Self> {
+        if !state.is_terminal() {
+            return None;
+        }
+        Some(match state.get_winner() {
+            Some(winner) => StateEval::Winner(winner),
+            None => StateEval::Draw,
+        })
+    }
+
+    // What a network gave the player to move.
+    pub fn network(player: Player, value: Value) -> Self {
+        match value {
+            Value::Scalar(value) => StateEval::Evaluation(player, value),
+            Value::Wdl(wdl) => StateEval::Wdl(player, wdl),
+        }
+    }
+
+    // Expected score of the player in [-1, 1].
+    pub fn value_for(&self, player: &Player) -> f64 {
+        match self {
+            StateEval::Winner(winner) if winner == player => 1.0,
+            StateEval::Winner(_) => -1.0,
+            StateEval::Draw => 0.0,
+            StateEval::Evaluation(p, value) if p == player => *value as f64,
+            StateEval::Evaluation(_, value) => -*value as f64,
+            StateEval::Wdl(p, wdl) if p == player => wdl.value() as f64,
+            StateEval::Wdl(_, wdl) => -wdl.value() as f64,
+        }
+    }
+}
+
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heuristic {
+    // Weight of a window of four with 1, 2 or 3 of the player's stones and
+    // none of the opponent's.
+    pub line_weights: [i32; 3],
+    // Weight of a window of four that one drop completes right away.
+    pub threat_weight: i32,
+    pub point_weight: i32,
+    pub center_weight: i32,
+    pub mobility_weight: i32,
+}
+
+impl Default for Heuristic {
+    fn default() -> Self {
+        Self {
+            line_weights: [1, 4, 16],
+            threat_weight: 24,
+            point_weight: 12,
+            center_weight: 2,
+            mobility_weight: 0,
+        }
+    }
+}
+
+// Every window of four cells on the board.
+fn windows(board: &Board) -> impl Iterator<Item = [Coordinate; 4]> + '_ {
+    let (width, height) = (board.width() as isize, board.height() as isize);
+    (0..width)
+        .flat_map(move |x| (0..height).map(move |y| (x, y)))
+        .flat_map(|(x, y)| {
+            DIRECTIONS.map(|(dx, dy)| [0, 1, 2, 3].map(|i| Coordinate::new(x + dx * i, y + dy * i)))
+        })
+        .filter(|window| window.iter().all(|&c| board.contains(c)))
+}
+
+// Number of open windows of four holding 1, 2 or 3 of the player's stones.
+pub fn line_counts(board: &Board, player: Player) -> [usize; 3] {
+    let mut counts = [0; 3];
+    for window in windows(board) {
+        let mut own = 0;
+        let mut blocked = false;
+        for coord in window {
+            match board.get(coord) {
+                Cell::Filled(p) if p == player => own += 1,
+                Cell::Filled(_) => blocked = true,
+                Cell::Empty => {}
+            }
+        }
+        if !blocked && (1..=3).contains(&own) {
+            counts[own - 1] += 1;
+        }
+    }
+    counts
+}
+
+// Number of open windows of three of the player's stones whose empty cell
+// can be dropped into now, each one a four on the next move.
+pub fn threats(board: &Board, player: Player) -> usize {
+    windows(board)
+        .filter(|window| {
+            let own = window
+                .iter()
+                .filter(|&&c| board.get(c) == Cell::Filled(player))
+                .count();
+            let empty = window
+                .iter()
+                .filter(|&&c| board.get(c) == Cell::Empty)
+                .collect::<Vec<_>>();
+            own == 3
+                && empty.len() == 1
+                && (empty[0].y() == 0 || board.get(*empty[0] + (0, -1)) != Cell::Empty)
+        })
+        .count()
+}
+
+impl Heuristic {
+    // Score of a non-terminal position from the point of view of the player.
+    pub fn score(&self, state: &BoardState, player: Player) -> i32 {
+        self.side_score(state, player) - self.side_score(state, player.next_player())
+    }
+
+    fn side_score(&self, state: &BoardState, player: Player) -> i32 {
+        let lines = line_counts(state.board(), player)
+            .iter()
+            .zip(self.line_weights)
+            .map(|(&count, weight)| count as i32 * weight)
+            .sum::<i32>();
+
+        let (width, height) = (state.board().width(), state.board().height());
+        let center = (0..width)
+            .flat_map(|x| (0..height).map(move |y| (x, y)))
+            .filter(|&(x, y)| {
+                state.cell(Coordinate::new(x as isize, y as isize)) == Cell::Filled(player)
+            })
+            .map(|(x, _)| (width / 2) as i32 - (2 * x as i32 + 1 - width as i32).abs() / 2)
+            .sum::<i32>();
+
+        let mobility = if self.mobility_weight != 0 && state.current_player() == player {
+            state.moves().count() as i32
+        } else {
+            0
+        };
+
+        let threats = if self.threat_weight != 0 {
+            threats(state.board(), player) as i32
+        } else {
+            0
+        };
+
+        lines
+            + self.threat_weight * threats
+            + self.point_weight * state.points(player) as i32
+            + self.center_weight * center
+            + self.mobility_weight * mobility
+    }
+}
+
+// Leaf evaluation for MCTS without a network: uniform move priors and the
+// heuristic score squashed into [-1, 1].
+#[derive(Debug, Clone, Copy)]
+pub struct HeuristicEvaluator {
+    pub heuristic: Heuristic,
+    // Score at which the evaluation reaches ~0.76.
+    pub scale: f32,
+}
+
+impl Default for HeuristicEvaluator {
+    fn default() -> Self {
+        Self {
+            heuristic: Heuristic {
+                mobility_weight: 1,
+                ..Default::default()
+            },
+            scale: 50.0,
+        }
+    }
+}
+
+impl HeuristicEvaluator {
+    pub fn evaluate(&self, state: &BoardState) -> StateEval {
+        let player = state.current_player();
+        if let Some(evaluation) = StateEval::terminal(state) {
+            return evaluation;
+        }
+
+        let score = self.heuristic.score(state, player) as f32;
+        StateEval::Evaluation(player, (score / self.scale).tanh())
+    }
+}
+
+impl<Spec> Evaluator<Spec> for HeuristicEvaluator
+where
+    Spec: MCTS<State = BoardState>,
+    MoveEvaluation<Spec>: Default,
+{
+    type StateEvaluation = StateEval;
+
+    fn evaluate_new_state(
+        &self,
+        state: &BoardState,
+        moves: &crate::action::MoveList,
+        _: Option<SearchHandle<Spec>>,
+    ) -> (Vec<MoveEvaluation<Spec>>, Self::StateEvaluation) {
+        let evals = moves.iter().map(|_| Default::default()).collect();
+        (evals, self.evaluate(state))
+    }
+
+    fn evaluate_existing_state(
+        &self,
+        _: &BoardState,
+        existing_evaln: &Self::StateEvaluation,
+        _: SearchHandle<Spec>,
+    ) -> Self::StateEvaluation {
+        existing_evaln.clone()
+    }
+
+    fn interpret_evaluation_for_player(
+        &self,
+        evaluation: &Self::StateEvaluation,
+        player: &Player,
+    ) -> f64 {
+        evaluation.value_for(player)
+    }
+}
+
+// A search with the heuristic at the leaves, the baseline without a network.
+#[derive(Clone)]
+pub struct HeuristicMCTS;
+
+impl HeuristicMCTS {
+    pub fn create_search(
+        state: BoardState,
+        evaluator: HeuristicEvaluator,
+        exploration: f64,
+    ) -> MCTSManager<Self> {
+        MCTSManager::new(
+            state,
+            HeuristicMCTS,
+            evaluator,
+            UCTPolicy::new(exploration),
+            ApproxTable::new(1024),
+        )
+    }
+}
+
+impl MCTS for HeuristicMCTS {
+    type State = BoardState;
+    type Eval = HeuristicEvaluator;
+    type TreePolicy = UCTPolicy<()>;
+    type NodeData = ();
+    type TranspositionTable = ApproxTable<Self>;
+    type ExtraThreadData = ();
+
+    fn cycle_behaviour(&self) -> CycleBehaviour<Self> {
+        CycleBehaviour::UseCurrentEvalWhenCycleDetected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{player::Player, testing::StateBuilder};
+
+    use super::{line_counts, threats, Heuristic, HeuristicEvaluator, StateEval};
+
+    #[test]
+    fn counts_open_lines() {
+        let empty = "        ";
+        let state = StateBuilder::new()
+            .board([empty, empty, empty, empty, empty, empty, empty, "XX  O   "])
+            .points(Player::Player2, 1)
+            .build();
+
+        let counts = line_counts(state.board(), Player::Player1);
+        // Horizontally only the window starting at column 0 is still open.
+        assert_eq!(counts[1], 1);
+
+        let heuristic = Heuristic {
+            line_weights: [0, 0, 0],
+            center_weight: 0,
+            ..Default::default()
+        };
+        assert_eq!(heuristic.score(&state, Player::Player1), -12);
+        assert_eq!(threats(state.board(), Player::Player1), 0);
+
+        // The gap can be dropped into, the same gap a row up can not.
+        let state = StateBuilder::new()
+            .board([
+                empty, empty, empty, empty, empty, empty, "OO O    ", "XX XO   ",
+            ])
+            .build();
+        assert_eq!(threats(state.board(), Player::Player1), 1);
+        assert_eq!(threats(state.board(), Player::Player2), 0);
+
+        match HeuristicEvaluator::default().evaluate(&state) {
+            StateEval::Evaluation(Player::Player1, value) => assert!(value > -1.0 && value < 1.0),
+            other => panic!("Unexpected evaluation {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,421 @@
+// HTTP service that evaluates input planes with a model, so clients can
+// query the network without linking TensorFlow themselves.
+//
+// POST /evaluate {"inputs": [<4 x width x height planes>, ...]}
+// responds with {"results": [{"policy": <3 or 5 x width x height planes>, "value": v}, ...]}
+//
+// The request may also name how the inputs are encoded, with "points",
+// "history", "side_to_move", "move_count" and "layout" fields like the keys
+// of a training config; inputs of another encoding have as many planes as it
+// uses. The layout is the one of the served model, the inputs and policies
+// are always sent channels first.
+//
+// RemoteModel is the client, self-play workers on machines without a GPU
+// use it to search with the network of the host serving it.
+use std::{
+    error::Error,
+    fs, io,
+    path::Path,
+    sync::{Arc, RwLock},
+    thread,
+    time::Duration,
+};
+
+use catzero::{TFModel, Tensor};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{info, warn};
+
+use crate::planes::{InputEncoding, InputPlanes};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(30);
+// Attempts of a request before a connection failure is given up on.
+const REMOTE_ATTEMPTS: usize = 3;
+
+// Hands out the model generation currently being served. A newer generation
+// can be swapped in while requests are in flight, they keep the model they
+// started with.
+pub struct ModelBroker {
+    current: RwLock<(usize, Arc<TFModel>)>,
+}
+
+impl ModelBroker {
+    pub fn new(generation: usize, model: TFModel) -> Self {
+        Self {
+            current: RwLock::new((generation, Arc::new(model))),
+        }
+    }
+
+    pub fn model(&self) -> Arc<TFModel> {
+        self.current.read().expect("Could not read model").1.clone()
+    }
+
+    pub fn generation(&self) -> usize {
+        self.current.read().expect("Could not read model").0
+    }
+
+    pub fn swap(&self, generation: usize, model: TFModel) {
+        *self.current.write().expect("Could not write model") = (generation, Arc::new(model));
+        info!(generation, "Now serving model");
+    }
+
+    // Loads the generation named in the registry file when it is newer than
+    // the one being served. Returns the generation that was swapped in.
+    pub fn reload_if_newer<F>(&self, registry: &Path, load: F) -> io::Result<Option<usize>>
+    where
+        F: FnOnce(usize) -> Result<TFModel, Box<dyn Error>>,
+    {
+        match read_latest(registry)? {
+            Some(latest) if latest > self.generation() => {
+                let model = load(latest).map_err(|e| io::Error::other(e.to_string()))?;
+                self.swap(latest, model);
+                Ok(Some(latest))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// The registry file holds the number of the latest promoted generation.
+pub fn read_latest(registry: &Path) -> io::Result<Option<usize>> {
+    match fs::read_to_string(registry) {
+        Ok(content) => Ok(content.trim().parse().ok()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Written to a temporary file first so readers never see a partial number.
+pub fn write_latest(registry: &Path, generation: usize) -> io::Result<()> {
+    let temporary = registry.with_extension("tmp");
+    fs::write(&temporary, generation.to_string())?;
+    fs::rename(temporary, registry)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub policy: Tensor<f32>,
+    pub value: f32,
+}
+
+pub fn parse_request(body: &str) -> Result<Vec<InputPlanes>, String> {
+    let mut request: Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
+    let inputs: Vec<Tensor<u8>> =
+        serde_json::from_value(request["inputs"].take()).map_err(|e| e.to_string())?;
+    let mut encoding = InputEncoding::default();
+    if let Some(points) = request["points"].as_str() {
+        encoding.points = points.parse()?;
+    }
+    if let Some(history) = request["history"].as_u64() {
+        encoding.history = history as usize;
+    }
+    if let Some(side_to_move) = request["side_to_move"].as_bool() {
+        encoding.side_to_move = side_to_move;
+    }
+    if let Some(move_count) = request["move_count"].as_bool() {
+        encoding.move_count = move_count;
+    }
+    if let Some(layout) = request["layout"].as_str() {
+        encoding.layout = layout.parse()?;
+    }
+
+    inputs
+        .into_iter()
+        .enumerate()
+        .map(|(i, input)| {
+            InputPlanes::from_tensor(input, encoding).map_err(|e| format!("Input {}: {}", i, e))
+        })
+        .collect()
+}
+
+pub fn evaluate(model: &TFModel, inputs: &[InputPlanes]) -> Result<Vec<Evaluation>, String> {
+    inputs
+        .iter()
+        .map(|input| {
+            let (policy, value) = model
+                .evaluate(input.to_tensorflow())
+                .map_err(|e| e.to_string())?;
+            // The policy planes have the size of the board that was sent,
+            // they are sent back channels first.
+            let (width, height) = (input.width(), input.height());
+            let policy = input
+                .encoding()
+                .layout
+                .to_channels_first(&policy, width, height);
+            let policy = policy
+                .chunks(width * height)
+                .map(|plane| plane.chunks(height).map(|r| r.to_vec()).collect())
+                .collect();
+            Ok(Evaluation { policy, value })
+        })
+        .collect()
+}
+
+pub fn encode_response(evaluations: &[Evaluation]) -> String {
+    let results = evaluations
+        .iter()
+        .map(|e| json!({ "policy": e.policy, "value": e.value }))
+        .collect::<Vec<_>>();
+    json!({ "results": results }).to_string()
+}
+
+pub fn encode_request(inputs: &[InputPlanes]) -> String {
+    let encoding = inputs
+        .first()
+        .map_or_else(InputEncoding::default, |input| input.encoding());
+    let planes = inputs
+        .iter()
+        .map(|input| input.planes())
+        .collect::<Vec<_>>();
+    json!({
+        "inputs": planes,
+        "points": encoding.points.to_string(),
+        "history": encoding.history,
+        "side_to_move": encoding.side_to_move,
+        "move_count": encoding.move_count,
+        "layout": encoding.layout.to_string(),
+    })
+    .to_string()
+}
+
+pub fn parse_response(body: &str) -> Result<Vec<Evaluation>, String> {
+    let mut response: Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
+    let results: Vec<Value> =
+        serde_json::from_value(response["results"].take()).map_err(|e| e.to_string())?;
+
+    results
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut result)| {
+            let policy = serde_json::from_value(result["policy"].take())
+                .map_err(|e| format!("Result {}: {}", i, e))?;
+            let value = result["value"]
+                .as_f64()
+                .ok_or_else(|| format!("Result {}: missing value", i))?;
+            Ok(Evaluation {
+                policy,
+                value: value as f32,
+            })
+        })
+        .collect()
+}
+
+// Evaluates positions on a server started with `serve`. Clones share the
+// connections to the server.
+#[derive(Clone)]
+pub struct RemoteModel {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl RemoteModel {
+    // The address of the server, such as "gpu-host:8080".
+    pub fn new(address: &str) -> Self {
+        let address = address.trim_end_matches('/');
+        let url = match address.contains("://") {
+            true => format!("{}/evaluate", address),
+            false => format!("http://{}/evaluate", address),
+        };
+        let agent = ureq::AgentBuilder::new().timeout(REMOTE_TIMEOUT).build();
+        Self { url, agent }
+    }
+
+    pub fn evaluate(&self, inputs: &[InputPlanes]) -> Result<Vec<Evaluation>, String> {
+        let body = encode_request(inputs);
+        let mut attempt = 1;
+        let response = loop {
+            match self
+                .agent
+                .post(&self.url)
+                .set("Content-Type", "application/json")
+                .send_string(&body)
+            {
+                Ok(response) => break response,
+                Err(ureq::Error::Status(code, response)) => {
+                    return Err(rejection(code, &response.into_string().unwrap_or_default()))
+                }
+                Err(e) if attempt < REMOTE_ATTEMPTS => {
+                    warn!(url = %self.url, error = %e, "Could not reach server, retrying");
+                    thread::sleep(POLL_INTERVAL);
+                    attempt += 1;
+                }
+                Err(e) => return Err(format!("Could not reach {}: {}", self.url, e)),
+            }
+        };
+
+        let evaluations = parse_response(&response.into_string().map_err(|e| e.to_string())?)?;
+        if evaluations.len() != inputs.len() {
+            return Err(format!(
+                "Sent {} inputs, received {} results",
+                inputs.len(),
+                evaluations.len()
+            ));
+        }
+        Ok(evaluations)
+    }
+}
+
+// The server explains in the body why it rejected a request.
+fn rejection(code: u16, body: &str) -> String {
+    let reason = serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|body| body["error"].as_str().map(String::from));
+    match reason {
+        Some(reason) => format!("Server rejected the request: {}", reason),
+        None => format!("Server answered {}", code),
+    }
+}
+
+// Serves requests until the server fails. Between requests, and at least
+// once a second, `poll` is called so the caller can swap in a newer model.
+pub fn serve<F>(broker: &ModelBroker, address: &str, mut poll: F) -> io::Result<()>
+where
+    F: FnMut(&ModelBroker),
+{
+    let server = Server::http(address).map_err(io::Error::other)?;
+    info!(address, "Serving model");
+
+    loop {
+        if let Some(request) = server.recv_timeout(POLL_INTERVAL)? {
+            // A client that hangs up only loses its own answer.
+            if let Err(e) = handle(&broker.model(), request) {
+                warn!(error = %e, "Could not answer request");
+            }
+        }
+        poll(broker);
+    }
+}
+
+fn handle(model: &TFModel, mut request: Request) -> io::Result<()> {
+    if request.method() != &Method::Post || request.url() != "/evaluate" {
+        return request.respond(Response::from_string("Not found").with_status_code(404));
+    }
+
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body)?;
+
+    let response = parse_request(&body)
+        .and_then(|inputs| evaluate(model, &inputs))
+        .map(|evaluations| encode_response(&evaluations));
+
+    match response {
+        Ok(json) => {
+            let header = Header::from_bytes("Content-Type", "application/json")
+                .expect("Could not create header");
+            request.respond(Response::from_string(json).with_header(header))
+        }
+        Err(e) => request.respond(
+            Response::from_string(json!({ "error": e }).to_string()).with_status_code(400),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tiny_http::{Response, Server};
+
+    use crate::{
+        planes::{InputEncoding, InputPlanes},
+        testing::temp_path,
+        BoardState,
+    };
+
+    use super::{
+        encode_request, encode_response, parse_request, read_latest, write_latest, Evaluation,
+        RemoteModel,
+    };
+
+    #[test]
+    fn parse_and_encode() {
+        let planes = vec![vec![vec![0u8; 8]; 8]; 4];
+        let body = serde_json::json!({ "inputs": [planes] }).to_string();
+        assert_eq!(parse_request(&body).expect("Could not parse").len(), 1);
+
+        let body = serde_json::json!({ "inputs": [[[[0]]]] }).to_string();
+        assert!(parse_request(&body).is_err());
+
+        let thermometer = vec![vec![vec![0u8; 8]; 8]; 8];
+        let body = json!({ "inputs": [thermometer], "points": "thermometer:3" }).to_string();
+        assert_eq!(parse_request(&body).expect("Could not parse").len(), 1);
+        let body = json!({ "inputs": [thermometer] }).to_string();
+        assert!(parse_request(&body).is_err());
+
+        let encoding = InputEncoding {
+            history: 1,
+            side_to_move: true,
+            ..Default::default()
+        };
+        let input = InputPlanes::encode(&BoardState::default(), encoding);
+        assert_eq!(
+            parse_request(&encode_request(&[input.clone()])),
+            Ok(vec![input])
+        );
+
+        let response = encode_response(&[Evaluation {
+            policy: vec![vec![vec![0.5]]],
+            value: -1.0,
+        }]);
+        assert_eq!(
+            response,
+            r#"{"results":[{"policy":[[[0.5]]],"value":-1.0}]}"#
+        );
+    }
+
+    #[test]
+    fn remote_model() {
+        // Answers every input with the same evaluation, and rejects requests
+        // of more than two inputs.
+        let server = Server::http("127.0.0.1:0").expect("Could not start server");
+        let address = server.server_addr().to_ip().unwrap().to_string();
+        let thread = std::thread::spawn(move || {
+            for mut request in server.incoming_requests().take(2) {
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let inputs = parse_request(&body).unwrap();
+                let evaluation = Evaluation {
+                    policy: vec![vec![vec![0.25; 4]; 2]; 3],
+                    value: 0.5,
+                };
+                let response = match inputs.len() {
+                    0..=2 => {
+                        Response::from_string(encode_response(&vec![evaluation; inputs.len()]))
+                    }
+                    _ => Response::from_string(json!({ "error": "Too many inputs" }).to_string())
+                        .with_status_code(400),
+                };
+                request.respond(response).unwrap();
+            }
+        });
+
+        let model = RemoteModel::new(&address);
+        let input = InputPlanes::from(&BoardState::default());
+        let evaluations = model
+            .evaluate(&[input.clone(), input.clone()])
+            .expect("Could not evaluate remotely");
+        assert_eq!(evaluations.len(), 2);
+        assert_eq!(evaluations[0].policy.len(), 3);
+        assert_eq!(evaluations[1].value, 0.5);
+
+        assert_eq!(
+            model.evaluate(&[input.clone(), input.clone(), input]),
+            Err(String::from("Server rejected the request: Too many inputs"))
+        );
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn registry_file() {
+        let path = temp_path("inference_test.latest");
+        std::fs::remove_file(&path).ok();
+        assert_eq!(read_latest(&path).expect("Could not read registry"), None);
+
+        write_latest(&path, 12).expect("Could not write registry");
+        assert_eq!(
+            read_latest(&path).expect("Could not read registry"),
+            Some(12)
+        );
+        std::fs::remove_file(&path).ok();
+    }
+}
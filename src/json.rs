@@ -0,0 +1,76 @@
+// The JSON front ends see of a game, shared by the browser bindings and the
+// game API. Players are 1 and 2 and actions are in the usual notation, d3
+// drops in column 3 and s3,0-3,1 switches two stones.
+use serde_json::{json, Value};
+
+use crate::{
+    board::{CascadeStep, MoveResult, TerminalResult},
+    player::Player,
+    BoardState,
+};
+use mcts::GameState;
+
+pub fn player_number(player: Player) -> u8 {
+    match player {
+        Player::Player1 => 1,
+        Player::Player2 => 2,
+    }
+}
+
+fn result_name(result: &MoveResult) -> String {
+    match result {
+        MoveResult::Winner(player) => format!("winner:{}", player_number(*player)),
+        MoveResult::Draw => String::from("draw"),
+        MoveResult::Three(matched) => format!("three:{}", player_number(matched.player)),
+    }
+}
+
+// A phase of the cascade of an action, so a front end can animate them:
+//
+//     {"results": ["three:1"], "lines": [[[2, 0], [3, 0], [4, 0]]],
+//      "cleared": [[2, 0], [3, 0], [4, 0]]}
+//
+// or {"terminal": "winner:1"} for the end of the game.
+pub fn step_json(step: &CascadeStep) -> Value {
+    match step {
+        CascadeStep::Terminal(result) => json!({ "terminal": result_name(result) }),
+        CascadeStep::Cleared(results, cleared) => json!({
+            "results": results.iter().map(result_name).collect::<Vec<_>>(),
+            // The cells of every scored line, in the order of the results.
+            "lines": results
+                .iter()
+                .map(|result| match result {
+                    MoveResult::Three(matched) => {
+                        matched.cells.iter().map(|c| [c.x(), c.y()]).collect()
+                    }
+                    _ => Vec::new(),
+                })
+                .collect::<Vec<_>>(),
+            "cleared": cleared.iter().map(|c| [c.x(), c.y()]).collect::<Vec<_>>(),
+        }),
+        CascadeStep::Settled => json!({}),
+    }
+}
+
+// Everything a front end needs to draw the position and offer the moves.
+// The winner is 0 for a draw or a game that is not over.
+pub fn state_json(state: &BoardState) -> Value {
+    let winner = match state.result() {
+        TerminalResult::Win(player) => player_number(player),
+        _ => 0,
+    };
+    json!({
+        "rules": state.rules().to_string(),
+        "rows": state.board().rows(),
+        "player": player_number(state.current_player()),
+        "points": [state.points(Player::Player1), state.points(Player::Player2)],
+        "history": state.history().map(|mov| mov.to_string()).collect::<Vec<_>>(),
+        "legal_moves": state
+            .available_moves()
+            .iter()
+            .map(|mov| mov.to_string())
+            .collect::<Vec<_>>(),
+        "over": state.is_terminal(),
+        "winner": winner,
+    })
+}
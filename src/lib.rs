@@ -1,24 +1,192 @@
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    iter,
+    sync::Arc,
+};
 
-use crate::board::{MoveResult, HEIGHT, WIDTH};
-use action::{BoardAction, Coordinate};
-use board::{Board, TerminalResult};
-use catzero::Tensor;
+use crate::board::{CascadeStep, MoveResult};
+use action::{BoardAction, Coordinate, MoveList};
+use board::{zobrist, Board, Cell, TerminalResult};
+use error::{InvariantError, MoveError, SwitchError};
 use mcts::GameState;
+use planes::{InputPlanes, Tensor};
 use player::Player;
+use rules::GameRules;
 
 pub mod action;
+pub mod agent;
+pub mod alphabeta;
+#[cfg(feature = "alphazero")]
 pub mod alphazero;
+#[cfg(feature = "alphazero")]
+pub mod api;
 pub mod board;
+pub mod book;
+#[cfg(feature = "alphazero")]
+pub mod calibration;
+#[cfg(feature = "alphazero")]
+pub mod config;
+#[cfg(feature = "alphazero")]
+pub mod elo;
+pub mod encoding;
+pub mod engine;
+pub mod error;
+#[cfg(feature = "alphazero")]
+pub mod export;
+pub mod fog;
+#[cfg(feature = "alphazero")]
+pub mod gamelog;
+pub mod heuristic;
+#[cfg(feature = "alphazero")]
+pub mod inference;
+pub mod json;
+#[cfg(feature = "alphazero")]
+pub mod live;
+#[cfg(feature = "alphazero")]
+pub mod metrics;
+pub mod notation;
+#[cfg(feature = "onnx")]
+pub mod onnx;
+pub mod opening;
+pub mod perft;
+pub mod planes;
 pub mod player;
+pub mod ponder;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod record;
+#[cfg(feature = "alphazero")]
+pub mod registry;
+pub mod render;
+pub mod rollout;
+pub mod rules;
+#[cfg(feature = "alphazero")]
+pub mod samples;
+pub mod session;
+pub mod solver;
+pub mod stats;
+pub mod testing;
+#[cfg(feature = "alphazero")]
+pub mod tfrecord;
+pub mod timecontrol;
+pub mod ugi;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod wdl;
 
-#[derive(Default, Clone, Hash)]
+// The undo history is left out when serializing. Deserialized states are
+// validated, see `StoredState`.
+#[derive(Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "StoredState")
+)]
 pub struct BoardState {
     board: Board,
     player_1_points: usize,
     player_2_points: usize,
     current_player: Player,
     winner: TerminalResult,
+    rules: GameRules,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    history: History,
+}
+
+// A state as it is read, before it is checked to be one a game can reach.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct StoredState {
+    board: Board,
+    player_1_points: usize,
+    player_2_points: usize,
+    current_player: Player,
+    winner: TerminalResult,
+    rules: GameRules,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<StoredState> for BoardState {
+    type Error = InvariantError;
+
+    fn try_from(stored: StoredState) -> Result<Self, Self::Error> {
+        let state = BoardState {
+            board: stored.board,
+            player_1_points: stored.player_1_points,
+            player_2_points: stored.player_2_points,
+            current_player: stored.current_player,
+            winner: stored.winner,
+            rules: stored.rules,
+            history: History::default(),
+        };
+        state.validate()?;
+        Ok(state)
+    }
+}
+
+// Everything an action changed, so it can be taken back. Keeping the board
+// from before the action is cheaper than reversing its cascade.
+#[derive(Clone)]
+struct Undo {
+    action: BoardAction,
+    board: Board,
+    player_1_points: usize,
+    player_2_points: usize,
+    current_player: Player,
+    winner: TerminalResult,
+}
+
+// The undo history, shared between clones. The search clones the state for
+// every playout, a clone only copies the handle to the last action instead
+// of every board the game went through.
+#[derive(Clone, Default)]
+struct History(Option<Arc<HistoryNode>>);
+
+struct HistoryNode {
+    undo: Undo,
+    previous: History,
+    len: usize,
+}
+
+impl History {
+    fn push(&mut self, undo: Undo) {
+        let previous = std::mem::take(self);
+        let len = previous.len() + 1;
+        self.0 = Some(Arc::new(HistoryNode {
+            undo,
+            previous,
+            len,
+        }));
+    }
+
+    fn pop(&mut self) -> Option<Undo> {
+        let node = self.0.take()?;
+        let (undo, previous) = match Arc::try_unwrap(node) {
+            Ok(node) => (node.undo, node.previous),
+            Err(node) => (node.undo.clone(), node.previous.clone()),
+        };
+        *self = previous;
+        Some(undo)
+    }
+
+    fn len(&self) -> usize {
+        self.0.as_ref().map_or(0, |node| node.len)
+    }
+
+    // Newest first.
+    fn iter(&self) -> impl Iterator<Item = &Undo> {
+        iter::successors(self.0.as_deref(), |node| node.previous.0.as_deref())
+            .map(|node| &node.undo)
+    }
+}
+
+// Transposition tables key on the Zobrist hash, the rules are assumed to be
+// the same for every state in one table.
+impl Hash for BoardState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.zobrist());
+    }
 }
 
 impl Debug for BoardState {
@@ -37,100 +205,247 @@ impl Debug for BoardState {
 impl GameState for BoardState {
     type Move = BoardAction;
     type Player = Player;
-    type MoveList = Vec<Self::Move>;
+    type MoveList = MoveList;
 
     fn current_player(&self) -> Self::Player {
         self.current_player.clone()
     }
 
     fn available_moves(&self) -> Self::MoveList {
-        match self.board.get_board_terminal_status() {
-            TerminalResult::None => {}
-            TerminalResult::Win(_) => return Vec::new(),
-            TerminalResult::Draw => return Vec::new(),
+        self.moves().collect()
+    }
+
+    fn make_move(&mut self, mov: &Self::Move) {
+        self.play(mov);
+    }
+
+    fn get_winner(&self) -> Option<Self::Player> {
+        match self.winner {
+            TerminalResult::None => match self.board.get_board_terminal_status(&self.rules) {
+                TerminalResult::None => None,
+                TerminalResult::Win(player) => Some(player),
+                TerminalResult::Draw => None,
+            },
+            TerminalResult::Win(player) => Some(player),
+            TerminalResult::Draw => None,
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.moves().next().is_none()
+    }
+}
+
+impl BoardState {
+    pub fn new(rules: GameRules) -> Self {
+        Self {
+            board: Board::new(rules.width, rules.height),
+            rules,
+            ..Default::default()
+        }
+    }
+
+    // Builds the position reached by playing the actions from the start,
+    // failing on the first action that is not legal at that point.
+    pub fn from_moves(rules: GameRules, moves: &[BoardAction]) -> Result<Self, String> {
+        let mut state = Self::new(rules);
+        for (ply, mov) in moves.iter().enumerate() {
+            if let Some(reason) = state.explain_illegal(mov) {
+                return Err(format!("move {} ({}): {}", ply + 1, mov, reason));
+            }
+            state.play(mov);
         }
+        Ok(state)
+    }
+
+    pub fn rules(&self) -> &GameRules {
+        &self.rules
+    }
+
+    // The same position played under other rules of the same board size.
+    pub fn with_rules(mut self, rules: GameRules) -> Self {
+        assert_eq!(
+            (rules.width, rules.height),
+            (self.board.width(), self.board.height()),
+            "Rules are for another board size"
+        );
+        self.rules = rules;
+        self.winner = self.board.get_board_terminal_status(&rules);
+        self
+    }
+
+    // Hash of the stones, the banked points and the player to move.
+    pub fn zobrist(&self) -> u64 {
+        let mut hash = self.board.zobrist()
+            ^ zobrist::points(0, self.player_1_points)
+            ^ zobrist::points(1, self.player_2_points);
+        if self.current_player == Player::Player2 {
+            hash ^= zobrist::PLAYER_2_TO_MOVE;
+        }
+        hash
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
 
-        let mut actions: Self::MoveList = (0..board::WIDTH)
-            .filter(|&col| self.board.is_col_free(col))
-            .map(|col| BoardAction::DropStone(self.current_player(), col))
-            .collect();
+    // The legal actions in the order of `available_moves`, generated as they
+    // are taken: drops first, then the switches when the player to move has
+    // points to spend. Nothing is allocated.
+    pub fn moves(&self) -> impl Iterator<Item = BoardAction> + '_ {
+        let open = self.board.get_board_terminal_status(&self.rules) == TerminalResult::None;
+        let player = self.current_player;
+        let drops = self
+            .board
+            .columns()
+            .filter(move |&col| open && self.board.is_col_free(col))
+            .map(move |col| BoardAction::DropStone(player, col));
+        let switches = (open && self.points(player) > 0)
+            .then(|| self.switches())
+            .into_iter()
+            .flatten();
+        drops.chain(switches)
+    }
 
-        let find_switch_actions = match self.current_player() {
-            Player::Player1 => self.player_1_points > 0,
-            Player::Player2 => self.player_2_points > 0,
+    // Replaces the contents of the buffer with the legal actions, so a search
+    // can reuse one buffer for every node.
+    pub fn fill_moves(&self, moves: &mut Vec<BoardAction>) {
+        moves.clear();
+        moves.extend(self.moves());
+    }
+
+    // Every pair of neighbouring stones of different players: the horizontal
+    // pairs, then the vertical ones, then the diagonals from the lower cell
+    // up-right and up-left. Cells off the board are empty, so the pairs
+    // hanging over an edge fall out.
+    fn switches(&self) -> impl Iterator<Item = BoardAction> + '_ {
+        const HORIZONTAL: &[(isize, isize)] = &[(1, 0)];
+        const VERTICAL: &[(isize, isize)] = &[(0, 1)];
+        const DIAGONAL: &[(isize, isize)] = &[(1, 1), (-1, 1)];
+        let passes: &[&[(isize, isize)]] = if self.rules.diagonal_switches {
+            &[HORIZONTAL, VERTICAL, DIAGONAL]
+        } else {
+            &[HORIZONTAL, VERTICAL]
         };
+        let (width, height) = (self.board.width() as isize, self.board.height() as isize);
+        passes.iter().flat_map(move |&directions| {
+            (0..width)
+                .flat_map(move |x| (0..height).map(move |y| Coordinate::new(x, y)))
+                .flat_map(move |base| directions.iter().map(move |&step| (base, base + step)))
+                .filter(move |&(base, next)| {
+                    matches!(
+                        (self.board.get(base), self.board.get(next)),
+                        (Cell::Filled(p), Cell::Filled(q)) if p != q
+                    )
+                })
+                .map(|(base, next)| BoardAction::SwitchStone(base, next))
+        })
+    }
+
+    pub fn points(&self, player: Player) -> usize {
+        match player {
+            Player::Player1 => self.player_1_points,
+            Player::Player2 => self.player_2_points,
+        }
+    }
+
+    pub fn cell(&self, coord: Coordinate) -> Cell {
+        self.board.get(coord)
+    }
+
+    // The outcome recorded by the last action played.
+    pub fn terminal_status(&self) -> &TerminalResult {
+        &self.winner
+    }
+
+    pub fn result(&self) -> TerminalResult {
+        match self.get_winner() {
+            Some(player) => TerminalResult::Win(player),
+            None if self.is_terminal() => TerminalResult::Draw,
+            None => TerminalResult::None,
+        }
+    }
 
-        if find_switch_actions {
-            // Collect horizontal switches
-            for x in 0..(board::WIDTH - 1) {
-                for y in 0..board::HEIGHT {
-                    let base_coord = Coordinate::new(x as isize, y as isize);
-                    let next_coord = base_coord + (1, 0);
-                    let next_cell = self.board.get(next_coord);
-                    let add_action = match (next_cell, self.board.get(base_coord)) {
-                        (board::Cell::Empty, board::Cell::Empty) => false,
-                        (board::Cell::Empty, board::Cell::Filled(_)) => false,
-                        (board::Cell::Filled(_), board::Cell::Empty) => false,
-                        (
-                            board::Cell::Filled(Player::Player1),
-                            board::Cell::Filled(Player::Player1),
-                        ) => false,
-                        (
-                            board::Cell::Filled(Player::Player2),
-                            board::Cell::Filled(Player::Player2),
-                        ) => false,
-                        (
-                            board::Cell::Filled(Player::Player1),
-                            board::Cell::Filled(Player::Player2),
-                        ) => true,
-                        (
-                            board::Cell::Filled(Player::Player2),
-                            board::Cell::Filled(Player::Player1),
-                        ) => true,
-                    };
-                    if add_action {
-                        actions.push(BoardAction::SwitchStone(base_coord, next_coord));
+    // Describes why an action can not be played in this state, in words that
+    // can be shown to a human player. Returns None for legal actions.
+    pub fn explain_illegal(&self, mov: &BoardAction) -> Option<String> {
+        self.check_move(mov).err().map(|error| error.to_string())
+    }
+
+    pub fn check_move(&self, mov: &BoardAction) -> Result<(), MoveError> {
+        if self.is_terminal() {
+            return Err(MoveError::GameOver);
+        }
+
+        match mov {
+            BoardAction::DropStone(player, _) if *player != self.current_player => {
+                Err(MoveError::WrongPlayer(self.current_player))
+            }
+            BoardAction::DropStone(_, col) if col.index() >= self.board.width() => {
+                Err(MoveError::ColumnOutOfBounds(*col))
+            }
+            BoardAction::DropStone(_, col) if !self.board.is_col_free(*col) => {
+                Err(MoveError::ColumnFull(*col))
+            }
+            BoardAction::DropStone(_, _) => Ok(()),
+            BoardAction::SwitchStone(a, b) => {
+                if self.points(self.current_player) == 0 {
+                    return Err(MoveError::NotEnoughPoints);
+                }
+
+                for coord in [a, b] {
+                    if !self.board.contains(*coord) {
+                        return Err(MoveError::OutOfBounds(*coord));
                     }
                 }
-            }
-            // Collect vertical switches
-            for x in 0..(board::WIDTH - 1) {
-                for y in 0..board::HEIGHT {
-                    let base_coord = Coordinate::new(x as isize, y as isize);
-                    let next_coord = base_coord + (0, 1);
-                    let next_cell = self.board.get(next_coord);
-                    let add_action = match (next_cell, self.board.get(base_coord)) {
-                        (board::Cell::Empty, board::Cell::Empty) => false,
-                        (board::Cell::Empty, board::Cell::Filled(_)) => false,
-                        (board::Cell::Filled(_), board::Cell::Empty) => false,
-                        (
-                            board::Cell::Filled(Player::Player1),
-                            board::Cell::Filled(Player::Player1),
-                        ) => false,
-                        (
-                            board::Cell::Filled(Player::Player2),
-                            board::Cell::Filled(Player::Player2),
-                        ) => false,
-                        (
-                            board::Cell::Filled(Player::Player1),
-                            board::Cell::Filled(Player::Player2),
-                        ) => true,
-                        (
-                            board::Cell::Filled(Player::Player2),
-                            board::Cell::Filled(Player::Player1),
-                        ) => true,
-                    };
-                    if add_action {
-                        actions.push(BoardAction::SwitchStone(base_coord, next_coord));
+
+                let (dx, dy) = ((a.x() - b.x()).abs(), (a.y() - b.y()).abs());
+                let diagonal = self.rules.diagonal_switches && (dx, dy) == (1, 1);
+                if dx + dy != 1 && !diagonal {
+                    return Err(MoveError::IllegalSwitch(SwitchError::NotAdjacent));
+                }
+
+                match (self.board.get(*a), self.board.get(*b)) {
+                    (board::Cell::Empty, _) => {
+                        Err(MoveError::IllegalSwitch(SwitchError::EmptyCell(*a)))
+                    }
+                    (_, board::Cell::Empty) => {
+                        Err(MoveError::IllegalSwitch(SwitchError::EmptyCell(*b)))
                     }
+                    (board::Cell::Filled(p), board::Cell::Filled(q)) if p == q => {
+                        Err(MoveError::IllegalSwitch(SwitchError::SamePlayer))
+                    }
+                    _ => Ok(()),
                 }
             }
         }
+    }
 
-        actions
+    // Plays the action only if it is legal, the state is left untouched
+    // otherwise.
+    pub fn try_make_move(&mut self, mov: &BoardAction) -> Result<Vec<MoveResult>, MoveError> {
+        self.check_move(mov)?;
+        Ok(self.play(mov))
     }
 
-    fn make_move(&mut self, mov: &Self::Move) {
+    // Same as make_move, but hands back what happened on the board.
+    pub fn play(&mut self, mov: &BoardAction) -> Vec<MoveResult> {
+        self.play_steps(mov)
+            .iter()
+            .flat_map(|step| step.results().iter().cloned())
+            .collect()
+    }
+
+    pub fn play_steps(&mut self, mov: &BoardAction) -> Vec<CascadeStep> {
+        self.history.push(Undo {
+            action: *mov,
+            board: self.board.clone(),
+            player_1_points: self.player_1_points,
+            player_2_points: self.player_2_points,
+            current_player: self.current_player,
+            winner: self.winner.clone(),
+        });
+
         if let BoardAction::SwitchStone(_, _) = mov {
             match self.current_player {
                 Player::Player1 => self.player_1_points -= 1,
@@ -138,14 +453,20 @@ impl GameState for BoardState {
             }
         }
 
-        let result = self.board.make_move(mov);
+        let steps = self
+            .board
+            .make_move_steps(self.current_player, mov, &self.rules);
+        let result = steps
+            .iter()
+            .flat_map(|step| step.results())
+            .collect::<Vec<_>>();
         let three_p1 = result
             .iter()
-            .filter(|&x| x == &MoveResult::Three(Player::Player1))
+            .filter(|x| x.scorer() == Some(Player::Player1))
             .count();
         let three_p2 = result
             .iter()
-            .filter(|&x| x == &MoveResult::Three(Player::Player2))
+            .filter(|x| x.scorer() == Some(Player::Player2))
             .count();
 
         self.player_1_points += three_p1;
@@ -158,29 +479,89 @@ impl GameState for BoardState {
             Some(MoveResult::Winner(player)) => TerminalResult::Win(*player),
             _ => TerminalResult::None,
         };
+
+        #[cfg(feature = "validate")]
+        if let Err(e) = self.validate() {
+            panic!("Broken invariant after {}: {}", mov, e);
+        }
+
+        steps
     }
 
-    fn get_winner(&self) -> Option<Self::Player> {
-        match self.winner {
-            TerminalResult::None => match self.board.get_board_terminal_status() {
-                TerminalResult::None => None,
-                TerminalResult::Win(player) => Some(player),
-                TerminalResult::Draw => None,
-            },
-            TerminalResult::Win(player) => Some(player),
-            TerminalResult::Draw => None,
+    // The invariants of the board, and a result that agrees with it.
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.board.validate(&self.rules)?;
+        let board = self.board.get_board_terminal_status(&self.rules);
+        if self.winner != board {
+            return Err(InvariantError::WrongResult {
+                stored: self.winner.clone(),
+                board,
+            });
         }
+        Ok(())
     }
 
-    fn is_terminal(&self) -> bool {
-        self.available_moves().is_empty()
+    // Takes back the last action played on this state and returns it.
+    pub fn undo(&mut self) -> Option<BoardAction> {
+        let undo = self.history.pop()?;
+        self.board = undo.board;
+        self.player_1_points = undo.player_1_points;
+        self.player_2_points = undo.player_2_points;
+        self.current_player = undo.current_player;
+        self.winner = undo.winner;
+        Some(undo.action)
+    }
+
+    // The actions played on this state, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &BoardAction> {
+        let mut actions = self
+            .history
+            .iter()
+            .map(|undo| &undo.action)
+            .collect::<Vec<_>>();
+        actions.reverse();
+        actions.into_iter()
+    }
+
+    // Actions played on this state, a state that was built without playing
+    // its actions starts at 0.
+    pub fn ply(&self) -> usize {
+        self.history.len()
+    }
+
+    // The boards before the last actions, newest first. A state that was
+    // built without playing its actions has none.
+    pub fn past_boards(&self) -> impl Iterator<Item = &Board> {
+        self.history.iter().map(|undo| &undo.board)
+    }
+
+    // The same position reflected left-right, the game is symmetric under
+    // this. The history is reflected along with it.
+    pub fn mirror(&self) -> BoardState {
+        let width = self.board.width();
+        let mut undos = self.history.iter().collect::<Vec<_>>();
+        undos.reverse();
+        let mut history = History::default();
+        for undo in undos {
+            history.push(Undo {
+                action: undo.action.mirrored(width),
+                board: undo.board.mirrored(),
+                ..undo.clone()
+            });
+        }
+        BoardState {
+            board: self.board.mirrored(),
+            history,
+            ..self.clone()
+        }
     }
 }
 
-// Input: 8 x 8 planes
-// -- History --
+// Input: 8 x 8 planes, see planes.rs
 // 1 Binary Plane for X
 // 1 Binary Plane for Y
+// -- History, optional --
+// 2 Binary Planes for X and Y per earlier board
 // -- Other   --
 // 1 Real Plane for points P1
 // 1 Real Plane for points P2
@@ -190,49 +571,429 @@ impl GameState for BoardState {
 // 1 Binary Plane for switch right
 // 1 Binary Plane for switch up
 
-fn tensor_to_tensorflow(tensor: Tensor<u8>) -> tensorflow::Tensor<f32> {
-    let flattened = tensor
-        .iter()
-        .flat_map(|x| x.iter().flatten().map(|x| *x as f32))
-        .collect::<Vec<_>>();
-    let tensor = tensorflow::Tensor::new(&[1, 4, 8, 8]);
-
-    tensor
-        .with_values(&flattened)
-        .expect("Could not use tensor")
+// Conversions take the default encoding, use `InputPlanes::encode` or
+// `BoardState::encode_into` for any other.
+impl From<&BoardState> for Tensor<u8> {
+    fn from(state: &BoardState) -> Self {
+        InputPlanes::from(state).into()
+    }
 }
 
-impl Into<Tensor<u8>> for BoardState {
-    fn into(self) -> Tensor<u8> {
-        let player = self.current_player();
-        let next_player = player.next_player();
-
-        let mut cross_plane = vec![vec![0u8; 8]; 8];
-        let mut circle_plane = vec![vec![0u8; 8]; 8];
-
-        for x in 0..WIDTH {
-            for y in 0..HEIGHT {
-                cross_plane[x][y] = match self.board.get(Coordinate::new(x as isize, y as isize)) {
-                    board::Cell::Filled(p) if p == player => 1,
-                    _ => 0,
-                };
-
-                circle_plane[x][y] = match self.board.get(Coordinate::new(x as isize, y as isize)) {
-                    board::Cell::Filled(p) if p == next_player => 1,
-                    _ => 0,
-                };
-            }
-        }
+impl From<BoardState> for Tensor<u8> {
+    fn from(state: BoardState) -> Self {
+        Self::from(&state)
+    }
+}
 
-        let real_p1_plane = vec![vec![self.player_1_points as u8; 8]; 8];
-        let real_p2_plane = vec![vec![self.player_2_points as u8; 8]; 8];
+#[cfg(feature = "alphazero")]
+impl From<&BoardState> for tensorflow::Tensor<f32> {
+    fn from(state: &BoardState) -> Self {
+        alphazero::game::NetworkGame::input(
+            state,
+            planes::InputEncoding::default(),
+            &mut Vec::new(),
+        )
+    }
+}
 
-        vec![cross_plane, circle_plane, real_p1_plane, real_p2_plane]
+#[cfg(feature = "alphazero")]
+impl From<BoardState> for tensorflow::Tensor<f32> {
+    fn from(state: BoardState) -> Self {
+        Self::from(&state)
     }
 }
 
-impl Into<tensorflow::Tensor<f32>> for BoardState {
-    fn into(self) -> tensorflow::Tensor<f32> {
-        tensor_to_tensorflow(self.into())
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{BoardAction, Coordinate},
+        board::{Cell, TerminalResult},
+        error::{MoveError, SwitchError},
+        player::Player,
+        rules::GameRules,
+        testing::{col, StateBuilder},
+        BoardState,
+    };
+
+    use mcts::GameState;
+
+    #[test]
+    fn explain_illegal_actions() {
+        let mut state = BoardState::default();
+        assert_eq!(
+            state.explain_illegal(&BoardAction::DropStone(Player::Player1, col(3))),
+            None
+        );
+        assert_eq!(
+            state.explain_illegal(&BoardAction::DropStone(Player::Player2, col(3))),
+            Some(String::from("it is Player1's turn"))
+        );
+
+        state.make_move(&BoardAction::DropStone(Player::Player1, col(3)));
+        state.make_move(&BoardAction::DropStone(Player::Player2, col(4)));
+
+        assert_eq!(
+            state.explain_illegal(&BoardAction::SwitchStone(
+                Coordinate::new(3, 0),
+                Coordinate::new(4, 0)
+            )),
+            Some(String::from("you have 0 switch points"))
+        );
+
+        state.player_1_points = 1;
+        assert_eq!(
+            state.explain_illegal(&BoardAction::SwitchStone(
+                Coordinate::new(3, 0),
+                Coordinate::new(4, 0)
+            )),
+            None
+        );
+        assert_eq!(
+            state.explain_illegal(&BoardAction::SwitchStone(
+                Coordinate::new(3, 0),
+                Coordinate::new(5, 0)
+            )),
+            Some(String::from("cells are not adjacent"))
+        );
+        assert_eq!(
+            state.explain_illegal(&BoardAction::SwitchStone(
+                Coordinate::new(3, 0),
+                Coordinate::new(3, 1)
+            )),
+            Some(String::from("cell (3, 1) is empty"))
+        );
+    }
+
+    #[test]
+    fn from_moves() {
+        let state = BoardState::from_moves(
+            GameRules::default(),
+            &[
+                BoardAction::DropStone(Player::Player1, col(3)),
+                BoardAction::DropStone(Player::Player2, col(3)),
+            ],
+        )
+        .expect("Could not build state");
+        assert_eq!(state.current_player(), Player::Player1);
+        assert_eq!(
+            state.cell(Coordinate::new(3, 1)),
+            Cell::Filled(Player::Player2)
+        );
+        assert_eq!(state.points(Player::Player2), 0);
+        assert_eq!(state.terminal_status(), &TerminalResult::None);
+
+        let error = BoardState::from_moves(
+            GameRules::default(),
+            &[
+                BoardAction::DropStone(Player::Player1, col(3)),
+                BoardAction::DropStone(Player::Player1, col(3)),
+            ],
+        );
+        assert_eq!(error.unwrap_err(), "move 2 (d3): it is Player2's turn");
+    }
+
+    #[test]
+    fn zobrist_transpositions() {
+        let play = |moves: &[(Player, usize)]| {
+            let moves = moves
+                .iter()
+                .map(|&(player, c)| BoardAction::DropStone(player, col(c)))
+                .collect::<Vec<_>>();
+            BoardState::from_moves(GameRules::default(), &moves).expect("Could not build state")
+        };
+
+        let a = play(&[
+            (Player::Player1, 1),
+            (Player::Player2, 2),
+            (Player::Player1, 3),
+        ]);
+        let b = play(&[
+            (Player::Player1, 3),
+            (Player::Player2, 2),
+            (Player::Player1, 1),
+        ]);
+        let c = play(&[(Player::Player1, 1), (Player::Player2, 2)]);
+
+        assert_eq!(a.zobrist(), b.zobrist());
+        assert_ne!(a.zobrist(), c.zobrist());
+        assert_ne!(BoardState::default().zobrist(), c.board().zobrist());
+    }
+
+    #[test]
+    fn diagonal_switches() {
+        let rules = GameRules {
+            width: 2,
+            height: 3,
+            ..Default::default()
+        };
+        let builder = StateBuilder::new()
+            .board(["  ", "XO", "XO"])
+            .points(Player::Player1, 1);
+        let diagonal = BoardAction::SwitchStone(Coordinate::new(0, 0), Coordinate::new(1, 1));
+
+        let state = builder.clone().build();
+        assert!(!state.available_moves().contains(&diagonal));
+        assert_eq!(
+            state.check_move(&diagonal),
+            Err(MoveError::IllegalSwitch(SwitchError::NotAdjacent))
+        );
+
+        let mut state = builder
+            .rules(GameRules {
+                diagonal_switches: true,
+                ..rules
+            })
+            .build();
+        assert!(state.available_moves().contains(&diagonal));
+        assert!(state.available_moves().contains(&BoardAction::SwitchStone(
+            Coordinate::new(1, 0),
+            Coordinate::new(0, 1)
+        )));
+        assert_eq!(state.try_make_move(&diagonal), Ok(vec![]));
+        assert_eq!(
+            state.board().get(Coordinate::new(1, 1)),
+            Cell::Filled(Player::Player1)
+        );
+    }
+
+    #[test]
+    fn move_generation() {
+        let state = StateBuilder::new()
+            .board(["  ", "XO", "XO"])
+            .points(Player::Player1, 1)
+            .rules(GameRules {
+                width: 2,
+                height: 3,
+                diagonal_switches: true,
+                ..Default::default()
+            })
+            .build();
+        let switch = |a: (isize, isize), b: (isize, isize)| {
+            BoardAction::SwitchStone(Coordinate::new(a.0, a.1), Coordinate::new(b.0, b.1))
+        };
+        let expected = vec![
+            BoardAction::DropStone(Player::Player1, col(0)),
+            BoardAction::DropStone(Player::Player1, col(1)),
+            switch((0, 0), (1, 0)),
+            switch((0, 1), (1, 1)),
+            switch((0, 0), (1, 1)),
+            switch((1, 0), (0, 1)),
+        ];
+        assert_eq!(state.available_moves().to_vec(), expected);
+
+        let mut moves = vec![switch((1, 1), (1, 2))];
+        state.fill_moves(&mut moves);
+        assert_eq!(moves, expected);
+        assert!(!state.is_terminal());
+
+        let state = StateBuilder::new().board(["    ", "XXXX"]).build();
+        assert_eq!(state.moves().next(), None);
+        assert!(state.is_terminal());
+    }
+
+    #[test]
+    fn try_make_move() {
+        let mut state = StateBuilder::new().board(["  ", "O ", "X ", "O "]).build();
+
+        assert_eq!(
+            state.try_make_move(&BoardAction::DropStone(Player::Player1, col(0))),
+            Ok(vec![])
+        );
+        let zobrist = state.zobrist();
+        assert_eq!(
+            state.try_make_move(&BoardAction::DropStone(Player::Player2, col(0))),
+            Err(MoveError::ColumnFull(col(0)))
+        );
+        assert_eq!(
+            state.try_make_move(&BoardAction::DropStone(Player::Player2, col(2))),
+            Err(MoveError::ColumnOutOfBounds(col(2)))
+        );
+        assert_eq!(
+            state.try_make_move(&BoardAction::SwitchStone(
+                Coordinate::new(0, 0),
+                Coordinate::new(0, 1)
+            )),
+            Err(MoveError::NotEnoughPoints)
+        );
+        assert_eq!(state.zobrist(), zobrist);
+    }
+
+    #[test]
+    fn undo() {
+        let mut state = StateBuilder::new()
+            .board([
+                "        ", "        ", "        ", "        ", "        ", "        ", "        ",
+                "XX O    ",
+            ])
+            .build();
+        let before = state.clone();
+
+        state.make_move(&BoardAction::DropStone(Player::Player1, col(2)));
+        assert_eq!(state.points(Player::Player1), 1);
+        state.make_move(&BoardAction::DropStone(Player::Player2, col(4)));
+        assert_eq!(state.history().count(), 2);
+
+        assert_eq!(
+            state.undo(),
+            Some(BoardAction::DropStone(Player::Player2, col(4)))
+        );
+        assert_eq!(
+            state.undo(),
+            Some(BoardAction::DropStone(Player::Player1, col(2)))
+        );
+        assert_eq!(state.undo(), None);
+
+        assert_eq!(state.zobrist(), before.zobrist());
+        assert_eq!(state.board(), before.board());
+        assert_eq!(state.current_player(), Player::Player1);
+        assert_eq!(state.points(Player::Player1), 0);
+        assert_eq!(state.terminal_status(), &TerminalResult::None);
+    }
+
+    // The vertical pairs run up to the top row in every column, the last
+    // column included.
+    #[test]
+    fn vertical_switches_in_the_last_column() {
+        let state = StateBuilder::new()
+            .board(["   X", "   O", "   X", "   O"])
+            .rules(GameRules {
+                width: 4,
+                height: 4,
+                ..Default::default()
+            })
+            .points(Player::Player1, 1)
+            .build();
+
+        let switches = state
+            .available_moves()
+            .into_iter()
+            .filter(|m| matches!(m, BoardAction::SwitchStone(..)))
+            .collect::<Vec<_>>();
+        let expected = (0..3)
+            .map(|y| BoardAction::SwitchStone(Coordinate::new(3, y), Coordinate::new(3, y + 1)))
+            .collect::<Vec<_>>();
+        assert_eq!(switches, expected);
+    }
+
+    #[test]
+    fn clones_share_the_history() {
+        let mut state = BoardState::default();
+        state.make_move(&BoardAction::DropStone(Player::Player1, col(0)));
+        state.make_move(&BoardAction::DropStone(Player::Player2, col(1)));
+
+        let mut clone = state.clone();
+        clone.make_move(&BoardAction::DropStone(Player::Player1, col(2)));
+        assert_eq!(clone.ply(), 3);
+        assert_eq!(
+            clone.history().copied().collect::<Vec<_>>(),
+            vec![
+                BoardAction::DropStone(Player::Player1, col(0)),
+                BoardAction::DropStone(Player::Player2, col(1)),
+                BoardAction::DropStone(Player::Player1, col(2)),
+            ]
+        );
+
+        // Taking back a shared action leaves the other state alone.
+        assert_eq!(
+            clone.undo(),
+            Some(BoardAction::DropStone(Player::Player1, col(2)))
+        );
+        assert_eq!(
+            clone.undo(),
+            Some(BoardAction::DropStone(Player::Player2, col(1)))
+        );
+        assert_eq!(clone.ply(), 1);
+        assert_eq!(state.ply(), 2);
+        assert_eq!(
+            state.undo(),
+            Some(BoardAction::DropStone(Player::Player2, col(1)))
+        );
+    }
+
+    #[test]
+    fn mirror() {
+        let mut state = StateBuilder::new()
+            .board(["    ", "OX  ", "XOX "])
+            .rules(GameRules {
+                width: 4,
+                height: 3,
+                diagonal_switches: true,
+                ..Default::default()
+            })
+            .points(Player::Player1, 1)
+            .build();
+        state.make_move(&BoardAction::DropStone(Player::Player1, col(3)));
+        state.make_move(&BoardAction::DropStone(Player::Player2, col(0)));
+
+        let mirrored = state.mirror();
+        assert_eq!(mirrored.board().rows(), vec!["   O", "  XO", "XXOX"]);
+        assert_eq!(mirrored.mirror().board(), state.board());
+        assert_eq!(mirrored.mirror().zobrist(), state.zobrist());
+        assert_eq!(
+            mirrored.history().copied().collect::<Vec<_>>(),
+            vec![
+                BoardAction::DropStone(Player::Player1, col(0)),
+                BoardAction::DropStone(Player::Player2, col(3))
+            ]
+        );
+
+        let mut moves = state
+            .available_moves()
+            .iter()
+            .map(|m| m.mirrored(4))
+            .collect::<Vec<_>>();
+        let mut expected = mirrored.available_moves().to_vec();
+        moves.sort_by_key(|m| m.to_index(4, 3));
+        expected.sort_by_key(|m| m.to_index(4, 3));
+        assert_eq!(moves, expected);
+
+        #[cfg(feature = "alphazero")]
+        {
+            let flatten = |state: &BoardState| {
+                state
+                    .legal_action_mask()
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .map(f32::from)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                crate::alphazero::mirror_policy(&flatten(&state), 4, 3),
+                flatten(&mirrored)
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let state = BoardState::from_moves(
+            GameRules::default(),
+            &[
+                BoardAction::DropStone(Player::Player1, col(3)),
+                BoardAction::DropStone(Player::Player2, col(4)),
+            ],
+        )
+        .expect("Could not build state");
+
+        let json = serde_json::to_string(&state).expect("Could not serialize state");
+        let loaded: BoardState = serde_json::from_str(&json).expect("Could not deserialize state");
+        assert_eq!(loaded.board(), state.board());
+        assert_eq!(loaded.zobrist(), state.zobrist());
+        assert_eq!(loaded.rules(), state.rules());
+
+        // Three in a row that were not cleared, and a stone above an empty
+        // cell.
+        let unresolved = json.replace("\"   XO   \"", "\"XXXO    \"");
+        assert_ne!(unresolved, json);
+        assert!(serde_json::from_str::<BoardState>(&unresolved).is_err());
+        let rows = serde_json::to_string(&crate::board::Board::from(["X ", "  "])).unwrap();
+        assert!(serde_json::from_str::<crate::board::Board>(&rows).is_err());
+
+        let action: BoardAction =
+            serde_json::from_str(r#"{"DropStone":["Player2",5]}"#).expect("Could not parse action");
+        assert_eq!(action, BoardAction::DropStone(Player::Player2, col(5)));
+        assert!(serde_json::from_str::<BoardAction>(r#"{"DropStone":["Player2",99]}"#).is_err());
     }
 }
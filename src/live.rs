@@ -0,0 +1,521 @@
+// Live games over WebSocket. Every connected client follows the game as it
+// is played, with a JSON text message per event:
+//
+//     {"type": "state", ...}                   the game, see json::state_json
+//     {"type": "move", "move": "d3", "player": 1, "steps": [...]}
+//     {"type": "search", "playouts": 1200, "best": "d3",
+//      "moves": [{"move": "d3", "visits": 800, "value": 0.12}, ...]}
+//     {"type": "error", "error": "..."}
+//
+// The steps are the cascade of the action, see json::step_json, and the
+// search events follow the engine while it thinks. Clients play with
+// {"type": "move", "move": "d3"} when a player the engine does not play is to
+// move, and start over with {"type": "new_game", "rules": "size=7x6",
+// "engines": [2]}, the engine playing the listed players, as long as the
+// engine can search the rules. A server started with the engine on both
+// sides plays one game after the other for spectators, clients only watch.
+use std::{
+    io::{self, ErrorKind},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use mcts::{GameState, MCTSManager, ThreadData, MCTS};
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+use tungstenite::Message;
+
+use crate::{
+    action::BoardAction,
+    json::{player_number, state_json, step_json},
+    player::Player,
+    rules::GameRules,
+    timecontrol::SearchLimit,
+    BoardState,
+};
+
+// How long a client waits for a message before it sends the events, and
+// the game for a command.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// Between the games the engine plays against itself.
+const GAME_PAUSE: Duration = Duration::from_secs(3);
+// Most visited moves in a search event.
+const SEARCH_MOVES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Move(String),
+    NewGame {
+        rules: GameRules,
+        engines: Vec<Player>,
+    },
+}
+
+pub fn parse_command(text: &str) -> Result<Command, String> {
+    let command: Value = serde_json::from_str(text).map_err(|e| format!("Bad JSON: {}", e))?;
+    match command["type"].as_str() {
+        Some("move") => command["move"]
+            .as_str()
+            .map(|mov| Command::Move(mov.to_string()))
+            .ok_or_else(|| String::from("Missing move")),
+        Some("new_game") => {
+            let rules = match command["rules"].as_str() {
+                Some(rules) => {
+                    GameRules::parse(rules).ok_or_else(|| format!("Bad rules: {}", rules))?
+                }
+                None => GameRules::default(),
+            };
+            let engines = match command["engines"].as_array() {
+                Some(players) => players
+                    .iter()
+                    .map(|player| match player.as_u64() {
+                        Some(1) => Ok(Player::Player1),
+                        Some(2) => Ok(Player::Player2),
+                        _ => Err(format!("Unknown player {}", player)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                None => vec![Player::Player2],
+            };
+            Ok(Command::NewGame { rules, engines })
+        }
+        _ => Err(format!("Unknown command: {}", text)),
+    }
+}
+
+// Hands every event to all connected clients. Clients that connect get the
+// last state, so they can draw the game at once.
+#[derive(Default)]
+pub struct Hub {
+    clients: Mutex<Vec<Sender<String>>>,
+    last_state: Mutex<Option<String>>,
+}
+
+impl Hub {
+    pub fn broadcast(&self, event: &Value) {
+        let text = event.to_string();
+        if event["type"] == "state" {
+            *self.last_state.lock().expect("Could not lock state") = Some(text.clone());
+        }
+        // Clients that left have dropped their receiver.
+        self.clients
+            .lock()
+            .expect("Could not lock clients")
+            .retain(|client| client.send(text.clone()).is_ok());
+    }
+
+    pub fn join(&self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        if let Some(state) = &*self.last_state.lock().expect("Could not lock state") {
+            sender.send(state.clone()).ok();
+        }
+        self.clients
+            .lock()
+            .expect("Could not lock clients")
+            .push(sender);
+        receiver
+    }
+}
+
+// The game that is being played and the engine that plays in it.
+pub struct LiveGame<Spec: MCTS> {
+    // Sets up the search of a position, fails for rules the engine can't
+    // search.
+    create: Box<dyn Fn(BoardState) -> Result<MCTSManager<Spec>, String> + Send>,
+    limit: SearchLimit,
+    threads: usize,
+    state: BoardState,
+    engines: Vec<Player>,
+    // Served for spectators, the clients can't start games.
+    watch_only: bool,
+}
+
+impl<Spec> LiveGame<Spec>
+where
+    Spec: MCTS<State = BoardState>,
+    ThreadData<Spec>: Default,
+{
+    pub fn new<F>(create: F, limit: SearchLimit, threads: usize, engines: Vec<Player>) -> Self
+    where
+        F: Fn(BoardState) -> Result<MCTSManager<Spec>, String> + Send + 'static,
+    {
+        let mut game = Self {
+            create: Box::new(create),
+            limit,
+            threads,
+            state: BoardState::default(),
+            engines,
+            watch_only: false,
+        };
+        game.watch_only = game.spectating();
+        game
+    }
+
+    pub fn state(&self) -> &BoardState {
+        &self.state
+    }
+
+    pub fn engine_to_move(&self) -> bool {
+        !self.state.is_terminal() && self.engines.contains(&self.state.current_player())
+    }
+
+    // The engine plays both sides, there is nobody to wait for.
+    pub fn spectating(&self) -> bool {
+        [Player::Player1, Player::Player2]
+            .iter()
+            .all(|player| self.engines.contains(player))
+    }
+
+    pub fn state_event(&self) -> Value {
+        let mut event = state_json(&self.state);
+        event["type"] = json!("state");
+        event
+    }
+
+    pub fn apply(&mut self, command: Command, emit: &mut dyn FnMut(Value)) {
+        match command {
+            Command::Move(action) => {
+                if let Err(e) = self.play_client_move(&action, emit) {
+                    emit(json!({ "type": "error", "error": e }));
+                }
+            }
+            Command::NewGame { .. } if self.watch_only => {
+                emit(json!({ "type": "error", "error": "The games are only for watching" }));
+            }
+            Command::NewGame { rules, engines } => {
+                if let Err(e) = self.start(rules, engines, emit) {
+                    emit(json!({ "type": "error", "error": e }));
+                }
+            }
+        }
+    }
+
+    // Starts the next game with the same rules and players.
+    pub fn restart(&mut self, emit: &mut dyn FnMut(Value)) {
+        let (rules, engines) = (*self.state.rules(), self.engines.clone());
+        if let Err(e) = self.start(rules, engines, emit) {
+            warn!(error = %e, "Could not start the next game");
+        }
+    }
+
+    fn start(
+        &mut self,
+        rules: GameRules,
+        engines: Vec<Player>,
+        emit: &mut dyn FnMut(Value),
+    ) -> Result<(), String> {
+        let state = BoardState::new(rules);
+        (self.create)(state.clone())
+            .map_err(|e| format!("The engine can't play {}: {}", rules, e))?;
+        info!(%rules, ?engines, "New live game");
+        self.state = state;
+        self.engines = engines;
+        emit(self.state_event());
+        Ok(())
+    }
+
+    fn play_client_move(
+        &mut self,
+        action: &str,
+        emit: &mut dyn FnMut(Value),
+    ) -> Result<(), String> {
+        if self.state.is_terminal() {
+            return Err(String::from("The game is over"));
+        }
+        if self.engine_to_move() {
+            return Err(String::from("The engine is to move"));
+        }
+        let mov = BoardAction::parse(action, self.state.current_player())
+            .ok_or_else(|| format!("Could not read {}", action))?;
+        self.state
+            .check_move(&mov)
+            .map_err(|e| format!("Can't play {}: {}", action, e))?;
+        self.play(mov, emit);
+        Ok(())
+    }
+
+    // Searches the position, with an event after every part of the search,
+    // and plays the best move.
+    pub fn engine_move(&mut self, emit: &mut dyn FnMut(Value)) {
+        // The rules were checked when the game started.
+        let mut manager =
+            (self.create)(self.state.clone()).expect("The engine searches the rules of the game");
+        self.limit.budget(&self.state).search_with_progress(
+            &mut manager,
+            self.threads,
+            |manager| emit(search_event(manager)),
+        );
+        match manager.best_move() {
+            Some(mov) => self.play(mov, emit),
+            None => emit(json!({ "type": "error", "error": "The engine found no move" })),
+        }
+    }
+
+    fn play(&mut self, mov: BoardAction, emit: &mut dyn FnMut(Value)) {
+        let player = player_number(self.state.current_player());
+        let steps = self
+            .state
+            .play_steps(&mov)
+            .iter()
+            .map(step_json)
+            .collect::<Vec<_>>();
+        emit(json!({
+            "type": "move",
+            "move": mov.to_string(),
+            "player": player,
+            "steps": steps,
+        }));
+        emit(self.state_event());
+    }
+}
+
+// The most visited moves of the root, with their values for the player to
+// move.
+fn search_event<Spec: MCTS<State = BoardState>>(manager: &MCTSManager<Spec>) -> Value {
+    let mut moves = manager
+        .tree()
+        .root_node()
+        .moves()
+        .map(|m| {
+            let value = match m.visits() {
+                0 => 0.0,
+                visits => m.sum_rewards() / visits as f64,
+            };
+            (m.get_move().to_string(), m.visits(), value)
+        })
+        .collect::<Vec<_>>();
+    moves.sort_by_key(|(_, visits, _)| std::cmp::Reverse(*visits));
+    let playouts = moves.iter().map(|(_, visits, _)| visits).sum::<u64>();
+    json!({
+        "type": "search",
+        "playouts": playouts,
+        "best": manager.best_move().map(|mov| mov.to_string()),
+        "moves": moves
+            .iter()
+            .take(SEARCH_MOVES)
+            .map(|(mov, visits, value)| json!({ "move": mov, "visits": visits, "value": value }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+// Accepts clients on the address and plays the game until the process
+// ends. Commands wait while the engine searches.
+pub fn serve<Spec>(mut game: LiveGame<Spec>, address: &str) -> io::Result<()>
+where
+    Spec: MCTS<State = BoardState>,
+    ThreadData<Spec>: Default,
+{
+    let listener = TcpListener::bind(address)?;
+    info!(address, "Serving live games");
+
+    let hub = Arc::new(Hub::default());
+    let (commands, received) = mpsc::channel();
+    let accepting = hub.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!(error = %e, "Could not accept client");
+                    continue;
+                }
+            };
+            let (hub, commands) = (accepting.clone(), commands.clone());
+            thread::spawn(move || {
+                if let Err(e) = client(stream, &hub, commands) {
+                    debug!(error = %e, "Client left");
+                }
+            });
+        }
+    });
+
+    let mut broadcast = |event: Value| hub.broadcast(&event);
+    broadcast(game.state_event());
+    loop {
+        while let Ok(command) = received.try_recv() {
+            game.apply(command, &mut broadcast);
+        }
+        if game.engine_to_move() {
+            game.engine_move(&mut broadcast);
+        } else if game.spectating() && game.state().is_terminal() {
+            thread::sleep(GAME_PAUSE);
+            game.restart(&mut broadcast);
+        } else if let Ok(command) = received.recv_timeout(POLL_INTERVAL) {
+            game.apply(command, &mut broadcast);
+        }
+    }
+}
+
+// Passes the events to the client and its commands to the game until it
+// leaves.
+fn client(stream: TcpStream, hub: &Hub, commands: Sender<Command>) -> Result<(), String> {
+    let mut socket = tungstenite::accept(stream).map_err(|e| e.to_string())?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .map_err(|e| e.to_string())?;
+    let events = hub.join();
+    debug!("Client joined");
+
+    loop {
+        while let Ok(event) = events.try_recv() {
+            socket
+                .send(Message::Text(event))
+                .map_err(|e| e.to_string())?;
+        }
+        match socket.read() {
+            Ok(Message::Text(text)) => match parse_command(&text) {
+                Ok(command) => commands.send(command).map_err(|e| e.to_string())?,
+                Err(e) => {
+                    let error = json!({ "type": "error", "error": e }).to_string();
+                    socket
+                        .send(Message::Text(error))
+                        .map_err(|e| e.to_string())?;
+                }
+            },
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use mcts::MCTSManager;
+
+    use crate::{
+        heuristic::{HeuristicEvaluator, HeuristicMCTS},
+        player::Player,
+        rules::GameRules,
+        timecontrol::SearchLimit,
+        BoardState,
+    };
+
+    use super::{parse_command, Command, Hub, LiveGame};
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            parse_command(r#"{"type": "move", "move": "d3"}"#),
+            Ok(Command::Move(String::from("d3")))
+        );
+        assert_eq!(
+            parse_command(r#"{"type": "new_game", "engines": [1, 2]}"#),
+            Ok(Command::NewGame {
+                rules: GameRules::default(),
+                engines: vec![Player::Player1, Player::Player2],
+            })
+        );
+        assert!(parse_command(r#"{"type": "new_game", "engines": [3]}"#).is_err());
+        assert!(parse_command(r#"{"type": "resign"}"#).is_err());
+        assert!(parse_command("d3").is_err());
+    }
+
+    // Searches the default board only, like a network trained for it.
+    fn create(state: BoardState) -> Result<MCTSManager<HeuristicMCTS>, String> {
+        match state.rules() == &GameRules::default() {
+            true => Ok(HeuristicMCTS::create_search(
+                state,
+                HeuristicEvaluator::default(),
+                1.4,
+            )),
+            false => Err(String::from("Not the default rules")),
+        }
+    }
+
+    #[test]
+    fn play_against_the_engine() {
+        let mut game = LiveGame::new(create, SearchLimit::Playouts(40), 1, vec![Player::Player2]);
+        let mut events = Vec::new();
+        let mut emit = |event: Value| events.push(event);
+
+        game.apply(Command::Move(String::from("d3")), &mut emit);
+        assert!(game.engine_to_move());
+        game.apply(Command::Move(String::from("d4")), &mut emit);
+        game.engine_move(&mut emit);
+        assert!(!game.engine_to_move());
+        assert!(!game.spectating());
+
+        let kinds = events
+            .iter()
+            .map(|event| event["type"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(&kinds[..3], ["move", "state", "error"]);
+        assert_eq!(&kinds[kinds.len() - 2..], ["move", "state"]);
+        let searches = events.iter().filter(|e| e["type"] == "search").count();
+        assert_eq!(searches, 10);
+        assert!(events[0]["steps"].is_array());
+        assert_eq!(
+            events.last().unwrap()["history"].as_array().map(Vec::len),
+            Some(2)
+        );
+        let search = &events[kinds.len() - 3];
+        assert!(search["playouts"].as_u64() > Some(0));
+        assert!(search["best"].is_string());
+    }
+
+    #[test]
+    fn new_games() {
+        let mut game = LiveGame::new(create, SearchLimit::Playouts(10), 1, vec![Player::Player2]);
+        let mut events = Vec::new();
+        let mut emit = |event: Value| events.push(event);
+
+        let rules = GameRules::parse("size=5x4").unwrap();
+        let engines = vec![Player::Player1];
+        game.apply(Command::NewGame { rules, engines }, &mut emit);
+        assert_eq!(game.state().rules(), &GameRules::default());
+        let engines = vec![Player::Player1];
+        let rules = GameRules::default();
+        game.apply(Command::NewGame { rules, engines }, &mut emit);
+        assert!(game.engine_to_move());
+        let kinds = events
+            .iter()
+            .map(|event| event["type"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, ["error", "state"]);
+
+        let engines = vec![Player::Player1, Player::Player2];
+        let mut spectated = LiveGame::new(create, SearchLimit::Playouts(10), 1, engines);
+        let mut events = Vec::new();
+        let mut emit = |event: Value| events.push(event);
+        let command = Command::NewGame {
+            rules: GameRules::default(),
+            engines: vec![Player::Player2],
+        };
+        spectated.apply(command, &mut emit);
+        assert!(spectated.spectating());
+        spectated.restart(&mut emit);
+        let kinds = events
+            .iter()
+            .map(|event| event["type"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, ["error", "state"]);
+    }
+
+    #[test]
+    fn hub() {
+        let hub = Hub::default();
+        let early = hub.join();
+        hub.broadcast(&json!({ "type": "state", "player": 1 }));
+        hub.broadcast(&json!({ "type": "search" }));
+
+        let late = hub.join();
+        assert_eq!(early.try_iter().count(), 2);
+        assert_eq!(
+            late.try_iter().collect::<Vec<_>>(),
+            vec![json!({ "type": "state", "player": 1 }).to_string()]
+        );
+        drop(early);
+        hub.broadcast(&json!({ "type": "search" }));
+        assert_eq!(hub.clients.lock().unwrap().len(), 1);
+    }
+}
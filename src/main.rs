@@ -0,0 +1,51 @@
+mod cli;
+
+const USAGE: &str = "\
+usage: m3c4 <command> [arguments] [--log-level LEVEL] [--log-json]
+commands:
+  train       run the self-play and training loop, --workers to play on other machines
+  selfplay    play an episode of self-play games without training
+  play        play against an engine in the terminal, --tui for a cursor
+  eval        play two engines against each other and rate them
+  tournament  play a round robin and rate all players from every result
+  analyze     search a position for the best move
+  serve       serve games against the engine as an HTTP/JSON API
+  live        stream live games and the engine's search to WebSocket clients
+  ugi         talk the UGI engine protocol on stdin and stdout
+  replay      step through a saved game record
+  export      write the training samples as NumPy arrays
+  book        build an opening book from the game records
+  perft       count the positions reached after every number of actions
+  worker      play self-play games for a training run on another machine";
+
+fn main() {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let logging = cli::init_logging(&mut args);
+    let command = (!args.is_empty()).then(|| args.remove(0));
+
+    let result = logging.and_then(|()| match command.as_deref() {
+        Some("train") => cli::train::run(args),
+        Some("selfplay") => cli::selfplay::run(args),
+        Some("play") => cli::play::run(args),
+        Some("eval") => cli::eval::run(args),
+        Some("tournament") => cli::tournament::run(args),
+        Some("analyze") => cli::analyze::run(args),
+        Some("ugi") => cli::ugi::run(args),
+        Some("serve") => cli::serve::run(args),
+        Some("live") => cli::live::run(args),
+        Some("replay") => cli::replay::run(args),
+        Some("worker") => cli::worker::run(args),
+        Some("export") => cli::export::run(args),
+        Some("book") => cli::book::run(args),
+        Some("perft") => cli::perft::run(args),
+        _ => Err(String::new()),
+    });
+
+    if let Err(e) = result {
+        if !e.is_empty() {
+            println!("{}", e);
+        }
+        println!("{}", USAGE);
+        std::process::exit(2);
+    }
+}
@@ -0,0 +1,288 @@
+// Scalars that show how training goes, recorded once per episode. They are
+// written to TensorBoard event files, `tensorboard --logdir data/metrics`,
+// and to a CSV file with a column per scalar.
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use catzero::{TFModel, TrainingData};
+
+use crate::{
+    alphazero::{pipeline::SelfPlayGame, MyMCTS},
+    board::CascadeStep,
+    planes::{InputEncoding, InputPlanes},
+    player::Player,
+    tfrecord::{put_bytes, put_double, put_key, put_varint, write_record},
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EpisodeMetrics {
+    pub episode: usize,
+    // Losses of the model that played the games on their samples, before it
+    // is trained on them.
+    pub policy_loss: Option<f32>,
+    pub value_loss: Option<f32>,
+    // Score of the candidate in its arena match.
+    pub arena_score: Option<f32>,
+    // Fractions of the self-play games.
+    pub player_1_wins: f32,
+    pub player_2_wins: f32,
+    pub draws: f32,
+    // Means per self-play game. A cascade is a clear that follows from
+    // stones falling after an earlier one of the same move.
+    pub game_length: f32,
+    pub cascades: f32,
+    pub points: f32,
+}
+
+impl EpisodeMetrics {
+    pub fn from_games(episode: usize, games: &[SelfPlayGame<MyMCTS>]) -> Self {
+        let mut metrics = Self {
+            episode,
+            ..Default::default()
+        };
+        if games.is_empty() {
+            return metrics;
+        }
+
+        for game in games {
+            match game.winner {
+                Some(Player::Player1) => metrics.player_1_wins += 1.0,
+                Some(Player::Player2) => metrics.player_2_wins += 1.0,
+                None => metrics.draws += 1.0,
+            }
+            metrics.game_length += game.moves.len() as f32;
+
+            let mut state = match game.histories.first() {
+                Some((start, _)) => start.clone(),
+                None => continue,
+            };
+            for mov in &game.moves {
+                let clears = state
+                    .play_steps(mov)
+                    .iter()
+                    .filter(|step| matches!(step, CascadeStep::Cleared(..)))
+                    .count();
+                metrics.cascades += clears.saturating_sub(1) as f32;
+            }
+            metrics.points +=
+                (state.points(Player::Player1) + state.points(Player::Player2)) as f32;
+        }
+
+        let count = games.len() as f32;
+        for value in [
+            &mut metrics.player_1_wins,
+            &mut metrics.player_2_wins,
+            &mut metrics.draws,
+            &mut metrics.game_length,
+            &mut metrics.cascades,
+            &mut metrics.points,
+        ] {
+            *value /= count;
+        }
+        metrics
+    }
+
+    // The value of every tag, None when it was not measured.
+    pub fn scalars(&self) -> [(&'static str, Option<f32>); 9] {
+        [
+            ("loss/policy", self.policy_loss),
+            ("loss/value", self.value_loss),
+            ("arena/score", self.arena_score),
+            ("selfplay/player_1_wins", Some(self.player_1_wins)),
+            ("selfplay/player_2_wins", Some(self.player_2_wins)),
+            ("selfplay/draws", Some(self.draws)),
+            ("selfplay/game_length", Some(self.game_length)),
+            ("selfplay/cascades", Some(self.cascades)),
+            ("selfplay/points", Some(self.points)),
+        ]
+    }
+}
+
+// Cross-entropy of the policy and squared error of the value, averaged over
+// at most `limit` samples spread over the data. The samples are encoded as
+// `input`.
+pub fn loss(
+    model: &TFModel,
+    data: &TrainingData,
+    limit: usize,
+    input: InputEncoding,
+) -> Result<(f32, f32), String> {
+    let step = (data.len() / limit.max(1)).max(1);
+    let mut policy_loss = 0.0;
+    let mut value_loss = 0.0;
+    let mut count = 0;
+    for i in (0..data.len()).step_by(step).take(limit) {
+        let planes = InputPlanes::from_tensor(data.inputs[i].clone(), input)
+            .map_err(|e| format!("Could not read sample {}: {}", i, e))?;
+        let (policy, value) = model
+            .evaluate(planes.to_tensorflow())
+            .map_err(|e| format!("Could not evaluate sample {}: {}", i, e))?;
+        let target = data.output_policy[i].iter().flatten().flatten();
+        policy_loss -= target
+            .zip(policy.iter())
+            .map(|(t, p)| t * p.max(1e-8).ln())
+            .sum::<f32>();
+        value_loss += (value - data.output_value[i]).powi(2);
+        count += 1;
+    }
+    let count = count.max(1) as f32;
+    Ok((policy_loss / count, value_loss / count))
+}
+
+pub enum MetricsWriter {
+    Csv(File),
+    TensorBoard(File),
+}
+
+impl MetricsWriter {
+    // Appends to the file, the header is written when it is new.
+    pub fn csv<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            let tags = EpisodeMetrics::default()
+                .scalars()
+                .map(|(tag, _)| tag)
+                .join(",");
+            writeln!(file, "episode,{}", tags)?;
+        }
+        Ok(MetricsWriter::Csv(file))
+    }
+
+    // A new event file in the directory, TensorBoard reads all of them.
+    pub fn tensorboard<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let name = format!("events.out.tfevents.{}.m3c4", now.as_secs());
+        let mut file = File::create(dir.as_ref().join(name))?;
+
+        let mut event = Vec::new();
+        put_double(&mut event, 1, now.as_secs_f64());
+        put_bytes(&mut event, 3, b"brain.Event:2");
+        write_record(&mut file, &event)?;
+        Ok(MetricsWriter::TensorBoard(file))
+    }
+
+    pub fn write(&mut self, metrics: &EpisodeMetrics) -> io::Result<()> {
+        match self {
+            MetricsWriter::Csv(file) => {
+                let values = metrics
+                    .scalars()
+                    .iter()
+                    .map(|(_, value)| value.map(|v| v.to_string()).unwrap_or_default())
+                    .collect::<Vec<_>>();
+                writeln!(file, "{},{}", metrics.episode, values.join(","))?;
+                file.flush()
+            }
+            MetricsWriter::TensorBoard(file) => {
+                let wall_time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                for (tag, value) in metrics.scalars() {
+                    if let Some(value) = value {
+                        write_record(file, &scalar_event(wall_time, metrics.episode, tag, value))?;
+                    }
+                }
+                file.flush()
+            }
+        }
+    }
+}
+
+// An Event protobuf holding a Summary with one simple value.
+fn scalar_event(wall_time: f64, step: usize, tag: &str, value: f32) -> Vec<u8> {
+    let mut summary_value = Vec::new();
+    put_bytes(&mut summary_value, 1, tag.as_bytes());
+    put_key(&mut summary_value, 2, 5);
+    summary_value.extend_from_slice(&value.to_le_bytes());
+    let mut summary = Vec::new();
+    put_bytes(&mut summary, 1, &summary_value);
+
+    let mut event = Vec::new();
+    put_double(&mut event, 1, wall_time);
+    put_key(&mut event, 2, 0);
+    put_varint(&mut event, step as u64);
+    put_bytes(&mut event, 5, &summary);
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        action::{BoardAction, Column},
+        alphazero::{pipeline::SelfPlayGame, MyMCTS},
+        player::Player,
+        testing::temp_path,
+        tfrecord::masked_crc,
+        BoardState,
+    };
+
+    use super::{EpisodeMetrics, MetricsWriter};
+
+    #[test]
+    fn write_episode_metrics() {
+        let drop = |player, col| BoardAction::DropStone(player, Column::new(col).unwrap());
+        let game = SelfPlayGame::<MyMCTS> {
+            histories: vec![(
+                BoardState::default(),
+                tensorflow::Tensor::new(&[1, 3, 8, 8]),
+            )],
+            moves: vec![drop(Player::Player1, 0), drop(Player::Player2, 1)],
+            stats: Vec::new(),
+            winner: Some(Player::Player2),
+            resigned: Some(Player::Player1),
+            would_resign: None,
+            seed: 0,
+            search_time: Duration::ZERO,
+        };
+        let mut metrics = EpisodeMetrics::from_games(4, &[game]);
+        assert_eq!(metrics.player_2_wins, 1.0);
+        assert_eq!(metrics.game_length, 2.0);
+        assert_eq!(metrics.points, 0.0);
+        metrics.arena_score = Some(0.75);
+
+        let path = temp_path("metrics_test.csv");
+        std::fs::remove_file(&path).ok();
+        MetricsWriter::csv(&path)
+            .and_then(|mut writer| writer.write(&metrics))
+            .expect("Could not write metrics");
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("episode,loss/policy,loss/value,arena/score"));
+        assert!(lines[1].starts_with("4,,,0.75,0,1,0,2,"));
+
+        let dir = temp_path("metrics_test");
+        std::fs::remove_dir_all(&dir).ok();
+        MetricsWriter::tensorboard(&dir)
+            .and_then(|mut writer| writer.write(&metrics))
+            .expect("Could not write events");
+        let file = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+        let bytes = std::fs::read(file.path()).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        // The version record and a record per measured scalar.
+        let mut records = 0;
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            let length = u64::from_le_bytes(rest[..8].try_into().unwrap()) as usize;
+            assert_eq!(rest[8..12], masked_crc(&rest[..8]).to_le_bytes());
+            let data = &rest[12..12 + length];
+            assert_eq!(
+                rest[12 + length..16 + length],
+                masked_crc(data).to_le_bytes()
+            );
+            rest = &rest[16 + length..];
+            records += 1;
+        }
+        assert_eq!(records, 1 + 7);
+    }
+}
@@ -0,0 +1,171 @@
+// Text notation of a full position, in the spirit of FEN:
+//
+//     8/8/8/8/8/8/3O4/2XX4 X 1 0
+//
+// The rows top row first separated by '/', X and O for the stones of player
+// 1 and 2 and a number for a run of empty cells, then the player to move and
+// the points of player 1 and player 2. The board size follows from the rows,
+// the other rules are the defaults. Positions no game can reach, with
+// floating stones or matches that were not cleared, are refused.
+use std::fmt::Write;
+
+use crate::{
+    action::Coordinate,
+    board::{Board, Cell, MAX_WIDTH},
+    player::Player,
+    rules::GameRules,
+    BoardState,
+};
+
+impl BoardState {
+    pub fn to_notation(&self) -> String {
+        let rows = self
+            .board
+            .rows()
+            .iter()
+            .map(|row| compress_row(row))
+            .collect::<Vec<_>>();
+        let player = match self.current_player {
+            Player::Player1 => 'X',
+            Player::Player2 => 'O',
+        };
+        format!(
+            "{} {} {} {}",
+            rows.join("/"),
+            player,
+            self.player_1_points,
+            self.player_2_points
+        )
+    }
+
+    pub fn parse_notation(s: &str) -> Option<Self> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        let (rows, player, points_1, points_2) = match fields[..] {
+            [rows, player, points_1, points_2] => (rows, player, points_1, points_2),
+            _ => return None,
+        };
+
+        let rows = rows
+            .split('/')
+            .map(expand_row)
+            .collect::<Option<Vec<_>>>()?;
+        let width = rows.first()?.len();
+        if rows.iter().any(|row| row.len() != width) || !Board::fits(width, rows.len()) {
+            return None;
+        }
+
+        let rules = GameRules {
+            width,
+            height: rows.len(),
+            ..Default::default()
+        };
+        let mut state = BoardState::new(rules);
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                state
+                    .board
+                    .set(*cell, Coordinate::new(x as isize, y as isize));
+            }
+        }
+
+        state.current_player = match player {
+            "X" => Player::Player1,
+            "O" => Player::Player2,
+            _ => return None,
+        };
+        state.player_1_points = points_1.parse().ok()?;
+        state.player_2_points = points_2.parse().ok()?;
+        state.winner = state.board.get_board_terminal_status(&state.rules);
+        state.validate().ok()?;
+
+        Some(state)
+    }
+}
+
+// "  XX O  " becomes "2XX1O2".
+fn compress_row(row: &str) -> String {
+    let mut out = String::new();
+    let mut empty = 0;
+    for c in row.chars() {
+        if c == ' ' {
+            empty += 1;
+            continue;
+        }
+        if empty > 0 {
+            write!(out, "{}", empty).expect("Could not write row");
+            empty = 0;
+        }
+        out.push(c);
+    }
+    if empty > 0 {
+        write!(out, "{}", empty).expect("Could not write row");
+    }
+    out
+}
+
+// None for rows wider than any board, before their cells are allocated.
+fn expand_row(row: &str) -> Option<Vec<Cell>> {
+    let mut cells = Vec::new();
+    let mut empty = String::new();
+    for c in row.chars().chain(std::iter::once('/')) {
+        if c.is_ascii_digit() {
+            empty.push(c);
+            continue;
+        }
+        if !empty.is_empty() {
+            let count: usize = empty.parse().ok()?;
+            if count == 0 || cells.len() + count > MAX_WIDTH {
+                return None;
+            }
+            cells.extend(std::iter::repeat_n(Cell::Empty, count));
+            empty.clear();
+        }
+        match c {
+            'X' => cells.push(Cell::Filled(Player::Player1)),
+            'O' => cells.push(Cell::Filled(Player::Player2)),
+            '/' => {}
+            _ => return None,
+        }
+        if cells.len() > MAX_WIDTH {
+            return None;
+        }
+    }
+    Some(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{player::Player, testing::StateBuilder, BoardState};
+
+    #[test]
+    fn notation_round_trip() {
+        let empty = "        ";
+        let state = StateBuilder::new()
+            .board([
+                empty, empty, empty, empty, empty, empty, "   O    ", "  XX    ",
+            ])
+            .points(Player::Player1, 1)
+            .build();
+
+        let notation = state.to_notation();
+        assert_eq!(notation, "8/8/8/8/8/8/3O4/2XX4 X 1 0");
+
+        let parsed = BoardState::parse_notation(&notation).expect("Could not parse notation");
+        assert_eq!(parsed.board(), state.board());
+        assert_eq!(parsed.points(Player::Player1), 1);
+        assert_eq!(parsed.to_notation(), notation);
+
+        let small = BoardState::parse_notation("6/X5/OX4 O 0 2").expect("Could not parse notation");
+        assert_eq!((small.board().width(), small.board().height()), (6, 3));
+
+        assert!(BoardState::parse_notation("8/7 X 0 0").is_none());
+        assert!(BoardState::parse_notation("8/8 Y 0 0").is_none());
+        assert!(BoardState::parse_notation("8/8 X 0").is_none());
+        assert!(BoardState::parse_notation("17/17 X 0 0").is_none());
+        assert!(BoardState::parse_notation("99999999999999/8 X 0 0").is_none());
+        assert!(BoardState::parse_notation("8X8/8X8 X 0 0").is_none());
+        // A stone above an empty cell and a match that was not cleared.
+        assert!(BoardState::parse_notation("6/X5/6 X 0 0").is_none());
+        assert!(BoardState::parse_notation("6/6/XXX3 O 0 0").is_none());
+    }
+}
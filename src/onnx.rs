@@ -0,0 +1,336 @@
+// Pure Rust inference with tract, so the engine can search with a trained
+// network without the Python and TensorFlow runtime. The saved TensorFlow
+// model of a generation is exported once with tf2onnx:
+//
+//     python -m tf2onnx.convert --saved-model <model dir> --output model.onnx
+//
+// The model takes [1, planes, width, height] inputs like the TensorFlow one
+// and has a policy output over the policy planes and a value output, either
+// a single value or the win, draw and loss probabilities of a WDL head.
+use std::{path::Path, sync::Arc};
+
+use mcts::{
+    transposition_table::ApproxTable, tree_policy::UCTPolicy, CycleBehaviour, Evaluator, GameState,
+    MCTSManager, MoveEvaluation, SearchHandle, MCTS,
+};
+use tract_onnx::prelude::{
+    tvec, DatumExt, Framework, InferenceModelExt, Tensor, TypedModel, TypedRunnableModel,
+};
+
+use crate::{
+    action::MoveList,
+    heuristic::StateEval,
+    planes::{masked_priors, policy_planes, InputEncoding, InputPlanes},
+    player::Player,
+    wdl::{Value, Wdl},
+    BoardState,
+};
+
+pub struct OnnxModel {
+    plan: TypedRunnableModel<TypedModel>,
+    width: usize,
+    height: usize,
+    diagonal_switches: bool,
+    encoding: InputEncoding,
+}
+
+impl OnnxModel {
+    // The board size, switch rule and input encoding have to match the ones
+    // the network was trained for, they fix the shapes of the input and the
+    // policy.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        diagonal_switches: bool,
+        encoding: InputEncoding,
+    ) -> Result<Self, String> {
+        let [a, b, c] = encoding.layout.dims(encoding.planes(), width, height);
+        let shape = [1, a, b, c];
+        let plan = tract_onnx::onnx()
+            .model_for_path(path)
+            .and_then(|model| model.with_input_fact(0, f32::fact(shape).into()))
+            .and_then(|model| model.into_optimized())
+            .and_then(|model| model.into_runnable())
+            .map_err(|e| format!("Could not load model: {}", e))?;
+        Ok(Self {
+            plan,
+            width,
+            height,
+            diagonal_switches,
+            encoding,
+        })
+    }
+
+    pub fn encoding(&self) -> InputEncoding {
+        self.encoding
+    }
+
+    // Whether the network can search the state, its policy fixes the board
+    // size and the switch rule.
+    pub fn check(&self, state: &BoardState) -> Result<(), String> {
+        let board = state.board();
+        if (board.width(), board.height()) != (self.width, self.height) {
+            return Err(format!(
+                "The model is for a {}x{} board, not {}x{}",
+                self.width,
+                self.height,
+                board.width(),
+                board.height()
+            ));
+        }
+        if state.rules().diagonal_switches != self.diagonal_switches {
+            return Err(format!(
+                "The model is for diagonal-switches={}",
+                self.diagonal_switches
+            ));
+        }
+        Ok(())
+    }
+
+    // The flattened policy, channels first, and the value for the player to
+    // move.
+    pub fn evaluate(&self, input: &InputPlanes) -> Result<(Vec<f32>, Value), String> {
+        if (input.width(), input.height()) != (self.width, self.height) {
+            return Err(format!(
+                "Model expects a {}x{} board, found {}x{}",
+                self.width,
+                self.height,
+                input.width(),
+                input.height()
+            ));
+        }
+        if input.encoding() != self.encoding {
+            return Err(format!(
+                "Model expects inputs encoded as {:?}, found {:?}",
+                self.encoding,
+                input.encoding()
+            ));
+        }
+        let tensor =
+            Tensor::from_shape(&input.dims(), &input.values()).map_err(|e| e.to_string())?;
+        let outputs = self
+            .plan
+            .run(tvec!(tensor.into()))
+            .map_err(|e| format!("Could not evaluate: {}", e))?;
+
+        // Exporters do not keep the order of the outputs, they are told
+        // apart by their size.
+        let policy_len = policy_planes(self.diagonal_switches) * self.width * self.height;
+        let mut policy = None;
+        let mut value = None;
+        for output in &outputs {
+            let view = output.as_slice::<f32>().map_err(|e| e.to_string())?;
+            match view.len() {
+                1 => value = Some(Value::Scalar(view[0])),
+                3 => {
+                    value = Some(Value::Wdl(Wdl::from_probabilities([
+                        view[0], view[1], view[2],
+                    ])))
+                }
+                len if len == policy_len => {
+                    policy = Some(self.encoding.layout.to_channels_first(
+                        view,
+                        self.width,
+                        self.height,
+                    ))
+                }
+                len => return Err(format!("Unexpected output of size {}", len)),
+            }
+        }
+        match (policy, value) {
+            (Some(policy), Some(value)) => Ok((policy, value)),
+            _ => Err(String::from("Model needs a policy and a value output")),
+        }
+    }
+}
+
+// Evaluates states with the network, the policy over the legal moves becomes
+// the priors. This is the search of builds without TensorFlow, the pipeline
+// plays an exported model through Network::Onnx and its own evaluator.
+#[derive(Clone)]
+pub struct OnnxEvaluator {
+    model: Arc<OnnxModel>,
+}
+
+impl OnnxEvaluator {
+    pub fn new(model: Arc<OnnxModel>) -> Self {
+        Self { model }
+    }
+}
+
+impl Evaluator<OnnxMCTS> for OnnxEvaluator {
+    type StateEvaluation = StateEval;
+
+    fn evaluate_new_state(
+        &self,
+        state: &BoardState,
+        moves: &MoveList,
+        _: Option<SearchHandle<OnnxMCTS>>,
+    ) -> (Vec<MoveEvaluation<OnnxMCTS>>, Self::StateEvaluation) {
+        if let Some(evaluation) = StateEval::terminal(state) {
+            return (vec![0.0; moves.len()], evaluation);
+        }
+
+        let (policy, value) = self
+            .model
+            .evaluate(&InputPlanes::encode(state, self.model.encoding))
+            .expect("Could not evaluate state");
+        let (width, height) = (self.model.width, self.model.height);
+        let priors = masked_priors(&policy, moves.iter().map(|mov| mov.to_index(width, height)));
+        (priors, StateEval::network(state.current_player(), value))
+    }
+
+    fn evaluate_existing_state(
+        &self,
+        _: &BoardState,
+        existing_evaln: &Self::StateEvaluation,
+        _: SearchHandle<OnnxMCTS>,
+    ) -> Self::StateEvaluation {
+        existing_evaln.clone()
+    }
+
+    fn interpret_evaluation_for_player(
+        &self,
+        evaluation: &Self::StateEvaluation,
+        player: &Player,
+    ) -> f64 {
+        evaluation.value_for(player)
+    }
+}
+
+// The network search without catzero, on the board size of the model.
+#[derive(Clone)]
+pub struct OnnxMCTS;
+
+impl OnnxMCTS {
+    pub fn create_search(
+        state: BoardState,
+        model: Arc<OnnxModel>,
+        exploration: f64,
+    ) -> Result<MCTSManager<Self>, String> {
+        model.check(&state)?;
+        Ok(MCTSManager::new(
+            state,
+            OnnxMCTS,
+            OnnxEvaluator::new(model),
+            UCTPolicy::new(exploration),
+            ApproxTable::new(1024),
+        ))
+    }
+}
+
+impl MCTS for OnnxMCTS {
+    type State = BoardState;
+    type Eval = OnnxEvaluator;
+    type TreePolicy = UCTPolicy<f64>;
+    type NodeData = ();
+    type TranspositionTable = ApproxTable<Self>;
+    type ExtraThreadData = ();
+
+    fn cycle_behaviour(&self) -> CycleBehaviour<Self> {
+        CycleBehaviour::UseCurrentEvalWhenCycleDetected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcts::GameState;
+    use prost::Message;
+    use tract_onnx::pb::{
+        type_proto, GraphProto, ModelProto, NodeProto, OperatorSetIdProto, TensorProto, TypeProto,
+        ValueInfoProto,
+    };
+
+    use crate::{
+        action::{BoardAction, Column},
+        planes::{InputEncoding, InputPlanes, PointsEncoding},
+        player::Player,
+        rules::GameRules,
+        testing::temp_path,
+        BoardState,
+    };
+
+    use super::OnnxModel;
+
+    // A stand-in network: the policy is the first three input planes and
+    // the value is the mean of the input.
+    fn write_model(path: &std::path::Path) {
+        let int64 = |name: &str, value: i64| TensorProto {
+            name: name.to_string(),
+            dims: vec![1],
+            data_type: 7,
+            int64_data: vec![value],
+            ..Default::default()
+        };
+        let node = |op: &str, inputs: &[&str], output: &str| NodeProto {
+            op_type: op.to_string(),
+            input: inputs.iter().map(|s| s.to_string()).collect(),
+            output: vec![output.to_string()],
+            ..Default::default()
+        };
+        // Float tensors, the shape is set when the model is loaded.
+        let value_info = |name: &str| ValueInfoProto {
+            name: name.to_string(),
+            r#type: Some(TypeProto {
+                value: Some(type_proto::Value::TensorType(type_proto::Tensor {
+                    elem_type: 1,
+                    shape: None,
+                })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let model = ModelProto {
+            ir_version: 7,
+            opset_import: vec![OperatorSetIdProto {
+                domain: String::new(),
+                version: 13,
+            }],
+            graph: Some(GraphProto {
+                node: vec![
+                    node("ReduceMean", &["input"], "value"),
+                    node("Slice", &["input", "starts", "ends", "axes"], "policy"),
+                ],
+                initializer: vec![int64("starts", 0), int64("ends", 3), int64("axes", 1)],
+                input: vec![value_info("input")],
+                output: vec![value_info("value"), value_info("policy")],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        std::fs::write(path, model.encode_to_vec()).unwrap();
+    }
+
+    #[test]
+    fn evaluate_exported_model() {
+        let path = temp_path("onnx_test.onnx");
+        write_model(&path);
+        let model = OnnxModel::load(&path, 5, 4, false, InputEncoding::default());
+        std::fs::remove_file(&path).unwrap();
+        let model = model.expect("Could not load model");
+
+        let mut state = BoardState::new(GameRules::parse("size=5x4").unwrap());
+        let mov = BoardAction::DropStone(Player::Player1, Column::new(2).unwrap());
+        state.make_move(&mov);
+
+        let (policy, value) = model.evaluate(&InputPlanes::from(&state)).unwrap();
+        assert_eq!(policy.len(), 3 * 5 * 4);
+        // The stone is in the opponent plane of the player to move.
+        assert_eq!(policy[5 * 4 + 2 * 4], 1.0);
+        assert!((value.expected() - 1.0 / 80.0).abs() < 1e-6);
+
+        assert!(model
+            .evaluate(&InputPlanes::from(&BoardState::default()))
+            .is_err());
+        assert!(model
+            .evaluate(&InputPlanes::encode(
+                &state,
+                InputEncoding {
+                    points: PointsEncoding::Clamped(4),
+                    ..Default::default()
+                }
+            ))
+            .is_err());
+    }
+}
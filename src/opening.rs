@@ -0,0 +1,79 @@
+use std::fmt::Display;
+
+use crate::action::BoardAction;
+
+// Number of plies used to classify openings in the statistics.
+pub const OPENING_PLIES: usize = 4;
+
+// Name of an opening: the first moves of a game in `BoardAction` notation.
+// A line and its mirror image share the same key.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OpeningKey(Vec<String>);
+
+impl OpeningKey {
+    pub fn moves(&self) -> &[String] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Display for OpeningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("-");
+        }
+        f.write_str(&self.0.join(" "))
+    }
+}
+
+// `width` is the width of the board the game was played on.
+pub fn classify(moves: &[BoardAction], plies: usize, width: usize) -> OpeningKey {
+    let moves = &moves[..moves.len().min(plies)];
+
+    let original = moves.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+    let mirrored = moves
+        .iter()
+        .map(|m| m.mirrored(width).to_string())
+        .collect::<Vec<_>>();
+
+    OpeningKey(original.min(mirrored))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{BoardAction, Coordinate},
+        player::Player,
+        testing::col,
+    };
+
+    use crate::board::WIDTH;
+
+    use super::classify;
+
+    #[test]
+    fn mirrored_openings_share_key() {
+        let left = [
+            BoardAction::DropStone(Player::Player1, col(1)),
+            BoardAction::DropStone(Player::Player2, col(2)),
+            BoardAction::SwitchStone(Coordinate::new(1, 0), Coordinate::new(2, 0)),
+        ];
+        let right = [
+            BoardAction::DropStone(Player::Player1, col(6)),
+            BoardAction::DropStone(Player::Player2, col(5)),
+            BoardAction::SwitchStone(Coordinate::new(5, 0), Coordinate::new(6, 0)),
+        ];
+
+        assert_eq!(classify(&left, 3, WIDTH), classify(&right, 3, WIDTH));
+        assert_eq!(classify(&left, 3, WIDTH).to_string(), "d1 d2 s1,0-2,0");
+        assert_eq!(classify(&left, 2, WIDTH).len(), 2);
+        assert_ne!(classify(&left, 1, WIDTH), classify(&left, 2, WIDTH));
+    }
+}
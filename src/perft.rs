@@ -0,0 +1,126 @@
+// Counts of the positions reached after a fixed number of actions, to check
+// the move generation against known numbers after rule changes and
+// optimizations. A game that ends early has no positions below it.
+use mcts::GameState;
+
+use crate::{action::BoardAction, BoardState};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Perft {
+    pub nodes: u64,
+    // How the leaves were reached.
+    pub drops: u64,
+    pub switches: u64,
+    // Leaves where the game is over.
+    pub ended: u64,
+}
+
+impl Perft {
+    fn add(&mut self, other: Perft) {
+        self.nodes += other.nodes;
+        self.drops += other.drops;
+        self.switches += other.switches;
+        self.ended += other.ended;
+    }
+}
+
+impl BoardState {
+    pub fn perft(&self, depth: usize) -> Perft {
+        if depth == 0 {
+            return Perft {
+                nodes: 1,
+                ..Default::default()
+            };
+        }
+        let mut perft = Perft::default();
+        count(&mut self.clone(), depth, &mut perft);
+        perft
+    }
+
+    // The counts below every available action, they add up to perft.
+    pub fn perft_divide(&self, depth: usize) -> Vec<(BoardAction, Perft)> {
+        let mut state = self.clone();
+        state
+            .available_moves()
+            .into_iter()
+            .map(|mov| {
+                state.play(&mov);
+                let perft = state.perft(depth.saturating_sub(1));
+                state.undo();
+                (mov, perft)
+            })
+            .collect()
+    }
+}
+
+fn count(state: &mut BoardState, depth: usize, perft: &mut Perft) {
+    for mov in state.available_moves() {
+        state.play(&mov);
+        if depth == 1 {
+            let ended = state.is_terminal() as u64;
+            let leaf = match mov {
+                BoardAction::DropStone(..) => Perft {
+                    nodes: 1,
+                    drops: 1,
+                    switches: 0,
+                    ended,
+                },
+                BoardAction::SwitchStone(..) => Perft {
+                    nodes: 1,
+                    drops: 0,
+                    switches: 1,
+                    ended,
+                },
+            };
+            perft.add(leaf);
+        } else {
+            count(state, depth - 1, perft);
+        }
+        state.undo();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{player::Player, rules::GameRules, testing::StateBuilder, BoardState};
+
+    use super::Perft;
+
+    #[test]
+    fn count_positions() {
+        let state = BoardState::default();
+        assert_eq!(state.perft(0).nodes, 1);
+        assert_eq!(
+            state.perft(1),
+            Perft {
+                nodes: 8,
+                drops: 8,
+                switches: 0,
+                ended: 0
+            }
+        );
+        assert_eq!(state.perft(3).nodes, 512);
+
+        let divided = state.perft_divide(3);
+        assert_eq!(divided.len(), 8);
+        assert!(divided.iter().all(|(_, perft)| perft.nodes == 64));
+
+        // A point buys the switches of X with its neighbours.
+        let empty = "        ";
+        let state = StateBuilder::new()
+            .board([empty, empty, empty, empty, empty, empty, empty, " XO     "])
+            .points(Player::Player1, 1)
+            .build();
+        let perft = state.perft(1);
+        assert_eq!((perft.drops, perft.switches), (8, 1));
+
+        // Only the drop between the stones makes four in a row.
+        let state = StateBuilder::new()
+            .board([empty, empty, empty, empty, empty, empty, empty, "XX XO   "])
+            .build();
+        assert_eq!(state.perft(1).ended, 1);
+
+        let small = BoardState::new(GameRules::parse("size=5x4").unwrap());
+        assert_eq!(small.perft(2).nodes, 25);
+    }
+}
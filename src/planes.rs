@@ -0,0 +1,866 @@
+use std::{convert::TryFrom, fmt::Display, str::FromStr};
+
+use crate::{
+    action::Coordinate,
+    board::{Cell, MAX_HEIGHT, MAX_WIDTH},
+    player::Player,
+    BoardState,
+};
+use mcts::GameState;
+
+// Planes of the default encoding, see `InputEncoding::planes` for the
+// others.
+pub const PLANES: usize = 4;
+
+// Values indexed [plane][x][y], the layout catzero uses for its tensors.
+pub type Tensor<T> = Vec<Vec<Vec<T>>>;
+
+const CURRENT_PLAYER: usize = 0;
+const OPPONENT: usize = 1;
+// Planes of the stones of one board, the player to move's and the opponent's.
+const STONE_PLANES: usize = 2;
+
+pub type Plane = Vec<Vec<u8>>;
+
+// Number of policy planes with or without diagonal switches.
+pub const fn policy_planes(diagonal_switches: bool) -> usize {
+    if diagonal_switches {
+        5
+    } else {
+        3
+    }
+}
+
+// Below this the legal actions are taken to have no mass left at all.
+const MIN_PRIOR_MASS: f64 = 1e-6;
+
+// The priors of the actions at the indices of a flattened policy,
+// renormalized so they sum to one. Whatever the network puts on illegal
+// actions would otherwise just lower every prior. Negative and non-finite
+// values count as zero, and when the legal actions have next to nothing
+// left they all get the same prior.
+pub fn masked_priors<I: IntoIterator<Item = usize>>(policy: &[f32], indices: I) -> Vec<f64> {
+    let mut priors: Vec<f64> = indices
+        .into_iter()
+        .map(|index| policy[index] as f64)
+        .map(|p| if p.is_finite() { p.max(0.0) } else { 0.0 })
+        .collect();
+    let sum: f64 = priors.iter().sum();
+    if sum < MIN_PRIOR_MASS {
+        let uniform = 1.0 / priors.len() as f64;
+        priors.iter_mut().for_each(|p| *p = uniform);
+    } else {
+        priors.iter_mut().for_each(|p| *p /= sum);
+    }
+    priors
+}
+
+// How the banked points are fed to the network. Points have no upper
+// bound, so a network trained on short games can see counts it never saw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PointsEncoding {
+    // The count in one plane per player, what the first networks use.
+    // Counts above 255 are stored as 255.
+    #[default]
+    Raw,
+    // The count in one plane per player, at most the limit.
+    Clamped(u8),
+    // The clamped count divided by the limit, so the network sees values
+    // in [0, 1]. The u8 planes hold the clamped count, the division happens
+    // in the conversion to f32.
+    Scaled(u8),
+    // A plane per point up to the limit, the first `points` planes of a
+    // player are ones.
+    Thermometer(u8),
+}
+
+impl PointsEncoding {
+    // Planes per player.
+    pub fn planes(&self) -> usize {
+        match *self {
+            PointsEncoding::Thermometer(limit) => limit as usize,
+            _ => 1,
+        }
+    }
+
+    // The value of every plane of a player with this many points.
+    fn encode(&self, points: usize) -> Vec<u8> {
+        match *self {
+            PointsEncoding::Raw => vec![points.min(u8::MAX as usize) as u8],
+            PointsEncoding::Clamped(limit) | PointsEncoding::Scaled(limit) => {
+                vec![points.min(limit as usize) as u8]
+            }
+            PointsEncoding::Thermometer(limit) => {
+                (0..limit as usize).map(|i| (i < points) as u8).collect()
+            }
+        }
+    }
+
+    // What a value of a points plane is worth to the network.
+    fn scale(&self) -> f32 {
+        match *self {
+            PointsEncoding::Scaled(limit) => 1.0 / limit as f32,
+            _ => 1.0,
+        }
+    }
+}
+
+// "raw", "clamp:<limit>", "scale:<limit>" or "thermometer:<limit>", the
+// limit is at least 1.
+impl FromStr for PointsEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let limit = |limit: &str| match limit.parse::<u8>() {
+            Ok(limit) if limit > 0 => Ok(limit),
+            _ => Err(format!("Bad points limit: {}", limit)),
+        };
+        match s.split_once(':') {
+            None if s == "raw" => Ok(PointsEncoding::Raw),
+            Some(("clamp", l)) => limit(l).map(PointsEncoding::Clamped),
+            Some(("scale", l)) => limit(l).map(PointsEncoding::Scaled),
+            Some(("thermometer", l)) => limit(l).map(PointsEncoding::Thermometer),
+            _ => Err(format!("Unknown points encoding: {}", s)),
+        }
+    }
+}
+
+impl Display for PointsEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PointsEncoding::Raw => write!(f, "raw"),
+            PointsEncoding::Clamped(limit) => write!(f, "clamp:{}", limit),
+            PointsEncoding::Scaled(limit) => write!(f, "scale:{}", limit),
+            PointsEncoding::Thermometer(limit) => write!(f, "thermometer:{}", limit),
+        }
+    }
+}
+
+// Order of the values of a flattened f32 input or policy, the u8 planes
+// are always indexed [plane][x][y].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    // [planes, width, height], what the catzero models use.
+    #[default]
+    ChannelsFirst,
+    // [width, height, planes], what Keras and the models exported from it
+    // use.
+    ChannelsLast,
+}
+
+impl Layout {
+    // The dimensions of a tensor of the layout.
+    pub fn dims(&self, planes: usize, width: usize, height: usize) -> [usize; 3] {
+        match self {
+            Layout::ChannelsFirst => [planes, width, height],
+            Layout::ChannelsLast => [width, height, planes],
+        }
+    }
+
+    // The planes, width and height of a tensor of the layout.
+    pub fn shape(&self, dims: [usize; 3]) -> [usize; 3] {
+        match (self, dims) {
+            (Layout::ChannelsFirst, dims) => dims,
+            (Layout::ChannelsLast, [width, height, planes]) => [planes, width, height],
+        }
+    }
+
+    // Where the value of a plane at a cell is in a flattened tensor.
+    pub fn index(
+        &self,
+        [planes, width, height]: [usize; 3],
+        plane: usize,
+        x: usize,
+        y: usize,
+    ) -> usize {
+        match self {
+            Layout::ChannelsFirst => (plane * width + x) * height + y,
+            Layout::ChannelsLast => (x * height + y) * planes + plane,
+        }
+    }
+
+    // Flattened values of the layout in the channels first order, for
+    // policies of any number of planes.
+    pub fn to_channels_first(&self, values: &[f32], width: usize, height: usize) -> Vec<f32> {
+        if *self == Layout::ChannelsFirst {
+            return values.to_vec();
+        }
+        let shape = [values.len() / (width * height), width, height];
+        let mut ordered = vec![0.0; values.len()];
+        for (i, value) in ordered.iter_mut().enumerate() {
+            let (plane, x, y) = (i / (width * height), (i / height) % width, i % height);
+            *value = values[self.index(shape, plane, x, y)];
+        }
+        ordered
+    }
+}
+
+// "nchw" or "nhwc", after the names of the frameworks for the batched
+// tensors.
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nchw" => Ok(Layout::ChannelsFirst),
+            "nhwc" => Ok(Layout::ChannelsLast),
+            _ => Err(format!("Unknown layout: {}", s)),
+        }
+    }
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Layout::ChannelsFirst => write!(f, "nchw"),
+            Layout::ChannelsLast => write!(f, "nhwc"),
+        }
+    }
+}
+
+// What the network is shown of a position besides the current board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InputEncoding {
+    pub points: PointsEncoding,
+    // Boards before the last actions, newest first. Boards from before the
+    // first action, or that a state built without its actions does not
+    // know, are empty.
+    pub history: usize,
+    // A plane of ones when player 1 is to move, after the points.
+    pub side_to_move: bool,
+    // A plane of the plies played, at most 255, which the network sees
+    // divided by the cells of the board. It comes last.
+    pub move_count: bool,
+    // How the f32 input is laid out, the network's policy is expected in
+    // the same layout.
+    pub layout: Layout,
+}
+
+impl InputEncoding {
+    // Planes of the whole input with this encoding.
+    pub fn planes(&self) -> usize {
+        self.side_to_move_plane() + self.side_to_move as usize + self.move_count as usize
+    }
+
+    // The dimensions of the f32 input of a batch of one on a board of the
+    // size, in the layout of the encoding.
+    pub fn dims(&self, width: usize, height: usize) -> [usize; 4] {
+        let [a, b, c] = self.layout.dims(self.planes(), width, height);
+        [1, a, b, c]
+    }
+
+    // Index of the first points plane, after the stones of every board.
+    fn points_plane(&self) -> usize {
+        STONE_PLANES * (1 + self.history)
+    }
+
+    fn side_to_move_plane(&self) -> usize {
+        self.points_plane() + 2 * self.points.planes()
+    }
+
+    // What a value of the plane is worth to the network, on a board of
+    // this many cells.
+    fn scale(&self, plane: usize, cells: usize) -> f32 {
+        if (self.points_plane()..self.side_to_move_plane()).contains(&plane) {
+            self.points.scale()
+        } else if self.move_count && plane == self.planes() - 1 {
+            1.0 / cells as f32
+        } else {
+            1.0
+        }
+    }
+}
+
+// The network input for one position, planes are indexed [plane][x][y]:
+// the stones of the player to move and the opponent's stones on the board
+// and then on each board of the history, and the banked points of player 1
+// and then of player 2, in as many planes as the points encoding uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputPlanes {
+    planes: Vec<Plane>,
+    encoding: InputEncoding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShapeError {
+    pub expected: Vec<usize>,
+    pub found: Vec<usize>,
+}
+
+impl Display for ShapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Expected tensor of shape {:?}, found {:?}",
+            self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for ShapeError {}
+
+impl InputPlanes {
+    pub fn width(&self) -> usize {
+        self.planes[0].len()
+    }
+
+    pub fn height(&self) -> usize {
+        self.planes[0][0].len()
+    }
+
+    pub fn shape(&self) -> [usize; 3] {
+        [self.planes.len(), self.width(), self.height()]
+    }
+
+    pub fn encoding(&self) -> InputEncoding {
+        self.encoding
+    }
+
+    pub fn current_player_plane(&self) -> &Plane {
+        &self.planes[CURRENT_PLAYER]
+    }
+
+    pub fn opponent_plane(&self) -> &Plane {
+        &self.planes[OPPONENT]
+    }
+
+    // The stones of the player to move and of the opponent the given number
+    // of actions ago, 1 for the board before the last action.
+    pub fn history_planes(&self, ago: usize) -> (&Plane, &Plane) {
+        assert!(
+            (1..=self.encoding.history).contains(&ago),
+            "No history planes for {} actions ago",
+            ago
+        );
+        let first = STONE_PLANES * ago;
+        (
+            &self.planes[first + CURRENT_PLAYER],
+            &self.planes[first + OPPONENT],
+        )
+    }
+
+    // The first plane of the points of each player, the only one unless
+    // the points are a thermometer.
+    pub fn player_1_points_plane(&self) -> &Plane {
+        &self.planes[self.encoding.points_plane()]
+    }
+
+    pub fn player_2_points_plane(&self) -> &Plane {
+        &self.planes[self.encoding.points_plane() + self.encoding.points.planes()]
+    }
+
+    pub fn side_to_move_plane(&self) -> Option<&Plane> {
+        let encoding = &self.encoding;
+        encoding
+            .side_to_move
+            .then(|| &self.planes[encoding.side_to_move_plane()])
+    }
+
+    pub fn move_count_plane(&self) -> Option<&Plane> {
+        let encoding = &self.encoding;
+        encoding
+            .move_count
+            .then(|| &self.planes[encoding.planes() - 1])
+    }
+
+    pub fn planes(&self) -> &[Plane] {
+        &self.planes
+    }
+
+    // The dimensions of the f32 input of a batch of one, in the layout of
+    // the encoding.
+    pub fn dims(&self) -> [usize; 4] {
+        self.encoding.dims(self.width(), self.height())
+    }
+
+    // The flattened values the network sees in the layout of the encoding,
+    // with the points scaled by their encoding.
+    pub fn values(&self) -> Vec<f32> {
+        let shape = self.shape();
+        let cells = self.width() * self.height();
+        let mut values = vec![0.0; self.planes.len() * cells];
+        for (i, plane) in self.planes.iter().enumerate() {
+            let scale = self.encoding.scale(i, cells);
+            for (x, column) in plane.iter().enumerate() {
+                for (y, value) in column.iter().enumerate() {
+                    values[self.encoding.layout.index(shape, i, x, y)] = *value as f32 * scale;
+                }
+            }
+        }
+        values
+    }
+
+    #[cfg(feature = "alphazero")]
+    pub fn to_tensorflow(&self) -> tensorflow::Tensor<f32> {
+        tensorflow::Tensor::new(&self.dims().map(|d| d as u64))
+            .with_values(&self.values())
+            .expect("Could not use tensor")
+    }
+
+    // The planes of a tensor that was encoded with the encoding. Every
+    // plane needs the same board size, at most the largest board.
+    pub fn from_tensor(tensor: Tensor<u8>, encoding: InputEncoding) -> Result<Self, ShapeError> {
+        let planes = encoding.planes();
+        let width = tensor.first().map_or(0, |p| p.len());
+        let height = tensor
+            .first()
+            .and_then(|p| p.first())
+            .map_or(0, |c| c.len());
+        let valid = tensor.len() == planes
+            && (1..=MAX_WIDTH).contains(&width)
+            && (1..=MAX_HEIGHT).contains(&height)
+            && tensor
+                .iter()
+                .all(|plane| plane.len() == width && plane.iter().all(|col| col.len() == height));
+
+        if !valid {
+            return Err(ShapeError {
+                expected: vec![planes, width, height],
+                found: vec![tensor.len(), width, height],
+            });
+        }
+
+        Ok(Self {
+            planes: tensor,
+            encoding,
+        })
+    }
+
+    // Accepts unbatched and batched tensors of one position in the layout
+    // of the encoding, scaled points and move counts are scaled back to
+    // their count.
+    #[cfg(feature = "alphazero")]
+    pub fn from_tensorflow(
+        tensor: &tensorflow::Tensor<f32>,
+        encoding: InputEncoding,
+    ) -> Result<Self, ShapeError> {
+        let planes = encoding.planes();
+        let dims = tensor
+            .dims()
+            .iter()
+            .map(|&d| d as usize)
+            .collect::<Vec<_>>();
+        let layout = encoding.layout;
+        let shape = match dims[..] {
+            [1, a, b, c] | [a, b, c] => Some(layout.shape([a, b, c])),
+            _ => None,
+        };
+        let (width, height) = match shape {
+            Some([p, w, h])
+                if p == planes && (1..=MAX_WIDTH).contains(&w) && (1..=MAX_HEIGHT).contains(&h) =>
+            {
+                (w, h)
+            }
+            _ => {
+                let [_, width, height] = shape.unwrap_or_default();
+                return Err(ShapeError {
+                    expected: layout.dims(planes, width, height).to_vec(),
+                    found: dims,
+                });
+            }
+        };
+
+        let shape = [planes, width, height];
+        let cells = width * height;
+        let mut decoded = vec![vec![vec![0u8; height]; width]; planes];
+        for (plane, columns) in decoded.iter_mut().enumerate() {
+            let scale = encoding.scale(plane, cells);
+            for (x, column) in columns.iter_mut().enumerate() {
+                for (y, value) in column.iter_mut().enumerate() {
+                    *value = (tensor[layout.index(shape, plane, x, y)] / scale).round() as u8;
+                }
+            }
+        }
+
+        Ok(Self {
+            planes: decoded,
+            encoding,
+        })
+    }
+
+    pub fn encode(state: &BoardState, encoding: InputEncoding) -> Self {
+        let (width, height) = (state.board.width(), state.board.height());
+        let mut planes = vec![vec![vec![0u8; height]; width]; encoding.planes()];
+        visit(state, encoding, |plane, x, y, value| {
+            planes[plane][x][y] = value
+        });
+        Self { planes, encoding }
+    }
+}
+
+// Calls `set` with the plane, x, y and value of every cell of the input that
+// is not zero.
+fn visit<F: FnMut(usize, usize, usize, u8)>(
+    state: &BoardState,
+    encoding: InputEncoding,
+    mut set: F,
+) {
+    let player = state.current_player();
+    let (width, height) = (state.board.width(), state.board.height());
+    let boards = std::iter::once(&state.board).chain(state.past_boards());
+    for (i, board) in boards.take(1 + encoding.history).enumerate() {
+        for (x, y) in (0..width).flat_map(|x| (0..height).map(move |y| (x, y))) {
+            match board.get(Coordinate::new(x as isize, y as isize)) {
+                Cell::Filled(p) if p == player => set(STONE_PLANES * i + CURRENT_PLAYER, x, y, 1),
+                Cell::Filled(_) => set(STONE_PLANES * i + OPPONENT, x, y, 1),
+                Cell::Empty => {}
+            }
+        }
+    }
+
+    // The remaining planes hold one value on every cell.
+    let mut fill = |plane: usize, value: u8| {
+        if value != 0 {
+            for (x, y) in (0..width).flat_map(|x| (0..height).map(move |y| (x, y))) {
+                set(plane, x, y, value);
+            }
+        }
+    };
+    let mut plane = encoding.points_plane();
+    for count in [state.player_1_points, state.player_2_points] {
+        for value in encoding.points.encode(count) {
+            fill(plane, value);
+            plane += 1;
+        }
+    }
+
+    if encoding.side_to_move {
+        fill(plane, (player == Player::Player1) as u8);
+        plane += 1;
+    }
+    if encoding.move_count {
+        fill(plane, state.ply().min(u8::MAX as usize) as u8);
+    }
+}
+
+impl From<&BoardState> for InputPlanes {
+    fn from(state: &BoardState) -> Self {
+        Self::encode(state, InputEncoding::default())
+    }
+}
+
+impl BoardState {
+    // Writes the values of `InputPlanes::values` to the buffer without
+    // building the planes, so one buffer can be reused for every position.
+    // What the buffer held is replaced.
+    pub fn encode_into(&self, encoding: InputEncoding, buf: &mut Vec<f32>) {
+        let (width, height) = (self.board.width(), self.board.height());
+        let shape = [encoding.planes(), width, height];
+        buf.clear();
+        buf.resize(shape.iter().product(), 0.0);
+        visit(self, encoding, |plane, x, y, value| {
+            let scale = encoding.scale(plane, width * height);
+            buf[encoding.layout.index(shape, plane, x, y)] = value as f32 * scale;
+        });
+    }
+
+    // A one for every legal action in the policy planes, indexed like
+    // `BoardAction::to_index`.
+    pub fn legal_action_mask(&self) -> Tensor<u8> {
+        let (width, height) = (self.board.width(), self.board.height());
+        let mut mask =
+            vec![vec![vec![0u8; height]; width]; policy_planes(self.rules.diagonal_switches)];
+        for mov in self.moves() {
+            let index = mov.to_index(width, height);
+            mask[index / (width * height)][(index / height) % width][index % height] = 1;
+        }
+        mask
+    }
+}
+
+impl From<InputPlanes> for Tensor<u8> {
+    fn from(input: InputPlanes) -> Self {
+        input.planes
+    }
+}
+
+#[cfg(feature = "alphazero")]
+impl From<InputPlanes> for tensorflow::Tensor<f32> {
+    fn from(input: InputPlanes) -> Self {
+        input.to_tensorflow()
+    }
+}
+
+// Tensors of the default encoding.
+impl TryFrom<Tensor<u8>> for InputPlanes {
+    type Error = ShapeError;
+
+    fn try_from(tensor: Tensor<u8>) -> Result<Self, Self::Error> {
+        Self::from_tensor(tensor, InputEncoding::default())
+    }
+}
+
+#[cfg(feature = "alphazero")]
+impl TryFrom<&tensorflow::Tensor<f32>> for InputPlanes {
+    type Error = ShapeError;
+
+    fn try_from(tensor: &tensorflow::Tensor<f32>) -> Result<Self, Self::Error> {
+        Self::from_tensorflow(tensor, InputEncoding::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use crate::{
+        action::{BoardAction, Column},
+        player::Player,
+        rules::GameRules,
+        testing::StateBuilder,
+        BoardState,
+    };
+    use mcts::GameState;
+
+    use super::{InputEncoding, InputPlanes, Layout, PointsEncoding, Tensor};
+
+    #[test]
+    fn round_trip() {
+        let mut state = BoardState::default();
+        state.make_move(&BoardAction::DropStone(
+            Player::Player1,
+            Column::new(2).unwrap(),
+        ));
+
+        let planes = InputPlanes::from(&state);
+        assert_eq!(planes.opponent_plane()[2][0], 1);
+        assert_eq!(planes.current_player_plane()[2][0], 0);
+
+        let tensor: Tensor<u8> = planes.clone().into();
+        assert_eq!(InputPlanes::try_from(tensor), Ok(planes.clone()));
+        #[cfg(feature = "alphazero")]
+        assert_eq!(
+            InputPlanes::try_from(&planes.to_tensorflow()),
+            Ok(planes.clone())
+        );
+        assert!(InputPlanes::try_from(vec![vec![vec![0u8; 8]; 8]; 3]).is_err());
+
+        let small = BoardState::new(GameRules {
+            width: 6,
+            height: 5,
+            ..Default::default()
+        });
+        let planes = InputPlanes::from(&small);
+        assert_eq!(planes.shape(), [4, 6, 5]);
+        #[cfg(feature = "alphazero")]
+        assert_eq!(planes.to_tensorflow().dims(), &[1, 4, 6, 5]);
+    }
+
+    #[test]
+    fn points_encodings() {
+        let points = |points| InputEncoding {
+            points,
+            ..Default::default()
+        };
+        let state = StateBuilder::new()
+            .board(["  ", "XO"])
+            .points(Player::Player1, 300)
+            .points(Player::Player2, 2)
+            .build();
+
+        let raw = InputPlanes::from(&state);
+        assert_eq!(raw.player_1_points_plane()[0][0], 255);
+        assert_eq!(raw.player_2_points_plane()[0][0], 2);
+
+        let clamped = InputPlanes::encode(&state, points(PointsEncoding::Clamped(4)));
+        assert_eq!(clamped.player_1_points_plane()[0][0], 4);
+        assert_eq!(clamped.player_2_points_plane()[0][0], 2);
+
+        let scaled = InputPlanes::encode(&state, points(PointsEncoding::Scaled(4)));
+        assert_eq!(scaled.shape(), [4, 2, 2]);
+        assert_eq!(
+            &scaled.values()[8..],
+            &[1.0, 1.0, 1.0, 1.0, 0.5, 0.5, 0.5, 0.5]
+        );
+        #[cfg(feature = "alphazero")]
+        assert_eq!(
+            InputPlanes::from_tensorflow(&scaled.to_tensorflow(), scaled.encoding()),
+            Ok(scaled.clone())
+        );
+
+        let thermometer = InputPlanes::encode(&state, points(PointsEncoding::Thermometer(3)));
+        assert_eq!(thermometer.shape(), [8, 2, 2]);
+        let firsts = thermometer.planes()[2..]
+            .iter()
+            .map(|plane| plane[0][0])
+            .collect::<Vec<_>>();
+        assert_eq!(firsts, vec![1, 1, 1, 1, 1, 0]);
+        assert_eq!(thermometer.player_2_points_plane()[0][0], 1);
+
+        let tensor: Tensor<u8> = thermometer.clone().into();
+        assert!(InputPlanes::try_from(tensor.clone()).is_err());
+        assert_eq!(
+            InputPlanes::from_tensor(tensor, thermometer.encoding()),
+            Ok(thermometer)
+        );
+
+        assert_eq!(
+            "clamp:8".parse::<PointsEncoding>(),
+            Ok(PointsEncoding::Clamped(8))
+        );
+        for encoding in ["raw", "scale:5", "thermometer:3"] {
+            assert_eq!(
+                encoding.parse::<PointsEncoding>().unwrap().to_string(),
+                encoding
+            );
+        }
+        assert!("clamp:0".parse::<PointsEncoding>().is_err());
+        assert!("log".parse::<PointsEncoding>().is_err());
+    }
+
+    #[test]
+    fn history_planes() {
+        let mut state = BoardState::new(GameRules::parse("size=4x3").unwrap());
+        let drop = |player, col| BoardAction::DropStone(player, Column::new(col).unwrap());
+        state.make_move(&drop(Player::Player1, 0));
+        state.make_move(&drop(Player::Player2, 3));
+
+        let encoding = InputEncoding {
+            history: 3,
+            ..Default::default()
+        };
+        let planes = InputPlanes::encode(&state, encoding);
+        assert_eq!(planes.shape(), [10, 4, 3]);
+        assert_eq!(encoding.planes(), 10);
+
+        // Seen from player 1, who is to move again.
+        let (own, opponent) = planes.history_planes(1);
+        assert_eq!((own[0][0], opponent[3][0]), (1, 0));
+        let (own, opponent) = planes.history_planes(2);
+        assert_eq!((own[0][0], opponent[3][0]), (0, 0));
+        assert_eq!(planes.history_planes(3), planes.history_planes(2));
+        assert_eq!(planes.player_1_points_plane(), &planes.planes()[8]);
+
+        let tensor: Tensor<u8> = planes.clone().into();
+        assert_eq!(InputPlanes::from_tensor(tensor, encoding), Ok(planes));
+
+        let mirrored = InputPlanes::encode(&state.mirror(), encoding);
+        assert_eq!(mirrored.history_planes(1).0[3][0], 1);
+    }
+
+    #[test]
+    fn side_to_move_and_move_count() {
+        let mut state = BoardState::new(GameRules::parse("size=4x3").unwrap());
+        let encoding = InputEncoding {
+            points: PointsEncoding::Scaled(2),
+            side_to_move: true,
+            move_count: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            InputPlanes::encode(&state, encoding).move_count_plane(),
+            Some(&vec![vec![0; 3]; 4])
+        );
+        assert_eq!(InputPlanes::from(&state).side_to_move_plane(), None);
+
+        state.make_move(&BoardAction::DropStone(
+            Player::Player1,
+            Column::new(0).unwrap(),
+        ));
+        let planes = InputPlanes::encode(&state, encoding);
+        assert_eq!(planes.shape(), [6, 4, 3]);
+        assert_eq!(planes.side_to_move_plane(), Some(&planes.planes()[4]));
+        assert_eq!(planes.side_to_move_plane().unwrap()[1][1], 0);
+        assert_eq!(planes.move_count_plane().unwrap()[1][1], 1);
+        assert_eq!(planes.values()[5 * 12], 1.0 / 12.0);
+        #[cfg(feature = "alphazero")]
+        assert_eq!(
+            InputPlanes::from_tensorflow(&planes.to_tensorflow(), encoding),
+            Ok(planes.clone())
+        );
+    }
+
+    #[test]
+    fn channels_last() {
+        let mut state = BoardState::new(GameRules::parse("size=3x2").unwrap());
+        state.make_move(&BoardAction::DropStone(
+            Player::Player1,
+            Column::new(1).unwrap(),
+        ));
+        let encoding = InputEncoding {
+            layout: Layout::ChannelsLast,
+            ..Default::default()
+        };
+
+        let first = InputPlanes::from(&state);
+        let last = InputPlanes::encode(&state, encoding);
+        assert_eq!(first.dims(), [1, 4, 3, 2]);
+        assert_eq!(last.dims(), [1, 3, 2, 4]);
+        // The stone of the opponent of the player to move at x = 1, y = 0.
+        assert_eq!(first.values()[6 + 2], 1.0);
+        assert_eq!(last.values()[2 * 4 + 1], 1.0);
+        assert_eq!(
+            Layout::ChannelsLast.to_channels_first(&last.values(), 3, 2),
+            first.values()
+        );
+        #[cfg(feature = "alphazero")]
+        assert_eq!(
+            InputPlanes::from_tensorflow(&last.to_tensorflow(), encoding),
+            Ok(last.clone())
+        );
+
+        assert_eq!("nhwc".parse::<Layout>(), Ok(Layout::ChannelsLast));
+        assert_eq!(Layout::ChannelsFirst.to_string(), "nchw");
+    }
+
+    #[test]
+    fn encode_into() {
+        let mut state = StateBuilder::new()
+            .board(["   ", "XO "])
+            .points(Player::Player1, 3)
+            .build();
+        let encoding = InputEncoding {
+            points: PointsEncoding::Thermometer(4),
+            history: 1,
+            side_to_move: true,
+            move_count: true,
+            layout: Layout::ChannelsLast,
+        };
+
+        let mut buf = vec![7.0; 3];
+        state.encode_into(encoding, &mut buf);
+        assert_eq!(buf, InputPlanes::encode(&state, encoding).values());
+
+        // The buffer is reused for the next position.
+        state.make_move(&BoardAction::DropStone(
+            Player::Player1,
+            Column::new(2).unwrap(),
+        ));
+        state.encode_into(encoding, &mut buf);
+        assert_eq!(buf, InputPlanes::encode(&state, encoding).values());
+
+        let tensor: Tensor<u8> = InputPlanes::from(&state).into();
+        assert_eq!(Tensor::<u8>::from(&state), tensor);
+    }
+
+    #[test]
+    fn masked_priors() {
+        let policy = [0.1, 0.4, 0.2, 0.3];
+        let priors = super::masked_priors(&policy, [1, 2]);
+        assert!((priors[0] - 2.0 / 3.0).abs() < 1e-6);
+        assert!((priors[1] - 1.0 / 3.0).abs() < 1e-6);
+
+        // Nothing left on the legal actions.
+        let policy = [1.0, 0.0, -0.5, f32::NAN];
+        assert_eq!(super::masked_priors(&policy, [1, 2, 3]), vec![1.0 / 3.0; 3]);
+        assert!(super::masked_priors(&policy, Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn legal_action_mask() {
+        let state = StateBuilder::new()
+            .board(["  ", "  ", "XO"])
+            .points(Player::Player1, 1)
+            .build();
+
+        let mask = state.legal_action_mask();
+        assert_eq!((mask.len(), mask[0].len(), mask[0][0].len()), (3, 2, 3));
+        assert_eq!(mask[0], vec![vec![1, 0, 0], vec![1, 0, 0]]);
+        assert_eq!(mask[1], vec![vec![0; 3]; 2]);
+        assert_eq!(mask[2], vec![vec![1, 0, 0], vec![0, 0, 0]]);
+        assert_eq!(
+            mask.iter().flatten().flatten().filter(|&&m| m == 1).count(),
+            state.available_moves().len()
+        );
+    }
+}
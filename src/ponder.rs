@@ -0,0 +1,152 @@
+// Searching on the opponent's time. After its move an engine guesses the
+// reply its search expects and keeps searching the position after that reply
+// in the background. When the opponent plays the guess, a ponder hit, the
+// engine carries on with the tree it grew, otherwise the tree is thrown away
+// and the search starts over from the position on the board.
+//
+// The opponent may take as long as they like, so the background search stops
+// after a number of playouts. Every playout adds at most one node, which
+// also bounds the memory the tree takes.
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use mcts::{GameState, MCTSManager, ThreadData, MCTS};
+use tracing::debug;
+
+use crate::{action::BoardAction, BoardState};
+
+// Playouts a ponder search runs at most, unless the engine says otherwise.
+pub const PLAYOUTS: usize = 100_000;
+
+// Playouts between checks whether the opponent has moved.
+const CHUNK: usize = 64;
+
+pub struct Ponder<Spec: MCTS> {
+    expected: BoardState,
+    stop: Arc<AtomicBool>,
+    search: JoinHandle<MCTSManager<Spec>>,
+}
+
+impl<Spec> Ponder<Spec>
+where
+    Spec: MCTS<State = BoardState> + 'static,
+    MCTSManager<Spec>: Send,
+    ThreadData<Spec>: Default,
+{
+    // `manager` searched `state` and chose `mov`, `create` sets up a search
+    // of a position the way the engine does. The search stops after
+    // `playouts`. None when the search has no reply in mind or the game ends
+    // before it.
+    pub fn start<F>(
+        manager: &MCTSManager<Spec>,
+        state: &BoardState,
+        mov: BoardAction,
+        threads: usize,
+        playouts: usize,
+        create: F,
+    ) -> Option<Self>
+    where
+        F: FnOnce(BoardState) -> MCTSManager<Spec>,
+    {
+        let line = manager.principal_variation(2);
+        let reply = match line.as_slice() {
+            [first, reply] if *first == mov => *reply,
+            _ => return None,
+        };
+
+        let mut expected = state.clone();
+        for action in [mov, reply] {
+            if expected.is_terminal() {
+                return None;
+            }
+            expected.make_move(&action);
+        }
+        if expected.is_terminal() {
+            return None;
+        }
+
+        debug!(%reply, playouts, "Pondering");
+        let mut manager = create(expected.clone());
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let search = thread::spawn(move || {
+            let mut done = 0;
+            while done < playouts && !stopped.load(Ordering::SeqCst) {
+                let chunk = CHUNK.min(playouts - done);
+                if threads > 1 {
+                    manager.playout_n_parallel(chunk as u32, threads);
+                } else {
+                    manager.playout_n(chunk);
+                }
+                done += chunk;
+            }
+            manager
+        });
+        Some(Self {
+            expected,
+            stop,
+            search,
+        })
+    }
+
+    // The position the engine is searching, after the reply it expects.
+    pub fn expected(&self) -> &BoardState {
+        &self.expected
+    }
+
+    // Stops the background search. Returns its tree on a ponder hit, when
+    // the engine has to move from the position it pondered on.
+    pub fn finish(self, state: &BoardState) -> Option<MCTSManager<Spec>> {
+        self.stop.store(true, Ordering::SeqCst);
+        let manager = self.search.join().ok()?;
+        let hit = state.zobrist() == self.expected.zobrist()
+            && state.board() == self.expected.board()
+            && state.rules() == self.expected.rules();
+        debug!(hit, "Stopped pondering");
+        hit.then_some(manager)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mcts::GameState;
+
+    use super::Ponder;
+    use crate::{
+        heuristic::{HeuristicEvaluator, HeuristicMCTS},
+        timecontrol::Budget,
+        BoardState,
+    };
+
+    #[test]
+    fn stops_after_the_playouts() {
+        let evaluator = HeuristicEvaluator::default();
+        let create = |state| HeuristicMCTS::create_search(state, evaluator, 1.4);
+        let state = BoardState::default();
+        let mut manager = create(state.clone());
+        Budget::Playouts(200).search(&mut manager, 1);
+        let mov = manager.best_move().unwrap();
+
+        let ponder = Ponder::start(&manager, &state, mov, 1, 100, create).unwrap();
+        let mut expected = ponder.expected().clone();
+        let pondered = ponder.finish(&expected).unwrap();
+        let visits = pondered
+            .tree()
+            .root_node()
+            .moves()
+            .map(|m| m.visits())
+            .sum::<u64>();
+        assert!(visits <= 100, "{}", visits);
+
+        // A miss throws the tree away.
+        let ponder = Ponder::start(&manager, &state, mov, 1, 100, create).unwrap();
+        let other = expected.moves().next().unwrap();
+        expected.make_move(&other);
+        assert!(ponder.finish(&expected).is_none());
+    }
+}
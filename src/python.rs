@@ -0,0 +1,180 @@
+// A Gym-style environment for Python training stacks, built as the `m3c4`
+// extension module:
+//
+//     env = m3c4.Env("size=7x6")
+//     observation = env.reset()
+//     observation, reward, done, info = env.step(env.legal_actions()[0])
+//
+// Observations are the network input planes of the player to move and
+// actions are indices into the policy planes, see `BoardAction::to_index`.
+use std::collections::HashMap;
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{
+    action::BoardAction,
+    board::TerminalResult,
+    planes::{policy_planes, InputPlanes, Tensor, PLANES},
+    player::Player,
+    render::RenderOptions,
+    rules::GameRules,
+    BoardState,
+};
+use mcts::GameState;
+
+type Step = (Tensor<f32>, f32, bool, HashMap<&'static str, usize>);
+
+#[pyclass(name = "Env")]
+pub struct Env {
+    state: BoardState,
+}
+
+#[pymethods]
+impl Env {
+    // Rules as read by GameRules::parse, the default rules without them.
+    #[new]
+    #[pyo3(signature = (rules = None))]
+    fn new(rules: Option<&str>) -> PyResult<Self> {
+        let rules = match rules {
+            Some(rules) => GameRules::parse(rules)
+                .ok_or_else(|| PyValueError::new_err(format!("Bad rules: {}", rules)))?,
+            None => GameRules::default(),
+        };
+        Ok(Self {
+            state: BoardState::new(rules),
+        })
+    }
+
+    // Starts a new game with the same rules.
+    fn reset(&mut self) -> Tensor<f32> {
+        self.state = BoardState::new(*self.state.rules());
+        self.observation()
+    }
+
+    // Plays the action for the player to move. The reward is seen from that
+    // player: 1 when the action wins the game, -1 when it hands the win to
+    // the opponent and 0 otherwise. Illegal actions raise a ValueError.
+    fn step(&mut self, action: usize) -> PyResult<Step> {
+        let (width, height) = self.size();
+        let player = self.state.current_player();
+        let mov = BoardAction::from_index(action, player, width, height)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown action {}", action)))?;
+        let results = self
+            .state
+            .try_make_move(&mov)
+            .map_err(|e| PyValueError::new_err(format!("Can't play {}: {}", mov, e)))?;
+
+        let reward = match self.state.result() {
+            TerminalResult::Win(winner) if winner == player => 1.0,
+            TerminalResult::Win(_) => -1.0,
+            _ => 0.0,
+        };
+        let threes = results
+            .iter()
+            .filter(|result| result.scorer() == Some(player))
+            .count();
+        let info = HashMap::from([
+            ("threes", threes),
+            ("player_1_points", self.state.points(Player::Player1)),
+            ("player_2_points", self.state.points(Player::Player2)),
+        ]);
+        Ok((self.observation(), reward, self.state.is_terminal(), info))
+    }
+
+    fn legal_actions(&self) -> Vec<usize> {
+        let (width, height) = self.size();
+        let mut actions = self
+            .state
+            .available_moves()
+            .iter()
+            .map(|mov| mov.to_index(width, height))
+            .collect::<Vec<_>>();
+        actions.sort_unstable();
+        actions
+    }
+
+    // The input planes, indexed [plane][x][y].
+    fn observation(&self) -> Tensor<f32> {
+        InputPlanes::from(&self.state)
+            .planes()
+            .iter()
+            .map(|plane| {
+                plane
+                    .iter()
+                    .map(|col| col.iter().map(|&x| x as f32).collect())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[getter]
+    fn observation_shape(&self) -> (usize, usize, usize) {
+        let (width, height) = self.size();
+        (PLANES, width, height)
+    }
+
+    #[getter]
+    fn action_count(&self) -> usize {
+        let (width, height) = self.size();
+        policy_planes(self.state.rules().diagonal_switches) * width * height
+    }
+
+    // 1 or 2.
+    #[getter]
+    fn current_player(&self) -> u8 {
+        match self.state.current_player() {
+            Player::Player1 => 1,
+            Player::Player2 => 2,
+        }
+    }
+
+    fn render(&self) -> String {
+        self.state.render_text(&RenderOptions::labelled())
+    }
+}
+
+impl Env {
+    fn size(&self) -> (usize, usize) {
+        (self.state.board().width(), self.state.board().height())
+    }
+}
+
+#[pymodule]
+fn m3c4(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Env>()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{BoardAction, Column},
+        player::Player,
+    };
+
+    use super::Env;
+
+    #[test]
+    fn play_an_episode() {
+        let mut env = Env::new(Some("size=5x4")).expect("Could not create env");
+        assert!(Env::new(Some("size=huge")).is_err());
+        assert_eq!(env.observation_shape(), (4, 5, 4));
+        assert_eq!(env.action_count(), 3 * 5 * 4);
+
+        let observation = env.reset();
+        assert_eq!(observation.len(), 4);
+        assert!(env.step(env.action_count()).is_err());
+
+        // X completes a row of four at once, which wins without clearing.
+        let drop =
+            |col| BoardAction::DropStone(Player::Player1, Column::new(col).unwrap()).to_index(5, 4);
+        for col in [0, 0, 1, 1, 3, 3] {
+            let (_, reward, done, _) = env.step(drop(col)).expect("Could not step");
+            assert_eq!((reward, done), (0.0, false));
+        }
+        assert!(env.legal_actions().contains(&drop(2)));
+        let (_, reward, done, info) = env.step(drop(2)).expect("Could not step");
+        assert_eq!((reward, done), (1.0, true));
+        assert_eq!(info["threes"], 0);
+        assert!(env.legal_actions().is_empty());
+    }
+}
@@ -0,0 +1,442 @@
+use std::{
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    action::BoardAction,
+    board::{MoveResult, TerminalResult},
+    player::Player,
+    rules::GameRules,
+    BoardState,
+};
+
+// How the search that chose a move went.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchStats {
+    pub playouts: usize,
+    // Visits of the chosen move and its mean value for the mover.
+    pub visits: u64,
+    pub value: f32,
+    pub time: Duration,
+}
+
+// How a self-play game was played, enough to check it and to play it again.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GameMeta {
+    // Generation of the model that played the game.
+    pub model: Option<usize>,
+    pub seed: Option<u64>,
+    pub playouts: Option<usize>,
+    pub exploration: Option<f64>,
+}
+
+// A played game, stored as one move per line in the `BoardAction` notation:
+//
+//     rules size=8x8 win=4 match=3 diagonals=true diagonal-switches=false cascade=true resolution=simultaneous
+//     start 8/8/8/8/8/8/8/8 X 0 0
+//     model 12
+//     seed 7301
+//     d3 playouts=500 visits=212 value=0.104 ms=38 policy=d2:0.150/d3:0.424/d4:0.426
+//     d4 # three O
+//     result O
+//
+// Statistics follow the move when it was chosen by a search, with the share
+// of the root visits of every move the search tried. What the move scored is
+// written as a comment. Files holding only moves start from the standard
+// position.
+#[derive(Debug, Default, Clone)]
+pub struct GameRecord {
+    pub start: BoardState,
+    pub meta: GameMeta,
+    pub moves: Vec<BoardAction>,
+    pub stats: Vec<Option<SearchStats>>,
+    // Visit shares of the search of every move, empty for moves that were
+    // not searched.
+    pub visits: Vec<Vec<(BoardAction, f32)>>,
+    // Set when the game was decided off the board, by resigning for example.
+    pub outcome: Option<TerminalResult>,
+}
+
+impl GameRecord {
+    pub fn new(moves: Vec<BoardAction>) -> Self {
+        Self::from_position(BoardState::default(), moves)
+    }
+
+    pub fn from_position(start: BoardState, moves: Vec<BoardAction>) -> Self {
+        Self {
+            start,
+            meta: GameMeta::default(),
+            stats: vec![None; moves.len()],
+            visits: vec![Vec::new(); moves.len()],
+            moves,
+            outcome: None,
+        }
+    }
+
+    pub fn push(&mut self, mov: BoardAction) {
+        self.moves.push(mov);
+        self.stats.push(None);
+        self.visits.push(Vec::new());
+    }
+
+    pub fn push_searched(&mut self, mov: BoardAction, stats: SearchStats) {
+        self.moves.push(mov);
+        self.stats.push(Some(stats));
+        self.visits.push(Vec::new());
+    }
+
+    // Replays the game from the initial position, returning the final state
+    // and the board results of every move.
+    pub fn replay(&self) -> (BoardState, Vec<Vec<MoveResult>>) {
+        let mut state = self.start.clone();
+        let results = self.moves.iter().map(|mov| state.play(mov)).collect();
+
+        (state, results)
+    }
+
+    pub fn result(&self) -> TerminalResult {
+        if let Some(outcome) = &self.outcome {
+            return outcome.clone();
+        }
+        let (state, _) = self.replay();
+        state.result()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let (_, results) = self.replay();
+
+        let mut content = String::new();
+        writeln!(content, "rules {}", self.start.rules()).expect("Could not format rules");
+        writeln!(content, "start {}", self.start.to_notation()).expect("Could not format start");
+        let meta = &self.meta;
+        if let Some(model) = meta.model {
+            writeln!(content, "model {}", model).expect("Could not format model");
+        }
+        if let Some(seed) = meta.seed {
+            writeln!(content, "seed {}", seed).expect("Could not format seed");
+        }
+        if let Some(playouts) = meta.playouts {
+            writeln!(content, "playouts {}", playouts).expect("Could not format playouts");
+        }
+        if let Some(exploration) = meta.exploration {
+            writeln!(content, "exploration {}", exploration).expect("Could not format exploration");
+        }
+        for (i, mov) in self.moves.iter().enumerate() {
+            write!(content, "{}", mov).expect("Could not format move");
+            if let Some(Some(stats)) = self.stats.get(i) {
+                write!(
+                    content,
+                    " playouts={} visits={} value={:.3} ms={}",
+                    stats.playouts,
+                    stats.visits,
+                    stats.value,
+                    stats.time.as_millis()
+                )
+                .expect("Could not format stats");
+            }
+            if let Some(visits) = self.visits.get(i).filter(|visits| !visits.is_empty()) {
+                let shares = visits
+                    .iter()
+                    .map(|(action, share)| format!("{}:{:.3}", action, share))
+                    .collect::<Vec<_>>();
+                write!(content, " policy={}", shares.join("/")).expect("Could not format policy");
+            }
+            if !results[i].is_empty() {
+                let scored = results[i].iter().map(describe).collect::<Vec<_>>();
+                write!(content, " # {}", scored.join(", ")).expect("Could not format results");
+            }
+            content.push('\n');
+        }
+        writeln!(content, "result {}", format_result(&self.result()))
+            .expect("Could not format result");
+
+        fs::write(path, content)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let invalid = |what: &str, line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid {}: {}", what, line),
+            )
+        };
+
+        let mut record = Self::default();
+        let mut rules = GameRules::default();
+        let mut player = Player::Player1;
+        // The rules come before the start position and the start before the
+        // moves, a later line would throw away what was read.
+        let mut started = false;
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "rules" if started => return Err(invalid("rules after the start", line)),
+                "rules" => {
+                    rules = GameRules::parse(value).ok_or_else(|| invalid("rules", line))?;
+                    record.start = BoardState::new(rules);
+                }
+                "start" if !record.moves.is_empty() => {
+                    return Err(invalid("start after the moves", line))
+                }
+                "start" => {
+                    started = true;
+                    let start = BoardState::parse_notation(value)
+                        .filter(|s| {
+                            (s.board().width(), s.board().height()) == (rules.width, rules.height)
+                        })
+                        .ok_or_else(|| invalid("start position", line))?;
+                    record.start = start.with_rules(rules);
+                    player = record.start.current_player;
+                }
+                "result" => {
+                    record.outcome =
+                        Some(parse_result(value).ok_or_else(|| invalid("result", line))?);
+                }
+                "model" => {
+                    record.meta.model = Some(value.parse().map_err(|_| invalid("model", line))?)
+                }
+                "seed" => {
+                    record.meta.seed = Some(value.parse().map_err(|_| invalid("seed", line))?)
+                }
+                "playouts" => {
+                    record.meta.playouts =
+                        Some(value.parse().map_err(|_| invalid("playouts", line))?)
+                }
+                "exploration" => {
+                    record.meta.exploration =
+                        Some(value.parse().map_err(|_| invalid("exploration", line))?)
+                }
+                _ => {
+                    let mov =
+                        BoardAction::parse(key, player).ok_or_else(|| invalid("move", line))?;
+                    let (policy, stats): (Vec<_>, Vec<_>) = value
+                        .split_whitespace()
+                        .partition(|field| field.starts_with("policy="));
+                    let stats = if stats.is_empty() {
+                        None
+                    } else {
+                        Some(
+                            parse_stats(&stats.join(" "))
+                                .ok_or_else(|| invalid("statistics", line))?,
+                        )
+                    };
+                    let visits = match policy.first() {
+                        Some(field) => parse_visits(&field["policy=".len()..], player)
+                            .ok_or_else(|| invalid("policy", line))?,
+                        None => Vec::new(),
+                    };
+                    started = true;
+                    record.moves.push(mov);
+                    record.stats.push(stats);
+                    record.visits.push(visits);
+                    player = player.next_player();
+                }
+            }
+        }
+
+        // The moves have to be legal, the record is replayed without checks.
+        let mut state = record.start.clone();
+        for mov in &record.moves {
+            state
+                .try_make_move(mov)
+                .map_err(|e| invalid("move", &format!("{} ({})", mov, e)))?;
+        }
+
+        // A result that follows from the board is not kept as an outcome.
+        if record.outcome.is_some() {
+            let (state, _) = record.replay();
+            if record.outcome == Some(state.result()) {
+                record.outcome = None;
+            }
+        }
+
+        Ok(record)
+    }
+
+    // Loads every `.game` file in a directory and its subdirectories.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> io::Result<Vec<Self>> {
+        let mut paths = Vec::new();
+        collect_game_files(dir.as_ref(), &mut paths)?;
+        paths.sort();
+
+        paths.into_iter().map(Self::load).collect()
+    }
+}
+
+fn collect_game_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_game_files(&path, paths)?;
+        } else if path.extension().and_then(|e| e.to_str()) == Some("game") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn describe(result: &MoveResult) -> String {
+    match result {
+        MoveResult::Three(matched) => format!("three {}", player_symbol(matched.player)),
+        MoveResult::Winner(player) => format!("winner {}", player_symbol(*player)),
+        MoveResult::Draw => String::from("draw"),
+    }
+}
+
+fn player_symbol(player: Player) -> char {
+    match player {
+        Player::Player1 => 'X',
+        Player::Player2 => 'O',
+    }
+}
+
+fn format_result(result: &TerminalResult) -> String {
+    match result {
+        TerminalResult::Win(player) => player_symbol(*player).to_string(),
+        TerminalResult::Draw => String::from("draw"),
+        TerminalResult::None => String::from("none"),
+    }
+}
+
+fn parse_result(s: &str) -> Option<TerminalResult> {
+    match s {
+        "X" => Some(TerminalResult::Win(Player::Player1)),
+        "O" => Some(TerminalResult::Win(Player::Player2)),
+        "draw" => Some(TerminalResult::Draw),
+        "none" => Some(TerminalResult::None),
+        _ => None,
+    }
+}
+
+fn parse_stats(s: &str) -> Option<SearchStats> {
+    let mut stats = SearchStats {
+        playouts: 0,
+        visits: 0,
+        value: 0.0,
+        time: Duration::ZERO,
+    };
+    for field in s.split_whitespace() {
+        let (key, value) = field.split_once('=')?;
+        match key {
+            "playouts" => stats.playouts = value.parse().ok()?,
+            "visits" => stats.visits = value.parse().ok()?,
+            "value" => stats.value = value.parse().ok()?,
+            "ms" => stats.time = Duration::from_millis(value.parse().ok()?),
+            _ => return None,
+        }
+    }
+    Some(stats)
+}
+
+// Shares written as `d2:0.150/s3,0-3,1:0.020`, for the actions of the mover.
+fn parse_visits(s: &str, player: Player) -> Option<Vec<(BoardAction, f32)>> {
+    s.split('/')
+        .map(|entry| {
+            let (action, share) = entry.rsplit_once(':')?;
+            Some((BoardAction::parse(action, player)?, share.parse().ok()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        action::BoardAction,
+        board::TerminalResult,
+        player::Player,
+        rules::GameRules,
+        testing::{col, temp_path},
+        BoardState,
+    };
+
+    use super::{GameMeta, GameRecord, SearchStats};
+
+    #[test]
+    fn save_and_load() {
+        let rules = GameRules {
+            width: 6,
+            height: 5,
+            cascade: false,
+            ..Default::default()
+        };
+        let start = BoardState::parse_notation("6/6/6/6/XX4 O 0 0")
+            .expect("Could not parse start")
+            .with_rules(rules);
+
+        let mut record = GameRecord::from_position(start, Vec::new());
+        record.push_searched(
+            BoardAction::DropStone(Player::Player2, col(5)),
+            SearchStats {
+                playouts: 100,
+                visits: 40,
+                value: -0.25,
+                time: Duration::from_millis(12),
+            },
+        );
+        record.visits[0] = vec![
+            (BoardAction::DropStone(Player::Player2, col(5)), 0.75),
+            (BoardAction::DropStone(Player::Player2, col(0)), 0.25),
+        ];
+        record.push(BoardAction::DropStone(Player::Player1, col(2)));
+        record.outcome = Some(TerminalResult::Win(Player::Player1));
+        record.meta = GameMeta {
+            model: Some(7),
+            seed: Some(42),
+            playouts: Some(100),
+            exploration: Some(1.45),
+        };
+
+        let path = temp_path("record_test.game");
+        record.save(&path).expect("Could not save record");
+        let loaded = GameRecord::load(&path).expect("Could not load record");
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.moves, record.moves);
+        assert_eq!(loaded.stats, record.stats);
+        assert_eq!(loaded.visits, record.visits);
+        assert_eq!(loaded.meta, record.meta);
+        assert_eq!(loaded.start.rules(), &rules);
+        assert_eq!(loaded.start.board(), record.start.board());
+        assert_eq!(loaded.result(), TerminalResult::Win(Player::Player1));
+
+        let (_, results) = loaded.replay();
+        assert_eq!(results[1].len(), 1);
+    }
+
+    #[test]
+    fn refuse_illegal_moves() {
+        let path = temp_path("record_illegal_test.game");
+        // The column is full after three stones.
+        std::fs::write(&path, "rules size=5x3\nd1\nd1\nd1\nd1\n").unwrap();
+        let loaded = GameRecord::load(&path);
+        std::fs::remove_file(&path).ok();
+        assert_eq!(
+            loaded.map(|_| ()).map_err(|e| e.kind()),
+            Err(std::io::ErrorKind::InvalidData)
+        );
+    }
+
+    #[test]
+    fn rules_come_first() {
+        let path = temp_path("record_order_test.game");
+        let start = "rules size=5x4\nstart 5/5/5/X4 O 0 0\n";
+        std::fs::write(&path, start).unwrap();
+        let loaded = GameRecord::load(&path).expect("Could not load record");
+        assert_eq!(loaded.start.current_player, Player::Player2);
+
+        std::fs::write(&path, format!("{}rules size=5x4 cascade=false\n", start)).unwrap();
+        let loaded = GameRecord::load(&path);
+        std::fs::remove_file(&path).ok();
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,329 @@
+// What is known about every model generation of a run, next to the models
+// in data/models. One JSON object per line and generation:
+//
+//     {"version": 12, "episode": 12, "data": [8, 11], "config": "9c1f...",
+//      "arena": {"opponent": 10, "wins": 6, "draws": 1, "losses": 3},
+//      "rating": 1043.5, "promoted": true,
+//      "input": {"points": "raw", "history": 0, "side_to_move": false,
+//                "move_count": false, "layout": "nchw"}}
+//
+// The version is the generation the model is saved as, the episode the one
+// it started. `data` holds the first and last episode whose games it was
+// trained on, none for the untrained first model. Models are named by
+// version, "best" for the last promoted one or "latest" for the newest.
+// `input` is how the model sees a position, every search with it has to
+// encode the positions the same way. Entries without one use the default.
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    alphazero::pipeline::ArenaResult,
+    config::TrainingConfig,
+    planes::{InputEncoding, Layout},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArenaRecord {
+    pub opponent: usize,
+    pub result: ArenaResult,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelEntry {
+    pub version: usize,
+    pub episode: usize,
+    pub data: Option<(usize, usize)>,
+    // `config_hash` of the config the model was trained with.
+    pub config: String,
+    // The match that decided whether it was promoted, the first model
+    // plays none.
+    pub arena: Option<ArenaRecord>,
+    pub rating: Option<f64>,
+    pub promoted: bool,
+    pub input: InputEncoding,
+}
+
+impl ModelEntry {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "version": self.version,
+            "episode": self.episode,
+            "data": self.data.map(|(first, last)| [first, last]),
+            "config": self.config,
+            "arena": self.arena.as_ref().map(|arena| json!({
+                "opponent": arena.opponent,
+                "wins": arena.result.wins,
+                "draws": arena.result.draws,
+                "losses": arena.result.losses,
+            })),
+            "rating": self.rating,
+            "promoted": self.promoted,
+            "input": {
+                "points": self.input.points.to_string(),
+                "history": self.input.history,
+                "side_to_move": self.input.side_to_move,
+                "move_count": self.input.move_count,
+                "layout": self.input.layout.to_string(),
+            },
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let number = |value: &Value, name: &str| {
+            value[name]
+                .as_u64()
+                .map(|n| n as usize)
+                .ok_or_else(|| format!("Missing {}", name))
+        };
+        let data = match &value["data"] {
+            Value::Null => None,
+            data => {
+                let range: [usize; 2] =
+                    serde_json::from_value(data.clone()).map_err(|e| e.to_string())?;
+                Some((range[0], range[1]))
+            }
+        };
+        let arena = match &value["arena"] {
+            Value::Null => None,
+            arena => Some(ArenaRecord {
+                opponent: number(arena, "opponent")?,
+                result: ArenaResult {
+                    wins: number(arena, "wins")?,
+                    draws: number(arena, "draws")?,
+                    losses: number(arena, "losses")?,
+                },
+            }),
+        };
+        let input = match &value["input"] {
+            Value::Null => InputEncoding::default(),
+            input => {
+                let text = |name: &str| {
+                    input[name]
+                        .as_str()
+                        .ok_or_else(|| format!("Missing {}", name))
+                };
+                let flag = |name: &str| {
+                    input[name]
+                        .as_bool()
+                        .ok_or_else(|| format!("Missing {}", name))
+                };
+                InputEncoding {
+                    points: text("points")?.parse()?,
+                    history: number(input, "history")?,
+                    side_to_move: flag("side_to_move")?,
+                    move_count: flag("move_count")?,
+                    layout: text("layout")?.parse::<Layout>()?,
+                }
+            }
+        };
+        Ok(Self {
+            version: number(value, "version")?,
+            episode: number(value, "episode")?,
+            data,
+            config: value["config"].as_str().unwrap_or_default().to_string(),
+            arena,
+            rating: value["rating"].as_f64(),
+            promoted: value["promoted"].as_bool().unwrap_or(false),
+            input,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelRegistry {
+    // Ordered by version.
+    entries: Vec<ModelEntry>,
+}
+
+impl ModelRegistry {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut registry = Self::default();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(line)
+                .map_err(|e| e.to_string())
+                .and_then(|value| ModelEntry::from_json(&value))
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+            registry.register(entry);
+        }
+        Ok(registry)
+    }
+
+    // Empty when the run has no registry yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                Self::parse(&content).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Written to a temporary file first, like the checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+        let content = self
+            .entries
+            .iter()
+            .map(|entry| entry.to_json().to_string() + "\n")
+            .collect::<String>();
+        fs::write(&temporary, content)?;
+        fs::rename(temporary, path)
+    }
+
+    // Adds the entry, or replaces the one of its version when a resumed
+    // episode registers it again.
+    pub fn register(&mut self, entry: ModelEntry) {
+        match self
+            .entries
+            .binary_search_by_key(&entry.version, |e| e.version)
+        {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
+    pub fn get(&self, version: usize) -> Option<&ModelEntry> {
+        self.entries.iter().find(|e| e.version == version)
+    }
+
+    pub fn entries(&self) -> &[ModelEntry] {
+        &self.entries
+    }
+
+    pub fn latest(&self) -> Option<&ModelEntry> {
+        self.entries.last()
+    }
+
+    pub fn best(&self) -> Option<&ModelEntry> {
+        self.entries.iter().rev().find(|e| e.promoted)
+    }
+
+    // How the model of the version sees a position, the default for models
+    // the registry does not know.
+    pub fn input(&self, version: usize) -> InputEncoding {
+        self.get(version)
+            .map_or_else(InputEncoding::default, |e| e.input)
+    }
+
+    // The version "best", "latest" or a version number names.
+    pub fn resolve(&self, name: &str) -> Result<usize, String> {
+        let entry = match name {
+            "best" => self.best(),
+            "latest" => self.latest(),
+            _ => {
+                let version = name
+                    .parse()
+                    .map_err(|_| format!("Unknown model version: {}", name))?;
+                self.get(version)
+            }
+        };
+        entry
+            .map(|e| e.version)
+            .ok_or_else(|| format!("No model {} in the registry", name))
+    }
+}
+
+// Tells configs apart that train differently. The episodes and the device
+// settings are left out, a run can be extended or moved to another machine.
+pub fn config_hash(config: &TrainingConfig) -> String {
+    let config = TrainingConfig {
+        episodes: 0,
+        device: Default::default(),
+        ..config.clone()
+    };
+    // FNV-1a, the hash has to be the same with every build.
+    let hash = config
+        .to_string()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        alphazero::pipeline::ArenaResult,
+        config::TrainingConfig,
+        planes::{InputEncoding, Layout},
+        testing::temp_path,
+    };
+
+    use super::{config_hash, ArenaRecord, ModelEntry, ModelRegistry};
+
+    fn entry(version: usize, promoted: bool) -> ModelEntry {
+        ModelEntry {
+            version,
+            episode: version,
+            data: version.checked_sub(1).map(|last| (0, last)),
+            config: config_hash(&TrainingConfig::default()),
+            arena: (version > 0).then(|| ArenaRecord {
+                opponent: version - 1,
+                result: ArenaResult {
+                    wins: 6,
+                    draws: 1,
+                    losses: 3,
+                },
+            }),
+            rating: Some(1000.0 + version as f64),
+            promoted,
+            input: InputEncoding {
+                points: PointsEncoding::Thermometer(version as u8 + 1),
+                history: version,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn register_and_resolve() {
+        let mut registry = ModelRegistry::default();
+        assert!(registry.resolve("best").is_err());
+
+        registry.register(entry(2, false));
+        registry.register(entry(0, true));
+        registry.register(entry(1, false));
+        registry.register(entry(1, true));
+        assert_eq!(registry.entries().len(), 3);
+        assert_eq!(registry.resolve("best"), Ok(1));
+        assert_eq!(registry.resolve("latest"), Ok(2));
+        assert_eq!(registry.resolve("0"), Ok(0));
+        assert!(registry.resolve("3").is_err());
+        assert!(registry.resolve("newest").is_err());
+        assert_eq!(registry.input(2).history, 2);
+        assert_eq!(registry.input(7), InputEncoding::default());
+
+        let path = temp_path("registry_test.jsonl");
+        registry.save(&path).expect("Could not save registry");
+        let loaded = ModelRegistry::load(&path).expect("Could not load registry");
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded, registry);
+        assert_eq!(
+            ModelRegistry::load(&path).unwrap(),
+            ModelRegistry::default()
+        );
+    }
+
+    #[test]
+    fn config_hashes() {
+        let config = TrainingConfig::default();
+        let mut extended = config.clone();
+        extended.episodes += 10;
+        assert_eq!(config_hash(&config), config_hash(&extended));
+
+        let mut changed = config.clone();
+        changed.pipeline.playouts += 1;
+        assert_ne!(config_hash(&config), config_hash(&changed));
+    }
+}
@@ -0,0 +1,245 @@
+use std::fmt::Write;
+
+use crate::{
+    action::{BoardAction, Coordinate},
+    board::{Board, Cell},
+    player::Player,
+    BoardState,
+};
+
+const RESET: &str = "\x1b[0m";
+const HIGHLIGHT: &str = "\x1b[7m";
+
+// The characters used for the cells of the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderTheme {
+    #[default]
+    Ascii,
+    Unicode,
+    Emoji,
+}
+
+impl RenderTheme {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "ascii" => Some(RenderTheme::Ascii),
+            "unicode" => Some(RenderTheme::Unicode),
+            "emoji" => Some(RenderTheme::Emoji),
+            _ => None,
+        }
+    }
+
+    fn glyph(&self, cell: Cell, highlighted: bool) -> &'static str {
+        match (self, cell, highlighted) {
+            (RenderTheme::Ascii, Cell::Empty, _) => " ",
+            (RenderTheme::Ascii, Cell::Filled(Player::Player1), false) => "X",
+            (RenderTheme::Ascii, Cell::Filled(Player::Player2), false) => "O",
+            (RenderTheme::Ascii, Cell::Filled(Player::Player1), true) => "x",
+            (RenderTheme::Ascii, Cell::Filled(Player::Player2), true) => "o",
+            (RenderTheme::Unicode, Cell::Empty, _) => "·",
+            (RenderTheme::Unicode, Cell::Filled(Player::Player1), false) => "●",
+            (RenderTheme::Unicode, Cell::Filled(Player::Player2), false) => "○",
+            (RenderTheme::Unicode, Cell::Filled(Player::Player1), true) => "◉",
+            (RenderTheme::Unicode, Cell::Filled(Player::Player2), true) => "◎",
+            (RenderTheme::Emoji, Cell::Empty, _) => "⚫",
+            (RenderTheme::Emoji, Cell::Filled(Player::Player1), false) => "🔴",
+            (RenderTheme::Emoji, Cell::Filled(Player::Player2), false) => "🟡",
+            (RenderTheme::Emoji, Cell::Filled(Player::Player1), true) => "🟥",
+            (RenderTheme::Emoji, Cell::Filled(Player::Player2), true) => "🟨",
+        }
+    }
+
+    fn cleared(&self) -> &'static str {
+        match self {
+            RenderTheme::Ascii => "*",
+            RenderTheme::Unicode => "×",
+            RenderTheme::Emoji => "💥",
+        }
+    }
+
+    // Number of terminal columns a single cell takes up.
+    fn cell_width(&self) -> usize {
+        match self {
+            RenderTheme::Ascii | RenderTheme::Unicode => 1,
+            RenderTheme::Emoji => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    pub theme: RenderTheme,
+    // Print column numbers below and row numbers next to the board.
+    pub labels: bool,
+    // Use ANSI colors for the stones and for highlighting.
+    pub colors: bool,
+    // The last action played, its stones are highlighted.
+    pub last_move: Option<BoardAction>,
+    // Cells cleared by the last action.
+    pub cleared: Vec<Coordinate>,
+}
+
+impl RenderOptions {
+    pub fn labelled() -> Self {
+        Self {
+            labels: true,
+            ..Default::default()
+        }
+    }
+}
+
+fn player_color(player: Player) -> &'static str {
+    match player {
+        Player::Player1 => "\x1b[31m",
+        Player::Player2 => "\x1b[33m",
+    }
+}
+
+// Cells that changed because of the action: the top stone of the column a
+// stone was dropped in, or both switched stones.
+fn last_move_cells(board: &Board, mov: &BoardAction) -> Vec<Coordinate> {
+    match mov {
+        BoardAction::DropStone(_, col) => (0..board.height())
+            .rev()
+            .map(|y| Coordinate::new(col.index() as isize, y as isize))
+            .find(|&coord| board.get(coord) != Cell::Empty)
+            .into_iter()
+            .collect(),
+        BoardAction::SwitchStone(a, b) => vec![*a, *b],
+    }
+}
+
+impl Board {
+    pub fn render_text(&self, options: &RenderOptions) -> String {
+        let highlighted = options
+            .last_move
+            .map(|mov| last_move_cells(self, &mov))
+            .unwrap_or_default();
+
+        // Row labels are padded so boards of ten or more rows stay aligned.
+        let label_width = (self.height() - 1).to_string().len();
+
+        let mut out = String::new();
+        for y in (0..self.height()).rev() {
+            if options.labels {
+                write!(out, "{:<width$} ", y, width = label_width).expect("Could not write label");
+            }
+            out.push('|');
+            for x in 0..self.width() {
+                let coord = Coordinate::new(x as isize, y as isize);
+                let cell = self.get(coord);
+                let highlight = highlighted.contains(&coord);
+                let cleared = options.cleared.contains(&coord);
+
+                let theme = options.theme;
+
+                match (cell, options.colors) {
+                    (Cell::Empty, _) if cleared => out.push_str(theme.cleared()),
+                    (Cell::Filled(player), true) => {
+                        out.push_str(player_color(player));
+                        if highlight || cleared {
+                            out.push_str(HIGHLIGHT);
+                        }
+                        out.push_str(theme.glyph(cell, false));
+                        out.push_str(RESET);
+                    }
+                    _ => out.push_str(theme.glyph(cell, highlight || cleared)),
+                }
+            }
+            out.push_str("|\n");
+        }
+
+        if options.labels {
+            out.push_str(&" ".repeat(label_width + 2));
+            for x in 0..self.width() {
+                write!(out, "{:<width$}", x, width = options.theme.cell_width())
+                    .expect("Could not write label");
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+impl BoardState {
+    // The board followed by the banked points and whose turn it is.
+    pub fn render_text(&self, options: &RenderOptions) -> String {
+        let mut out = self.board.render_text(options);
+        let name = |player: Player| {
+            let glyph = options.theme.glyph(Cell::Filled(player), false);
+            if options.colors {
+                format!("{}{}{}", player_color(player), glyph, RESET)
+            } else {
+                String::from(glyph)
+            }
+        };
+
+        writeln!(
+            out,
+            "Points: {} {} / {} {}",
+            name(Player::Player1),
+            self.player_1_points,
+            name(Player::Player2),
+            self.player_2_points
+        )
+        .expect("Could not write points");
+        writeln!(out, "Turn: {}", name(self.current_player)).expect("Could not write turn");
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{BoardAction, Column},
+        player::Player,
+        BoardState,
+    };
+    use mcts::GameState;
+
+    use super::{RenderOptions, RenderTheme};
+
+    #[test]
+    fn render_with_labels_and_last_move() {
+        let mut state = BoardState::default();
+        let mov = BoardAction::DropStone(Player::Player1, Column::new(2).unwrap());
+        state.make_move(&mov);
+
+        let options = RenderOptions {
+            last_move: Some(mov),
+            ..RenderOptions::labelled()
+        };
+        let text = state.render_text(&options);
+        let lines = text.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0], "7 |        |");
+        assert_eq!(lines[7], "0 |  x     |");
+        assert_eq!(lines[8], "   01234567");
+        assert_eq!(lines[9], "Points: X 0 / O 0");
+        assert_eq!(lines[10], "Turn: O");
+    }
+
+    #[test]
+    fn render_themes() {
+        let mut state = BoardState::default();
+        state.make_move(&BoardAction::DropStone(
+            Player::Player1,
+            Column::new(0).unwrap(),
+        ));
+
+        let render = |theme| {
+            let options = RenderOptions {
+                theme,
+                ..RenderOptions::labelled()
+            };
+            state.board.render_text(&options)
+        };
+
+        assert!(render(RenderTheme::Unicode).contains("0 |●·······|"));
+        let emoji = render(RenderTheme::Emoji);
+        assert!(emoji.contains("0 |🔴⚫⚫⚫⚫⚫⚫⚫|"));
+        assert!(emoji.ends_with("   0 1 2 3 4 5 6 7 \n"));
+    }
+}
@@ -0,0 +1,182 @@
+// Light policies used to play out games to the end in rollout evaluators.
+use rand::{distributions::WeightedIndex, prelude::Distribution, seq::SliceRandom, Rng};
+
+use crate::{action::BoardAction, player::Player, BoardState};
+use mcts::GameState;
+
+pub trait RolloutPolicy {
+    fn choose<R: Rng>(
+        &self,
+        state: &BoardState,
+        moves: &[BoardAction],
+        rng: &mut R,
+    ) -> Option<BoardAction>;
+}
+
+// Plays moves until the game ends and returns the winner.
+pub fn rollout<P: RolloutPolicy, R: Rng>(
+    state: &BoardState,
+    policy: &P,
+    rng: &mut R,
+) -> Option<Player> {
+    let mut state = state.clone();
+    let mut moves = Vec::new();
+    loop {
+        state.fill_moves(&mut moves);
+        if moves.is_empty() {
+            break;
+        }
+        let chosen = policy
+            .choose(&state, &moves, rng)
+            .expect("Could not choose action");
+        state.make_move(&chosen);
+    }
+    state.get_winner()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UniformPolicy;
+
+impl RolloutPolicy for UniformPolicy {
+    fn choose<R: Rng>(
+        &self,
+        _: &BoardState,
+        moves: &[BoardAction],
+        rng: &mut R,
+    ) -> Option<BoardAction> {
+        moves.choose(rng).copied()
+    }
+}
+
+// Takes immediate wins, blocks the opponent's immediate wins and otherwise
+// picks a move at random, preferring drops near the center and switches
+// that create a match.
+#[derive(Debug, Clone, Copy)]
+pub struct HeuristicPolicy {
+    pub center_weight: f64,
+    pub match_weight: f64,
+    pub switch_weight: f64,
+}
+
+impl Default for HeuristicPolicy {
+    fn default() -> Self {
+        Self {
+            center_weight: 1.0,
+            match_weight: 8.0,
+            switch_weight: 0.5,
+        }
+    }
+}
+
+impl HeuristicPolicy {
+    fn wins(state: &BoardState, player: Player, mov: &BoardAction) -> bool {
+        let mut state = state.clone();
+        state.current_player = player;
+        state.play(mov);
+        state.get_winner() == Some(player)
+    }
+
+    fn weight(&self, state: &BoardState, mov: &BoardAction) -> f64 {
+        match mov {
+            BoardAction::DropStone(_, col) => {
+                let width = state.board().width() as f64;
+                let distance = (col.index() as f64 - (width - 1.0) / 2.0).abs();
+                1.0 + self.center_weight * (width / 2.0 - distance)
+            }
+            BoardAction::SwitchStone(_, _) => {
+                let player = state.current_player;
+                let mut next = state.clone();
+                let scores = next
+                    .play(mov)
+                    .iter()
+                    .any(|result| result.scorer() == Some(player));
+                if scores {
+                    self.match_weight
+                } else {
+                    self.switch_weight
+                }
+            }
+        }
+    }
+}
+
+impl RolloutPolicy for HeuristicPolicy {
+    fn choose<R: Rng>(
+        &self,
+        state: &BoardState,
+        moves: &[BoardAction],
+        rng: &mut R,
+    ) -> Option<BoardAction> {
+        let player = state.current_player;
+        let win = moves.iter().find(|mov| {
+            matches!(mov, BoardAction::DropStone(_, _)) && Self::wins(state, player, mov)
+        });
+        if let Some(win) = win {
+            return Some(*win);
+        }
+
+        let opponent = player.next_player();
+        let threat = state.board().columns().find(|&col| {
+            state.board.is_col_free(col)
+                && Self::wins(state, opponent, &BoardAction::DropStone(opponent, col))
+        });
+        if let Some(col) = threat {
+            let block = BoardAction::DropStone(player, col);
+            if moves.contains(&block) {
+                return Some(block);
+            }
+        }
+
+        let weights = moves
+            .iter()
+            .map(|mov| self.weight(state, mov))
+            .collect::<Vec<_>>();
+        match WeightedIndex::new(&weights) {
+            Ok(index) => Some(moves[index.sample(rng)]),
+            Err(_) => moves.choose(rng).copied(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{BoardAction, Column},
+        player::Player,
+        testing::StateBuilder,
+    };
+    use mcts::GameState;
+
+    use super::{HeuristicPolicy, RolloutPolicy};
+
+    #[test]
+    fn takes_wins_and_blocks() {
+        let empty = "        ";
+        let policy = HeuristicPolicy::default();
+        let mut rng = rand::thread_rng();
+
+        let state = StateBuilder::new()
+            .board([empty, empty, empty, empty, empty, empty, empty, "XX XO   "])
+            .build();
+        let moves = state.available_moves();
+        assert_eq!(
+            policy.choose(&state, &moves, &mut rng),
+            Some(BoardAction::DropStone(
+                Player::Player1,
+                Column::new(2).unwrap()
+            ))
+        );
+
+        let state = StateBuilder::new()
+            .board([empty, empty, empty, empty, empty, empty, empty, "OO OXX  "])
+            .build();
+        let moves = state.available_moves();
+        assert_eq!(
+            policy.choose(&state, &moves, &mut rng),
+            Some(BoardAction::DropStone(
+                Player::Player1,
+                Column::new(2).unwrap()
+            ))
+        );
+    }
+}
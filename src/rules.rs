@@ -0,0 +1,155 @@
+use std::fmt::Display;
+
+use crate::board::{Board, HEIGHT, WIDTH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameRules {
+    // Size of the board the game is played on.
+    pub width: usize,
+    pub height: usize,
+    // A line of exactly this many stones wins the game.
+    pub win_length: usize,
+    // Lines of at least this many stones, other than a winning line, score
+    // a point and are cleared.
+    pub match_length: usize,
+    // Whether diagonal lines count for wins and matches.
+    pub diagonals: bool,
+    // Whether stones may also be switched with a diagonal neighbour.
+    pub diagonal_switches: bool,
+    // Resolve the matches that form when stones fall after a clear.
+    // When disabled only the matches created by the action itself score.
+    pub cascade: bool,
+    pub resolution: ResolutionPolicy,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            width: WIDTH,
+            height: HEIGHT,
+            win_length: 4,
+            match_length: 3,
+            diagonals: true,
+            diagonal_switches: false,
+            cascade: true,
+            resolution: ResolutionPolicy::default(),
+        }
+    }
+}
+
+// Order in which matches are scored and removed when one cascade phase
+// contains several of them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResolutionPolicy {
+    // All matches on the board are scored and removed at once.
+    #[default]
+    Simultaneous,
+    // The mover's matches are resolved first, the opponent's matches only
+    // if they survive the gravity that follows.
+    MoverFirst,
+    // One match per phase, starting with the lowest on the board.
+    BottomUp,
+}
+
+// Written as `size=8x8 win=4 match=3 diagonals=true diagonal-switches=false cascade=true
+// resolution=simultaneous`, on one line.
+impl Display for GameRules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "size={}x{} win={} match={} diagonals={} diagonal-switches={} cascade={} resolution={}",
+            self.width,
+            self.height,
+            self.win_length,
+            self.match_length,
+            self.diagonals,
+            self.diagonal_switches,
+            self.cascade,
+            self.resolution
+        )
+    }
+}
+
+impl Display for ResolutionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ResolutionPolicy::Simultaneous => "simultaneous",
+            ResolutionPolicy::MoverFirst => "mover-first",
+            ResolutionPolicy::BottomUp => "bottom-up",
+        })
+    }
+}
+
+impl GameRules {
+    // Fields that are left out keep their default value.
+    pub fn parse(s: &str) -> Option<GameRules> {
+        let mut rules = GameRules::default();
+        for field in s.split_whitespace() {
+            let (key, value) = field.split_once('=')?;
+            match key {
+                "size" => {
+                    let (width, height) = value.split_once('x')?;
+                    rules.width = width.parse().ok()?;
+                    rules.height = height.parse().ok()?;
+                    if !Board::fits(rules.width, rules.height) {
+                        return None;
+                    }
+                }
+                "win" => rules.win_length = parse_length(value)?,
+                "match" => rules.match_length = parse_length(value)?,
+                "diagonals" => rules.diagonals = value.parse().ok()?,
+                "diagonal-switches" => rules.diagonal_switches = value.parse().ok()?,
+                "cascade" => rules.cascade = value.parse().ok()?,
+                "resolution" => rules.resolution = ResolutionPolicy::parse(value)?,
+                _ => return None,
+            }
+        }
+        Some(rules)
+    }
+}
+
+// Lines need at least two stones.
+fn parse_length(s: &str) -> Option<usize> {
+    s.parse().ok().filter(|&length| length >= 2)
+}
+
+impl ResolutionPolicy {
+    pub fn parse(s: &str) -> Option<ResolutionPolicy> {
+        match s {
+            "simultaneous" => Some(ResolutionPolicy::Simultaneous),
+            "mover-first" => Some(ResolutionPolicy::MoverFirst),
+            "bottom-up" => Some(ResolutionPolicy::BottomUp),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GameRules;
+
+    #[test]
+    fn parse_size() {
+        let rules = GameRules::parse("size=7x6 cascade=false").expect("Could not parse rules");
+        assert_eq!((rules.width, rules.height), (7, 6));
+        assert!(!rules.cascade);
+        assert_eq!(GameRules::parse(&rules.to_string()), Some(rules));
+
+        assert_eq!(GameRules::parse("size=0x6"), None);
+        assert_eq!(GameRules::parse("size=8"), None);
+    }
+
+    #[test]
+    fn parse_lines() {
+        let rules = GameRules::parse("win=5 match=4 diagonals=false diagonal-switches=true")
+            .expect("Could not parse rules");
+        assert_eq!((rules.win_length, rules.match_length), (5, 4));
+        assert!(!rules.diagonals);
+        assert!(rules.diagonal_switches);
+        assert_eq!(GameRules::parse(&rules.to_string()), Some(rules));
+
+        assert_eq!(GameRules::parse("win=1"), None);
+    }
+}
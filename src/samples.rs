@@ -0,0 +1,258 @@
+// Training samples stored in a memory-mapped file, so large replay buffers
+// can be sampled from without holding every position in memory.
+//
+// The file starts with a header: the magic bytes, the input shape and the
+// policy shape as little endian u32s. It is followed by fixed size records
+// of the input planes (u8), the policy planes (f32) and the value (f32),
+// so a sample is found directly from its index. A record cut short by a
+// crash is left out when reading and cut off before appending.
+use std::{
+    convert::TryInto,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use catzero::{Tensor, TrainingData};
+use memmap2::Mmap;
+use rand::{seq::index, Rng};
+
+const MAGIC: &[u8; 4] = b"M3C4";
+const HEADER_LEN: usize = 4 + 6 * 4;
+
+pub type Shape = [usize; 3];
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn record_len(input: Shape, policy: Shape) -> usize {
+    input.iter().product::<usize>() + 4 * policy.iter().product::<usize>() + 4
+}
+
+fn shape_of<T>(tensor: &Tensor<T>) -> Shape {
+    [
+        tensor.len(),
+        tensor.first().map_or(0, |p| p.len()),
+        tensor
+            .first()
+            .and_then(|p| p.first())
+            .map_or(0, |r| r.len()),
+    ]
+}
+
+pub struct SampleWriter {
+    writer: BufWriter<File>,
+    input: Shape,
+    policy: Shape,
+}
+
+impl SampleWriter {
+    // Opens the file for appending, writing the header when it is new.
+    pub fn open<P: AsRef<Path>>(path: P, input: Shape, policy: Shape) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if file.metadata()?.len() == 0 {
+            let mut header = MAGIC.to_vec();
+            for dim in input.iter().chain(policy.iter()) {
+                header.extend_from_slice(&(*dim as u32).to_le_bytes());
+            }
+            file.write_all(&header)?;
+        } else {
+            let store = SampleStore::open(&path)?;
+            if store.input != input || store.policy != policy {
+                return Err(invalid("Sample file has different shapes"));
+            }
+            // Appending after a partial record would shift every record
+            // after it.
+            let whole = HEADER_LEN + store.len() * record_len(input, policy);
+            drop(store);
+            file.set_len(whole as u64)?;
+        }
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            input,
+            policy,
+        })
+    }
+
+    pub fn append(&mut self, data: &TrainingData) -> io::Result<()> {
+        for i in 0..data.len() {
+            let (input, policy) = (&data.inputs[i], &data.output_policy[i]);
+            if shape_of(input) != self.input || shape_of(policy) != self.policy {
+                return Err(invalid("Sample does not match the file shapes"));
+            }
+
+            let input = input
+                .iter()
+                .flatten()
+                .flatten()
+                .copied()
+                .collect::<Vec<_>>();
+            self.writer.write_all(&input)?;
+            for value in policy.iter().flatten().flatten() {
+                self.writer.write_all(&value.to_le_bytes())?;
+            }
+            self.writer.write_all(&data.output_value[i].to_le_bytes())?;
+        }
+        self.writer.flush()
+    }
+}
+
+pub struct SampleStore {
+    map: Mmap,
+    input: Shape,
+    policy: Shape,
+}
+
+impl SampleStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: the map is undefined behaviour when the file changes
+        // underneath it. SampleWriter only appends whole records, past the
+        // end of the map, and only ever cuts off a partial record, which no
+        // index reaches. Nothing else may write the file while it is open.
+        let map = unsafe { Mmap::map(&file)? };
+
+        if map.len() < HEADER_LEN || &map[..4] != MAGIC {
+            return Err(invalid("Not a sample file"));
+        }
+        let dims = map[4..HEADER_LEN]
+            .chunks(4)
+            .map(|b| u32::from_le_bytes(b.try_into().expect("Could not read dimension")) as usize)
+            .collect::<Vec<_>>();
+
+        let (input, policy) = ([dims[0], dims[1], dims[2]], [dims[3], dims[4], dims[5]]);
+        if input.contains(&0) || policy.contains(&0) {
+            return Err(invalid("Sample file has an empty shape"));
+        }
+        Ok(Self { map, input, policy })
+    }
+
+    // Whole records only, a partial one at the end is not counted.
+    pub fn len(&self) -> usize {
+        (self.map.len() - HEADER_LEN) / record_len(self.input, self.policy)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<(Tensor<u8>, Tensor<f32>, f32)> {
+        if index >= self.len() {
+            return None;
+        }
+        let start = HEADER_LEN + index * record_len(self.input, self.policy);
+        let input_len = self.input.iter().product::<usize>();
+        let policy_len = 4 * self.policy.iter().product::<usize>();
+
+        let input = &self.map[start..start + input_len];
+        let policy = self.map[start + input_len..start + input_len + policy_len]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().expect("Could not read policy")))
+            .collect::<Vec<_>>();
+        let value_start = start + input_len + policy_len;
+        let value = f32::from_le_bytes(
+            self.map[value_start..value_start + 4]
+                .try_into()
+                .expect("Could not read value"),
+        );
+
+        Some((
+            reshape(input, self.input),
+            reshape(&policy, self.policy),
+            value,
+        ))
+    }
+
+    // Draws distinct samples uniformly, as training data for the trainer.
+    pub fn sample<R: Rng>(&self, amount: usize, rng: &mut R) -> TrainingData {
+        let indices = index::sample(rng, self.len(), amount.min(self.len()));
+        self.collect(indices.into_iter())
+    }
+
+    pub fn training_data(&self) -> TrainingData {
+        self.collect(0..self.len())
+    }
+
+    fn collect<I: Iterator<Item = usize>>(&self, indices: I) -> TrainingData {
+        let mut data = TrainingData {
+            inputs: Vec::new(),
+            output_policy: Vec::new(),
+            output_value: Vec::new(),
+        };
+        for i in indices {
+            let (input, policy, value) = self.get(i).expect("Sample index out of range");
+            data.inputs.push(input);
+            data.output_policy.push(policy);
+            data.output_value.push(value);
+        }
+        data
+    }
+}
+
+fn reshape<T: Copy>(values: &[T], shape: Shape) -> Tensor<T> {
+    values
+        .chunks(shape[1] * shape[2])
+        .map(|plane| plane.chunks(shape[2]).map(|row| row.to_vec()).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use catzero::TrainingData;
+
+    use crate::testing::temp_path;
+
+    use super::{SampleStore, SampleWriter};
+
+    #[test]
+    fn write_and_sample() {
+        let path = temp_path("samples_test.bin");
+        std::fs::remove_file(&path).ok();
+
+        let data = TrainingData {
+            inputs: vec![
+                vec![vec![vec![1u8, 2]; 2]; 4],
+                vec![vec![vec![3u8, 4]; 2]; 4],
+            ],
+            output_policy: vec![vec![vec![vec![0.5f32, 0.25]; 2]; 3]; 2],
+            output_value: vec![1.0, -1.0],
+        };
+
+        let mut writer =
+            SampleWriter::open(&path, [4, 2, 2], [3, 2, 2]).expect("Could not open writer");
+        writer.append(&data).expect("Could not append");
+        writer.append(&data).expect("Could not append");
+
+        let store = SampleStore::open(&path).expect("Could not open store");
+        assert_eq!(store.len(), 4);
+
+        let (input, policy, value) = store.get(1).expect("Missing sample");
+        assert_eq!(input, data.inputs[1]);
+        assert_eq!(policy, data.output_policy[1]);
+        assert_eq!(value, -1.0);
+
+        let sampled = store.sample(3, &mut rand::thread_rng());
+        assert_eq!(sampled.len(), 3);
+        drop(store);
+
+        // A crash in the middle of a record leaves it out, the next writer
+        // cuts it off.
+        let whole = std::fs::metadata(&path).unwrap().len();
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(whole + 5).unwrap();
+        drop(file);
+        assert_eq!(SampleStore::open(&path).unwrap().len(), 4);
+        let mut writer =
+            SampleWriter::open(&path, [4, 2, 2], [3, 2, 2]).expect("Could not open writer");
+        writer.append(&data).expect("Could not append");
+        let store = SampleStore::open(&path).expect("Could not open store");
+        assert_eq!(store.len(), 6);
+        assert_eq!(store.get(5).expect("Missing sample").2, -1.0);
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,263 @@
+use std::{fmt::Write, fs, io, path::Path, time::Duration};
+
+use crate::{action::BoardAction, board::MoveResult, player::Player, rules::GameRules, BoardState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    pub remaining: Duration,
+    pub increment: Duration,
+}
+
+impl Clock {
+    pub fn new(remaining: Duration, increment: Duration) -> Self {
+        Self {
+            remaining,
+            increment,
+        }
+    }
+
+    pub fn is_flagged(&self) -> bool {
+        self.remaining.is_zero()
+    }
+
+    fn spend(&mut self, elapsed: Duration) {
+        self.remaining = self.remaining.saturating_sub(elapsed);
+        if !self.is_flagged() {
+            self.remaining += self.increment;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clocks {
+    pub player_1: Clock,
+    pub player_2: Clock,
+}
+
+impl Clocks {
+    pub fn new(clock: Clock) -> Self {
+        Self {
+            player_1: clock,
+            player_2: clock,
+        }
+    }
+
+    pub fn get(&self, player: Player) -> &Clock {
+        match player {
+            Player::Player1 => &self.player_1,
+            Player::Player2 => &self.player_2,
+        }
+    }
+
+    pub fn get_mut(&mut self, player: Player) -> &mut Clock {
+        match player {
+            Player::Player1 => &mut self.player_1,
+            Player::Player2 => &mut self.player_2,
+        }
+    }
+}
+
+// A game in progress that can be stored on disk and picked up again later.
+// The moves are the undo history of the state.
+#[derive(Debug, Clone)]
+pub struct GameSession {
+    state: BoardState,
+    clocks: Option<Clocks>,
+}
+
+impl Default for GameSession {
+    fn default() -> Self {
+        Self::new(GameRules::default())
+    }
+}
+
+impl GameSession {
+    pub fn new(rules: GameRules) -> Self {
+        Self {
+            state: BoardState::new(rules),
+            clocks: None,
+        }
+    }
+
+    pub fn with_clocks(rules: GameRules, clocks: Clocks) -> Self {
+        Self {
+            clocks: Some(clocks),
+            ..Self::new(rules)
+        }
+    }
+
+    pub fn state(&self) -> &BoardState {
+        &self.state
+    }
+
+    pub fn rules(&self) -> &GameRules {
+        self.state.rules()
+    }
+
+    // The actions played, oldest first.
+    pub fn history(&self) -> Vec<BoardAction> {
+        self.state.history().copied().collect()
+    }
+
+    pub fn clocks(&self) -> Option<&Clocks> {
+        self.clocks.as_ref()
+    }
+
+    pub fn play(&mut self, mov: &BoardAction) -> Vec<MoveResult> {
+        self.state.play(mov)
+    }
+
+    // Takes back the last action. Time spent on the clocks is not given back.
+    pub fn undo(&mut self) -> Option<BoardAction> {
+        self.state.undo()
+    }
+
+    // Plays the action and charges the time it took to the mover's clock.
+    pub fn play_timed(&mut self, mov: &BoardAction, elapsed: Duration) -> Vec<MoveResult> {
+        let player = self.state.current_player;
+        if let Some(clocks) = self.clocks.as_mut() {
+            clocks.get_mut(player).spend(elapsed);
+        }
+        self.play(mov)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut content = String::new();
+        writeln!(content, "rules {}", self.rules()).expect("Could not format rules");
+        if let Some(clocks) = &self.clocks {
+            for (name, clock) in [("1", clocks.player_1), ("2", clocks.player_2)] {
+                writeln!(
+                    content,
+                    "clock {} {} {}",
+                    name,
+                    clock.remaining.as_millis(),
+                    clock.increment.as_millis()
+                )
+                .expect("Could not format clock");
+            }
+        }
+        for mov in self.state.history() {
+            writeln!(content, "move {}", mov).expect("Could not format move");
+        }
+
+        fs::write(path, content)
+    }
+
+    // Rebuilds the session by replaying the stored moves under the stored rules.
+    pub fn resume<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid session line: {}", line),
+            )
+        };
+
+        let mut rules = GameRules::default();
+        let mut clocks: Option<Clocks> = None;
+        let mut moves = Vec::new();
+
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, value) = line.split_once(' ').ok_or_else(|| invalid(line))?;
+            match key {
+                "rules" => rules = GameRules::parse(value).ok_or_else(|| invalid(line))?,
+                "clock" => {
+                    let fields = value
+                        .split_whitespace()
+                        .map(|f| f.parse::<u64>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| invalid(line))?;
+                    let (player, remaining, increment) = match fields[..] {
+                        [1, remaining, increment] => (Player::Player1, remaining, increment),
+                        [2, remaining, increment] => (Player::Player2, remaining, increment),
+                        _ => return Err(invalid(line)),
+                    };
+                    let clock = Clock::new(
+                        Duration::from_millis(remaining),
+                        Duration::from_millis(increment),
+                    );
+                    *clocks.get_or_insert(Clocks::new(clock)).get_mut(player) = clock;
+                }
+                "move" => moves.push(value.to_string()),
+                _ => return Err(invalid(line)),
+            }
+        }
+
+        let mut session = Self::new(rules);
+        session.clocks = clocks;
+        for mov in moves {
+            let action = BoardAction::parse(&mov, session.state.current_player)
+                .ok_or_else(|| invalid(&mov))?;
+            session
+                .state
+                .try_make_move(&action)
+                .map_err(|e| invalid(&format!("{} ({})", mov, e)))?;
+        }
+
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        action::BoardAction,
+        player::Player,
+        rules::{GameRules, ResolutionPolicy},
+        testing::{col, temp_path},
+    };
+    use mcts::GameState;
+
+    use super::{Clock, Clocks, GameSession};
+
+    #[test]
+    fn save_and_resume() {
+        let rules = GameRules {
+            resolution: ResolutionPolicy::BottomUp,
+            ..Default::default()
+        };
+        let clock = Clock::new(Duration::from_secs(60), Duration::from_secs(1));
+        let mut session = GameSession::with_clocks(rules, Clocks::new(clock));
+
+        session.play_timed(
+            &BoardAction::DropStone(Player::Player1, col(3)),
+            Duration::from_secs(5),
+        );
+        session.play_timed(
+            &BoardAction::DropStone(Player::Player2, col(4)),
+            Duration::from_secs(2),
+        );
+
+        let path = temp_path("session_test.session");
+        session.save(&path).expect("Could not save session");
+        let resumed = GameSession::resume(&path).expect("Could not resume session");
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(resumed.rules(), &rules);
+        assert_eq!(resumed.history(), session.history());
+        assert_eq!(resumed.clocks(), session.clocks());
+        assert_eq!(
+            resumed.clocks().unwrap().player_1.remaining,
+            Duration::from_secs(56)
+        );
+        assert_eq!(resumed.state().current_player(), Player::Player1);
+    }
+
+    #[test]
+    fn refuse_illegal_moves() {
+        let path = temp_path("session_illegal_test.session");
+        std::fs::write(
+            &path,
+            "rules size=5x3\nmove d1\nmove d1\nmove d1\nmove d1\n",
+        )
+        .unwrap();
+        let resumed = GameSession::resume(&path);
+        std::fs::remove_file(&path).ok();
+        assert_eq!(
+            resumed.map(|_| ()).map_err(|e| e.kind()),
+            Err(std::io::ErrorKind::InvalidData)
+        );
+    }
+}
@@ -0,0 +1,316 @@
+// MCTS with solver-style backups: terminal results are propagated up the tree
+// as proven wins and losses, so solved subtrees are no longer searched.
+//
+// The mcts crate does not let us hook into its backpropagation, so the
+// solver keeps its own single threaded tree.
+use std::{fmt::Display, time::Instant};
+
+use rand::Rng;
+
+use crate::{
+    action::BoardAction,
+    player::Player,
+    rollout::{rollout, RolloutPolicy},
+    timecontrol::Budget,
+    BoardState,
+};
+use mcts::GameState;
+
+// A game theoretic value for the player to move, in plies until the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Proof {
+    Win(usize),
+    Loss(usize),
+    Draw,
+}
+
+impl Proof {
+    fn value(&self) -> f64 {
+        match self {
+            Proof::Win(_) => 1.0,
+            Proof::Loss(_) => -1.0,
+            Proof::Draw => 0.0,
+        }
+    }
+}
+
+impl Display for Proof {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Proof::Win(plies) => write!(f, "win in {}", plies),
+            Proof::Loss(plies) => write!(f, "loss in {}", plies),
+            Proof::Draw => write!(f, "draw"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SolverResult {
+    pub best_move: Option<BoardAction>,
+    pub proof: Option<Proof>,
+    // Expected value of the best move for the player to move.
+    pub value: f64,
+    pub playouts: usize,
+}
+
+struct Node {
+    mov: Option<BoardAction>,
+    children: Vec<usize>,
+    unexpanded: Vec<BoardAction>,
+    visits: u32,
+    // Sum of the values seen from the point of view of the parent's player.
+    total: f64,
+    proof: Option<Proof>,
+}
+
+impl Node {
+    fn new(mov: Option<BoardAction>, state: &BoardState) -> Self {
+        Self {
+            mov,
+            children: Vec::new(),
+            unexpanded: state.moves().collect(),
+            visits: 0,
+            total: 0.0,
+            proof: None,
+        }
+    }
+}
+
+pub struct MctsSolver<P> {
+    pub exploration: f64,
+    pub policy: P,
+}
+
+impl<P: RolloutPolicy> MctsSolver<P> {
+    pub fn new(exploration: f64, policy: P) -> Self {
+        Self {
+            exploration,
+            policy,
+        }
+    }
+
+    // Runs playouts until the budget is spent or the root is solved, at
+    // least one so there is a move to play.
+    pub fn search<R: Rng>(&self, state: &BoardState, budget: Budget, rng: &mut R) -> SolverResult {
+        let start = Instant::now();
+        let spent = |done: usize| match budget {
+            Budget::Playouts(playouts) => done >= playouts.max(1),
+            Budget::Time(time) => done > 0 && start.elapsed() >= time,
+        };
+        let mut tree = vec![Node::new(None, state)];
+        let mut done = 0;
+        while !spent(done) && tree[0].proof.is_none() {
+            self.playout(&mut tree, 0, &mut state.clone(), rng);
+            done += 1;
+        }
+
+        let root = &tree[0];
+        let best = match root.proof {
+            // Pick the quickest win, or the slowest loss.
+            Some(Proof::Win(_)) => root
+                .children
+                .iter()
+                .filter_map(|&c| match tree[c].proof {
+                    Some(Proof::Loss(n)) => Some((c, n)),
+                    _ => None,
+                })
+                .min_by_key(|&(_, n)| n)
+                .map(|(c, _)| c),
+            Some(Proof::Loss(_)) => root
+                .children
+                .iter()
+                .filter_map(|&c| match tree[c].proof {
+                    Some(Proof::Win(n)) => Some((c, n)),
+                    _ => None,
+                })
+                .max_by_key(|&(_, n)| n)
+                .map(|(c, _)| c),
+            _ => root
+                .children
+                .iter()
+                .copied()
+                .filter(|&c| !matches!(tree[c].proof, Some(Proof::Win(_))))
+                .max_by_key(|&c| tree[c].visits)
+                .or_else(|| root.children.first().copied()),
+        };
+
+        SolverResult {
+            best_move: best.and_then(|c| tree[c].mov),
+            proof: root.proof,
+            value: best.map_or(0.0, |c| match tree[c].proof {
+                Some(proof) => -proof.value(),
+                None => tree[c].total / tree[c].visits.max(1) as f64,
+            }),
+            playouts: done,
+        }
+    }
+
+    // Returns the value of the playout for the player to move at the node.
+    fn playout<R: Rng>(
+        &self,
+        tree: &mut Vec<Node>,
+        index: usize,
+        state: &mut BoardState,
+        rng: &mut R,
+    ) -> f64 {
+        if let Some(proof) = tree[index].proof {
+            return proof.value();
+        }
+        if state.is_terminal() {
+            let proof = terminal_proof(state);
+            tree[index].proof = Some(proof);
+            return proof.value();
+        }
+
+        let value = if let Some(mov) = tree[index].unexpanded.pop() {
+            state.make_move(&mov);
+            let mut child = Node::new(Some(mov), state);
+            let value = if state.is_terminal() {
+                let proof = terminal_proof(state);
+                child.proof = Some(proof);
+                -proof.value()
+            } else {
+                match rollout(state, &self.policy, rng) {
+                    Some(winner) if winner == state.current_player() => -1.0,
+                    Some(_) => 1.0,
+                    None => 0.0,
+                }
+            };
+            child.visits = 1;
+            child.total = value;
+            tree.push(child);
+            let child = tree.len() - 1;
+            tree[index].children.push(child);
+            value
+        } else {
+            let child = self.select(tree, index);
+            let mov = tree[child].mov.expect("Child without a move");
+            state.make_move(&mov);
+            let value = -self.playout(tree, child, state, rng);
+            tree[child].visits += 1;
+            tree[child].total += value;
+            value
+        };
+
+        self.update_proof(tree, index);
+        match tree[index].proof {
+            Some(proof) => proof.value(),
+            None => value,
+        }
+    }
+
+    // UCT over the children that are not already proven wins for the opponent.
+    fn select(&self, tree: &[Node], index: usize) -> usize {
+        let node = &tree[index];
+        let parent_visits = node.children.iter().map(|&c| tree[c].visits).sum::<u32>();
+        let log = (parent_visits.max(1) as f64).ln();
+
+        let candidates = node
+            .children
+            .iter()
+            .copied()
+            .filter(|&c| !matches!(tree[c].proof, Some(Proof::Win(_))))
+            .collect::<Vec<_>>();
+        let candidates = if candidates.is_empty() {
+            node.children.clone()
+        } else {
+            candidates
+        };
+
+        candidates
+            .into_iter()
+            .max_by(|&a, &b| {
+                let uct = |c: usize| {
+                    let child = &tree[c];
+                    let visits = child.visits.max(1) as f64;
+                    child.total / visits + self.exploration * (log / visits).sqrt()
+                };
+                uct(a)
+                    .partial_cmp(&uct(b))
+                    .expect("Could not compare UCT values")
+            })
+            .expect("Node without children")
+    }
+
+    fn update_proof(&self, tree: &mut [Node], index: usize) {
+        let node = &tree[index];
+
+        let quickest_win = node
+            .children
+            .iter()
+            .filter_map(|&c| match tree[c].proof {
+                Some(Proof::Loss(n)) => Some(n),
+                _ => None,
+            })
+            .min();
+        if let Some(n) = quickest_win {
+            tree[index].proof = Some(Proof::Win(n + 1));
+            return;
+        }
+
+        if !node.unexpanded.is_empty() || node.children.iter().any(|&c| tree[c].proof.is_none()) {
+            return;
+        }
+
+        let proofs = node
+            .children
+            .iter()
+            .filter_map(|&c| tree[c].proof)
+            .collect::<Vec<_>>();
+        tree[index].proof = if proofs.contains(&Proof::Draw) {
+            Some(Proof::Draw)
+        } else {
+            proofs
+                .iter()
+                .filter_map(|proof| match proof {
+                    Proof::Win(n) => Some(n + 1),
+                    _ => None,
+                })
+                .max()
+                .map(Proof::Loss)
+        };
+    }
+}
+
+fn terminal_proof(state: &BoardState) -> Proof {
+    let player: Player = state.current_player();
+    match state.get_winner() {
+        Some(winner) if winner == player => Proof::Win(0),
+        Some(_) => Proof::Loss(0),
+        None => Proof::Draw,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{BoardAction, Column},
+        player::Player,
+        rollout::UniformPolicy,
+        testing::StateBuilder,
+        timecontrol::Budget,
+    };
+
+    use super::{MctsSolver, Proof};
+
+    #[test]
+    fn proves_immediate_win() {
+        let empty = "        ";
+        let state = StateBuilder::new()
+            .board([empty, empty, empty, empty, empty, empty, empty, "XX XO   "])
+            .build();
+
+        let solver = MctsSolver::new(1.4, UniformPolicy);
+        let result = solver.search(&state, Budget::Playouts(1000), &mut rand::thread_rng());
+
+        assert_eq!(result.proof, Some(Proof::Win(1)));
+        assert_eq!(
+            result.best_move,
+            Some(BoardAction::DropStone(
+                Player::Player1,
+                Column::new(2).unwrap()
+            ))
+        );
+        assert_eq!(Proof::Win(1).to_string(), "win in 1");
+    }
+}
@@ -0,0 +1,233 @@
+use std::{collections::BTreeMap, fmt::Display, io, path::Path};
+
+use crate::{
+    action::BoardAction,
+    board::{MoveResult, TerminalResult},
+    opening::{self, OpeningKey},
+    player::Player,
+    record::GameRecord,
+};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Outcomes {
+    pub games: usize,
+    pub player_1_wins: usize,
+    pub player_2_wins: usize,
+    pub draws: usize,
+}
+
+impl Outcomes {
+    pub fn add(&mut self, result: &TerminalResult) {
+        self.games += 1;
+        match result {
+            TerminalResult::Win(Player::Player1) => self.player_1_wins += 1,
+            TerminalResult::Win(Player::Player2) => self.player_2_wins += 1,
+            TerminalResult::Draw => self.draws += 1,
+            TerminalResult::None => {}
+        }
+    }
+
+    pub fn win_rate(&self, player: Player) -> f64 {
+        if self.games == 0 {
+            return 0.0;
+        }
+        let wins = match player {
+            Player::Player1 => self.player_1_wins,
+            Player::Player2 => self.player_2_wins,
+        };
+        wins as f64 / self.games as f64
+    }
+}
+
+// Aggregated statistics over a set of game records.
+// A cascade is counted for every move that cleared at least one match.
+#[derive(Debug, Default, Clone)]
+pub struct GameStats {
+    pub outcomes: Outcomes,
+    pub plies: usize,
+    pub cascades: usize,
+    pub matches: usize,
+    pub switches: usize,
+    pub by_first_move: BTreeMap<String, Outcomes>,
+    pub by_opening: BTreeMap<OpeningKey, Outcomes>,
+}
+
+impl GameStats {
+    pub fn from_records<'a, I: IntoIterator<Item = &'a GameRecord>>(records: I) -> Self {
+        let mut stats = Self::default();
+        for record in records {
+            stats.add(record);
+        }
+        stats
+    }
+
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        Ok(Self::from_records(&GameRecord::load_dir(dir)?))
+    }
+
+    pub fn add(&mut self, record: &GameRecord) {
+        let (_, results) = record.replay();
+        let result = record.result();
+
+        self.outcomes.add(&result);
+        self.plies += record.moves.len();
+        self.switches += record
+            .moves
+            .iter()
+            .filter(|m| matches!(m, BoardAction::SwitchStone(_, _)))
+            .count();
+
+        for move_results in &results {
+            let matches = move_results
+                .iter()
+                .filter(|r| matches!(r, MoveResult::Three(_)))
+                .count();
+            self.matches += matches;
+            if matches > 0 {
+                self.cascades += 1;
+            }
+        }
+
+        if let Some(first) = record.moves.first() {
+            self.by_first_move
+                .entry(first.to_string())
+                .or_default()
+                .add(&result);
+        }
+
+        self.by_opening
+            .entry(opening::classify(
+                &record.moves,
+                opening::OPENING_PLIES,
+                record.start.board().width(),
+            ))
+            .or_default()
+            .add(&result);
+    }
+
+    pub fn games(&self) -> usize {
+        self.outcomes.games
+    }
+
+    pub fn average_length(&self) -> f64 {
+        self.per_game(self.plies)
+    }
+
+    pub fn average_cascades(&self) -> f64 {
+        self.per_game(self.cascades)
+    }
+
+    pub fn average_matches(&self) -> f64 {
+        self.per_game(self.matches)
+    }
+
+    pub fn switch_frequency(&self) -> f64 {
+        if self.plies == 0 {
+            0.0
+        } else {
+            self.switches as f64 / self.plies as f64
+        }
+    }
+
+    fn per_game(&self, total: usize) -> f64 {
+        if self.games() == 0 {
+            0.0
+        } else {
+            total as f64 / self.games() as f64
+        }
+    }
+}
+
+impl Display for GameStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Games: {}", self.games())?;
+        writeln!(
+            f,
+            "Results: p1 {} / p2 {} / draw {}",
+            self.outcomes.player_1_wins, self.outcomes.player_2_wins, self.outcomes.draws
+        )?;
+        writeln!(f, "Average game length: {:.2}", self.average_length())?;
+        writeln!(
+            f,
+            "Average cascades per game: {:.2}",
+            self.average_cascades()
+        )?;
+        writeln!(f, "Average matches per game: {:.2}", self.average_matches())?;
+        writeln!(f, "Switch frequency: {:.3}", self.switch_frequency())?;
+        writeln!(f, "Win rate by first move (p1 / p2):")?;
+        for (mov, outcomes) in &self.by_first_move {
+            writeln!(
+                f,
+                "  {:<10} {:>5} games  {:.3} / {:.3}",
+                mov,
+                outcomes.games,
+                outcomes.win_rate(Player::Player1),
+                outcomes.win_rate(Player::Player2)
+            )?;
+        }
+        writeln!(f, "Win rate by opening (p1 / p2):")?;
+        for (opening, outcomes) in &self.by_opening {
+            writeln!(
+                f,
+                "  {:<30} {:>5} games  {:.3} / {:.3}",
+                opening.to_string(),
+                outcomes.games,
+                outcomes.win_rate(Player::Player1),
+                outcomes.win_rate(Player::Player2)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::BoardAction, player::Player, record::GameRecord, rules::GameRules, testing::col,
+        BoardState,
+    };
+
+    use super::GameStats;
+
+    #[test]
+    fn aggregate_records() {
+        let cleared = GameRecord::new(
+            [0, 1, 0, 1, 0, 1, 0]
+                .iter()
+                .enumerate()
+                .map(|(i, &c)| {
+                    let player = if i % 2 == 0 {
+                        Player::Player1
+                    } else {
+                        Player::Player2
+                    };
+                    BoardAction::DropStone(player, col(c))
+                })
+                .collect(),
+        );
+        let short = GameRecord::new(vec![BoardAction::DropStone(Player::Player1, col(3))]);
+
+        let stats = GameStats::from_records(&[cleared, short]);
+
+        assert_eq!(stats.games(), 2);
+        assert_eq!(stats.plies, 8);
+        assert_eq!(stats.switches, 0);
+        // Three stones in a column clear before the fourth lands.
+        assert_eq!(stats.cascades, 2);
+        assert_eq!(stats.by_first_move["d0"].games, 1);
+        assert_eq!(stats.by_first_move["d3"].games, 1);
+        assert_eq!(stats.by_opening.len(), 2);
+    }
+
+    // The openings are mirrored across the board the games were played on.
+    #[test]
+    fn openings_on_narrow_boards() {
+        let rules = GameRules::parse("size=5x4").unwrap();
+        let game = |c| GameRecord {
+            start: BoardState::new(rules),
+            ..GameRecord::new(vec![BoardAction::DropStone(Player::Player1, col(c))])
+        };
+        let stats = GameStats::from_records(&[game(0), game(4)]);
+        assert_eq!(stats.by_opening.len(), 1);
+    }
+}
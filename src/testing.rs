@@ -0,0 +1,169 @@
+// Helpers for writing rule tests against hand-drawn positions.
+use std::{fmt::Write, path::PathBuf};
+
+use crate::{
+    action::{Column, Coordinate},
+    board::{Board, Cell},
+    player::Player,
+    rules::GameRules,
+    BoardState,
+};
+
+// Fails with both boards printed next to each other, differing cells marked.
+#[macro_export]
+macro_rules! assert_boards_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        if let Some(diff) = $crate::testing::board_diff(&$left, &$right) {
+            panic!("boards are not equal (left | right | diff):\n{}", diff);
+        }
+    };
+}
+
+// A file in the temp directory for a test, named after the process so runs
+// of the suite at the same time do not share it.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("m3c4_{}_{}", std::process::id(), name))
+}
+
+pub fn col(col: usize) -> Column {
+    Column::new(col).expect("Column out of bounds")
+}
+
+// Returns None when the boards are equal, otherwise a side by side rendering
+// of the left board, the right board and a row marking the differing cells.
+pub fn board_diff(left: &Board, right: &Board) -> Option<String> {
+    if left == right {
+        return None;
+    }
+
+    // Boards of different sizes are compared over the larger of the two.
+    let width = left.width().max(right.width());
+    let height = left.height().max(right.height());
+
+    let row = |board: &Board, y: usize| {
+        (0..width)
+            .map(|x| match board.get(coord(x, y)) {
+                Cell::Empty => ' ',
+                Cell::Filled(Player::Player1) => 'X',
+                Cell::Filled(Player::Player2) => 'O',
+            })
+            .collect::<String>()
+    };
+
+    let mut out = String::new();
+    for y in (0..height).rev() {
+        let marks = (0..width)
+            .map(|x| {
+                if left.get(coord(x, y)) == right.get(coord(x, y)) {
+                    ' '
+                } else {
+                    '^'
+                }
+            })
+            .collect::<String>();
+        writeln!(
+            out,
+            "{} |{}|  |{}|  |{}|",
+            y,
+            row(left, y),
+            row(right, y),
+            marks
+        )
+        .expect("Could not write diff");
+    }
+
+    Some(out)
+}
+
+fn coord(x: usize, y: usize) -> Coordinate {
+    Coordinate::new(x as isize, y as isize)
+}
+
+// Builds a BoardState from a drawn board, the top row first like Board::from.
+#[derive(Debug, Clone, Default)]
+pub struct StateBuilder {
+    state: BoardState,
+}
+
+impl StateBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The rules take the size of the drawn board.
+    pub fn board<const N: usize>(mut self, rows: [&str; N]) -> Self {
+        self.state.board = Board::from(rows);
+        self.state.rules.width = self.state.board.width();
+        self.state.rules.height = self.state.board.height();
+        self
+    }
+
+    pub fn points(mut self, player: Player, points: usize) -> Self {
+        match player {
+            Player::Player1 => self.state.player_1_points = points,
+            Player::Player2 => self.state.player_2_points = points,
+        }
+        self
+    }
+
+    pub fn to_move(mut self, player: Player) -> Self {
+        self.state.current_player = player;
+        self
+    }
+
+    // A board of another size than the rules is replaced by an empty one.
+    pub fn rules(mut self, rules: GameRules) -> Self {
+        if (rules.width, rules.height) != (self.state.board.width(), self.state.board.height()) {
+            self.state.board = Board::new(rules.width, rules.height);
+        }
+        self.state.rules = rules;
+        self
+    }
+
+    pub fn build(self) -> BoardState {
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{BoardAction, Column},
+        board::Board,
+        player::Player,
+    };
+    use mcts::GameState;
+
+    use super::{board_diff, StateBuilder};
+
+    #[test]
+    fn builder_and_diff() {
+        let rows = [
+            "        ", "        ", "        ", "        ", "        ", "        ", "        ",
+            "XO      ",
+        ];
+        let mut state = StateBuilder::new()
+            .board(rows)
+            .points(Player::Player2, 1)
+            .to_move(Player::Player2)
+            .build();
+        assert_eq!(state.points(Player::Player2), 1);
+        assert!(state
+            .available_moves()
+            .iter()
+            .any(|mov| matches!(mov, BoardAction::SwitchStone(_, _))));
+
+        state.make_move(&BoardAction::DropStone(
+            Player::Player2,
+            Column::new(2).unwrap(),
+        ));
+        let expected = Board::from([
+            "        ", "        ", "        ", "        ", "        ", "        ", "        ",
+            "XOO     ",
+        ]);
+        assert_boards_eq!(state.board(), &expected);
+
+        let diff = board_diff(&Board::from(rows), &expected).expect("Boards should differ");
+        assert!(diff.contains("0 |XO      |  |XOO     |  |  ^     |"));
+    }
+}
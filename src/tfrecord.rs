@@ -0,0 +1,96 @@
+// The protobuf wire format and the TFRecord framing that TensorFlow reads,
+// written by hand for the few messages the crate produces: event files for
+// TensorBoard and tf.train.Example records for tf.data.
+use std::io::{self, Write};
+
+pub(crate) fn put_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+pub(crate) fn put_key(buffer: &mut Vec<u8>, field: u64, wire_type: u64) {
+    put_varint(buffer, field << 3 | wire_type);
+}
+
+pub(crate) fn put_double(buffer: &mut Vec<u8>, field: u64, value: f64) {
+    put_key(buffer, field, 1);
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn put_bytes(buffer: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    put_key(buffer, field, 2);
+    put_varint(buffer, bytes.len() as u64);
+    buffer.extend_from_slice(bytes);
+}
+
+// A packed repeated field of floats.
+pub(crate) fn put_floats(buffer: &mut Vec<u8>, field: u64, values: &[f32]) {
+    let bytes = values
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect::<Vec<_>>();
+    put_bytes(buffer, field, &bytes);
+}
+
+// A packed repeated field of varints.
+pub(crate) fn put_varints(buffer: &mut Vec<u8>, field: u64, values: &[u64]) {
+    let mut bytes = Vec::new();
+    for &value in values {
+        put_varint(&mut bytes, value);
+    }
+    put_bytes(buffer, field, &bytes);
+}
+
+// TFRecord framing: the length, its checksum, the data and its checksum.
+pub(crate) fn write_record<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+    let length = (data.len() as u64).to_le_bytes();
+    out.write_all(&length)?;
+    out.write_all(&masked_crc(&length).to_le_bytes())?;
+    out.write_all(data)?;
+    out.write_all(&masked_crc(data).to_le_bytes())
+}
+
+pub(crate) fn masked_crc(data: &[u8]) -> u32 {
+    let crc = crc32c(data);
+    (crc.rotate_right(15)).wrapping_add(0xa282_ead8)
+}
+
+// CRC-32C (Castagnoli), bit by bit, the records are few and small.
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32c, masked_crc, put_varints, write_record};
+
+    #[test]
+    fn frame_records() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+
+        let mut message = Vec::new();
+        put_varints(&mut message, 1, &[1, 300]);
+        assert_eq!(message, [0x0a, 3, 1, 0xac, 0x02]);
+
+        let mut out = Vec::new();
+        write_record(&mut out, &message).expect("Could not write record");
+        assert_eq!(out.len(), 8 + 4 + message.len() + 4);
+        assert_eq!(out[..8], 5u64.to_le_bytes());
+        assert_eq!(out[8..12], masked_crc(&out[..8]).to_le_bytes());
+        assert_eq!(out[12..17], message[..]);
+    }
+}
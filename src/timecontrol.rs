@@ -0,0 +1,249 @@
+// How long a search runs: a number of playouts, a fixed time per move or a
+// share of the time left on a game clock.
+use std::time::{Duration, Instant};
+
+use mcts::{GameState, MCTSManager, ThreadData, MCTS};
+use tracing::{debug, debug_span};
+
+use crate::{session::Clock, BoardState};
+
+// A search that reports its progress runs a number of playouts in this many
+// parts, and a time in parts of at most this long.
+const PROGRESS_STEPS: usize = 10;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchLimit {
+    Playouts(usize),
+    MoveTime(Duration),
+    // The time of every move is taken from the clock by the allocator.
+    Clock(Clock, TimeAllocator),
+}
+
+impl SearchLimit {
+    // What the search of the position may spend.
+    pub fn budget(&self, state: &BoardState) -> Budget {
+        match self {
+            SearchLimit::Playouts(playouts) => Budget::Playouts(*playouts),
+            SearchLimit::MoveTime(time) => Budget::Time(*time),
+            SearchLimit::Clock(clock, allocator) => Budget::Time(allocator.allocate(clock, state)),
+        }
+    }
+
+    // The same limit for the mover's clock as it is now, other limits do not
+    // change.
+    pub fn with_clock(self, clock: Clock) -> Self {
+        match self {
+            SearchLimit::Clock(_, allocator) => SearchLimit::Clock(clock, allocator),
+            limit => limit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Budget {
+    Playouts(usize),
+    Time(Duration),
+}
+
+impl Budget {
+    // Runs the search until the budget is spent, in parallel when there are
+    // threads to spare. There is always at least one playout, so the search
+    // has a move even when the clock is out.
+    pub fn search<Spec: MCTS>(&self, manager: &mut MCTSManager<Spec>, threads: usize)
+    where
+        ThreadData<Spec>: Default,
+    {
+        let _span = debug_span!("search", budget = ?self, threads).entered();
+        let start = Instant::now();
+        match *self {
+            Budget::Playouts(playouts) if threads > 1 => {
+                manager.playout_n_parallel(playouts.max(1) as u32, threads)
+            }
+            Budget::Playouts(playouts) => manager.playout_n(playouts.max(1)),
+            Budget::Time(time) if time.is_zero() => manager.playout_n(1),
+            Budget::Time(time) if threads > 1 => manager.playout_parallel_for(time, threads),
+            Budget::Time(time) => manager.playout_until(|| start.elapsed() >= time),
+        }
+        debug!(ms = start.elapsed().as_millis() as u64, "Searched");
+    }
+
+    // The same search in parts, calling `progress` after every part so the
+    // search can be followed while it runs.
+    pub fn search_with_progress<Spec: MCTS, F>(
+        &self,
+        manager: &mut MCTSManager<Spec>,
+        threads: usize,
+        mut progress: F,
+    ) where
+        ThreadData<Spec>: Default,
+        F: FnMut(&MCTSManager<Spec>),
+    {
+        for part in self.parts() {
+            part.search(manager, threads);
+            progress(manager);
+        }
+    }
+
+    // The parts of a search that reports its progress, at least one.
+    pub fn parts(&self) -> Vec<Budget> {
+        match *self {
+            Budget::Playouts(playouts) => {
+                let playouts = playouts.max(1);
+                let step = playouts.div_ceil(PROGRESS_STEPS);
+                (0..playouts)
+                    .step_by(step)
+                    .map(|done| Budget::Playouts(step.min(playouts - done)))
+                    .collect()
+            }
+            Budget::Time(time) => {
+                let mut parts = vec![Budget::Time(time.min(PROGRESS_INTERVAL))];
+                let mut left = time - time.min(PROGRESS_INTERVAL);
+                while !left.is_zero() {
+                    let part = left.min(PROGRESS_INTERVAL);
+                    parts.push(Budget::Time(part));
+                    left -= part;
+                }
+                parts
+            }
+        }
+    }
+}
+
+// Splits the time on a clock over the moves that are likely left. Positions
+// in the middle of the game get more than the rest: the opening has few
+// different moves and on a nearly full board there is little left to decide.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeAllocator {
+    // Moves the clock is planned to last at least.
+    pub min_moves_left: usize,
+    // Kept back for the time the move takes to reach the board.
+    pub overhead: Duration,
+    // Most of the remaining time a single move may take.
+    pub max_share: f64,
+}
+
+impl Default for TimeAllocator {
+    fn default() -> Self {
+        Self {
+            min_moves_left: 10,
+            overhead: Duration::from_millis(50),
+            max_share: 0.3,
+        }
+    }
+}
+
+impl TimeAllocator {
+    pub fn allocate(&self, clock: &Clock, state: &BoardState) -> Duration {
+        let available = clock.remaining.saturating_sub(self.overhead);
+        if available.is_zero() {
+            return Duration::ZERO;
+        }
+
+        // Both players fill about half of the empty cells.
+        let board = state.board();
+        let cells = board.width() * board.height();
+        let stones = board.stone_count();
+        let moves_left = ((cells - stones) / 2).max(self.min_moves_left);
+        let base = available.as_secs_f64() / moves_left as f64 + clock.increment.as_secs_f64();
+
+        // Highest with a third of the board filled, a third of that on an
+        // empty or a full board.
+        let fill = stones as f64 / cells as f64;
+        let phase = 1.5 - ((fill - 1.0 / 3.0).abs() * 3.0).min(1.0);
+        // Switches make for more moves to look at than the drops alone.
+        let branching =
+            (state.available_moves().len() as f64 / board.width() as f64).clamp(1.0, 2.0);
+
+        let time = (base * phase * branching).min(available.as_secs_f64() * self.max_share);
+        Duration::from_secs_f64(time)
+    }
+}
+
+// A clock written as minutes and an optional increment in seconds, "5+3".
+pub fn parse_clock(s: &str) -> Option<Clock> {
+    let (minutes, increment) = s.split_once('+').unwrap_or((s, "0"));
+    let minutes = minutes.trim().parse::<f64>().ok().filter(|m| *m > 0.0)?;
+    let increment = increment.trim().parse::<f64>().ok().filter(|i| *i >= 0.0)?;
+    Some(Clock::new(
+        Duration::from_secs_f64(minutes * 60.0),
+        Duration::from_secs_f64(increment),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        agent::{Agent, SolverAgent},
+        heuristic::{HeuristicEvaluator, HeuristicMCTS},
+        session::Clock,
+        testing::StateBuilder,
+        BoardState,
+    };
+
+    use super::{parse_clock, Budget, SearchLimit, TimeAllocator};
+
+    #[test]
+    fn allocate_time() {
+        assert_eq!(
+            parse_clock("5+3"),
+            Some(Clock::new(Duration::from_secs(300), Duration::from_secs(3)))
+        );
+        assert_eq!(
+            parse_clock("0.5").map(|clock| clock.remaining),
+            Some(Duration::from_secs(30))
+        );
+        assert!(parse_clock("0").is_none());
+        assert!(parse_clock("5+").is_none());
+
+        let allocator = TimeAllocator::default();
+        let clock = Clock::new(Duration::from_secs(60), Duration::ZERO);
+        let empty = "        ";
+        let opening = BoardState::default();
+        let middlegame = StateBuilder::new()
+            .board([
+                empty, empty, empty, empty, empty, "  O  X  ", "OOXXOOXX", "XXOOXXOO",
+            ])
+            .build();
+        let opening_time = allocator.allocate(&clock, &opening);
+        let middlegame_time = allocator.allocate(&clock, &middlegame);
+        assert!(opening_time > Duration::ZERO);
+        assert!(middlegame_time > opening_time);
+        assert!(middlegame_time <= Duration::from_secs(18));
+
+        // Almost out of time the move may not flag.
+        let short = Clock::new(Duration::from_millis(400), Duration::ZERO);
+        assert!(allocator.allocate(&short, &middlegame) < Duration::from_millis(350));
+        let flagged = Clock::new(Duration::from_millis(20), Duration::ZERO);
+        assert_eq!(allocator.allocate(&flagged, &opening), Duration::ZERO);
+
+        let limit = SearchLimit::Clock(clock, allocator);
+        assert_eq!(limit.budget(&opening), Budget::Time(opening_time));
+        assert_eq!(
+            SearchLimit::Playouts(100).with_clock(short),
+            SearchLimit::Playouts(100)
+        );
+    }
+
+    #[test]
+    fn out_of_time_still_searches() {
+        assert_eq!(
+            Budget::Time(Duration::ZERO).parts(),
+            [Budget::Time(Duration::ZERO)]
+        );
+        assert_eq!(Budget::Playouts(0).parts(), [Budget::Playouts(1)]);
+        assert_eq!(Budget::Playouts(25).parts().len(), 9);
+
+        let state = BoardState::default();
+        let mut manager =
+            HeuristicMCTS::create_search(state.clone(), HeuristicEvaluator::default(), 1.4);
+        Budget::Time(Duration::ZERO).search(&mut manager, 1);
+        assert!(manager.best_move().is_some());
+
+        let mut solver = SolverAgent::new(10000, 1);
+        solver.set_limit(SearchLimit::MoveTime(Duration::ZERO));
+        assert!(solver.choose(&state).is_some());
+    }
+}
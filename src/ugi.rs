@@ -0,0 +1,484 @@
+// The UGI protocol, UCI for games other than chess, so GUIs, match managers
+// and test scripts can drive the engine over stdin and stdout:
+//
+//     > ugi
+//     < id name m3c4 0.1.0
+//     < ...
+//     < ugiok
+//     > position startpos moves d3 d4
+//     > go movetime 1000
+//     < info depth 8 nodes 5120 time 1000 nps 5120 score cp 12 pv d3 d2 ...
+//     < bestmove d3
+//
+// A position is `startpos` or `fen` and the notation of notation.rs, then
+// the moves played from it. `go` searches for `movetime <ms>`, `nodes
+// <playouts>` or by the clocks given as `p1time`, `p2time`, `p1inc` and
+// `p2inc` in milliseconds, and the `Playouts` option without a limit. The
+// search runs beside the input, with an info line after every part of it,
+// until its limit or `stop`. Other commands wait for it, except `isready`.
+// The score is the value of the best move for the player to move, times 100.
+//
+// A `fen` position has to be on the board of the engine, it is played with
+// the engine's rules.
+use std::{
+    io::{self, BufRead, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, ScopedJoinHandle},
+    time::{Duration, Instant},
+};
+
+use mcts::{GameState, MCTSManager, ThreadData, MCTS};
+
+use crate::{
+    action::BoardAction,
+    board::TerminalResult,
+    player::Player,
+    rules::GameRules,
+    session::Clock,
+    timecontrol::{SearchLimit, TimeAllocator},
+    BoardState,
+};
+
+// Moves of the principal variation in the info line.
+const PV_LENGTH: usize = 12;
+
+// Sets up the search of a position, fails when the engine can't search it.
+type Create<Spec> = dyn Fn(BoardState) -> Result<MCTSManager<Spec>, String> + Send + Sync;
+
+pub struct Ugi<Spec: MCTS> {
+    name: String,
+    rules: GameRules,
+    create: Arc<Create<Spec>>,
+    state: BoardState,
+    playouts: usize,
+    threads: usize,
+}
+
+impl<Spec> Ugi<Spec>
+where
+    Spec: MCTS<State = BoardState>,
+    ThreadData<Spec>: Default,
+{
+    pub fn new<F>(name: &str, rules: GameRules, create: F) -> Self
+    where
+        F: Fn(BoardState) -> Result<MCTSManager<Spec>, String> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            rules,
+            create: Arc::new(create),
+            state: BoardState::new(rules),
+            playouts: 10000,
+            threads: 1,
+        }
+    }
+
+    pub fn with_playouts(self, playouts: usize) -> Self {
+        Self { playouts, ..self }
+    }
+
+    pub fn with_threads(self, threads: usize) -> Self {
+        Self { threads, ..self }
+    }
+
+    pub fn state(&self) -> &BoardState {
+        &self.state
+    }
+
+    // Reads commands until `quit` or the end of the input. `go` searches on
+    // another thread, so `stop` is read while it runs.
+    pub fn run<R: BufRead, W: Write + Send>(&mut self, input: R, output: W) -> io::Result<()> {
+        let output = Mutex::new(output);
+        thread::scope(|scope| {
+            let mut searching: Option<(Arc<AtomicBool>, ScopedJoinHandle<'_, io::Result<()>>)> =
+                None;
+            for line in input.lines() {
+                let line = line?;
+                let command = line.split_whitespace().next().unwrap_or_default();
+                if command != "isready" {
+                    if let Some((stop, search)) = searching.take() {
+                        if matches!(command, "stop" | "quit") {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                        search.join().expect("Search panicked")?;
+                    }
+                }
+
+                if command == "go" {
+                    let limit = match self.go_limit(&line) {
+                        Ok(limit) => limit,
+                        Err(reply) => {
+                            write_lines(&output, &[reply])?;
+                            continue;
+                        }
+                    };
+                    let stop = Arc::new(AtomicBool::new(false));
+                    let (create, state) = (self.create.clone(), self.state.clone());
+                    let (threads, stopped, output) = (self.threads, stop.clone(), &output);
+                    let search = scope.spawn(move || {
+                        let mut written = Ok(());
+                        search(&*create, &state, limit, threads, &stopped, &mut |line| {
+                            if written.is_ok() {
+                                written = write_lines(output, &[line]);
+                            }
+                        });
+                        written
+                    });
+                    searching = Some((stop, search));
+                    continue;
+                }
+
+                match self.handle(&line) {
+                    Some(replies) => write_lines(&output, &replies)?,
+                    None => break,
+                }
+            }
+            match searching {
+                Some((_, search)) => search.join().expect("Search panicked"),
+                None => Ok(()),
+            }
+        })
+    }
+
+    // The lines to answer a command with, None for `quit`. Commands the
+    // engine does not know are answered with an info string. `go` searches
+    // until its limit before it answers.
+    pub fn handle(&mut self, line: &str) -> Option<Vec<String>> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Some(Vec::new()),
+        };
+        let replies = match command {
+            "ugi" => vec![
+                format!("id name {} {}", self.name, env!("CARGO_PKG_VERSION")),
+                String::from("id author BlockCat"),
+                format!(
+                    "option name Playouts type spin default {} min 1 max 100000000",
+                    self.playouts
+                ),
+                format!(
+                    "option name Threads type spin default {} min 1 max 256",
+                    self.threads
+                ),
+                String::from("ugiok"),
+            ],
+            "isready" => vec![String::from("readyok")],
+            "uginewgame" | "newgame" => {
+                self.state = BoardState::new(self.rules);
+                Vec::new()
+            }
+            "position" => self.position(args).err().into_iter().collect(),
+            "go" => match self.go_limit(line) {
+                Ok(limit) => {
+                    let mut replies = Vec::new();
+                    let stop = AtomicBool::new(false);
+                    search(
+                        &*self.create,
+                        &self.state,
+                        limit,
+                        self.threads,
+                        &stop,
+                        &mut |line| replies.push(line),
+                    );
+                    replies
+                }
+                Err(reply) => vec![reply],
+            },
+            "setoption" => self.set_option(args).err().into_iter().collect(),
+            "query" => vec![self.query(args)],
+            "stop" => Vec::new(),
+            "quit" => return None,
+            _ => vec![format!("info string unknown command: {}", command)],
+        };
+        Some(replies)
+    }
+
+    fn position(&mut self, args: &[&str]) -> Result<(), String> {
+        let moves_at = args
+            .iter()
+            .position(|&word| word == "moves")
+            .unwrap_or(args.len());
+        let mut state = match &args[..moves_at] {
+            ["startpos"] => BoardState::new(self.rules),
+            ["fen", notation @ ..] => {
+                let state = BoardState::parse_notation(&notation.join(" "))
+                    .ok_or_else(|| format!("info string bad position: {}", notation.join(" ")))?;
+                let size = (state.board().width(), state.board().height());
+                if size != (self.rules.width, self.rules.height) {
+                    return Err(format!(
+                        "info string the engine plays on a {}x{} board, not {}x{}",
+                        self.rules.width, self.rules.height, size.0, size.1
+                    ));
+                }
+                state.with_rules(self.rules)
+            }
+            _ => return Err(String::from("info string expected startpos or fen")),
+        };
+
+        for word in args.iter().skip(moves_at + 1) {
+            let mov = BoardAction::parse(word, state.current_player())
+                .ok_or_else(|| format!("info string bad move: {}", word))?;
+            if let Some(reason) = state.explain_illegal(&mov) {
+                return Err(format!("info string illegal move {}: {}", word, reason));
+            }
+            state.play(&mov);
+        }
+        self.state = state;
+        Ok(())
+    }
+
+    fn go_limit(&self, line: &str) -> Result<SearchLimit, String> {
+        let args = line.split_whitespace().skip(1).collect::<Vec<_>>();
+        match parse_go(&args, &self.state) {
+            Some(limit) => Ok(limit.unwrap_or(SearchLimit::Playouts(self.playouts))),
+            None => Err(format!("info string bad go command: {}", line.trim())),
+        }
+    }
+
+    // setoption name <name> value <value>
+    fn set_option(&mut self, args: &[&str]) -> Result<(), String> {
+        let (name, value) = match args {
+            ["name", name, "value", value] => (*name, *value),
+            _ => return Err(String::from("info string expected name and value")),
+        };
+        let number = value
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| format!("info string bad value for {}: {}", name, value))?;
+        match name.to_ascii_lowercase().as_str() {
+            "playouts" => self.playouts = number,
+            "threads" => self.threads = number,
+            _ => return Err(format!("info string unknown option: {}", name)),
+        }
+        Ok(())
+    }
+
+    fn query(&self, args: &[&str]) -> String {
+        let state = &self.state;
+        let response = match args {
+            ["gameover"] => state.is_terminal().to_string(),
+            ["p1turn"] => (state.current_player() == Player::Player1).to_string(),
+            ["result"] => String::from(match state.result() {
+                TerminalResult::Win(Player::Player1) => "p1win",
+                TerminalResult::Win(Player::Player2) => "p2win",
+                TerminalResult::Draw => "draw",
+                TerminalResult::None => "none",
+            }),
+            _ => return format!("info string unknown query: {}", args.join(" ")),
+        };
+        format!("response {}", response)
+    }
+}
+
+fn write_lines<W: Write>(output: &Mutex<W>, lines: &[String]) -> io::Result<()> {
+    let mut output = output.lock().expect("Output lock poisoned");
+    for line in lines {
+        writeln!(output, "{}", line)?;
+    }
+    output.flush()
+}
+
+// Searches the position in parts with an info line after each, until the
+// limit or until `stop` is set, and ends with the best move.
+fn search<Spec>(
+    create: &Create<Spec>,
+    state: &BoardState,
+    limit: SearchLimit,
+    threads: usize,
+    stop: &AtomicBool,
+    emit: &mut dyn FnMut(String),
+) where
+    Spec: MCTS<State = BoardState>,
+    ThreadData<Spec>: Default,
+{
+    if state.is_terminal() {
+        return emit(String::from("bestmove 0000"));
+    }
+    let mut manager = match create(state.clone()) {
+        Ok(manager) => manager,
+        Err(e) => {
+            emit(format!("info string {}", e));
+            return emit(String::from("bestmove 0000"));
+        }
+    };
+
+    let start = Instant::now();
+    for part in limit.budget(state).parts() {
+        part.search(&mut manager, threads);
+        emit(info(&manager, start.elapsed()));
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+    let best = manager
+        .best_move()
+        .map_or_else(|| String::from("0000"), |mov| mov.to_string());
+    emit(format!("bestmove {}", best));
+}
+
+fn info<Spec: MCTS<State = BoardState>>(manager: &MCTSManager<Spec>, elapsed: Duration) -> String {
+    let elapsed = elapsed.as_millis() as u64;
+    let root = manager.tree().root_node();
+    let nodes = root.moves().map(|m| m.visits()).sum::<u64>();
+    let best = manager.best_move();
+    let value = root
+        .moves()
+        .find(|m| Some(*m.get_move()) == best)
+        .filter(|m| m.visits() > 0)
+        .map_or(0.0, |m| m.sum_rewards() / m.visits() as f64);
+    let pv = manager
+        .principal_variation(PV_LENGTH)
+        .iter()
+        .map(|mov| mov.to_string())
+        .collect::<Vec<_>>();
+
+    format!(
+        "info depth {} nodes {} time {} nps {} score cp {} pv {}",
+        pv.len(),
+        nodes,
+        elapsed,
+        nodes * 1000 / elapsed.max(1),
+        (value * 100.0).round() as i64,
+        pv.join(" ")
+    )
+}
+
+// The limit of `go`, None inside when there is none and the default applies.
+// The clock of the player to move counts, the other one is ignored.
+fn parse_go(args: &[&str], state: &BoardState) -> Option<Option<SearchLimit>> {
+    let (time, increment) = match state.current_player() {
+        Player::Player1 => ("p1time", "p1inc"),
+        Player::Player2 => ("p2time", "p2inc"),
+    };
+    let mut limit = None;
+    let mut clock = None;
+    let mut clock_increment = Duration::ZERO;
+    for pair in args.chunks(2) {
+        let (name, value) = match pair {
+            [name, value] => (*name, value.parse::<u64>().ok()?),
+            _ => return None,
+        };
+        match name {
+            "movetime" => limit = Some(SearchLimit::MoveTime(Duration::from_millis(value))),
+            "nodes" => limit = Some(SearchLimit::Playouts(value as usize)),
+            name if name == time => clock = Some(Duration::from_millis(value)),
+            name if name == increment => clock_increment = Duration::from_millis(value),
+            "p1time" | "p2time" | "p1inc" | "p2inc" => {}
+            _ => return None,
+        }
+    }
+    Some(limit.or_else(|| {
+        clock.map(|remaining| {
+            SearchLimit::Clock(
+                Clock::new(remaining, clock_increment),
+                TimeAllocator::default(),
+            )
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::BoardAction,
+        heuristic::{HeuristicEvaluator, HeuristicMCTS},
+        player::Player,
+        rules::GameRules,
+    };
+    use mcts::GameState;
+    use std::time::{Duration, Instant};
+
+    use super::Ugi;
+
+    fn engine() -> Ugi<HeuristicMCTS> {
+        Ugi::new("m3c4", GameRules::default(), |state| {
+            Ok(HeuristicMCTS::create_search(
+                state,
+                HeuristicEvaluator::default(),
+                1.4,
+            ))
+        })
+        .with_playouts(50)
+    }
+
+    #[test]
+    fn handshake_and_queries() {
+        let mut ugi = engine();
+        let replies = ugi.handle("ugi").unwrap();
+        assert!(replies[0].starts_with("id name m3c4"));
+        assert_eq!(replies.last().unwrap(), "ugiok");
+        assert_eq!(ugi.handle("isready").unwrap(), vec!["readyok"]);
+        assert_eq!(ugi.handle("query p1turn").unwrap(), vec!["response true"]);
+        assert_eq!(
+            ugi.handle("query gameover").unwrap(),
+            vec!["response false"]
+        );
+        assert_eq!(ugi.handle("query result").unwrap(), vec!["response none"]);
+        assert!(ugi
+            .handle("setoption name Threads value 2")
+            .unwrap()
+            .is_empty());
+        assert_eq!(ugi.handle("setoption name Hash value 16").unwrap().len(), 1);
+        assert!(ugi.handle("fly").unwrap()[0].starts_with("info string"));
+        assert_eq!(ugi.handle("quit"), None);
+    }
+
+    #[test]
+    fn position_and_go() {
+        let mut ugi = engine();
+        assert!(ugi
+            .handle("position startpos moves d3 d3")
+            .unwrap()
+            .is_empty());
+        assert_eq!(ugi.state().current_player(), Player::Player1);
+        assert_eq!(ugi.state().ply(), 2);
+
+        let replies = ugi.handle("go nodes 40").unwrap();
+        assert_eq!(replies.len(), 11);
+        assert!(replies[0].starts_with("info depth"));
+        assert!(replies[9].contains(" nodes 40 "));
+        assert!(replies[9].contains(" pv "));
+        let best = replies[10].strip_prefix("bestmove ").expect("No best move");
+        let mov = BoardAction::parse(best, Player::Player1).expect("Bad best move");
+        assert_eq!(ugi.state().explain_illegal(&mov), None);
+
+        let notation = ugi.state().to_notation();
+        assert!(ugi
+            .handle(&format!("position fen {} moves {}", notation, best))
+            .unwrap()
+            .is_empty());
+        assert_eq!(ugi.state().current_player(), Player::Player2);
+        assert_eq!(ugi.handle("position fen 7/7/7 X 0 0").unwrap().len(), 1);
+        assert_eq!(ugi.state().current_player(), Player::Player2);
+
+        // A bad move leaves the position as it was.
+        assert_eq!(ugi.handle("position startpos moves d9").unwrap().len(), 1);
+        assert_eq!(ugi.state().current_player(), Player::Player2);
+        assert_eq!(ugi.handle("go depth 3").unwrap().len(), 1);
+        assert!(ugi.handle("uginewgame").unwrap().is_empty());
+        assert_eq!(ugi.state().current_player(), Player::Player1);
+    }
+
+    #[test]
+    fn stop_a_search() {
+        let mut ugi = engine();
+        let input = "position startpos\ngo movetime 60000\nisready\nstop\nquit\n";
+        let mut output = Vec::new();
+        let start = Instant::now();
+        ugi.run(input.as_bytes(), &mut output)
+            .expect("Could not run");
+        assert!(start.elapsed() < Duration::from_secs(30));
+
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert!(lines.contains(&"readyok"));
+        assert!(lines.iter().any(|line| line.starts_with("info depth")));
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+        assert_ne!(lines.last(), Some(&"bestmove 0000"));
+    }
+}
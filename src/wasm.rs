@@ -0,0 +1,162 @@
+// Bindings for a browser front end, so it hosts the game with the same rules
+// code as the engine. Actions are passed in the usual notation, d3 drops in
+// column 3 and s3,0-3,1 switches two stones, and players are 1 and 2.
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    action::{BoardAction, Coordinate},
+    board::{Cell, TerminalResult},
+    json::{player_number, step_json},
+    player::Player,
+    rules::GameRules,
+    BoardState,
+};
+use mcts::GameState;
+
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Game {
+    state: BoardState,
+}
+
+#[wasm_bindgen]
+impl Game {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Game {
+        Self::default()
+    }
+
+    // Rules as read by GameRules::parse, such as "size=7x6 cascade=false".
+    pub fn with_rules(rules: &str) -> Result<Game, String> {
+        let rules = GameRules::parse(rules).ok_or_else(|| format!("Bad rules: {}", rules))?;
+        Ok(Game {
+            state: BoardState::new(rules),
+        })
+    }
+
+    pub fn rules(&self) -> String {
+        self.state.rules().to_string()
+    }
+
+    pub fn width(&self) -> usize {
+        self.state.board().width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.state.board().height()
+    }
+
+    // 0 for an empty cell, otherwise the player owning the stone.
+    pub fn cell(&self, x: usize, y: usize) -> u8 {
+        match self.state.cell(Coordinate::new(x as isize, y as isize)) {
+            Cell::Empty => 0,
+            Cell::Filled(player) => player_number(player),
+        }
+    }
+
+    // The rows top row first, see Board::rows.
+    pub fn rows(&self) -> Vec<String> {
+        self.state.board().rows()
+    }
+
+    pub fn current_player(&self) -> u8 {
+        player_number(self.state.current_player())
+    }
+
+    pub fn points(&self, player: u8) -> Result<usize, String> {
+        Ok(self.state.points(parse_player(player)?))
+    }
+
+    pub fn legal_moves(&self) -> Vec<String> {
+        self.state
+            .available_moves()
+            .iter()
+            .map(|mov| mov.to_string())
+            .collect()
+    }
+
+    pub fn history(&self) -> Vec<String> {
+        self.state.history().map(|mov| mov.to_string()).collect()
+    }
+
+    // Plays a legal action and returns how it resolved, as a JSON array with
+    // an entry per cascade phase so the front end can animate them:
+    //
+    //     [{"results": ["three:1"], "lines": [[[2, 0], [3, 0], [4, 0]]],
+    //       "cleared": [[2, 0], [3, 0], [4, 0]]},
+    //      {"terminal": "winner:1"}]
+    pub fn play(&mut self, action: &str) -> Result<String, String> {
+        let player = self.state.current_player();
+        let mov = BoardAction::parse(action, player)
+            .ok_or_else(|| format!("Could not read {}", action))?;
+        self.state
+            .check_move(&mov)
+            .map_err(|e| format!("Can't play {}: {}", action, e))?;
+
+        let steps = self
+            .state
+            .play_steps(&mov)
+            .iter()
+            .map(step_json)
+            .collect::<Vec<_>>();
+        Ok(Value::Array(steps).to_string())
+    }
+
+    // Takes back the last action and returns it.
+    pub fn undo(&mut self) -> Option<String> {
+        self.state.undo().map(|mov| mov.to_string())
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.state.is_terminal()
+    }
+
+    // The winning player, 0 for a draw or a game that is not over.
+    pub fn winner(&self) -> u8 {
+        match self.state.result() {
+            TerminalResult::Win(player) => player_number(player),
+            _ => 0,
+        }
+    }
+}
+
+fn parse_player(player: u8) -> Result<Player, String> {
+    match player {
+        1 => Ok(Player::Player1),
+        2 => Ok(Player::Player2),
+        _ => Err(format!("Unknown player {}", player)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::Game;
+
+    #[test]
+    fn play_through_bindings() {
+        let mut game = Game::with_rules("size=5x4").expect("Could not create game");
+        assert_eq!((game.width(), game.height()), (5, 4));
+        assert!(Game::with_rules("size=huge").is_err());
+
+        for action in ["d0", "d0", "d1", "d1"] {
+            assert_eq!(game.play(action), Ok(String::from("[]")));
+        }
+        assert!(game.play("s0,0-0,1").is_err());
+
+        let steps: Value = serde_json::from_str(&game.play("d2").expect("Could not play")).unwrap();
+        assert_eq!(steps[0]["results"][0], "three:1");
+        assert_eq!(steps[0]["cleared"].as_array().map(Vec::len), Some(3));
+        assert_eq!(steps[0]["lines"][0][2], serde_json::json!([2, 0]));
+        assert_eq!(game.points(1), Ok(1));
+        assert_eq!(game.cell(0, 0), 2);
+        assert_eq!(game.current_player(), 2);
+
+        assert_eq!(game.undo(), Some(String::from("d2")));
+        assert_eq!(game.history().len(), 4);
+        assert!(game.legal_moves().contains(&String::from("d4")));
+        assert_eq!(game.winner(), 0);
+    }
+}
@@ -0,0 +1,134 @@
+// Win, draw and loss probabilities for the player to move. A scalar value
+// maps a draw to 0, the same as a position the network knows nothing about,
+// so a WDL head predicts how drawish a position is on its own. The search
+// backs up the expected score, the win minus the loss probability.
+//
+// catzero trains and runs a single value, so the train pipeline and the
+// TensorFlow search only ever see a scalar. A WDL head is trained outside
+// catzero on the targets of `export` and played through the onnx models.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Wdl {
+    pub win: f32,
+    pub draw: f32,
+    pub loss: f32,
+}
+
+impl Wdl {
+    pub const WIN: Wdl = Wdl {
+        win: 1.0,
+        draw: 0.0,
+        loss: 0.0,
+    };
+    pub const DRAW: Wdl = Wdl {
+        win: 0.0,
+        draw: 1.0,
+        loss: 0.0,
+    };
+    pub const LOSS: Wdl = Wdl {
+        win: 0.0,
+        draw: 0.0,
+        loss: 1.0,
+    };
+
+    // The target of a finished game for the player, a draw when nobody won.
+    pub fn outcome<P: PartialEq>(winner: Option<&P>, player: &P) -> Self {
+        match winner {
+            Some(winner) if winner == player => Wdl::WIN,
+            Some(_) => Wdl::LOSS,
+            None => Wdl::DRAW,
+        }
+    }
+
+    // Spreads a scalar value in [-1, 1] so the expected score is the value.
+    // The targets 1, 0 and -1 give back the outcome they stand for.
+    pub fn from_value(value: f32) -> Self {
+        let value = value.clamp(-1.0, 1.0);
+        Wdl {
+            win: value.max(0.0),
+            draw: 1.0 - value.abs(),
+            loss: (-value).max(0.0),
+        }
+    }
+
+    // The outputs of a head in the order win, draw, loss, scaled so they
+    // sum to one. Negative and non-finite outputs count as zero, a head
+    // that gives nothing at all reads as a draw.
+    pub fn from_probabilities(outputs: [f32; 3]) -> Self {
+        let [win, draw, loss] = outputs.map(|p| if p.is_finite() { p.max(0.0) } else { 0.0 });
+        let sum = win + draw + loss;
+        if sum <= 0.0 {
+            return Wdl::DRAW;
+        }
+        Wdl {
+            win: win / sum,
+            draw: draw / sum,
+            loss: loss / sum,
+        }
+    }
+
+    // Expected score in [-1, 1].
+    pub fn value(&self) -> f32 {
+        self.win - self.loss
+    }
+
+    // The same position seen by the opponent.
+    pub fn flipped(&self) -> Self {
+        Wdl {
+            win: self.loss,
+            draw: self.draw,
+            loss: self.win,
+        }
+    }
+
+    pub fn to_array(&self) -> [f32; 3] {
+        [self.win, self.draw, self.loss]
+    }
+}
+
+// What the value head of a network gives for the player to move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Scalar(f32),
+    Wdl(Wdl),
+}
+
+impl Value {
+    // Expected score in [-1, 1].
+    pub fn expected(&self) -> f32 {
+        match self {
+            Value::Scalar(value) => *value,
+            Value::Wdl(wdl) => wdl.value(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Wdl;
+
+    #[test]
+    fn targets() {
+        assert_eq!(Wdl::outcome(Some(&1), &1), Wdl::WIN);
+        assert_eq!(Wdl::outcome(Some(&2), &1), Wdl::LOSS);
+        assert_eq!(Wdl::outcome(None, &1), Wdl::DRAW);
+
+        for wdl in [Wdl::WIN, Wdl::DRAW, Wdl::LOSS] {
+            assert_eq!(Wdl::from_value(wdl.value()), wdl);
+        }
+        assert_eq!(Wdl::LOSS.flipped(), Wdl::WIN);
+
+        let wdl = Wdl::from_value(-0.25);
+        assert_eq!(wdl.to_array(), [0.0, 0.75, 0.25]);
+        assert_eq!(wdl.value(), -0.25);
+    }
+
+    #[test]
+    fn probabilities() {
+        let wdl = Wdl::from_probabilities([2.0, 1.0, 1.0]);
+        assert_eq!(wdl.to_array(), [0.5, 0.25, 0.25]);
+        assert_eq!(wdl.value(), 0.25);
+
+        assert_eq!(Wdl::from_probabilities([0.0, -1.0, f32::NAN]), Wdl::DRAW);
+    }
+}